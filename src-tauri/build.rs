@@ -1,3 +1,24 @@
+use std::process::Command;
+
 fn main() {
+    // Bake build provenance into the binary for get_env_config.
+    // Builds outside a git checkout (e.g. from a source tarball) get no hash.
+    let git_hash = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .unwrap_or_default();
+    if !git_hash.is_empty() {
+        println!("cargo:rustc-env=GIT_HASH={}", git_hash);
+    }
+    println!("cargo:rerun-if-changed=../.git/HEAD");
+    println!("cargo:rerun-if-changed=../.git/refs/heads");
+
+    if let Ok(target) = std::env::var("TARGET") {
+        println!("cargo:rustc-env=BUILD_TARGET={}", target);
+    }
+
     tauri_build::build()
 }
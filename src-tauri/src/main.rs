@@ -10,6 +10,7 @@
 // 3. NATIVE DIALOGS - Open/Save dialogs via Tauri plugins
 // 4. SYSTEM TRAY - Background app with tray icon
 // 5. CONFIGURATION - Loading defaults.json with path expansion
+// 6. LOGGING - Rolling log files in the config directory
 //
 // The JavaScript frontend calls these functions via `window.__TAURI__.core.invoke()`.
 // Each #[tauri::command] function becomes callable from JS.
@@ -23,22 +24,149 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 use base64::{engine::general_purpose::STANDARD, Engine};
-use rusqlite::{params, Connection};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use rusqlite::{params, Connection, OptionalExtension};
 use serde::{Deserialize, Serialize};
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
 use std::collections::HashMap;
 use std::fs;
 use std::io::Write;
 use std::path::Path;
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use zip::write::SimpleFileOptions;
 use zip::ZipWriter;
 use tauri::{
     image::Image,
     menu::{Menu, MenuItem},
-    tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent},
+    tray::{MouseButton, MouseButtonState, TrayIcon, TrayIconBuilder, TrayIconEvent},
     Emitter, Manager, State,
 };
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, ShortcutState};
 use tauri_plugin_window_state::{AppHandleExt, StateFlags};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::Rotation;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt::format::FmtSpan;
+
+// ============================================================================
+// Command Errors
+// ============================================================================
+//
+// Commands report failures as a CommandError, which reaches JavaScript as
+// `{ kind: "NotFound", message: "File not found: ..." }` so the frontend can
+// react to the kind and show the message. Helpers below the commands mostly
+// still return `Result<T, String>`; a command decides which kind their
+// message belongs to with `.map_err(CommandError::Io)` and friends.
+//
+// A few commands keep their own narrower enums (DirectoryError, SaveError,
+// ClipboardImageError), which serialize the same way.
+//
+// ============================================================================
+
+/// Error returned by commands, tagged by kind
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", content = "message")]
+enum CommandError {
+    /// The file, folder or library entry doesn't exist
+    NotFound(String),
+    /// Reading or writing failed for another reason
+    Io(String),
+    /// The data couldn't be parsed (bad JSON, not an image, ...)
+    Parse(String),
+    /// The library database failed
+    Db(String),
+    /// The request itself was invalid
+    Validation(String),
+    /// The OS refused access, or the path is outside the allowed folders
+    Permission(String),
+    /// The document uses a format version this build can't handle
+    UnsupportedVersion(String),
+    /// The same operation is already in progress
+    AlreadyRunning(String),
+    /// The file or image is over a configured size limit
+    TooLarge(String),
+}
+
+impl CommandError {
+    fn message(&self) -> &str {
+        match self {
+            CommandError::NotFound(m)
+            | CommandError::Io(m)
+            | CommandError::Parse(m)
+            | CommandError::Db(m)
+            | CommandError::Validation(m)
+            | CommandError::Permission(m)
+            | CommandError::UnsupportedVersion(m)
+            | CommandError::AlreadyRunning(m)
+            | CommandError::TooLarge(m) => m,
+        }
+    }
+
+    /// Classify an I/O error by its kind, prefixing `context` to the message
+    fn io(context: &str, error: std::io::Error) -> Self {
+        let message = format!("{}: {}", context, error);
+        match error.kind() {
+            std::io::ErrorKind::NotFound => CommandError::NotFound(message),
+            std::io::ErrorKind::PermissionDenied => CommandError::Permission(message),
+            _ => CommandError::Io(message),
+        }
+    }
+}
+
+impl std::fmt::Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+/// Lets helpers that report plain strings use `?` on ones that already
+/// return a CommandError
+impl From<CommandError> for String {
+    fn from(e: CommandError) -> Self {
+        e.to_string()
+    }
+}
+
+impl From<std::io::Error> for CommandError {
+    fn from(e: std::io::Error) -> Self {
+        match e.kind() {
+            std::io::ErrorKind::NotFound => CommandError::NotFound(e.to_string()),
+            std::io::ErrorKind::PermissionDenied => CommandError::Permission(e.to_string()),
+            _ => CommandError::Io(e.to_string()),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for CommandError {
+    fn from(e: rusqlite::Error) -> Self {
+        CommandError::Db(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for CommandError {
+    fn from(e: serde_json::Error) -> Self {
+        CommandError::Parse(e.to_string())
+    }
+}
+
+/// Error types DbState::with_conn can return, so DB helpers can fail with
+/// either a plain message or a CommandError
+trait DbError: std::fmt::Display {
+    fn reopen_failed(e: rusqlite::Error) -> Self;
+}
+
+impl DbError for String {
+    fn reopen_failed(e: rusqlite::Error) -> Self {
+        format!("Failed to reopen database: {}", e)
+    }
+}
+
+impl DbError for CommandError {
+    fn reopen_failed(e: rusqlite::Error) -> Self {
+        CommandError::Db(String::reopen_failed(e))
+    }
+}
 
 // ============================================================================
 // Database State
@@ -54,32 +182,482 @@ use tauri_plugin_window_state::{AppHandleExt, StateFlags};
 // Tables:
 //   - files: Main table with path, filename, thumbnail, metadata
 //   - files_fts: FTS5 virtual table for full-text search
+//   - search_history: recent search queries, for suggestions
+//   - settings: editor settings shared by the backend and frontend (see SETTINGS)
+//   - recent_folders: folders opened in the file browser, for quick access
+//   - tag_colors: chip colour of each keyword
+//   - collections, collection_members: named sets of documents
+//
+// Rows for trashed files are soft-deleted (deleted_at set) and excluded from
+// listings and search. Triggers drop them from collections at the same time.
+//
+// Schema changes after the original tables are made through MIGRATIONS,
+// tracked with SQLite's user_version pragma.
 //
 // ============================================================================
 
 /// Wrapper to hold the database connection. The Mutex ensures thread-safe access
 /// since Tauri commands can run on different threads.
-struct DbState(Mutex<Connection>);
+/// The second field caches whether the search index folds accents (see
+/// folds_accents) and is cleared whenever the connection is replaced.
+struct DbState(Mutex<Connection>, Mutex<Option<bool>>);
+
+impl DbState {
+    fn new(conn: Connection) -> Self {
+        DbState(Mutex::new(conn), Mutex::new(None))
+    }
+
+    /// Whether the search index on `conn` (this state's connection) folds
+    /// accents. Read from the schema once and kept until the connection is
+    /// replaced or the tokenizer changes, since searches ask on every keystroke.
+    fn folds_accents(&self, conn: &Connection) -> Result<bool, rusqlite::Error> {
+        let mut cached = self.1.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(folds) = *cached {
+            return Ok(folds);
+        }
+        let folds = fts_folds_accents(conn)?;
+        *cached = Some(folds);
+        Ok(folds)
+    }
+
+    /// Drop what folds_accents remembered about the schema
+    fn forget_schema(&self) {
+        *self.1.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = None;
+    }
+
+    /// Run `op` with the connection. A bad handle gets one recovery attempt:
+    /// if a panic poisoned the lock the connection is reopened before `op`
+    /// runs, and if `op` fails because the database file went away or is
+    /// damaged it is reopened for the next call. `op` isn't run again, since
+    /// it may have written part of its work already; read-only operations
+    /// that are safe to repeat use with_read_conn instead.
+    fn with_conn<T, E, F>(&self, op: F) -> Result<T, E>
+    where
+        E: DbError,
+        F: FnMut(&mut Connection) -> Result<T, E>,
+    {
+        self.with_conn_reopening(init_database, false, op)
+    }
+
+    /// with_conn for an `op` that only reads, which is also retried once on
+    /// the reopened connection
+    fn with_read_conn<T, E, F>(&self, op: F) -> Result<T, E>
+    where
+        E: DbError,
+        F: FnMut(&mut Connection) -> Result<T, E>,
+    {
+        self.with_conn_reopening(init_database, true, op)
+    }
+
+    fn with_conn_reopening<T, E, F, R>(&self, reopen: R, retry: bool, mut op: F) -> Result<T, E>
+    where
+        E: DbError,
+        F: FnMut(&mut Connection) -> Result<T, E>,
+        R: Fn() -> Result<Connection, rusqlite::Error>,
+    {
+        let mut conn = match self.0.lock() {
+            Ok(conn) => conn,
+            Err(poisoned) => {
+                tracing::warn!("Database lock poisoned by a panic, reopening the connection");
+                self.0.clear_poison();
+                let mut conn = poisoned.into_inner();
+                self.forget_schema();
+                *conn = reopen().map_err(E::reopen_failed)?;
+                conn
+            }
+        };
+
+        match op(&mut conn) {
+            Err(e) if is_bad_connection_error(&e.to_string()) => {
+                tracing::warn!(error = %e, retry, "Database connection failed, reopening");
+                self.forget_schema();
+                *conn = reopen().map_err(E::reopen_failed)?;
+                if retry {
+                    op(&mut conn)
+                } else {
+                    Err(e)
+                }
+            }
+            result => result,
+        }
+    }
+
+    /// Replace the connection with a freshly opened one
+    fn reconnect(&self) -> Result<(), String> {
+        let mut conn = self.lock_for_replacing();
+        *conn = init_database().map_err(|e| format!("Failed to reopen database: {}", e))?;
+        Ok(())
+    }
+
+    /// Lock the connection in order to replace it. A poisoned lock doesn't
+    /// matter here, since the connection is about to be thrown away.
+    fn lock_for_replacing(&self) -> std::sync::MutexGuard<'_, Connection> {
+        let conn = self.0.lock().unwrap_or_else(|poisoned| {
+            self.0.clear_poison();
+            poisoned.into_inner()
+        });
+        self.forget_schema();
+        conn
+    }
+}
+
+/// Errors that mean the connection itself is unusable (the file was moved,
+/// deleted or damaged) rather than that a query was wrong
+fn is_bad_connection_error(error: &str) -> bool {
+    const MESSAGES: &[&str] = &[
+        "database disk image is malformed",
+        "file is not a database",
+        "disk I/O error",
+        "unable to open database file",
+        "attempt to write a readonly database",
+    ];
+    MESSAGES.iter().any(|m| error.contains(m))
+}
 
 /// Managed state for active ZIP archives being built by bulk export.
 /// Each archive is identified by a UUID string key.
 struct ZipState(Mutex<HashMap<String, Mutex<ZipWriter<fs::File>>>>);
 
-/// Initialize the SQLite database with FTS5 (Full-Text Search) support.
-/// Creates tables and triggers if they don't exist.
+/// Set by cancel_rebuild to stop an in-progress library rebuild.
+/// Kept outside DbState because the rebuild holds the database lock.
+struct RebuildCancel(AtomicBool);
+
+/// Set while db_rebuild_from_library runs, so a second rebuild is turned
+/// away instead of waiting for the database lock and repeating the work
+struct RebuildRunning(AtomicBool);
+
+impl RebuildRunning {
+    /// Claim the rebuild, or AlreadyRunning if another one holds it. The
+    /// claim is released when the guard is dropped, however the rebuild ends.
+    fn start(&self) -> Result<RunningGuard<'_>, CommandError> {
+        self.0
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .map_err(|_| CommandError::AlreadyRunning("A library rebuild is already running".into()))?;
+        Ok(RunningGuard(&self.0))
+    }
+}
+
+struct RunningGuard<'a>(&'a AtomicBool);
+
+impl Drop for RunningGuard<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+/// Running claim and cancel flag for one kind of long library operation
+/// (thumbnail regeneration, disk usage, backup). Each kind has its own, so
+/// cancelling one never stops another or the rebuild.
+struct OperationSlot {
+    running: AtomicBool,
+    cancel: AtomicBool,
+    name: &'static str,
+}
+
+impl OperationSlot {
+    const fn new(name: &'static str) -> Self {
+        OperationSlot { running: AtomicBool::new(false), cancel: AtomicBool::new(false), name }
+    }
+
+    /// Claim the slot, then clear any old cancel, so a call turned away
+    /// with AlreadyRunning can't undo a cancel aimed at the running one
+    fn start(&self) -> Result<RunningGuard<'_>, CommandError> {
+        self.running
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .map_err(|_| CommandError::AlreadyRunning(format!("{} is already running", self.name)))?;
+        self.cancel.store(false, Ordering::Relaxed);
+        Ok(RunningGuard(&self.running))
+    }
+
+    fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Slot for regenerate_thumbnails, stopped by cancel_regenerate_thumbnails
+struct ThumbnailRegenerationSlot(OperationSlot);
+
+/// Slot for library_disk_usage, stopped by cancel_library_disk_usage
+struct DiskUsageSlot(OperationSlot);
+
+/// Slot for backup_library, stopped by cancel_backup_library
+struct LibraryBackupSlot(OperationSlot);
+
+/// Prepared statements kept per connection by `prepare_cached`. Large enough
+/// for every fixed query plus each variant of the search query.
+const STATEMENT_CACHE_CAPACITY: usize = 64;
+
+/// Size in bytes the write-ahead log is truncated to after a checkpoint
+const WAL_SIZE_LIMIT: i64 = 4 * 1024 * 1024;
+
+/// Open the library database in the config directory and bring its schema
+/// up to date.
 fn init_database() -> Result<Connection, rusqlite::Error> {
-    let db_path = dirs::config_dir()
+    open_database(&database_path())
+}
+
+/// Location of the library database file
+fn database_path() -> std::path::PathBuf {
+    dirs::config_dir()
         .unwrap_or_else(|| std::path::PathBuf::from("."))
         .join("ssce-desktop")
-        .join("library.db");
+        .join("library.db")
+}
+
+fn open_database(db_path: &Path) -> Result<Connection, rusqlite::Error> {
+    open_database_migrating(db_path).map(|(conn, _)| conn)
+}
 
+/// Open the database at `db_path`, also returning the number of migrations
+/// that had to be applied to it
+fn open_database_migrating(db_path: &Path) -> Result<(Connection, usize), rusqlite::Error> {
     // Ensure directory exists
     if let Some(parent) = db_path.parent() {
         let _ = fs::create_dir_all(parent);
     }
 
-    let conn = Connection::open(&db_path)?;
+    let conn = Connection::open(db_path)?;
+    configure_connection(&conn)?;
+    let migrated = init_schema(&conn)?;
+
+    Ok((conn, migrated))
+}
+
+/// Payload of the backend-ready event: the library database is open and
+/// its schema up to date
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BackendReady {
+    schema_version: i64,
+    /// Whether opening the database upgraded its schema
+    migrated: bool,
+}
+
+/// Payload of the backend-error event: the library database couldn't be
+/// opened, so the app is running on an empty in-memory library
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BackendError {
+    database_path: String,
+    message: String,
+}
+
+/// Open the library database for startup. If that fails the app still
+/// starts, on an empty in-memory database, so the frontend can show the
+/// error instead of the process exiting with no window.
+fn open_startup_database(db_path: &Path) -> (Connection, Result<BackendReady, BackendError>) {
+    let opened = open_database_migrating(db_path).and_then(|(conn, migrated)| {
+        let schema_version = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        Ok((conn, BackendReady { schema_version, migrated: migrated > 0 }))
+    });
+
+    match opened {
+        Ok((conn, ready)) => (conn, Ok(ready)),
+        Err(e) => {
+            let error = BackendError { database_path: db_path.display().to_string(), message: e.to_string() };
+            let conn = Connection::open_in_memory()
+                .and_then(|conn| init_schema(&conn).map(|_| conn))
+                .expect("Failed to create in-memory database");
+            (conn, Err(error))
+        }
+    }
+}
+
+/// Per-connection settings, applied whenever the database is (re)opened
+fn configure_connection(conn: &Connection) -> Result<(), rusqlite::Error> {
+    conn.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
+    conn.pragma_update(None, "journal_mode", journal_mode_for(conn))?;
+    // Once checkpointed, a WAL that grew past this is cut back to it
+    conn.pragma_update(None, "journal_size_limit", WAL_SIZE_LIMIT)?;
+    conn.busy_timeout(std::time::Duration::from_secs(5))?;
+    Ok(())
+}
+
+/// WAL on local disks: readers don't block the writer, and a crash can't
+/// leave a half-written page. WAL relies on shared memory that network file
+/// systems don't provide reliably, so a database on one uses the rollback
+/// journal instead.
+fn journal_mode_for(conn: &Connection) -> &'static str {
+    match database_filesystem(conn) {
+        Some(fs) if is_network_filesystem(&fs) => {
+            tracing::warn!(filesystem = %fs, "library.db is on a network file system, not using WAL");
+            "DELETE"
+        }
+        _ => "WAL",
+    }
+}
+
+/// File system type of the folder holding a connection's database file,
+/// or None for in-memory databases and when it can't be determined
+fn database_filesystem(conn: &Connection) -> Option<String> {
+    let path = Path::new(conn.path().filter(|p| !p.is_empty())?);
+    filesystem_type(path.parent().unwrap_or(path))
+}
+
+/// Whether a file system type, as named by filesystem_type, is a network
+/// file system. A FUSE mount whose driver couldn't be told apart ("fuse",
+/// or macFUSE) may well be sshfs or similar, so it counts as one.
+fn is_network_filesystem(fs: &str) -> bool {
+    matches!(
+        fs,
+        "nfs"
+            | "smbfs"
+            | "smb2"
+            | "cifs"
+            | "afs"
+            | "9p"
+            | "ceph"
+            | "webdav"
+            | "afpfs"
+            | "fuse"
+            | "fuse.sshfs"
+            | "fuse.rclone"
+            | "fuse.s3fs"
+            | "fuse.gcsfuse"
+            | "fuse.smbnetfs"
+            | "macfuse"
+            | "osxfuse"
+    )
+}
+
+/// File system type of the volume holding `path` (e.g. "ext4", "nfs")
+#[cfg(target_os = "linux")]
+fn filesystem_type(path: &Path) -> Option<String> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    // SAFETY: statfs is plain data, and c_path is a valid NUL-terminated
+    // string for the whole call
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+
+    // Magic numbers from statfs(2); f_type's width varies by architecture
+    #[allow(clippy::unnecessary_cast)]
+    let name = match stat.f_type as u64 & 0xFFFF_FFFF {
+        0xEF53 => "ext4",
+        0x9123_683E => "btrfs",
+        0x5846_5342 => "xfs",
+        0x2FC1_2FC1 => "zfs",
+        0x0102_1994 => "tmpfs",
+        0x794C_7630 => "overlay",
+        0x4d44 => "vfat",
+        0x5346_544E => "ntfs",
+        0x6969 => "nfs",
+        0x517B => "smbfs",
+        0xFF53_4D42 => "cifs",
+        0xFE53_4D42 => "smb2",
+        0x5346_414F => "afs",
+        0x0102_1997 => "9p",
+        0x00C3_6400 => "ceph",
+        // All FUSE file systems share one magic; the mount table names the
+        // driver (e.g. "fuse.sshfs")
+        0x6573_5546 => {
+            let mountinfo = fs::read_to_string("/proc/self/mountinfo").unwrap_or_default();
+            let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+            return Some(mount_filesystem_type(&mountinfo, &path).unwrap_or_else(|| "fuse".to_string()));
+        }
+        other => return Some(format!("0x{:x}", other)),
+    };
+    Some(name.to_string())
+}
+
+/// File system type of the mount holding `path`, from the text of
+/// /proc/self/mountinfo: the entry with the longest mount point that
+/// contains `path`
+#[cfg(target_os = "linux")]
+fn mount_filesystem_type(mountinfo: &str, path: &Path) -> Option<String> {
+    // Spaces and a few other characters in mount points are octal escapes
+    let unescape = |field: &str| -> String {
+        let mut out = Vec::with_capacity(field.len());
+        let bytes = field.as_bytes();
+        let mut i = 0;
+        while i < bytes.len() {
+            let octal = bytes.get(i + 1..i + 4).and_then(|d| std::str::from_utf8(d).ok());
+            match octal.and_then(|d| u8::from_str_radix(d, 8).ok()) {
+                Some(byte) if bytes[i] == b'\\' => {
+                    out.push(byte);
+                    i += 4;
+                }
+                _ => {
+                    out.push(bytes[i]);
+                    i += 1;
+                }
+            }
+        }
+        String::from_utf8_lossy(&out).into_owned()
+    };
+
+    mountinfo
+        .lines()
+        .filter_map(|line| {
+            // id parent dev root mount-point options [optional...] - type source super-options
+            let (mount, rest) = line.split_once(" - ")?;
+            let mount_point = unescape(mount.split(' ').nth(4)?);
+            let fs_type = rest.split(' ').next()?;
+            path.starts_with(&mount_point).then(|| (mount_point.len(), fs_type.to_string()))
+        })
+        .max_by_key(|(len, _)| *len)
+        .map(|(_, fs_type)| fs_type)
+}
+
+/// File system type of the volume holding `path` (e.g. "apfs", "smbfs")
+#[cfg(target_os = "macos")]
+fn filesystem_type(path: &Path) -> Option<String> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    // SAFETY: statfs is plain data, and c_path is a valid NUL-terminated
+    // string for the whole call
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    // SAFETY: the kernel fills f_fstypename with a NUL-terminated name
+    let name = unsafe { std::ffi::CStr::from_ptr(stat.f_fstypename.as_ptr()) };
+    Some(name.to_string_lossy().into_owned())
+}
+
+/// File system type of the volume holding `path`. Not detected on this
+/// platform.
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn filesystem_type(_path: &Path) -> Option<String> {
+    None
+}
+
+/// Create the tables and triggers if they don't exist, then apply any
+/// pending migrations. Also registers the app's SQL functions, which every
+/// connection needs. Returns the number of migrations applied.
+fn init_schema(conn: &Connection) -> Result<usize, rusqlite::Error> {
+    register_sql_functions(conn)?;
+    create_base_schema(conn)?;
+    run_migrations(conn)
+}
+
+/// `ssce_contains_terms(text, query)`: whether every word of `query` occurs
+/// in `text`, ignoring case but not accents. Used for accent-sensitive search.
+fn register_sql_functions(conn: &Connection) -> Result<(), rusqlite::Error> {
+    use rusqlite::functions::FunctionFlags;
+
+    conn.create_scalar_function(
+        "ssce_contains_terms",
+        2,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| {
+            let text = nfc(&ctx.get::<Option<String>>(0)?.unwrap_or_default()).to_lowercase();
+            let query = nfc(&ctx.get::<String>(1)?).to_lowercase();
+            Ok(query
+                .split(|c: char| !c.is_alphanumeric())
+                .filter(|w| !w.is_empty())
+                .all(|term| text.contains(term)))
+        },
+    )
+}
 
+/// Create the original tables and triggers (with FTS5 full-text search).
+/// This must not change; later changes are applied on top of it by MIGRATIONS.
+fn create_base_schema(conn: &Connection) -> Result<(), rusqlite::Error> {
     // Create main files table
     conn.execute(
         "CREATE TABLE IF NOT EXISTS files (
@@ -137,1504 +715,16966 @@ fn init_database() -> Result<Connection, rusqlite::Error> {
         [],
     )?;
 
-    Ok(conn)
+    Ok(())
 }
 
-// ============================================================================
-// Database Types
-// ============================================================================
-//
-// These structs define the shape of data passed between JavaScript and Rust.
-// Serde handles JSON serialization/deserialization automatically.
-//
-// In JS, you'd call: invoke('db_upsert_file', { file: { path: '...', filename: '...' } })
-// Rust receives it as a LibraryFile struct.
-//
-// ============================================================================
-
-/// Represents a file entry in the library database.
-/// Maps directly to the 'files' table columns.
-#[derive(Serialize, Deserialize)]
-struct LibraryFile {
-    id: Option<i64>,
-    path: String,
-    filename: String,
-    thumbnail: Option<String>,
-    title: Option<String>,
-    summary: Option<String>,
-    keywords: Option<String>,
-    modified: Option<String>,
-    last_opened: Option<String>,
-    snapshot_count: i32,
+/// How the full-text index splits text into words (FTS5's unicode61
+/// tokenizer). Read from `search.tokenizer` in defaults.json; by default
+/// accents are ignored and underscores separate words.
+#[derive(Debug, Clone, PartialEq)]
+struct FtsTokenizer {
+    /// unicode61 remove_diacritics: 0 keeps accents, 1 and 2 fold them (2
+    /// also handles letters carrying more than one mark)
+    remove_diacritics: u8,
+    /// Punctuation to treat as part of a word
+    token_chars: String,
+    /// Letters or digits to treat as word breaks
+    separators: String,
 }
 
-#[derive(Deserialize)]
-struct SearchParams {
-    query: Option<String>,
-    from_date: Option<String>,
-    to_date: Option<String>,
-    limit: Option<i32>,
+impl Default for FtsTokenizer {
+    fn default() -> Self {
+        FtsTokenizer {
+            remove_diacritics: 2,
+            token_chars: String::new(),
+            separators: "_".to_string(),
+        }
+    }
 }
 
-// ============================================================================
-// Database Commands
-// ============================================================================
-//
-// These commands are called from JavaScript to manage the library database.
-// The State<DbState> parameter is automatically injected by Tauri - it gives
-// access to the shared database connection we set up in main().
-//
-// JS usage: await invoke('db_upsert_file', { file: {...} })
-//
-// ============================================================================
-
-/// Add or update a file in the library database.
-/// Uses SQLite's UPSERT (INSERT ... ON CONFLICT) to handle both cases.
-/// Called when opening or saving .ssce files to keep the library up to date.
-#[tauri::command]
-fn db_upsert_file(state: State<DbState>, file: LibraryFile) -> Result<i64, String> {
-    let conn = state.0.lock().map_err(|e| e.to_string())?;
-
-    conn.execute(
-        "INSERT INTO files (path, filename, thumbnail, title, summary, keywords, modified, last_opened, snapshot_count)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
-         ON CONFLICT(path) DO UPDATE SET
-             filename = excluded.filename,
-             thumbnail = excluded.thumbnail,
-             title = excluded.title,
-             summary = excluded.summary,
-             keywords = excluded.keywords,
-             modified = excluded.modified,
-             last_opened = excluded.last_opened,
-             snapshot_count = excluded.snapshot_count",
-        params![
-            file.path,
-            file.filename,
-            file.thumbnail,
-            file.title,
-            file.summary,
-            file.keywords,
-            file.modified,
-            file.last_opened,
-            file.snapshot_count,
-        ],
-    )
-    .map_err(|e| e.to_string())?;
+impl FtsTokenizer {
+    fn from_config(config: &Defaults) -> Self {
+        let configured = &config.section("search")["tokenizer"];
+        let defaults = FtsTokenizer::default();
+        // A double quote would end the tokenize option, so it's never allowed
+        let chars = |name: &str, default: String| {
+            configured[name].as_str().map_or(default, |c| c.chars().filter(|&c| c != '"').collect())
+        };
+        FtsTokenizer {
+            remove_diacritics: configured["removeDiacritics"]
+                .as_u64()
+                .filter(|n| *n <= 2)
+                .map_or(defaults.remove_diacritics, |n| n as u8),
+            token_chars: chars("tokenChars", defaults.token_chars),
+            separators: chars("separators", defaults.separators),
+        }
+    }
 
-    let id = conn.last_insert_rowid();
-    Ok(id)
+    /// Value of the FTS5 tokenize option, e.g. `unicode61 remove_diacritics 2 separators '_'`
+    fn spec(&self) -> String {
+        let quote = |chars: &str| format!("'{}'", chars.replace('\'', "''"));
+        let mut spec = format!("unicode61 remove_diacritics {}", self.remove_diacritics);
+        if !self.token_chars.is_empty() {
+            spec.push_str(&format!(" tokenchars {}", quote(&self.token_chars)));
+        }
+        if !self.separators.is_empty() {
+            spec.push_str(&format!(" separators {}", quote(&self.separators)));
+        }
+        spec
+    }
 }
 
-/// Get recent files ordered by last_opened (most recent first).
-/// Used to populate the "Recent Files" dialog in the UI.
-#[tauri::command]
-fn db_get_recent_files(state: State<DbState>, limit: i32) -> Result<Vec<LibraryFile>, String> {
-    let conn = state.0.lock().map_err(|e| e.to_string())?;
-
-    let mut stmt = conn
-        .prepare(
-            "SELECT id, path, filename, thumbnail, title, summary, keywords, modified, last_opened, snapshot_count
-             FROM files
-             WHERE last_opened IS NOT NULL
-             ORDER BY last_opened DESC
-             LIMIT ?1",
-        )
-        .map_err(|e| e.to_string())?;
-
-    let files = stmt
-        .query_map([limit], |row| {
-            Ok(LibraryFile {
-                id: Some(row.get(0)?),
-                path: row.get(1)?,
-                filename: row.get(2)?,
-                thumbnail: row.get(3)?,
-                title: row.get(4)?,
-                summary: row.get(5)?,
-                keywords: row.get(6)?,
-                modified: row.get(7)?,
-                last_opened: row.get(8)?,
-                snapshot_count: row.get(9)?,
-            })
-        })
-        .map_err(|e| e.to_string())?
-        .collect::<Result<Vec<_>, _>>()
-        .map_err(|e| e.to_string())?;
-
-    Ok(files)
+/// The tokenize option files_fts was created with, if it has one. The
+/// index keeps its tokenizer this way, so rebuilding it doesn't need the config.
+fn current_fts_tokenizer(conn: &Connection) -> Result<Option<String>, rusqlite::Error> {
+    let sql: Option<String> = conn
+        .query_row("SELECT sql FROM sqlite_master WHERE name = 'files_fts'", [], |row| row.get(0))
+        .optional()?;
+    Ok(sql.and_then(|sql| {
+        let (_, rest) = sql.split_once("tokenize=\"")?;
+        rest.split_once('"').map(|(spec, _)| spec.to_string())
+    }))
 }
 
-/// Search files using FTS5 full-text search with optional date range filters.
-/// Used by the "Search Library" dialog for finding files by keyword.
-/// Supports prefix matching (typing "scr" matches "screenshot").
-#[tauri::command]
-fn db_search_files(state: State<DbState>, params: SearchParams) -> Result<Vec<LibraryFile>, String> {
-    let conn = state.0.lock().map_err(|e| e.to_string())?;
+/// Whether the full-text index strips accents, so queries must be folded
+/// to match it (unless search.tokenizer says otherwise, it does)
+fn fts_folds_accents(conn: &Connection) -> Result<bool, rusqlite::Error> {
+    Ok(!current_fts_tokenizer(conn)?.is_some_and(|spec| spec.contains("remove_diacritics 0")))
+}
 
-    let limit = params.limit.unwrap_or(50);
+/// Rebuild the full-text index if it doesn't use `tokenizer`. Returns
+/// whether it was rebuilt.
+fn apply_fts_tokenizer(conn: &Connection, tokenizer: &FtsTokenizer) -> Result<bool, rusqlite::Error> {
+    let spec = tokenizer.spec();
+    if current_fts_tokenizer(conn)?.as_deref() == Some(spec.as_str()) {
+        return Ok(false);
+    }
+    let tx = conn.unchecked_transaction()?;
+    recreate_fts_index_with(&tx, &spec)?;
+    tx.commit()?;
+    Ok(true)
+}
 
-    // Build query based on whether we have a search term
-    let (sql, use_fts) = if let Some(ref query) = params.query {
-        if query.trim().is_empty() {
-            (String::from(
-                "SELECT id, path, filename, thumbnail, title, summary, keywords, modified, last_opened, snapshot_count
-                 FROM files
-                 WHERE 1=1"
-            ), false)
-        } else {
-            (String::from(
-                "SELECT f.id, f.path, f.filename, f.thumbnail, f.title, f.summary, f.keywords, f.modified, f.last_opened, f.snapshot_count
-                 FROM files f
-                 JOIN files_fts fts ON f.id = fts.rowid
-                 WHERE files_fts MATCH ?1"
-            ), true)
-        }
-    } else {
-        (String::from(
-            "SELECT id, path, filename, thumbnail, title, summary, keywords, modified, last_opened, snapshot_count
-             FROM files
-             WHERE 1=1"
-        ), false)
-    };
+/// Columns of files indexed by files_fts, in the table's column order
+/// (RankWeights::bm25_sql lists its weights in the same order)
+const FTS_COLUMNS: &[&str] = &["filename", "title", "summary", "keywords", "content", "author"];
 
-    // Add date filters and ordering
-    let mut sql = sql;
-    if params.from_date.is_some() {
-        sql.push_str(" AND modified >= ?2");
-    }
-    if params.to_date.is_some() {
-        sql.push_str(" AND modified <= ?3");
-    }
-    sql.push_str(" ORDER BY modified DESC LIMIT ?4");
+/// Create the current FTS5 virtual table and the triggers that keep it in
+/// sync with the files table. Used by migrations and db_rebuild_index, so
+/// any change here needs a migration that calls recreate_fts_index.
+/// Columns the files table doesn't have yet (earlier migrations run before
+/// they are added) are left out.
+fn create_fts_index(conn: &Connection, tokenize: &str) -> Result<(), rusqlite::Error> {
+    let existing = conn
+        .prepare("SELECT name FROM pragma_table_info('files')")?
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<std::collections::HashSet<_>, _>>()?;
+    let columns: Vec<&str> = FTS_COLUMNS.iter().copied().filter(|c| existing.contains(*c)).collect();
+    let list = columns.join(", ");
+    let prefixed = |prefix: &str| columns.iter().map(|c| format!("{}.{}", prefix, c)).collect::<Vec<_>>().join(", ");
+    let (new, old) = (prefixed("new"), prefixed("old"));
 
-    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    conn.execute(
+        &format!(
+            "CREATE VIRTUAL TABLE IF NOT EXISTS files_fts USING fts5(
+            {},
+            content='files',
+            content_rowid='id',
+            tokenize=\"{}\"
+        )",
+            list, tokenize
+        ),
+        [],
+    )?;
 
-    // Bind parameters based on query type
-    let files = if use_fts {
-        let query = params.query.as_ref().unwrap();
-        // Convert simple search to FTS5 format (prefix matching)
-        let fts_query = query
-            .split_whitespace()
-            .map(|w| format!("{}*", w))
-            .collect::<Vec<_>>()
-            .join(" ");
+    conn.execute(
+        &format!(
+            "CREATE TRIGGER IF NOT EXISTS files_ai AFTER INSERT ON files BEGIN
+            INSERT INTO files_fts(rowid, {list})
+            VALUES (new.id, {new});
+        END"
+        ),
+        [],
+    )?;
 
-        stmt.query_map(
-            params![
-                fts_query,
-                params.from_date.unwrap_or_default(),
-                params.to_date.unwrap_or_default(),
-                limit
-            ],
-            |row| {
-                Ok(LibraryFile {
-                    id: Some(row.get(0)?),
-                    path: row.get(1)?,
-                    filename: row.get(2)?,
-                    thumbnail: row.get(3)?,
-                    title: row.get(4)?,
-                    summary: row.get(5)?,
-                    keywords: row.get(6)?,
-                    modified: row.get(7)?,
-                    last_opened: row.get(8)?,
-                    snapshot_count: row.get(9)?,
-                })
-            },
-        )
-        .map_err(|e| e.to_string())?
-        .collect::<Result<Vec<_>, _>>()
-        .map_err(|e| e.to_string())?
-    } else {
-        stmt.query_map(
-            params![
-                "",  // placeholder for FTS query
-                params.from_date.unwrap_or_default(),
-                params.to_date.unwrap_or_default(),
-                limit
-            ],
-            |row| {
-                Ok(LibraryFile {
-                    id: Some(row.get(0)?),
-                    path: row.get(1)?,
-                    filename: row.get(2)?,
-                    thumbnail: row.get(3)?,
-                    title: row.get(4)?,
-                    summary: row.get(5)?,
-                    keywords: row.get(6)?,
-                    modified: row.get(7)?,
-                    last_opened: row.get(8)?,
-                    snapshot_count: row.get(9)?,
-                })
-            },
-        )
-        .map_err(|e| e.to_string())?
-        .collect::<Result<Vec<_>, _>>()
-        .map_err(|e| e.to_string())?
-    };
-
-    Ok(files)
-}
-
-/// Remove a file from the library database
-#[tauri::command]
-fn db_remove_file(state: State<DbState>, path: String) -> Result<(), String> {
-    let conn = state.0.lock().map_err(|e| e.to_string())?;
+    conn.execute(
+        &format!(
+            "CREATE TRIGGER IF NOT EXISTS files_ad AFTER DELETE ON files BEGIN
+            INSERT INTO files_fts(files_fts, rowid, {list})
+            VALUES ('delete', old.id, {old});
+        END"
+        ),
+        [],
+    )?;
 
-    conn.execute("DELETE FROM files WHERE path = ?1", params![path])
-        .map_err(|e| e.to_string())?;
+    conn.execute(
+        &format!(
+            "CREATE TRIGGER IF NOT EXISTS files_au AFTER UPDATE ON files BEGIN
+            INSERT INTO files_fts(files_fts, rowid, {list})
+            VALUES ('delete', old.id, {old});
+            INSERT INTO files_fts(rowid, {list})
+            VALUES (new.id, {new});
+        END"
+        ),
+        [],
+    )?;
 
     Ok(())
 }
 
-/// Update last_opened timestamp for a file
-#[tauri::command]
-fn db_update_last_opened(state: State<DbState>, path: String, timestamp: String) -> Result<(), String> {
-    let conn = state.0.lock().map_err(|e| e.to_string())?;
-
-    conn.execute(
-        "UPDATE files SET last_opened = ?1 WHERE path = ?2",
-        params![timestamp, path],
-    )
-    .map_err(|e| e.to_string())?;
+/// Drop the FTS5 table and triggers, create them with the current
+/// definition, and repopulate the index from the files table. The
+/// tokenizer is kept (the default one if the table had none).
+/// Callers are responsible for running this inside a transaction.
+fn recreate_fts_index(conn: &Connection) -> Result<(), rusqlite::Error> {
+    let tokenize = current_fts_tokenizer(conn)?.unwrap_or_else(|| FtsTokenizer::default().spec());
+    recreate_fts_index_with(conn, &tokenize)
+}
 
+fn recreate_fts_index_with(conn: &Connection, tokenize: &str) -> Result<(), rusqlite::Error> {
+    conn.execute_batch(
+        "DROP TRIGGER IF EXISTS files_ai;
+         DROP TRIGGER IF EXISTS files_ad;
+         DROP TRIGGER IF EXISTS files_au;
+         DROP TABLE IF EXISTS files_fts;",
+    )?;
+    create_fts_index(conn, tokenize)?;
+    conn.execute("INSERT INTO files_fts(files_fts) VALUES('rebuild')", [])?;
     Ok(())
 }
 
-/// Scan the library folder and index all .ssce files found.
-/// Called via "Rebuild from Library" button in Recent Files dialog.
-/// Extracts metadata (thumbnail, title, keywords) from each file.
-/// Also removes stale entries for files that no longer exist.
-#[tauri::command]
-fn db_rebuild_from_library(state: State<DbState>, library_path: String) -> Result<i32, String> {
-    let conn = state.0.lock().map_err(|e| e.to_string())?;
-
-    let path = Path::new(&library_path);
-    if !path.exists() {
-        return Err(format!("Library path does not exist: {}", library_path));
-    }
-
-    let mut count = 0;
-
-    // Recursively find all .ssce files
-    fn scan_dir(dir: &Path, conn: &Connection, count: &mut i32) -> Result<(), String> {
-        let entries = fs::read_dir(dir).map_err(|e| e.to_string())?;
+/// A single schema migration: plain SQL, or Rust code for changes that need
+/// to share logic with the rest of the app
+enum Migration {
+    Sql(&'static str),
+    Code(fn(&Connection) -> Result<(), rusqlite::Error>),
+}
 
-        for entry in entries {
-            let entry = entry.map_err(|e| e.to_string())?;
-            let path = entry.path();
+/// Schema migrations, applied in order on top of the base schema above.
+/// Entry N upgrades the database to version N+1; the current version is
+/// stored in SQLite's `PRAGMA user_version`.
+/// Never edit a migration once it has shipped - append a new one instead.
+const MIGRATIONS: &[Migration] = &[
+    // 1: content hash for duplicate detection
+    Migration::Sql(
+        "ALTER TABLE files ADD COLUMN content_hash TEXT;
+         CREATE INDEX IF NOT EXISTS idx_files_content_hash ON files(content_hash);",
+    ),
+    // 2: file size in bytes
+    Migration::Sql("ALTER TABLE files ADD COLUMN size INTEGER;"),
+    // 3: store modified as UTC ISO-8601 with milliseconds (see normalize_timestamp)
+    Migration::Sql(
+        "UPDATE files SET modified = strftime('%Y-%m-%dT%H:%M:%fZ', modified)
+         WHERE strftime('%Y-%m-%dT%H:%M:%fZ', modified) IS NOT NULL;",
+    ),
+    // 4: index document text (snapshot titles/summaries, text layers)
+    Migration::Code(|conn| {
+        conn.execute("ALTER TABLE files ADD COLUMN content TEXT", [])?;
+        recreate_fts_index(conn)
+    }),
+    // 5: store paths in normalize_path form
+    Migration::Code(normalize_stored_paths),
+    // 6: soft delete - rows for trashed files are hidden rather than removed
+    Migration::Sql("ALTER TABLE files ADD COLUMN deleted_at TEXT;"),
+    // 7: search history for suggestions
+    Migration::Sql(
+        "CREATE TABLE search_history (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            query TEXT NOT NULL,
+            searched_at TEXT NOT NULL,
+            result_count INTEGER NOT NULL
+        );",
+    ),
+    // 8: editor settings (values stored as JSON)
+    Migration::Sql(
+        "CREATE TABLE settings (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        );",
+    ),
+    // 9: folders visited in the file browser
+    Migration::Sql(
+        "CREATE TABLE recent_folders (
+            path TEXT PRIMARY KEY,
+            last_visited TEXT NOT NULL,
+            visit_count INTEGER NOT NULL DEFAULT 1
+        );",
+    ),
+    // 10: store indexed text in Unicode NFC
+    Migration::Code(normalize_stored_text),
+    // 11: colours of keyword chips
+    Migration::Sql(
+        "CREATE TABLE tag_colors (
+            keyword TEXT PRIMARY KEY,
+            color TEXT NOT NULL
+        );",
+    ),
+    // 12: named collections of documents; members leave when their file is
+    // deleted or soft-deleted
+    Migration::Sql(
+        "CREATE TABLE collections (
+            id INTEGER PRIMARY KEY,
+            name TEXT UNIQUE NOT NULL,
+            created TEXT NOT NULL
+        );
+        CREATE TABLE collection_members (
+            collection_id INTEGER NOT NULL,
+            file_id INTEGER NOT NULL,
+            PRIMARY KEY (collection_id, file_id)
+        );
+        CREATE TRIGGER collection_members_file_deleted AFTER DELETE ON files BEGIN
+            DELETE FROM collection_members WHERE file_id = old.id;
+        END;
+        CREATE TRIGGER collection_members_file_trashed AFTER UPDATE OF deleted_at ON files
+        WHEN new.deleted_at IS NOT NULL BEGIN
+            DELETE FROM collection_members WHERE file_id = new.id;
+        END;",
+    ),
+    // 13: exact form of paths that aren't valid UTF-8 (see encode_os_path)
+    Migration::Sql("ALTER TABLE files ADD COLUMN encoded_path TEXT;"),
+    // 14: how many times each file has been opened (see db_update_last_opened)
+    Migration::Sql("ALTER TABLE files ADD COLUMN open_count INTEGER NOT NULL DEFAULT 0;"),
+    // 15: counter bumped whenever searchable names change, so caches built
+    // from them (see SuggestionVocab) know when to refresh
+    Migration::Sql(
+        "CREATE TABLE index_generation (id INTEGER PRIMARY KEY CHECK (id = 1), value INTEGER NOT NULL);
+        INSERT INTO index_generation (id, value) VALUES (1, 0);
+        CREATE TRIGGER index_generation_ai AFTER INSERT ON files BEGIN
+            UPDATE index_generation SET value = value + 1;
+        END;
+        CREATE TRIGGER index_generation_ad AFTER DELETE ON files BEGIN
+            UPDATE index_generation SET value = value + 1;
+        END;
+        CREATE TRIGGER index_generation_au AFTER UPDATE OF filename, title, keywords, deleted_at ON files BEGIN
+            UPDATE index_generation SET value = value + 1;
+        END;",
+    ),
+    // 16: explicit tokenizer (accent folding, underscores as separators);
+    // search.tokenizer can change it afterwards (see apply_fts_tokenizer)
+    Migration::Code(|conn| recreate_fts_index_with(conn, &FtsTokenizer::default().spec())),
+    // 17: author and created date from the front matter; author is searched
+    // (FTS), created can stand in for modified in date filters
+    Migration::Code(|conn| {
+        conn.execute_batch(
+            "ALTER TABLE files ADD COLUMN author TEXT;
+            ALTER TABLE files ADD COLUMN created TEXT;
+            CREATE INDEX idx_files_created ON files(created);",
+        )?;
+        recreate_fts_index(conn)
+    }),
+];
 
-            if path.is_dir() {
-                scan_dir(&path, conn, count)?;
-            } else if path.extension().map(|e| e == "ssce").unwrap_or(false) {
-                // Read and parse the .ssce file
-                let content = fs::read_to_string(&path).map_err(|e| e.to_string())?;
-                let json: serde_json::Value =
-                    serde_json::from_str(&content).map_err(|e| e.to_string())?;
-
-                let filename = path
-                    .file_name()
-                    .map(|n| n.to_string_lossy().to_string())
-                    .unwrap_or_default();
-
-                let thumbnail = json.get("thumbnail").and_then(|v| v.as_str()).map(String::from);
-                let keywords = json.get("keywords").and_then(|v| {
-                    v.as_array().map(|arr| {
-                        arr.iter()
-                            .filter_map(|k| k.as_str())
-                            .collect::<Vec<_>>()
-                            .join(" ")
-                    })
-                });
+/// Rewrite every stored path with normalize_path. Where two rows turn out
+/// to be the same file (e.g. differing only in case on a case-insensitive
+/// filesystem) the most recently opened one is kept.
+fn normalize_stored_paths(conn: &Connection) -> Result<(), rusqlite::Error> {
+    let mut stmt = conn.prepare("SELECT id, path FROM files ORDER BY last_opened DESC, id DESC")?;
+    let rows = stmt
+        .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
 
-                let front_matter = json.get("frontMatter");
-                let title = front_matter
-                    .and_then(|fm| fm.get("title"))
-                    .and_then(|v| v.as_str())
-                    .map(String::from);
-                let summary = front_matter
-                    .and_then(|fm| fm.get("summary"))
-                    .and_then(|v| v.as_str())
-                    .map(String::from);
-                let modified = front_matter
-                    .and_then(|fm| fm.get("modified"))
-                    .and_then(|v| v.as_str())
-                    .map(String::from);
-
-                let snapshot_count = json
-                    .get("snapshots")
-                    .and_then(|v| v.as_array())
-                    .map(|arr| arr.len() as i32)
-                    .unwrap_or(0);
-
-                let path_str = path.to_string_lossy().to_string();
-
-                // Use modified date as last_opened during rebuild (so files show in Recent)
-                let last_opened = modified.clone();
-
-                conn.execute(
-                    "INSERT INTO files (path, filename, thumbnail, title, summary, keywords, modified, last_opened, snapshot_count)
-                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
-                     ON CONFLICT(path) DO UPDATE SET
-                         filename = excluded.filename,
-                         thumbnail = excluded.thumbnail,
-                         title = excluded.title,
-                         summary = excluded.summary,
-                         keywords = excluded.keywords,
-                         modified = excluded.modified,
-                         last_opened = COALESCE(files.last_opened, excluded.last_opened),
-                         snapshot_count = excluded.snapshot_count",
-                    params![path_str, filename, thumbnail, title, summary, keywords, modified, last_opened, snapshot_count],
-                )
-                .map_err(|e| e.to_string())?;
-
-                *count += 1;
-            }
+    let mut seen = std::collections::HashSet::new();
+    for (id, path) in rows {
+        let normalized = normalize_path(&path);
+        if !seen.insert(normalized.clone()) {
+            conn.execute("DELETE FROM files WHERE id = ?1", params![id])?;
+        } else if normalized != path {
+            conn.execute("UPDATE files SET path = ?1 WHERE id = ?2", params![normalized, id])?;
         }
-
-        Ok(())
     }
 
-    scan_dir(path, &conn, &mut count)?;
+    Ok(())
+}
 
-    // Clean up stale entries (files in DB that no longer exist)
-    let mut stmt = conn
-        .prepare("SELECT id, path FROM files")
-        .map_err(|e| e.to_string())?;
+/// Apply any migrations newer than the database's current schema version.
+/// Each migration runs in its own transaction together with the version bump.
+/// Returns the number of migrations applied.
+fn run_migrations(conn: &Connection) -> Result<usize, rusqlite::Error> {
+    migrate_to(conn, MIGRATIONS.len())
+}
 
-    let stale_ids: Vec<i64> = stmt
-        .query_map([], |row| {
-            let id: i64 = row.get(0)?;
-            let path: String = row.get(1)?;
-            Ok((id, path))
-        })
-        .map_err(|e| e.to_string())?
-        .filter_map(|r| r.ok())
-        .filter(|(_, path)| !Path::new(path).exists())
-        .map(|(id, _)| id)
-        .collect();
+/// Apply pending migrations up to (and including) schema version `target`
+fn migrate_to(conn: &Connection, target: usize) -> Result<usize, rusqlite::Error> {
+    let current: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    let mut applied = 0;
 
-    for id in &stale_ids {
-        conn.execute("DELETE FROM files WHERE id = ?1", params![id])
-            .map_err(|e| e.to_string())?;
+    for (index, migration) in MIGRATIONS
+        .iter()
+        .enumerate()
+        .take(target)
+        .skip(current.max(0) as usize)
+    {
+        let tx = conn.unchecked_transaction()?;
+        match migration {
+            Migration::Sql(sql) => tx.execute_batch(sql)?,
+            Migration::Code(apply) => apply(&tx)?,
+        }
+        tx.pragma_update(None, "user_version", (index + 1) as i64)?;
+        tx.commit()?;
+        applied += 1;
     }
 
-    Ok(count)
+    Ok(applied)
 }
 
-// ============================================================================
-// File System Commands
-// ============================================================================
-//
-// These commands provide file system access to the JavaScript frontend.
-// They handle reading/writing images and .ssce project files.
-//
-// ============================================================================
+/// Unicode NFC form of indexed text and search queries, so a precomposed
+/// "é" and "e" followed by a combining accent compare equal
+fn nfc(text: &str) -> String {
+    text.nfc().collect()
+}
 
-/// Represents a file or directory entry for directory listings
-#[derive(Serialize)]
-struct FileEntry {
-    name: String,
-    is_dir: bool,
-    size: u64,
+/// Remove accents and other combining marks: "Café" becomes "Cafe"
+fn fold_accents(text: &str) -> String {
+    text.nfd().filter(|c| !is_combining_mark(*c)).nfc().collect()
 }
 
-/// Browse a directory and return list of files/directories.
-/// Used for custom file browser dialogs (not currently used - native dialogs preferred).
-/// Filters: "all", "ssce", "images"
+/// Canonical form of a space-joined keyword list: NFC, lowercase, one space
+/// between tokens, and each keyword kept only the first time it appears
+fn normalize_keyword_list(input: &str) -> String {
+    let mut keywords: Vec<String> = Vec::new();
+    for token in nfc(input).split_whitespace() {
+        let keyword = token.to_lowercase();
+        if !keywords.contains(&keyword) {
+            keywords.push(keyword);
+        }
+    }
+    keywords.join(" ")
+}
+
+/// Preview how a keyword string will be stored ("Tax  tax TAX" -> "tax")
 #[tauri::command]
-fn browse_directory(dir: String, filter: String) -> Result<Vec<FileEntry>, String> {
-    let path = Path::new(&dir);
+fn normalize_keywords(input: String) -> String {
+    normalize_keyword_list(&input)
+}
 
-    if !path.exists() {
-        return Err(format!("Directory does not exist: {}", dir));
-    }
+/// NFC-normalize the text columns of existing rows. The FTS update trigger
+/// reindexes each changed row.
+fn normalize_stored_text(conn: &Connection) -> Result<(), rusqlite::Error> {
+    let mut stmt = conn.prepare("SELECT id, filename, title, summary, keywords, content FROM files")?;
+    let rows = stmt
+        .query_map([], |row| {
+            let text: [Option<String>; 5] = [row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?];
+            Ok((row.get::<_, i64>(0)?, text))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
 
-    if !path.is_dir() {
-        return Err(format!("Path is not a directory: {}", dir));
+    for (id, text) in rows {
+        let normalized = text.clone().map(|t| t.as_deref().map(nfc));
+        if normalized != text {
+            conn.execute(
+                "UPDATE files SET filename = ?2, title = ?3, summary = ?4, keywords = ?5, content = ?6 WHERE id = ?1",
+                params![id, normalized[0], normalized[1], normalized[2], normalized[3], normalized[4]],
+            )?;
+        }
     }
 
-    let mut entries: Vec<FileEntry> = Vec::new();
+    Ok(())
+}
 
-    let read_dir = fs::read_dir(path).map_err(|e| format!("Failed to read directory: {}", e))?;
+/// Normalize a path for storing in and looking up from the library, so the
+/// same file always maps to the same row: `.` and `..` are resolved, the
+/// platform separator is used throughout, and case is folded on platforms
+/// whose filesystems are case-insensitive by default (Windows, macOS).
+/// Works on the text alone, so it is safe for files that no longer exist
+/// and gives migrations the same result whatever is on disk.
+fn normalize_path(path: &str) -> String {
+    normalize_path_with(path, cfg!(any(windows, target_os = "macos")), cfg!(windows))
+}
 
-    for entry in read_dir {
-        let entry = entry.map_err(|e| format!("Failed to read entry: {}", e))?;
-        let metadata = entry.metadata().map_err(|e| format!("Failed to get metadata: {}", e))?;
-        let name = entry.file_name().to_string_lossy().to_string();
+/// `path` with symlinks resolved (see resolve_links), or as given if it
+/// can't be. Commands call this once on path arguments that name library
+/// rows, so a document reached through a linked folder maps to the row the
+/// rebuild scan made for it.
+fn resolve_path(path: &str) -> String {
+    resolve_links(Path::new(path)).map_or_else(|| path.to_string(), |p| p.to_string_lossy().to_string())
+}
 
-        // Skip hidden files (starting with .)
-        if name.starts_with('.') {
-            continue;
-        }
+/// Canonical form of an absolute path: of the file itself if it exists, or
+/// of its folder for a file that doesn't (yet)
+fn resolve_links(path: &Path) -> Option<std::path::PathBuf> {
+    if !path.is_absolute() {
+        return None;
+    }
+    dunce::canonicalize(path)
+        .ok()
+        .or_else(|| Some(dunce::canonicalize(path.parent()?).ok()?.join(path.file_name()?)))
+}
 
-        let is_dir = metadata.is_dir();
-        let size = if is_dir { 0 } else { metadata.len() };
+fn normalize_path_with(path: &str, case_insensitive: bool, windows: bool) -> String {
+    let is_separator = |c: char| c == '/' || (windows && c == '\\');
+    let separator = if windows { "\\" } else { "/" };
 
-        // Apply filter for files (directories always included)
-        if !is_dir {
-            let lower_name = name.to_lowercase();
-            let include = match filter.as_str() {
-                "ssce" => lower_name.ends_with(".ssce"),
-                "images" => {
-                    lower_name.ends_with(".png")
-                        || lower_name.ends_with(".jpg")
-                        || lower_name.ends_with(".jpeg")
-                        || lower_name.ends_with(".gif")
-                        || lower_name.ends_with(".webp")
-                        || lower_name.ends_with(".bmp")
-                }
-                _ => true, // "all" or any other value
-            };
+    let unc = windows && path.len() > 2 && path.chars().take(2).all(is_separator);
+    let absolute = path.starts_with(is_separator);
 
-            if !include {
-                continue;
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in path.split(is_separator) {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                // Never pop past the root or a drive letter ("C:")
+                let can_pop = segments
+                    .last()
+                    .map(|last| *last != ".." && !(segments.len() == 1 && windows && last.ends_with(':')))
+                    .unwrap_or(false);
+                if can_pop {
+                    segments.pop();
+                } else if !absolute && segments.is_empty() {
+                    segments.push("..");
+                }
             }
+            _ => segments.push(segment),
         }
-
-        entries.push(FileEntry { name, is_dir, size });
     }
 
-    // Sort: directories first, then files, both alphabetically
-    entries.sort_by(|a, b| {
-        match (a.is_dir, b.is_dir) {
-            (true, false) => std::cmp::Ordering::Less,
-            (false, true) => std::cmp::Ordering::Greater,
-            _ => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
-        }
-    });
-
-    Ok(entries)
-}
+    let prefix = if unc {
+        "\\\\"
+    } else if absolute {
+        separator
+    } else {
+        ""
+    };
+    let mut normalized = format!("{}{}", prefix, segments.join(separator));
 
-/// Load an image file and return as base64-encoded data URL.
-/// The data URL format (data:image/png;base64,...) can be used directly
-/// as an <img> src or drawn onto a canvas.
-#[tauri::command]
-fn load_image(path: String) -> Result<String, String> {
-    let file_path = Path::new(&path);
+    // A bare drive letter keeps its root separator, e.g. C:\
+    if windows && segments.len() == 1 && normalized.ends_with(':') {
+        normalized.push_str(separator);
+    }
 
-    if !file_path.exists() {
-        return Err(format!("File does not exist: {}", path));
+    if case_insensitive {
+        normalized = normalized.to_lowercase();
     }
 
-    let data = fs::read(file_path).map_err(|e| format!("Failed to read file: {}", e))?;
+    normalized
+}
 
-    // Determine MIME type from extension
-    let extension = file_path
-        .extension()
-        .and_then(|e| e.to_str())
-        .unwrap_or("")
-        .to_lowercase();
-
-    let mime_type = match extension.as_str() {
-        "png" => "image/png",
-        "jpg" | "jpeg" => "image/jpeg",
-        "gif" => "image/gif",
-        "webp" => "image/webp",
-        "bmp" => "image/bmp",
-        _ => "application/octet-stream",
-    };
+// ============================================================================
+// Database Types
+// ============================================================================
+//
+// These structs define the shape of data passed between JavaScript and Rust.
+// Serde handles JSON serialization/deserialization automatically.
+//
+// In JS, you'd call: invoke('db_upsert_file', { file: { path: '...', filename: '...' } })
+// Rust receives it as a LibraryFile struct.
+//
+// ============================================================================
 
-    let base64_data = STANDARD.encode(&data);
-    Ok(format!("data:{};base64,{}", mime_type, base64_data))
+/// Represents a file entry in the library database.
+/// Maps directly to the 'files' table columns.
+#[derive(Serialize, Deserialize)]
+struct LibraryFile {
+    id: Option<i64>,
+    path: String,
+    filename: String,
+    thumbnail: Option<String>,
+    title: Option<String>,
+    summary: Option<String>,
+    keywords: Option<String>,
+    modified: Option<String>,
+    last_opened: Option<String>,
+    snapshot_count: i32,
+    /// File size in bytes, filled in by the backend from the file on disk.
+    /// None if the file couldn't be stat'ed.
+    #[serde(default)]
+    size: Option<i64>,
+    /// Found by the fuzzy fallback of db_search_files rather than by an
+    /// index match
+    #[serde(default)]
+    fuzzy: bool,
+    /// The real path in encode_os_path form when it isn't valid UTF-8
+    /// (`path` is then a lossy copy, kept for display and search)
+    #[serde(default)]
+    encoded_path: Option<String>,
+    /// Times opened, counted by db_update_last_opened
+    #[serde(default)]
+    open_count: i64,
+    /// frontMatter.author, if the document has one
+    #[serde(default)]
+    author: Option<String>,
+    /// frontMatter.created, normalized like `modified`
+    #[serde(default)]
+    created: Option<String>,
 }
 
-/// Save base64-encoded image data to a file.
-/// Accepts data URL format (strips the "data:image/png;base64," prefix).
-/// Creates parent directories if they don't exist.
-#[tauri::command]
-fn save_image(path: String, data: String) -> Result<(), String> {
-    // Strip data URL prefix if present (e.g., "data:image/png;base64,")
-    let base64_data = if let Some(comma_pos) = data.find(',') {
-        &data[comma_pos + 1..]
-    } else {
-        &data
-    };
+/// Build a LibraryFile from a row selecting id, path, filename, thumbnail,
+/// title, summary, keywords, modified, last_opened, snapshot_count, size,
+/// encoded_path, open_count, author, created
+fn library_file_from_row(row: &rusqlite::Row) -> rusqlite::Result<LibraryFile> {
+    Ok(LibraryFile {
+        id: Some(row.get(0)?),
+        path: row.get(1)?,
+        filename: row.get(2)?,
+        thumbnail: row.get(3)?,
+        title: row.get(4)?,
+        summary: row.get(5)?,
+        keywords: row.get(6)?,
+        modified: row.get(7)?,
+        last_opened: row.get(8)?,
+        snapshot_count: row.get(9)?,
+        size: row.get(10)?,
+        fuzzy: false,
+        encoded_path: row.get(11)?,
+        open_count: row.get(12)?,
+        author: row.get(13)?,
+        created: row.get(14)?,
+    })
+}
 
-    let decoded = STANDARD
-        .decode(base64_data)
-        .map_err(|e| format!("Failed to decode base64: {}", e))?;
+/// Size of a file in bytes, or None if it can't be read (e.g. deleted
+/// between listing and stat)
+fn file_size(path: &Path) -> Option<i64> {
+    fs::metadata(path).ok().map(|m| m.len() as i64)
+}
 
-    // Create parent directories if they don't exist
-    if let Some(parent) = Path::new(&path).parent() {
-        fs::create_dir_all(parent).map_err(|e| format!("Failed to create directories: {}", e))?;
-    }
+#[derive(Deserialize, Debug, Clone)]
+struct SearchParams {
+    query: Option<String>,
+    from_date: Option<String>,
+    to_date: Option<String>,
+    limit: Option<i32>,
+    /// Number of results to skip, for paging through results
+    #[serde(default)]
+    offset: Option<i64>,
+    /// Match accents exactly ("café" no longer finds "cafe"). By default
+    /// accents are ignored.
+    #[serde(default)]
+    accent_sensitive: Option<bool>,
+    /// When nothing matches, fall back to filenames and titles that are
+    /// spelled similarly to the query (see fuzzy_search)
+    #[serde(default)]
+    fuzzy: Option<bool>,
+    /// Only return documents inside this folder (at any depth)
+    #[serde(default)]
+    path_prefix: Option<String>,
+    /// Only return documents whose author contains this (ignoring ASCII case)
+    #[serde(default)]
+    author: Option<String>,
+    /// Which date from_date and to_date apply to
+    #[serde(default)]
+    date_field: SearchDateField,
+    /// Only match the query against these FTS columns (e.g. ["title"]);
+    /// all of them when absent or empty
+    #[serde(default)]
+    fields: Option<Vec<String>>,
+}
 
-    fs::write(&path, decoded).map_err(|e| format!("Failed to write file: {}", e))?;
+/// The date a search's from/to range is compared against
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum SearchDateField {
+    /// When the document was last saved
+    #[default]
+    Modified,
+    /// frontMatter.created; documents without one are left out
+    Created,
+}
 
-    Ok(())
+impl SearchDateField {
+    fn column(self) -> &'static str {
+        match self {
+            SearchDateField::Modified => "modified",
+            SearchDateField::Created => "created",
+        }
+    }
 }
 
-/// Load a .ssce JSON file and return its contents
-#[tauri::command]
-fn load_ssce(path: String) -> Result<String, String> {
-    let file_path = Path::new(&path);
+/// A folder in the form paths are stored (see normalize_path), ending in a
+/// separator so "/lib/docs" doesn't also match "/lib/docs-old"
+fn folder_prefix(folder: &str) -> String {
+    let mut prefix = normalize_path(folder);
+    let separator = std::path::MAIN_SEPARATOR;
+    if !prefix.ends_with(separator) {
+        prefix.push(separator);
+    }
+    prefix
+}
 
-    if !file_path.exists() {
-        return Err(format!("File does not exist: {}", path));
+/// `text` with LIKE's wildcards (and the `\` escape itself) escaped, for
+/// a pattern used with `ESCAPE '\'`
+fn escape_like(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
     }
+    escaped
+}
 
-    fs::read_to_string(file_path).map_err(|e| format!("Failed to read file: {}", e))
+/// A LIKE pattern (with `ESCAPE '\'`) matching every path that starts with
+/// `prefix`; LIKE's wildcards in the prefix are matched literally
+fn like_prefix_pattern(prefix: &str) -> String {
+    format!("{}%", escape_like(prefix))
 }
 
-/// Save JSON data to a .ssce file
-#[tauri::command]
-fn save_ssce(path: String, data: String) -> Result<(), String> {
-    // Create parent directories if they don't exist
-    if let Some(parent) = Path::new(&path).parent() {
-        fs::create_dir_all(parent).map_err(|e| format!("Failed to create directories: {}", e))?;
-    }
+/// A LIKE pattern (with `ESCAPE '\'`) matching text that contains `part`
+fn like_contains_pattern(part: &str) -> String {
+    format!("%{}%", escape_like(part))
+}
+
+/// Number of results returned when the caller doesn't give a limit
+const DEFAULT_RESULT_LIMIT: i32 = 50;
+/// Largest number of results a single query may return
+const MAX_RESULT_LIMIT: i32 = 1000;
 
-    fs::write(&path, data).map_err(|e| format!("Failed to write file: {}", e))
+/// Clamp a caller-supplied result limit to 1..=MAX_RESULT_LIMIT,
+/// using DEFAULT_RESULT_LIMIT when none is given
+fn clamp_limit(limit: Option<i32>) -> i32 {
+    limit
+        .unwrap_or(DEFAULT_RESULT_LIMIT)
+        .clamp(1, MAX_RESULT_LIMIT)
 }
 
-/// Save text content to a file (for HTML export, etc.)
-#[tauri::command]
-fn save_text_file(path: String, content: String) -> Result<(), String> {
-    // Create parent directories if they don't exist
-    if let Some(parent) = Path::new(&path).parent() {
-        fs::create_dir_all(parent).map_err(|e| format!("Failed to create directories: {}", e))?;
-    }
+/// Format used for `modified` in the database: UTC with milliseconds, the same
+/// as JavaScript's toISOString(). Being fixed-width, it sorts and compares
+/// correctly as a plain string.
+const DB_TIMESTAMP_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.3fZ";
 
-    fs::write(&path, content).map_err(|e| format!("Failed to write file: {}", e))
+/// Convert an RFC 3339 timestamp (any offset) to DB_TIMESTAMP_FORMAT.
+/// Values that don't parse are stored unchanged.
+fn normalize_timestamp(value: &str) -> String {
+    chrono::DateTime::parse_from_rfc3339(value)
+        .map(|dt| dt.with_timezone(&chrono::Utc).format(DB_TIMESTAMP_FORMAT).to_string())
+        .unwrap_or_else(|_| value.to_string())
 }
 
-/// Metadata extracted from a .ssce file
-#[derive(Serialize)]
-struct SsceMetadata {
-    thumbnail: Option<String>,
-    snapshot_count: u32,
+/// The current time in DB_TIMESTAMP_FORMAT
+fn now_timestamp() -> String {
+    chrono::Utc::now().format(DB_TIMESTAMP_FORMAT).to_string()
 }
 
-/// Extract thumbnail and snapshot count from a .ssce file
+/// The current time in the format stored in `modified` and `last_opened`
+/// (UTC, millisecond precision, e.g. "2025-03-01T14:00:00.000Z")
 #[tauri::command]
-fn get_ssce_metadata(path: String) -> Result<SsceMetadata, String> {
-    let file_path = Path::new(&path);
-
-    if !file_path.exists() {
-        return Ok(SsceMetadata {
-            thumbnail: None,
-            snapshot_count: 0,
-        });
-    }
+fn now_iso() -> String {
+    now_timestamp()
+}
 
-    let content = fs::read_to_string(file_path)
-        .map_err(|e| format!("Failed to read file: {}", e))?;
+/// Display format used by format_timestamp when none is given
+const DEFAULT_DISPLAY_FORMAT: &str = "%Y-%m-%d %H:%M";
 
-    // Parse JSON
-    let json: serde_json::Value = serde_json::from_str(&content)
-        .map_err(|e| format!("Failed to parse JSON: {}", e))?;
+/// Format a stored timestamp for display. `format` is a strftime pattern
+/// (default "%Y-%m-%d %H:%M"); `timezone` is an IANA name such as
+/// "Europe/London", "UTC", or "local" (the default) for the system zone.
+#[tauri::command]
+#[tracing::instrument(err)]
+fn format_timestamp(iso: String, format: Option<String>, timezone: Option<String>) -> Result<String, CommandError> {
+    format_timestamp_in(&iso, format.as_deref().unwrap_or(DEFAULT_DISPLAY_FORMAT), timezone.as_deref())
+}
 
-    // Get thumbnail field if it exists
-    let thumbnail = json.get("thumbnail")
-        .and_then(|t| t.as_str())
-        .map(|s| s.to_string());
+fn format_timestamp_in(iso: &str, format: &str, timezone: Option<&str>) -> Result<String, CommandError> {
+    let dt = chrono::DateTime::parse_from_rfc3339(iso.trim())
+        .map_err(|e| CommandError::Validation(format!("Invalid timestamp '{}': {}", iso, e)))?;
 
-    // Get snapshot count
-    let snapshot_count = json.get("snapshots")
-        .and_then(|s| s.as_array())
-        .map(|arr| arr.len() as u32)
-        .unwrap_or(0);
+    // Checked up front, since formatting with a bad pattern would panic
+    let items = chrono::format::StrftimeItems::new(format)
+        .parse()
+        .map_err(|_| CommandError::Validation(format!("Invalid date format: {}", format)))?;
 
-    Ok(SsceMetadata {
-        thumbnail,
-        snapshot_count,
-    })
+    let formatted = match timezone.map(str::trim) {
+        None | Some("") | Some("local") => dt.with_timezone(&chrono::Local).format_with_items(items.iter()).to_string(),
+        Some(name) => {
+            let tz: chrono_tz::Tz = name
+                .parse()
+                .map_err(|_| CommandError::Validation(format!("Unknown timezone: {}", name)))?;
+            dt.with_timezone(&tz).format_with_items(items.iter()).to_string()
+        }
+    };
+    Ok(formatted)
 }
 
-/// Extract thumbnail from a .ssce file (legacy, kept for compatibility)
-/// Returns the thumbnail data URL if present, or null if not found
-#[tauri::command]
-fn get_ssce_thumbnail(path: String) -> Result<Option<String>, String> {
-    let file_path = Path::new(&path);
+/// Parse a search date bound into DB_TIMESTAMP_FORMAT.
+/// Accepts a full RFC 3339 timestamp, a date and time without offset, or a
+/// plain date (YYYY-MM-DD). Values without an offset are in `tz`. A plain
+/// date means the start of that day, or the end of it when `end_of_day` is
+/// set, so a "to" date includes everything modified on that day.
+fn parse_date_bound<Tz: chrono::TimeZone>(
+    value: &str,
+    end_of_day: bool,
+    tz: &Tz,
+) -> Result<String, String> {
+    use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Utc};
 
-    if !file_path.exists() {
-        return Ok(None);
-    }
+    let value = value.trim();
+    let to_db = |dt: chrono::DateTime<Utc>| dt.format(DB_TIMESTAMP_FORMAT).to_string();
 
-    let content = fs::read_to_string(file_path)
-        .map_err(|e| format!("Failed to read file: {}", e))?;
+    if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Ok(to_db(dt.with_timezone(&Utc)));
+    }
 
-    // Parse JSON and extract thumbnail field
-    let json: serde_json::Value = serde_json::from_str(&content)
-        .map_err(|e| format!("Failed to parse JSON: {}", e))?;
+    let naive = ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|fmt| NaiveDateTime::parse_from_str(value, fmt).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d").ok().map(|date| {
+                let time = if end_of_day {
+                    NaiveTime::from_hms_milli_opt(23, 59, 59, 999).unwrap()
+                } else {
+                    NaiveTime::MIN
+                };
+                date.and_time(time)
+            })
+        })
+        .ok_or_else(|| {
+            format!("Invalid date '{}': expected YYYY-MM-DD or an ISO-8601 timestamp", value)
+        })?;
 
-    // Get thumbnail field if it exists
-    if let Some(thumbnail) = json.get("thumbnail") {
-        if let Some(thumb_str) = thumbnail.as_str() {
-            return Ok(Some(thumb_str.to_string()));
-        }
-    }
+    // earliest() resolves DST overlaps; a time skipped by DST has no mapping
+    let local = tz
+        .from_local_datetime(&naive)
+        .earliest()
+        .ok_or_else(|| format!("Invalid date '{}': time does not exist in local timezone", value))?;
 
-    Ok(None)
+    Ok(to_db(local.with_timezone(&Utc)))
 }
 
-/// Check if a file exists
-#[tauri::command]
-fn file_exists(path: String) -> bool {
-    Path::new(&path).exists()
+/// Clamp a caller-supplied offset so it is never negative
+fn clamp_offset(offset: Option<i64>) -> i64 {
+    offset.unwrap_or(0).max(0)
 }
 
 // ============================================================================
-// Autosave Commands
+// Database Commands
 // ============================================================================
 //
-// Autosave provides crash recovery by periodically saving work to temp files.
-// Files are stored in ~/.ssce-temp/ (Linux) or %APPDATA%/.ssce-temp (Windows).
-// On startup, the app checks for recovery files and prompts to restore.
+// These commands are called from JavaScript to manage the library database.
+// The State<DbState> parameter is automatically injected by Tauri - it gives
+// access to the shared database connection we set up in main().
+//
+// JS usage: await invoke('db_upsert_file', { file: {...} })
 //
 // ============================================================================
 
-/// Autosave file entry with metadata for listing recovery files
-#[derive(Serialize)]
-struct AutosaveEntry {
-    name: String,
-    path: String,
-    mtime: u64,
+/// Add or update a file in the library database.
+/// Uses SQLite's UPSERT (INSERT ... ON CONFLICT) to handle both cases.
+/// Called when opening or saving .ssce files to keep the library up to date.
+#[tauri::command]
+#[tracing::instrument(skip(state, file), fields(path = %file.path), err)]
+fn db_upsert_file(state: State<DbState>, file: LibraryFile) -> Result<i64, CommandError> {
+    let path = normalize_path(&resolve_path(&file.path));
+    state.with_conn(|conn| {
+        let size = file_size(Path::new(&path));
+        let modified = file.modified.as_deref().map(normalize_timestamp);
+        let created = file.created.as_deref().map(normalize_timestamp);
+
+        let mut stmt = conn
+            .prepare_cached(
+                "INSERT INTO files (path, filename, thumbnail, title, summary, keywords, modified, last_opened, snapshot_count, size, encoded_path, author, created)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)
+                 ON CONFLICT(path) DO UPDATE SET
+                     filename = excluded.filename,
+                     thumbnail = excluded.thumbnail,
+                     title = excluded.title,
+                     summary = excluded.summary,
+                     keywords = excluded.keywords,
+                     modified = excluded.modified,
+                     last_opened = excluded.last_opened,
+                     snapshot_count = excluded.snapshot_count,
+                     size = excluded.size,
+                     encoded_path = excluded.encoded_path,
+                     author = excluded.author,
+                     created = excluded.created,
+                     deleted_at = NULL",
+            )?;
+        stmt.execute(params![
+            path,
+            nfc(&file.filename),
+            file.thumbnail,
+            file.title.as_deref().map(nfc),
+            file.summary.as_deref().map(nfc),
+            file.keywords.as_deref().map(normalize_keyword_list),
+            modified,
+            file.last_opened,
+            file.snapshot_count,
+            size,
+            file.encoded_path,
+            file.author.as_deref().map(str::trim).filter(|a| !a.is_empty()).map(nfc),
+            created,
+        ])?;
+
+        let id = conn.last_insert_rowid();
+        Ok(id)
+    })
 }
 
-/// Save autosave data to a temp file
-/// Creates the directory if it doesn't exist
+/// Get recent files ordered by last_opened (most recent first).
+/// Used to populate the "Recent Files" dialog in the UI.
+/// The limit is clamped to 1..=1000.
 #[tauri::command]
-fn save_autosave(data: String, filename: String, directory: String) -> Result<String, String> {
-    let dir_path = Path::new(&directory);
-
-    // Create directory if it doesn't exist
-    if !dir_path.exists() {
-        fs::create_dir_all(dir_path)
-            .map_err(|e| format!("Failed to create autosave directory: {}", e))?;
-    }
+#[tracing::instrument(skip(state), err)]
+fn db_get_recent_files(state: State<DbState>, limit: i32) -> Result<Vec<LibraryFile>, CommandError> {
+    state.with_read_conn(|conn| recent_files(conn, limit).map_err(CommandError::from))
+}
 
-    let file_path = dir_path.join(&filename);
-    let full_path = file_path.to_string_lossy().to_string();
+fn recent_files(conn: &Connection, limit: i32) -> Result<Vec<LibraryFile>, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT id, path, filename, thumbnail, title, summary, keywords, modified, last_opened, snapshot_count, size, encoded_path, open_count, author, created
+         FROM files
+         WHERE last_opened IS NOT NULL AND deleted_at IS NULL
+         ORDER BY last_opened DESC
+         LIMIT ?1",
+    )?;
 
-    fs::write(&file_path, &data)
-        .map_err(|e| format!("Failed to write autosave file: {}", e))?;
+    let files = stmt
+        .query_map([clamp_limit(Some(limit))], library_file_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
 
-    Ok(full_path)
+    Ok(files)
 }
 
-/// Delete an autosave temp file
+/// Get the most opened files (ties broken by most recently opened), for
+/// the "Most Used" view. Files never opened are left out.
+/// The limit is clamped to 1..=1000.
 #[tauri::command]
-fn delete_autosave(path: String) -> Result<(), String> {
-    let file_path = Path::new(&path);
+#[tracing::instrument(skip(state), err)]
+fn db_most_used_files(state: State<DbState>, limit: i32) -> Result<Vec<LibraryFile>, CommandError> {
+    state.with_read_conn(|conn| most_used_files(conn, limit).map_err(CommandError::from))
+}
 
-    if file_path.exists() {
-        fs::remove_file(file_path)
-            .map_err(|e| format!("Failed to delete autosave file: {}", e))?;
-    }
+fn most_used_files(conn: &Connection, limit: i32) -> Result<Vec<LibraryFile>, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT id, path, filename, thumbnail, title, summary, keywords, modified, last_opened, snapshot_count, size, encoded_path, open_count, author, created
+         FROM files
+         WHERE open_count > 0 AND deleted_at IS NULL
+         ORDER BY open_count DESC, last_opened DESC
+         LIMIT ?1",
+    )?;
 
-    Ok(())
+    let files = stmt
+        .query_map([clamp_limit(Some(limit))], library_file_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(files)
 }
 
-/// List autosave files in a directory
-/// Returns files with .ssce extension, sorted by modification time (newest first)
+/// Get files ordered by their modified date (most recent first), whether or
+/// not they have ever been opened. Lets a freshly rebuilt library show
+/// content before anything has been opened.
+/// The limit is clamped to 1..=1000.
 #[tauri::command]
-fn list_autosave_files(directory: String) -> Result<Vec<AutosaveEntry>, String> {
-    let dir_path = Path::new(&directory);
-
-    if !dir_path.exists() {
-        // Directory doesn't exist, no recovery files
-        return Ok(Vec::new());
-    }
+#[tracing::instrument(skip(state), err)]
+fn db_get_recently_modified(state: State<DbState>, limit: i32) -> Result<Vec<LibraryFile>, CommandError> {
+    state.with_read_conn(|conn| recently_modified(conn, limit).map_err(CommandError::from))
+}
 
-    if !dir_path.is_dir() {
-        return Err(format!("Path is not a directory: {}", directory));
-    }
+fn recently_modified(conn: &Connection, limit: i32) -> Result<Vec<LibraryFile>, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT id, path, filename, thumbnail, title, summary, keywords, modified, last_opened, snapshot_count, size, encoded_path, open_count, author, created
+         FROM files
+         WHERE modified IS NOT NULL AND deleted_at IS NULL
+         ORDER BY modified DESC
+         LIMIT ?1",
+    )?;
 
-    let mut entries: Vec<AutosaveEntry> = Vec::new();
+    let files = stmt
+        .query_map([clamp_limit(Some(limit))], library_file_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
 
-    let read_dir = fs::read_dir(dir_path)
-        .map_err(|e| format!("Failed to read autosave directory: {}", e))?;
+    Ok(files)
+}
 
-    for entry in read_dir {
-        let entry = entry.map_err(|e| format!("Failed to read entry: {}", e))?;
-        let metadata = entry.metadata().map_err(|e| format!("Failed to get metadata: {}", e))?;
+/// Search files using FTS5 full-text search with optional date range filters.
+/// Used by the "Search Library" dialog for finding files by keyword.
+/// Supports prefix matching (typing "scr" matches "screenshot").
+/// `limit` defaults to 50 and is clamped to 1..=1000; `offset` skips results
+/// for paging. Dates may be plain dates (in local time, `to_date` inclusive
+/// of the whole day) or ISO-8601 timestamps.
+/// With `fuzzy`, a query that matches nothing returns near misses instead
+/// ("reprot" finds "report"), each flagged `fuzzy`.
+/// A missing or blank query (or one that is only punctuation) lists the
+/// most recently modified documents, newest first, within any filters -
+/// with no filters at all that is simply the newest `limit` documents.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle, state), err)]
+fn db_search_files(
+    app_handle: tauri::AppHandle,
+    state: State<DbState>,
+    params: SearchParams,
+) -> Result<Vec<LibraryFile>, CommandError> {
+    let weights = RankWeights::from_config(&load_defaults_typed(&app_handle));
+    let params = SearchParams { path_prefix: params.path_prefix.as_deref().map(resolve_path), ..params };
+    state.with_conn(|conn| {
+        let query = params.query.clone().unwrap_or_default();
+        let folds_accents = state.folds_accents(conn)?;
+        let files = search_files_ranked(conn, params.clone(), &weights, folds_accents, &chrono::Local)?;
 
-        // Skip directories
-        if metadata.is_dir() {
-            continue;
+        // History is a convenience - never fail the search because of it
+        if !query.trim().is_empty() {
+            if let Err(e) = record_search(conn, query.trim(), files.len() as i64) {
+                tracing::warn!("Failed to record search history: {}", e);
+            }
         }
 
-        let name = entry.file_name().to_string_lossy().to_string();
+        Ok(files)
+    })
+}
 
-        // Only include .ssce files
-        if !name.to_lowercase().ends_with(".ssce") {
-            continue;
-        }
+/// Oldest searches beyond this many are dropped from the history
+const MAX_SEARCH_HISTORY: i64 = 200;
 
-        // Get modification time as unix timestamp
-        let mtime = metadata
-            .modified()
-            .map_err(|e| format!("Failed to get mtime: {}", e))?
-            .duration_since(std::time::UNIX_EPOCH)
-            .map(|d| d.as_secs())
-            .unwrap_or(0);
+/// A previously run search
+#[derive(Serialize, Debug, PartialEq)]
+struct SearchHistoryEntry {
+    query: String,
+    searched_at: String,
+    result_count: i64,
+}
 
-        let path = entry.path().to_string_lossy().to_string();
+/// Add a search to the history. Repeating the previous query (e.g. paging
+/// through results) updates that entry instead of adding another.
+fn record_search(conn: &Connection, query: &str, result_count: i64) -> Result<(), rusqlite::Error> {
+    let now = now_timestamp();
 
-        entries.push(AutosaveEntry { name, path, mtime });
+    let updated = conn
+        .prepare_cached(
+            "UPDATE search_history SET searched_at = ?2, result_count = ?3
+             WHERE id = (SELECT MAX(id) FROM search_history) AND query = ?1",
+        )?
+        .execute(params![query, now, result_count])?;
+    if updated == 0 {
+        conn.prepare_cached("INSERT INTO search_history (query, searched_at, result_count) VALUES (?1, ?2, ?3)")?
+            .execute(params![query, now, result_count])?;
     }
 
-    // Sort by modification time, newest first
-    entries.sort_by(|a, b| b.mtime.cmp(&a.mtime));
+    conn.prepare_cached("DELETE FROM search_history WHERE id <= (SELECT MAX(id) FROM search_history) - ?1")?
+        .execute(params![MAX_SEARCH_HISTORY])?;
 
-    Ok(entries)
+    Ok(())
 }
 
-/// Get the user's home directory
+/// Most recent distinct searches, newest first
 #[tauri::command]
-fn get_home_dir() -> Result<String, String> {
-    dirs::home_dir()
-        .map(|p| p.to_string_lossy().to_string())
-        .ok_or_else(|| "Could not determine home directory".to_string())
+#[tracing::instrument(skip(state), err)]
+fn db_recent_searches(state: State<DbState>, limit: i32) -> Result<Vec<SearchHistoryEntry>, CommandError> {
+    state.with_read_conn(|conn| recent_searches(conn, limit).map_err(CommandError::from))
 }
 
-/// Get the user's downloads directory
-#[tauri::command]
-fn get_downloads_dir() -> Result<String, String> {
-    dirs::download_dir()
-        .map(|p| p.to_string_lossy().to_string())
-        .ok_or_else(|| "Could not determine downloads directory".to_string())
-}
+fn recent_searches(conn: &Connection, limit: i32) -> Result<Vec<SearchHistoryEntry>, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT query, searched_at, result_count FROM search_history
+         WHERE id IN (SELECT MAX(id) FROM search_history GROUP BY query)
+         ORDER BY id DESC
+         LIMIT ?1",
+    )?;
 
-// ============================================================================
-// Configuration Commands
-// ============================================================================
-//
-// Configuration is loaded from defaults.json which contains:
-// - Tool defaults (colours, sizes, line styles)
-// - File paths (library folder, default open/save directories)
-// - UI settings (colour palette, presets)
-//
-// User customizations are saved to ~/.config/ssce-desktop/defaults.json
-// which takes priority over the bundled defaults.
-//
-// ============================================================================
+    let entries = stmt
+        .query_map(params![clamp_limit(Some(limit))], |row| {
+            Ok(SearchHistoryEntry {
+                query: row.get(0)?,
+                searched_at: row.get(1)?,
+                result_count: row.get(2)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
 
-/// Environment settings returned to JavaScript (build info only)
-#[derive(Serialize)]
-struct EnvConfig {
-    show_build_timestamp: bool,
-    build_timestamp: Option<String>,
+    Ok(entries)
 }
 
-/// Read build info settings.
-/// Returns the build timestamp for display in the window title/footer.
+/// Forget all past searches
 #[tauri::command]
-fn get_env_config(app_handle: tauri::AppHandle) -> Result<EnvConfig, String> {
-    // Check if we should show build timestamp (defaults to true)
-    // Can be disabled via SHOW_BUILD_TIMESTAMP=false in environment
-    let show_build_timestamp = std::env::var("SHOW_BUILD_TIMESTAMP")
-        .map(|v| v.to_lowercase() != "false")
-        .unwrap_or(true);
+#[tracing::instrument(skip(state), err)]
+fn db_clear_search_history(state: State<DbState>) -> Result<(), CommandError> {
+    state.with_conn(|conn| {
+        conn.execute("DELETE FROM search_history", [])?;
+        Ok(())
+    })
+}
 
-    // Get build timestamp from build-time.txt file (written by build-and-install.sh)
-    let build_timestamp = if show_build_timestamp {
-        // Try multiple locations for build-time.txt
-        let mut time_str: Option<String> = None;
+/// Convert what the user typed into an FTS5 MATCH expression.
+/// Each word becomes a quoted prefix term ("scr"*), so FTS5 syntax characters
+/// like quotes, brackets, * or ^ can never produce an invalid expression -
+/// they are treated as word separators, the same as the tokenizer does.
+/// Returns None if nothing searchable is left.
+fn build_fts_query(query: &str) -> Option<String> {
+    let terms: Vec<String> = query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| format!("\"{}\"*", w))
+        .collect();
 
-        // 1. Development path (relative to src-tauri directory)
-        let dev_path = Path::new("../src/config/build-time.txt");
-        if dev_path.exists() {
-            time_str = fs::read_to_string(dev_path).ok().map(|s| s.trim().to_string());
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" "))
+    }
+}
+
+/// Restrict an FTS5 expression from build_fts_query to the named columns,
+/// e.g. `{title summary} : ("scr"*)`. Names must be FTS_COLUMNS; no names
+/// leaves the expression alone.
+fn restrict_fts_columns(fts_query: String, fields: &[String]) -> Result<String, CommandError> {
+    if fields.is_empty() {
+        return Ok(fts_query);
+    }
+
+    let mut columns: Vec<&str> = Vec::new();
+    for field in fields {
+        let column = FTS_COLUMNS
+            .iter()
+            .copied()
+            .find(|c| c.eq_ignore_ascii_case(field.trim()))
+            .ok_or_else(|| {
+                CommandError::Validation(format!(
+                    "Unknown search field: {} (expected one of {})",
+                    field,
+                    FTS_COLUMNS.join(", ")
+                ))
+            })?;
+        if !columns.contains(&column) {
+            columns.push(column);
         }
+    }
 
-        // 2. Production path (bundled with app)
-        if time_str.is_none() {
-            if let Ok(resource_dir) = app_handle.path().resource_dir() {
-                let resource_path = resource_dir.join("config/build-time.txt");
-                if resource_path.exists() {
-                    time_str = fs::read_to_string(&resource_path).ok().map(|s| s.trim().to_string());
-                }
-            }
+    Ok(format!("{{{}}} : ({})", columns.join(" "), fts_query))
+}
+
+/// How much a match in each indexed column counts towards a result's rank
+/// (FTS5 bm25 column weights). Read from `search.rankWeights` in
+/// defaults.json; a title hit outranks the same word in keywords by default.
+#[derive(Debug, Clone, PartialEq)]
+struct RankWeights {
+    filename: f64,
+    title: f64,
+    summary: f64,
+    keywords: f64,
+    content: f64,
+    author: f64,
+}
+
+impl Default for RankWeights {
+    fn default() -> Self {
+        RankWeights {
+            filename: 2.0,
+            title: 3.0,
+            summary: 1.0,
+            keywords: 1.0,
+            content: 1.0,
+            author: 1.0,
         }
+    }
+}
 
-        // 3. Linux-specific production path
-        #[cfg(target_os = "linux")]
-        if time_str.is_none() {
-            let linux_path = Path::new("/usr/lib/SSCE Desktop/config/build-time.txt");
-            if linux_path.exists() {
-                time_str = fs::read_to_string(linux_path).ok().map(|s| s.trim().to_string());
-            }
+impl RankWeights {
+    /// Weights from the config. Missing, negative or non-numeric entries
+    /// keep their default.
+    fn from_config(config: &Defaults) -> Self {
+        let configured = &config.section("search")["rankWeights"];
+        let weight = |name: &str, default: f64| {
+            configured[name]
+                .as_f64()
+                .filter(|w| w.is_finite() && *w >= 0.0)
+                .unwrap_or(default)
+        };
+        let defaults = RankWeights::default();
+        RankWeights {
+            filename: weight("filename", defaults.filename),
+            title: weight("title", defaults.title),
+            summary: weight("summary", defaults.summary),
+            keywords: weight("keywords", defaults.keywords),
+            content: weight("content", defaults.content),
+            author: weight("author", defaults.author),
         }
+    }
 
-        time_str
+    /// bm25() call for files_fts, weights in the table's column order
+    fn bm25_sql(&self) -> String {
+        format!(
+            "bm25(files_fts, {:?}, {:?}, {:?}, {:?}, {:?}, {:?})",
+            self.filename, self.title, self.summary, self.keywords, self.content, self.author
+        )
+    }
+}
+
+/// Searches with terms are ordered by weighted relevance (then by date);
+/// listings without terms are newest first
+fn search_files_ranked<Tz: chrono::TimeZone>(
+    conn: &Connection,
+    params: SearchParams,
+    weights: &RankWeights,
+    folds_accents: bool,
+    tz: &Tz,
+) -> Result<Vec<LibraryFile>, CommandError> {
+    let limit = clamp_limit(params.limit);
+    let offset = clamp_offset(params.offset);
+
+    let from_date = params
+        .from_date
+        .as_deref()
+        .map(|d| parse_date_bound(d, false, tz))
+        .transpose()
+        .map_err(CommandError::Validation)?;
+    let to_date = params
+        .to_date
+        .as_deref()
+        .map(|d| parse_date_bound(d, true, tz))
+        .transpose()
+        .map_err(CommandError::Validation)?;
+
+    // FTS matching ignores accents when `folds_accents` (see
+    // fts_folds_accents); accent-sensitive searches also check the exact terms
+    let query = params.query.as_deref().map(nfc).unwrap_or_default();
+
+    // Queries made up only of punctuation are treated like an empty search
+    let fields = params.fields.as_deref().unwrap_or_default();
+    let fts_query = build_fts_query(&if folds_accents { fold_accents(&query) } else { query.clone() })
+        .map(|q| restrict_fts_columns(q, fields))
+        .transpose()?;
+
+    let exact_terms = if params.accent_sensitive.unwrap_or(false) && fts_query.is_some() {
+        query.clone()
     } else {
-        None
+        String::new()
     };
 
-    Ok(EnvConfig {
-        show_build_timestamp,
-        build_timestamp,
-    })
-}
+    // Each filter adds its own clause and binds only the parameters it uses,
+    // so the listing (no terms) and search queries share nothing positional
+    let prefix = params.path_prefix.as_deref().filter(|p| !p.is_empty()).map(folder_prefix);
+    let prefix_pattern = prefix.as_deref().map(like_prefix_pattern);
+    let author = params.author.as_deref().map(str::trim).filter(|a| !a.is_empty()).map(nfc);
+    let author_pattern = author.as_deref().map(like_contains_pattern);
 
-/// Get the user config directory path for SSCE
-/// Returns ~/.config/ssce-desktop on Linux, AppData on Windows
-fn get_user_config_dir() -> Result<std::path::PathBuf, String> {
-    dirs::config_dir()
-        .map(|p| p.join("ssce-desktop"))
-        .ok_or_else(|| "Could not determine config directory".to_string())
-}
+    let mut sql = String::from(
+        "SELECT f.id, f.path, f.filename, f.thumbnail, f.title, f.summary, f.keywords, f.modified, f.last_opened, f.snapshot_count, f.size, f.encoded_path, f.open_count, f.author, f.created
+         FROM files f",
+    );
+    let mut bindings: Vec<(&str, &dyn rusqlite::ToSql)> = Vec::new();
 
-/// Load the defaults.json configuration file
-/// Priority: user config > bundled config > dev config
-/// Expands ~ in paths.defaultImageLoad and paths.defaultImageSave
-#[tauri::command]
-fn get_defaults_config(app_handle: tauri::AppHandle) -> Result<String, String> {
-    let json_str: String;
+    if let Some(fts_query) = &fts_query {
+        sql.push_str(" JOIN files_fts fts ON f.id = fts.rowid WHERE files_fts MATCH :query AND f.deleted_at IS NULL");
+        bindings.push((":query", fts_query));
+    } else {
+        sql.push_str(" WHERE f.deleted_at IS NULL");
+    }
 
-    // First, check for user-customized config
-    if let Ok(user_config_dir) = get_user_config_dir() {
-        let user_config_path = user_config_dir.join("defaults.json");
-        if user_config_path.exists() {
-            json_str = fs::read_to_string(&user_config_path)
-                .map_err(|e| format!("Failed to read user defaults.json: {}", e))?;
-            return expand_paths_in_config(json_str);
-        }
+    if !exact_terms.is_empty() {
+        sql.push_str(
+            " AND ssce_contains_terms(
+                 f.filename || ' ' || IFNULL(f.title, '') || ' ' || IFNULL(f.summary, '') || ' ' ||
+                 IFNULL(f.keywords, '') || ' ' || IFNULL(f.content, ''), :exact_terms)",
+        );
+        bindings.push((":exact_terms", &exact_terms));
     }
 
-    // Try development path (relative to src-tauri directory)
-    let dev_path = Path::new("../src/config/defaults.json");
-    if dev_path.exists() {
-        json_str = fs::read_to_string(dev_path)
-            .map_err(|e| format!("Failed to read defaults.json: {}", e))?;
-        return expand_paths_in_config(json_str);
+    // LIKE ignores ASCII case, so the exact comparison keeps "Docs" from
+    // matching "docs" where paths are case-sensitive
+    if let (Some(prefix), Some(pattern)) = (&prefix, &prefix_pattern) {
+        sql.push_str(" AND f.path LIKE :prefix_pattern ESCAPE '\\' AND substr(f.path, 1, length(:prefix)) = :prefix");
+        bindings.push((":prefix_pattern", pattern));
+        bindings.push((":prefix", prefix));
     }
 
-    // Try production path (bundled with app) using Tauri v2 API
-    if let Ok(resource_dir) = app_handle.path().resource_dir() {
-        let resource_path = resource_dir.join("config/defaults.json");
-        if resource_path.exists() {
-            json_str = fs::read_to_string(&resource_path)
-                .map_err(|e| format!("Failed to read defaults.json: {}", e))?;
-            return expand_paths_in_config(json_str);
-        }
+    if let Some(pattern) = &author_pattern {
+        sql.push_str(" AND f.author LIKE :author ESCAPE '\\'");
+        bindings.push((":author", pattern));
     }
 
-    // Try Linux-specific production path (deb package location)
-    #[cfg(target_os = "linux")]
-    {
-        let linux_prod_path = Path::new("/usr/lib/SSCE Desktop/config/defaults.json");
-        if linux_prod_path.exists() {
-            json_str = fs::read_to_string(linux_prod_path)
-                .map_err(|e| format!("Failed to read defaults.json: {}", e))?;
-            return expand_paths_in_config(json_str);
-        }
+    let date_column = params.date_field.column();
+    if let Some(from_date) = &from_date {
+        sql.push_str(&format!(" AND f.{} >= :from_date", date_column));
+        bindings.push((":from_date", from_date));
+    }
+    if let Some(to_date) = &to_date {
+        sql.push_str(&format!(" AND f.{} <= :to_date", date_column));
+        bindings.push((":to_date", to_date));
     }
 
-    // Fallback: return error to trigger frontend fallback
-    Err("defaults.json not found in any config paths".to_string())
-}
+    // bm25 scores are negative, lower is a better match
+    if fts_query.is_some() {
+        sql.push_str(&format!(" ORDER BY {}, f.modified DESC", weights.bm25_sql()));
+    } else {
+        sql.push_str(" ORDER BY f.modified DESC");
+    }
+    sql.push_str(" LIMIT :limit OFFSET :offset");
+    bindings.push((":limit", &limit));
+    bindings.push((":offset", &offset));
 
-/// Expand ~ to home directory in paths section of config JSON
-fn expand_paths_in_config(json_str: String) -> Result<String, String> {
-    let home_dir = dirs::home_dir()
-        .map(|p| p.to_string_lossy().to_string())
-        .unwrap_or_default();
+    // Only the filters (and weights) vary the SQL, so each combination is
+    // cached separately
+    let mut stmt = conn.prepare_cached(&sql)?;
 
-    // Parse JSON
-    let mut config: serde_json::Value = serde_json::from_str(&json_str)
-        .map_err(|e| format!("Failed to parse defaults.json: {}", e))?;
-
-    // Expand paths in the "paths" section if it exists
-    if let Some(paths) = config.get_mut("paths") {
-        if let Some(paths_obj) = paths.as_object_mut() {
-            for (_key, value) in paths_obj.iter_mut() {
-                if let Some(path_str) = value.as_str() {
-                    if path_str.starts_with("~/") {
-                        *value = serde_json::Value::String(
-                            format!("{}{}", home_dir, &path_str[1..])
-                        );
-                    }
-                }
-            }
-        }
+    let has_terms = fts_query.is_some();
+    let files = stmt
+        .query_map(bindings.as_slice(), library_file_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Near misses only stand in for an empty first page
+    if files.is_empty() && has_terms && offset == 0 && params.fuzzy.unwrap_or(false) {
+        let filters = SearchFilters {
+            from_date: from_date.as_deref(),
+            to_date: to_date.as_deref(),
+            date_field: params.date_field,
+            prefix: prefix.as_deref(),
+            author: author.as_deref(),
+        };
+        return fuzzy_search(conn, &query, &filters, limit);
     }
 
-    // Serialize back to JSON
-    serde_json::to_string(&config)
-        .map_err(|e| format!("Failed to serialize config: {}", e))
+    Ok(files)
 }
 
-/// Save defaults.json to user config directory
-/// This allows user customization without modifying bundled files
-#[tauri::command]
-fn save_defaults_config(data: String) -> Result<String, String> {
-    // Validate JSON before saving
-    let _: serde_json::Value = serde_json::from_str(&data)
-        .map_err(|e| format!("Invalid JSON: {}", e))?;
+/// Lowest Jaro-Winkler similarity for a fuzzy match
+const FUZZY_MIN_SIMILARITY: f64 = 0.85;
+/// The fuzzy fallback only compares against this many of the most recently
+/// modified documents, so it stays quick in a huge library
+const FUZZY_SCAN_LIMIT: i64 = 5000;
 
-    let user_config_dir = get_user_config_dir()?;
+/// Lowercased, accent-free words of `text`
+fn fuzzy_words(text: &str) -> Vec<String> {
+    fold_accents(&nfc(text))
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(String::from)
+        .collect()
+}
 
-    // Create config directory if it doesn't exist
-    if !user_config_dir.exists() {
-        fs::create_dir_all(&user_config_dir)
-            .map_err(|e| format!("Failed to create config directory: {}", e))?;
+/// How closely `query_words` match `candidate_words`: the mean, over the
+/// query words, of each one's best Jaro-Winkler similarity
+fn fuzzy_score(query_words: &[String], candidate_words: &[String]) -> f64 {
+    if query_words.is_empty() || candidate_words.is_empty() {
+        return 0.0;
     }
 
-    let config_path = user_config_dir.join("defaults.json");
-    let config_path_str = config_path.to_string_lossy().to_string();
-
-    fs::write(&config_path, &data)
-        .map_err(|e| format!("Failed to write defaults.json: {}", e))?;
-
-    Ok(config_path_str)
+    let total: f64 = query_words
+        .iter()
+        .map(|q| {
+            candidate_words
+                .iter()
+                .map(|c| strsim::jaro_winkler(q, c))
+                .fold(0.0, f64::max)
+        })
+        .sum();
+    total / query_words.len() as f64
 }
 
-/// Get the path where user config would be saved
-#[tauri::command]
-fn get_user_config_path() -> Result<String, String> {
-    let user_config_dir = get_user_config_dir()?;
-    let config_path = user_config_dir.join("defaults.json");
-    Ok(config_path.to_string_lossy().to_string())
+/// The filters of a search, already parsed, for fuzzy_search
+struct SearchFilters<'a> {
+    from_date: Option<&'a str>,
+    to_date: Option<&'a str>,
+    date_field: SearchDateField,
+    /// From folder_prefix
+    prefix: Option<&'a str>,
+    author: Option<&'a str>,
 }
 
-/// Open a file in the default browser
-#[tauri::command]
-fn open_in_default_app(path: String) -> Result<(), String> {
-    // Convert to file:// URL
-    let url = if path.starts_with("file://") {
-        path
-    } else {
-        format!("file://{}", path)
-    };
+/// Documents whose filename or title is spelled like `query`, best first,
+/// marked `fuzzy`. The filters apply as in search_files_ranked.
+fn fuzzy_search(
+    conn: &Connection,
+    query: &str,
+    filters: &SearchFilters,
+    limit: i32,
+) -> Result<Vec<LibraryFile>, CommandError> {
+    let query_words = fuzzy_words(query);
 
-    #[cfg(target_os = "linux")]
-    {
-        // Skip xdg-open - try browsers directly to avoid text editor association
-        let browsers = [
-            "google-chrome",
-            "google-chrome-stable",
-            "chromium",
-            "chromium-browser",
-            "firefox",
-        ];
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT id, path, filename, thumbnail, title, summary, keywords, modified, last_opened, snapshot_count, size, encoded_path, open_count, author, created
+         FROM files
+         WHERE deleted_at IS NULL
+           AND (?1 IS NULL OR {date} >= ?1)
+           AND (?2 IS NULL OR {date} <= ?2)
+           AND (?4 IS NULL OR (path LIKE ?5 ESCAPE '\\' AND substr(path, 1, length(?4)) = ?4))
+           AND (?6 IS NULL OR author LIKE ?6 ESCAPE '\\')
+         ORDER BY modified DESC
+         LIMIT ?3",
+        date = filters.date_field.column()
+    ))?;
+    let candidates = stmt
+        .query_map(
+            params![
+                filters.from_date,
+                filters.to_date,
+                FUZZY_SCAN_LIMIT,
+                filters.prefix,
+                filters.prefix.map(like_prefix_pattern),
+                filters.author.map(like_contains_pattern)
+            ],
+            library_file_from_row,
+        )?
+        .collect::<Result<Vec<_>, _>>()?;
 
-        for browser in browsers {
-            if std::process::Command::new(browser)
-                .arg(&url)
-                .spawn()
-                .is_ok()
-            {
-                return Ok(());
-            }
+    let mut scored: Vec<(f64, LibraryFile)> = candidates
+        .into_iter()
+        .filter_map(|file| {
+            let stem = Path::new(&file.filename)
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let words = fuzzy_words(&format!("{} {}", stem, file.title.as_deref().unwrap_or_default()));
+            let score = fuzzy_score(&query_words, &words);
+            (score >= FUZZY_MIN_SIMILARITY).then_some((score, LibraryFile { fuzzy: true, ..file }))
+        })
+        .collect();
+
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    Ok(scored.into_iter().take(limit as usize).map(|(_, file)| file).collect())
+}
+
+/// Most distinct words kept for search suggestions (the most common ones)
+const SUGGESTION_VOCAB_LIMIT: usize = 20_000;
+/// Words shorter than this are neither suggested nor corrected
+const SUGGESTION_MIN_WORD_LEN: usize = 3;
+
+/// Words from every document's filename, title and keywords with how many
+/// documents use them, built on the first db_search_suggestions call and
+/// rebuilt when index_generation says they have changed
+struct SuggestionVocab(Mutex<Option<Vocabulary>>);
+
+struct Vocabulary {
+    /// index_generation and file count when built; the count catches a
+    /// database swapped for another one at the same generation
+    stamp: (i64, i64),
+    words: HashMap<String, usize>,
+}
+
+fn vocabulary_stamp(conn: &Connection) -> Result<(i64, i64), rusqlite::Error> {
+    conn.query_row(
+        "SELECT (SELECT value FROM index_generation), (SELECT COUNT(*) FROM files)",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+}
+
+fn build_vocabulary(conn: &Connection) -> Result<Vocabulary, rusqlite::Error> {
+    let stamp = vocabulary_stamp(conn)?;
+    let mut stmt = conn.prepare("SELECT filename, title, keywords FROM files WHERE deleted_at IS NULL")?;
+    let mut rows = stmt.query([])?;
+
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    while let Some(row) = rows.next()? {
+        let filename: String = row.get(0)?;
+        let stem = Path::new(&filename).file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        let text = format!(
+            "{} {} {}",
+            stem,
+            row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+            row.get::<_, Option<String>>(2)?.unwrap_or_default()
+        );
+        let words: std::collections::HashSet<String> = fuzzy_words(&text)
+            .into_iter()
+            .filter(|w| w.chars().count() >= SUGGESTION_MIN_WORD_LEN)
+            .collect();
+        for word in words {
+            *counts.entry(word).or_default() += 1;
         }
+    }
 
-        Err("No browser found. Install Chrome, Chromium, or Firefox.".to_string())
+    if counts.len() > SUGGESTION_VOCAB_LIMIT {
+        let mut ranked: Vec<(String, usize)> = counts.into_iter().collect();
+        ranked.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        ranked.truncate(SUGGESTION_VOCAB_LIMIT);
+        counts = ranked.into_iter().collect();
     }
 
-    #[cfg(target_os = "windows")]
-    {
-        std::process::Command::new("cmd")
-            .args(["/C", "start", "", &url])
-            .spawn()
-            .map_err(|e| format!("Failed to open file: {}", e))?;
-        Ok(())
+    Ok(Vocabulary { stamp, words: counts })
+}
+
+/// "Did you mean" alternatives for a search that found nothing: `query`
+/// with each word the library doesn't contain swapped for the nearest one
+/// it does (by edit distance, more common words first). Best first, at most
+/// `limit` (default 5); empty when every word is known or nothing is close.
+#[tauri::command]
+#[tracing::instrument(skip(state, vocab), err)]
+fn db_search_suggestions(
+    state: State<DbState>,
+    vocab: State<SuggestionVocab>,
+    query: String,
+    limit: Option<usize>,
+) -> Result<Vec<String>, CommandError> {
+    state.with_conn(|conn| search_suggestions(conn, &vocab, &query, limit.unwrap_or(5)))
+}
+
+fn search_suggestions(
+    conn: &Connection,
+    vocab: &SuggestionVocab,
+    query: &str,
+    limit: usize,
+) -> Result<Vec<String>, CommandError> {
+    let mut cached = vocab.0.lock().map_err(|e| CommandError::Db(format!("Suggestion cache lock poisoned: {}", e)))?;
+    let stamp = vocabulary_stamp(conn)?;
+    if cached.as_ref().is_none_or(|v| v.stamp != stamp) {
+        *cached = Some(build_vocabulary(conn)?);
     }
+    let Some(vocabulary) = cached.as_ref() else {
+        return Ok(Vec::new());
+    };
 
-    #[cfg(target_os = "macos")]
-    {
-        std::process::Command::new("open")
-            .arg(&url)
-            .spawn()
-            .map_err(|e| format!("Failed to open file: {}", e))?;
-        Ok(())
+    // For each word, the words to try in its place (itself when known)
+    let mut choices: Vec<Vec<&str>> = Vec::new();
+    let mut corrected = false;
+    let query_words = fuzzy_words(query);
+    for word in &query_words {
+        let len = word.chars().count();
+        if vocabulary.words.contains_key(word.as_str()) || len < SUGGESTION_MIN_WORD_LEN {
+            choices.push(vec![word.as_str()]);
+            continue;
+        }
+
+        let max_distance = if len <= 4 { 1 } else { 2 };
+        let mut near: Vec<(usize, usize, &str)> = vocabulary
+            .words
+            .iter()
+            .filter(|(candidate, _)| candidate.chars().count().abs_diff(len) <= max_distance)
+            .filter_map(|(candidate, &count)| {
+                let distance = strsim::damerau_levenshtein(word, candidate);
+                (distance <= max_distance).then_some((distance, count, candidate.as_str()))
+            })
+            .collect();
+        if near.is_empty() {
+            return Ok(Vec::new());
+        }
+        near.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)).then_with(|| a.2.cmp(b.2)));
+        corrected = true;
+        choices.push(near.into_iter().map(|(_, _, candidate)| candidate).collect());
+    }
+
+    if !corrected {
+        return Ok(Vec::new());
+    }
+
+    // The nth suggestion takes each word's nth choice (or its last)
+    let widest = choices.iter().map(Vec::len).max().unwrap_or(0);
+    let mut suggestions: Vec<String> = Vec::new();
+    for n in 0..widest {
+        let suggestion = choices.iter().map(|c| c[n.min(c.len() - 1)]).collect::<Vec<_>>().join(" ");
+        if !suggestions.contains(&suggestion) {
+            suggestions.push(suggestion);
+        }
+        if suggestions.len() == limit {
+            break;
+        }
     }
+    Ok(suggestions)
 }
 
-// ============================================================================
-// Bulk Export Commands
-// ============================================================================
-//
-// Commands for the Bulk Export / Backup feature. Allows exporting multiple
-// .ssce files to PNG/JPEG images, optionally into a ZIP archive.
-//
-// ============================================================================
+/// Remove a file from the library database
+#[tauri::command]
+#[tracing::instrument(skip(state), err)]
+fn db_remove_file(state: State<DbState>, path: String) -> Result<(), CommandError> {
+    let path = resolve_path(&path);
+    state.with_conn(|conn| {
+        conn.prepare_cached("DELETE FROM files WHERE path = ?1")
+            .and_then(|mut stmt| stmt.execute(params![normalize_path(&path)]))?;
 
-/// File info returned when scanning a directory for .ssce files
-#[derive(Serialize)]
-struct SsceFileInfo {
-    name: String,
-    path: String,
-    date: Option<String>,
-    size: u64,
+        Ok(())
+    })
 }
 
-/// Summary of files per month for the date filter UI
-#[derive(Serialize)]
-struct MonthSummary {
-    month: String,
-    count: u32,
+/// Record that a file was opened: sets last_opened and counts the open
+#[tauri::command]
+#[tracing::instrument(skip(state), err)]
+fn db_update_last_opened(state: State<DbState>, path: String, timestamp: String) -> Result<(), CommandError> {
+    let path = resolve_path(&path);
+    state.with_conn(|conn| record_open(conn, &path, &timestamp).map_err(CommandError::from))
 }
 
-/// Parse a YYYY-MM-DD date from a filename
-fn parse_date_from_filename(filename: &str) -> Option<String> {
-    // Look for YYYY-MM-DD pattern
-    let bytes = filename.as_bytes();
-    for i in 0..filename.len().saturating_sub(9) {
-        if bytes.get(i + 4) == Some(&b'-')
-            && bytes.get(i + 7) == Some(&b'-')
-            && filename[i..i + 4].chars().all(|c| c.is_ascii_digit())
-            && filename[i + 5..i + 7].chars().all(|c| c.is_ascii_digit())
-            && filename[i + 8..i + 10].chars().all(|c| c.is_ascii_digit())
-        {
-            let date = &filename[i..i + 10];
-            let month: u32 = filename[i + 5..i + 7].parse().unwrap_or(0);
-            let day: u32 = filename[i + 8..i + 10].parse().unwrap_or(0);
-            if month >= 1 && month <= 12 && day >= 1 && day <= 31 {
-                return Some(date.to_string());
-            }
-        }
-    }
-    None
+fn record_open(conn: &Connection, path: &str, timestamp: &str) -> Result<(), rusqlite::Error> {
+    // One statement, so the count and timestamp can't drift apart
+    conn.prepare_cached("UPDATE files SET last_opened = ?1, open_count = open_count + 1 WHERE path = ?2")
+        .and_then(|mut stmt| stmt.execute(params![timestamp, normalize_path(path)]))?;
+
+    Ok(())
 }
 
-/// List all .ssce files in a directory with parsed date metadata
+/// Update a file's title, summary and/or keywords without a full upsert.
+/// Fields passed as None are left unchanged; the FTS update trigger keeps
+/// the search index in sync.
 #[tauri::command]
-fn list_ssce_files(directory: String) -> Result<Vec<SsceFileInfo>, String> {
-    let dir_path = Path::new(&directory);
+#[tracing::instrument(skip(state), err)]
+fn db_update_metadata(
+    state: State<DbState>,
+    path: String,
+    title: Option<String>,
+    summary: Option<String>,
+    keywords: Option<String>,
+) -> Result<(), CommandError> {
+    let path = resolve_path(&path);
+    state.with_conn(|conn| update_metadata(conn, &path, title.clone(), summary.clone(), keywords.clone()))
+}
 
-    if !dir_path.exists() {
-        return Ok(Vec::new());
-    }
+fn update_metadata(
+    conn: &Connection,
+    path: &str,
+    title: Option<String>,
+    summary: Option<String>,
+    keywords: Option<String>,
+) -> Result<(), CommandError> {
+    let updated = conn
+        .prepare_cached(
+            "UPDATE files SET
+                 title = COALESCE(?1, title),
+                 summary = COALESCE(?2, summary),
+                 keywords = COALESCE(?3, keywords)
+             WHERE path = ?4",
+        )
+        .and_then(|mut stmt| {
+            stmt.execute(params![
+                title.as_deref().map(nfc),
+                summary.as_deref().map(nfc),
+                keywords.as_deref().map(normalize_keyword_list),
+                normalize_path(path)
+            ])
+        })?;
 
-    if !dir_path.is_dir() {
-        return Err(format!("Path is not a directory: {}", directory));
+    if updated == 0 {
+        return Err(CommandError::NotFound(format!("File is not in the library: {}", path)));
     }
 
-    let mut entries: Vec<SsceFileInfo> = Vec::new();
+    Ok(())
+}
 
-    let read_dir = fs::read_dir(dir_path)
-        .map_err(|e| format!("Failed to read directory: {}", e))?;
+/// Replace the keyword `old` with `new` on every document that has it, or
+/// remove it where `new` is empty. Both are normalized the way the indexer
+/// stores keywords (see normalize_keyword_list), so matching ignores case,
+/// including outside ASCII. Returns the number of documents changed.
+#[tauri::command]
+#[tracing::instrument(skip(state), err)]
+fn db_rename_keyword(state: State<DbState>, old: String, new: String) -> Result<usize, CommandError> {
+    state.with_conn(|conn| rename_keyword(conn, &old, &new))
+}
 
-    for entry in read_dir {
-        let entry = entry.map_err(|e| format!("Failed to read entry: {}", e))?;
-        let metadata = entry.metadata().map_err(|e| format!("Failed to get metadata: {}", e))?;
+/// The normalized form of `input` if it is one keyword (or, with
+/// `allow_empty`, none)
+fn single_keyword(input: &str, allow_empty: bool) -> Result<String, CommandError> {
+    let keyword = normalize_keyword_list(input);
+    if (keyword.is_empty() && !allow_empty) || keyword.contains(' ') {
+        return Err(CommandError::Validation(format!("Not a single keyword: '{}'", input.trim())));
+    }
+    Ok(keyword)
+}
 
-        if metadata.is_dir() {
-            continue;
-        }
+fn rename_keyword(conn: &Connection, old: &str, new: &str) -> Result<usize, CommandError> {
+    let old_key = single_keyword(old, false)?;
+    let new_key = single_keyword(new, true)?;
 
-        let name = entry.file_name().to_string_lossy().to_string();
-        if !name.to_lowercase().ends_with(".ssce") {
-            continue;
+    let tx = conn.unchecked_transaction()?;
+    let mut changed = 0;
+    {
+        // Stored keywords may predate normalization, so every list is
+        // normalized before comparing rather than narrowed with LIKE
+        let mut select = tx.prepare("SELECT id, keywords FROM files WHERE keywords <> ''")?;
+        let rows = select
+            .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut update = tx.prepare("UPDATE files SET keywords = ?1 WHERE id = ?2")?;
+
+        // The colour follows the keyword, unless the new one already has one
+        if old_key != new_key {
+            if !new_key.is_empty() {
+                tx.execute(
+                    "INSERT OR IGNORE INTO tag_colors (keyword, color)
+                     SELECT ?2, color FROM tag_colors WHERE keyword = ?1",
+                    params![old_key, new_key],
+                )?;
+            }
+            tx.execute("DELETE FROM tag_colors WHERE keyword = ?1", params![old_key])?;
         }
 
-        let path = entry.path().to_string_lossy().to_string();
-        let date = parse_date_from_filename(&name);
-        let size = metadata.len();
+        for (id, keywords) in rows {
+            let normalized = normalize_keyword_list(&keywords);
+            if !normalized.split(' ').any(|k| k == old_key) {
+                continue;
+            }
 
-        entries.push(SsceFileInfo { name, path, date, size });
+            let renamed: Vec<&str> = normalized
+                .split(' ')
+                .map(|k| if k == old_key { new_key.as_str() } else { k })
+                .collect();
+            update.execute(params![normalize_keyword_list(&renamed.join(" ")), id])?;
+            changed += 1;
+        }
     }
+    tx.commit()?;
 
-    // Sort by name
-    entries.sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()));
+    Ok(changed)
+}
 
-    Ok(entries)
+/// Two keywords used on the same documents, and on how many
+#[derive(Serialize, Debug, PartialEq)]
+struct KeywordPair {
+    /// The pair in alphabetical order
+    a: String,
+    b: String,
+    count: u32,
 }
 
-/// Get a summary of .ssce file counts grouped by month
+/// Keywords per document counted by keyword_cooccurrence when the caller
+/// doesn't say. A document with n keywords makes n(n-1)/2 pairs, so huge
+/// tag lists are cut to their first keywords.
+const DEFAULT_COOCCURRENCE_KEYWORDS: usize = 50;
+/// Upper bound on the caller's max_keywords
+const MAX_COOCCURRENCE_KEYWORDS: usize = 200;
+
+/// Pairs of keywords that appear together on at least `min_count`
+/// documents (default 2), most frequent first. Only the first
+/// `max_keywords` keywords of each document are paired (default 50, at
+/// most 200), which keeps the work bounded for documents with long tag
+/// lists.
 #[tauri::command]
-fn get_monthly_summary(directory: String) -> Result<Vec<MonthSummary>, String> {
-    let files = list_ssce_files(directory)?;
-    let mut counts: HashMap<String, u32> = HashMap::new();
+#[tracing::instrument(skip(state), err)]
+fn db_keyword_cooccurrence(
+    state: State<DbState>,
+    min_count: Option<u32>,
+    max_keywords: Option<usize>,
+) -> Result<Vec<KeywordPair>, CommandError> {
+    let max_keywords = max_keywords
+        .unwrap_or(DEFAULT_COOCCURRENCE_KEYWORDS)
+        .clamp(2, MAX_COOCCURRENCE_KEYWORDS);
+    state.with_read_conn(|conn| {
+        keyword_cooccurrence(conn, min_count.unwrap_or(2).max(1), max_keywords).map_err(CommandError::from)
+    })
+}
 
-    for file in &files {
-        if let Some(ref date) = file.date {
-            // Extract YYYY-MM from YYYY-MM-DD
-            let month = &date[..7];
-            *counts.entry(month.to_string()).or_insert(0) += 1;
+/// One pass over the stored keyword lists, counting each pair once per
+/// document. Keywords are numbered as they're first seen so the pair
+/// counts are keyed by two integers rather than two strings.
+fn keyword_cooccurrence(
+    conn: &Connection,
+    min_count: u32,
+    max_keywords: usize,
+) -> Result<Vec<KeywordPair>, rusqlite::Error> {
+    let mut stmt = conn.prepare("SELECT keywords FROM files WHERE deleted_at IS NULL AND keywords IS NOT NULL")?;
+    let mut rows = stmt.query([])?;
+
+    let mut ids: HashMap<String, usize> = HashMap::new();
+    let mut names: Vec<String> = Vec::new();
+    let mut counts: HashMap<(usize, usize), u32> = HashMap::new();
+    let mut document: Vec<usize> = Vec::with_capacity(max_keywords);
+    while let Some(row) = rows.next()? {
+        let keywords: String = row.get(0)?;
+        document.clear();
+        // Stored lists are already normalized (see normalize_keyword_list);
+        // lowercasing again covers rows written before that
+        for keyword in keywords.split_whitespace().map(str::to_lowercase) {
+            if document.len() == max_keywords {
+                break;
+            }
+            let next = names.len();
+            let id = *ids.entry(keyword.clone()).or_insert(next);
+            if id == next {
+                names.push(keyword);
+            }
+            if !document.contains(&id) {
+                document.push(id);
+            }
+        }
+
+        for (i, &first) in document.iter().enumerate() {
+            for &second in &document[i + 1..] {
+                let key = if names[first] < names[second] { (first, second) } else { (second, first) };
+                *counts.entry(key).or_insert(0) += 1;
+            }
         }
     }
 
-    let mut summaries: Vec<MonthSummary> = counts
+    let mut pairs: Vec<KeywordPair> = counts
         .into_iter()
-        .map(|(month, count)| MonthSummary { month, count })
+        .filter(|(_, count)| *count >= min_count)
+        .map(|((a, b), count)| KeywordPair {
+            a: names[a].clone(),
+            b: names[b].clone(),
+            count,
+        })
         .collect();
-
-    // Sort by month descending (most recent first)
-    summaries.sort_by(|a, b| b.month.cmp(&a.month));
-
-    Ok(summaries)
+    pairs.sort_by(|x, y| y.count.cmp(&x.count).then_with(|| x.a.cmp(&y.a)).then_with(|| x.b.cmp(&y.b)));
+    Ok(pairs)
 }
 
-/// Save an exported image (base64 data URL) to a file path.
-/// Reuses the same pattern as save_image but explicitly for bulk export.
-#[tauri::command]
-fn save_exported_image(path: String, data: String) -> Result<(), String> {
-    // Strip data URL prefix if present
-    let base64_data = if let Some(comma_pos) = data.find(',') {
-        &data[comma_pos + 1..]
+/// A "#RGB" or "#RRGGBB" colour in "#RRGGBB" form, upper case
+fn normalize_hex_color(color: &str) -> Result<String, CommandError> {
+    let hex = color
+        .trim()
+        .strip_prefix('#')
+        .filter(|hex| matches!(hex.len(), 3 | 6) && hex.chars().all(|c| c.is_ascii_hexdigit()))
+        .ok_or_else(|| CommandError::Validation(format!("Not a hex colour like #FF0000: '{}'", color)))?;
+
+    let hex = if hex.len() == 3 {
+        hex.chars().flat_map(|c| [c, c]).collect()
     } else {
-        &data
+        hex.to_string()
     };
+    Ok(format!("#{}", hex.to_ascii_uppercase()))
+}
 
-    let decoded = STANDARD
-        .decode(base64_data)
-        .map_err(|e| format!("Failed to decode base64: {}", e))?;
+/// Set the chip colour shown for a keyword, or remove it with a null
+/// colour. Colours are "#RGB" or "#RRGGBB" and are stored as "#RRGGBB".
+#[tauri::command]
+#[tracing::instrument(skip(state), err)]
+fn db_set_tag_color(state: State<DbState>, keyword: String, color: Option<String>) -> Result<(), CommandError> {
+    state.with_conn(|conn| set_tag_color(conn, &keyword, color.as_deref()))
+}
 
-    // Create parent directories if needed
-    if let Some(parent) = Path::new(&path).parent() {
-        fs::create_dir_all(parent).map_err(|e| format!("Failed to create directories: {}", e))?;
+fn set_tag_color(conn: &Connection, keyword: &str, color: Option<&str>) -> Result<(), CommandError> {
+    let key = normalize_keyword_list(keyword);
+    if key.is_empty() || key.contains(' ') {
+        return Err(CommandError::Validation(format!("Not a single keyword: '{}'", keyword)));
     }
 
-    fs::write(&path, decoded).map_err(|e| format!("Failed to write file: {}", e))?;
+    match color {
+        Some(color) => conn.execute(
+            "INSERT INTO tag_colors (keyword, color) VALUES (?1, ?2)
+             ON CONFLICT(keyword) DO UPDATE SET color = excluded.color",
+            params![key, normalize_hex_color(color)?],
+        )?,
+        None => conn.execute("DELETE FROM tag_colors WHERE keyword = ?1", params![key])?,
+    };
 
     Ok(())
 }
 
-/// Create a new ZIP archive and return an ID for subsequent operations.
-/// The archive stays open until zip_finalize is called.
+/// Every keyword that has a chip colour, mapped to the colour
 #[tauri::command]
-fn zip_create(state: State<ZipState>, output_path: String) -> Result<String, String> {
-    // Create parent directories if needed
-    if let Some(parent) = Path::new(&output_path).parent() {
-        fs::create_dir_all(parent).map_err(|e| format!("Failed to create directories: {}", e))?;
-    }
-
-    let file = fs::File::create(&output_path)
-        .map_err(|e| format!("Failed to create ZIP file: {}", e))?;
-
-    let writer = ZipWriter::new(file);
-    let id = format!("{}", std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_nanos());
+#[tracing::instrument(skip(state), err)]
+fn db_get_tag_colors(state: State<DbState>) -> Result<HashMap<String, String>, CommandError> {
+    state.with_read_conn(|conn| tag_colors(conn))
+}
 
-    let mut map = state.0.lock().map_err(|e| e.to_string())?;
-    map.insert(id.clone(), Mutex::new(writer));
+fn tag_colors(conn: &Connection) -> Result<HashMap<String, String>, CommandError> {
+    let mut stmt = conn.prepare("SELECT keyword, color FROM tag_colors")?;
+    let colors = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+    Ok(colors)
+}
 
-    Ok(id)
+/// Outcome of db_bulk_tag for one path: the new keywords, or why it failed
+#[derive(Serialize, Debug)]
+struct BulkTagResult {
+    path: String,
+    keywords: Option<String>,
+    error: Option<String>,
 }
 
-/// Add a file to an open ZIP archive.
-/// Data is base64-encoded (with optional data URL prefix).
+/// Add and remove keywords on several documents at once. Keywords are
+/// normalized the way the indexer stores them (see normalize_keyword_list)
+/// and never duplicated. Paths that aren't in the library, or are in the
+/// trash, are reported in their result; the rest are still updated.
 #[tauri::command]
-fn zip_add_file(state: State<ZipState>, zip_id: String, entry_name: String, base64_data: String) -> Result<(), String> {
-    // Strip data URL prefix if present
-    let raw_base64 = if let Some(comma_pos) = base64_data.find(',') {
-        &base64_data[comma_pos + 1..]
-    } else {
-        &base64_data
-    };
+#[tracing::instrument(skip(state), err)]
+fn db_bulk_tag(
+    state: State<DbState>,
+    paths: Vec<String>,
+    add: Vec<String>,
+    remove: Vec<String>,
+) -> Result<Vec<BulkTagResult>, CommandError> {
+    let paths: Vec<String> = paths.iter().map(|p| resolve_path(p)).collect();
+    state.with_conn(|conn| bulk_tag(conn, &paths, &add, &remove))
+}
 
-    let decoded = STANDARD
-        .decode(raw_base64)
-        .map_err(|e| format!("Failed to decode base64: {}", e))?;
+fn bulk_tag(
+    conn: &Connection,
+    paths: &[String],
+    add: &[String],
+    remove: &[String],
+) -> Result<Vec<BulkTagResult>, CommandError> {
+    let add = normalize_keyword_list(&add.join(" "));
+    let remove = normalize_keyword_list(&remove.join(" "));
+    if add.is_empty() && remove.is_empty() {
+        return Err(CommandError::Validation("No keywords to add or remove".to_string()));
+    }
+    let remove: Vec<&str> = remove.split_whitespace().collect();
 
-    let map = state.0.lock().map_err(|e| e.to_string())?;
-    let writer_mutex = map.get(&zip_id)
-        .ok_or_else(|| format!("ZIP archive not found: {}", zip_id))?;
+    let tx = conn.unchecked_transaction()?;
+    let mut results = Vec::with_capacity(paths.len());
+    {
+        let mut select = tx.prepare("SELECT keywords FROM files WHERE path = ?1 AND deleted_at IS NULL")?;
+        let mut update = tx.prepare("UPDATE files SET keywords = ?1 WHERE path = ?2")?;
 
-    let mut writer = writer_mutex.lock().map_err(|e| e.to_string())?;
+        for path in paths {
+            let normalized = normalize_path(path);
+            let Some(current) = select
+                .query_row(params![normalized], |row| row.get::<_, Option<String>>(0))
+                .optional()?
+            else {
+                results.push(BulkTagResult {
+                    path: path.clone(),
+                    keywords: None,
+                    error: Some(format!("File is not in the library: {}", path)),
+                });
+                continue;
+            };
 
-    let options = SimpleFileOptions::default()
-        .compression_method(zip::CompressionMethod::Deflated);
+            let merged = normalize_keyword_list(&format!("{} {}", current.unwrap_or_default(), add));
+            let keywords: Vec<&str> = merged.split_whitespace().filter(|k| !remove.contains(k)).collect();
+            let joined = keywords.join(" ");
+            update.execute(params![joined, normalized])?;
 
-    writer.start_file(&entry_name, options)
-        .map_err(|e| format!("Failed to start ZIP entry: {}", e))?;
+            results.push(BulkTagResult {
+                path: path.clone(),
+                keywords: Some(joined),
+                error: None,
+            });
+        }
+    }
+    tx.commit()?;
 
-    writer.write_all(&decoded)
-        .map_err(|e| format!("Failed to write ZIP entry: {}", e))?;
+    Ok(results)
+}
 
-    Ok(())
+/// A named collection and how many documents it holds
+#[derive(Serialize, Debug, PartialEq)]
+struct CollectionSummary {
+    name: String,
+    created: String,
+    file_count: i64,
 }
 
-/// Add a file from disk to an open ZIP archive by its filesystem path.
+/// Id of the collection called `name`
+fn collection_id(conn: &Connection, name: &str) -> Result<i64, CommandError> {
+    conn.query_row("SELECT id FROM collections WHERE name = ?1", params![name.trim()], |row| row.get(0))
+        .optional()?
+        .ok_or_else(|| CommandError::NotFound(format!("No collection named '{}'", name.trim())))
+}
+
+/// Create an empty collection. Names are trimmed and must be unique.
 #[tauri::command]
-fn zip_add_path(state: State<ZipState>, zip_id: String, entry_name: String, file_path: String) -> Result<(), String> {
-    let contents = fs::read(&file_path)
-        .map_err(|e| format!("Failed to read file {}: {}", file_path, e))?;
+#[tracing::instrument(skip(state), err)]
+fn db_create_collection(state: State<DbState>, name: String) -> Result<(), CommandError> {
+    state.with_conn(|conn| create_collection(conn, &name))
+}
 
-    let map = state.0.lock().map_err(|e| e.to_string())?;
-    let writer_mutex = map.get(&zip_id)
-        .ok_or_else(|| format!("ZIP archive not found: {}", zip_id))?;
+fn create_collection(conn: &Connection, name: &str) -> Result<(), CommandError> {
+    let name = nfc(name.trim());
+    if name.is_empty() {
+        return Err(CommandError::Validation("Collection name must not be empty".to_string()));
+    }
 
-    let mut writer = writer_mutex.lock().map_err(|e| e.to_string())?;
+    let now = now_timestamp();
+    let inserted = conn.execute(
+        "INSERT OR IGNORE INTO collections (name, created) VALUES (?1, ?2)",
+        params![name, now],
+    )?;
+    if inserted == 0 {
+        return Err(CommandError::Validation(format!("A collection named '{}' already exists", name)));
+    }
 
-    let options = SimpleFileOptions::default()
-        .compression_method(zip::CompressionMethod::Deflated);
+    Ok(())
+}
 
-    writer.start_file(&entry_name, options)
-        .map_err(|e| format!("Failed to start ZIP entry: {}", e))?;
+/// Add documents to a collection. Every path must be in the library, or
+/// nothing is added. Returns how many weren't members already.
+#[tauri::command]
+#[tracing::instrument(skip(state, paths), fields(count = paths.len()), err)]
+fn db_add_to_collection(state: State<DbState>, name: String, paths: Vec<String>) -> Result<usize, CommandError> {
+    let paths: Vec<String> = paths.iter().map(|p| resolve_path(p)).collect();
+    state.with_conn(|conn| add_to_collection(conn, &name, &paths))
+}
 
-    writer.write_all(&contents)
-        .map_err(|e| format!("Failed to write ZIP entry: {}", e))?;
+fn add_to_collection(conn: &Connection, name: &str, paths: &[String]) -> Result<usize, CommandError> {
+    let tx = conn.unchecked_transaction()?;
+    let id = collection_id(&tx, &nfc(name))?;
+    let mut added = 0;
+    {
+        let mut select = tx.prepare("SELECT id FROM files WHERE path = ?1 AND deleted_at IS NULL")?;
+        let mut insert =
+            tx.prepare("INSERT OR IGNORE INTO collection_members (collection_id, file_id) VALUES (?1, ?2)")?;
 
-    Ok(())
+        for path in paths {
+            let file_id: i64 = select
+                .query_row(params![normalize_path(path)], |row| row.get(0))
+                .optional()?
+                .ok_or_else(|| CommandError::NotFound(format!("File is not in the library: {}", path)))?;
+            added += insert.execute(params![id, file_id])?;
+        }
+    }
+    tx.commit()?;
+
+    Ok(added)
 }
 
-/// Get system information for the About dialog.
-/// Returns platform, OS version, arch, and app version.
+/// Take documents out of a collection. Paths that aren't members are
+/// ignored. Returns how many were removed.
 #[tauri::command]
-fn get_system_info(window: tauri::Window) -> Result<HashMap<String, String>, String> {
-    let mut info = HashMap::new();
+#[tracing::instrument(skip(state, paths), fields(count = paths.len()), err)]
+fn db_remove_from_collection(state: State<DbState>, name: String, paths: Vec<String>) -> Result<usize, CommandError> {
+    let paths: Vec<String> = paths.iter().map(|p| resolve_path(p)).collect();
+    state.with_conn(|conn| remove_from_collection(conn, &name, &paths))
+}
 
-    info.insert("platform".to_string(), std::env::consts::OS.to_string());
-    info.insert("arch".to_string(), std::env::consts::ARCH.to_string());
+fn remove_from_collection(conn: &Connection, name: &str, paths: &[String]) -> Result<usize, CommandError> {
+    let tx = conn.unchecked_transaction()?;
+    let id = collection_id(&tx, &nfc(name))?;
+    let mut removed = 0;
+    {
+        let mut delete = tx.prepare(
+            "DELETE FROM collection_members
+             WHERE collection_id = ?1 AND file_id = (SELECT id FROM files WHERE path = ?2)",
+        )?;
+        for path in paths {
+            removed += delete.execute(params![id, normalize_path(path)])?;
+        }
+    }
+    tx.commit()?;
 
-    // App version from Cargo.toml (embedded by Tauri)
-    info.insert("appVersion".to_string(), env!("CARGO_PKG_VERSION").to_string());
+    Ok(removed)
+}
 
-    // Get OS details
-    #[cfg(target_os = "linux")]
-    {
-        // Try to read /etc/os-release for distro info
-        if let Ok(content) = fs::read_to_string("/etc/os-release") {
-            for line in content.lines() {
-                if let Some(name) = line.strip_prefix("PRETTY_NAME=") {
-                    let name = name.trim_matches('"');
-                    info.insert("osVersion".to_string(), name.to_string());
-                    break;
-                }
-            }
-        }
-        // Kernel version
-        if let Ok(uname) = fs::read_to_string("/proc/version") {
-            if let Some(kernel) = uname.split_whitespace().nth(2) {
-                info.insert("kernelVersion".to_string(), kernel.to_string());
-            }
-        }
-    }
+/// Every collection with its document count, by name
+#[tauri::command]
+#[tracing::instrument(skip(state), err)]
+fn db_list_collections(state: State<DbState>) -> Result<Vec<CollectionSummary>, CommandError> {
+    state.with_read_conn(|conn| list_collections(conn))
+}
 
-    #[cfg(target_os = "windows")]
-    {
-        info.insert("osVersion".to_string(), "Windows".to_string());
+fn list_collections(conn: &Connection) -> Result<Vec<CollectionSummary>, CommandError> {
+    let mut stmt = conn.prepare(
+        "SELECT c.name, c.created, COUNT(m.file_id)
+         FROM collections c
+         LEFT JOIN collection_members m ON m.collection_id = c.id
+         GROUP BY c.id
+         ORDER BY c.name COLLATE NOCASE",
+    )?;
+    let collections = stmt
+        .query_map([], |row| {
+            Ok(CollectionSummary {
+                name: row.get(0)?,
+                created: row.get(1)?,
+                file_count: row.get(2)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(collections)
+}
+
+/// The documents in a collection, most recently modified first
+#[tauri::command]
+#[tracing::instrument(skip(state), err)]
+fn db_collection_files(state: State<DbState>, name: String) -> Result<Vec<LibraryFile>, CommandError> {
+    state.with_read_conn(|conn| collection_files(conn, &name))
+}
+
+fn collection_files(conn: &Connection, name: &str) -> Result<Vec<LibraryFile>, CommandError> {
+    let id = collection_id(conn, &nfc(name))?;
+    let mut stmt = conn.prepare(
+        "SELECT f.id, f.path, f.filename, f.thumbnail, f.title, f.summary, f.keywords, f.modified, f.last_opened, f.snapshot_count, f.size, f.encoded_path, f.open_count, f.author, f.created
+         FROM collection_members m
+         JOIN files f ON f.id = m.file_id
+         WHERE m.collection_id = ?1 AND f.deleted_at IS NULL
+         ORDER BY f.modified DESC",
+    )?;
+    let files = stmt
+        .query_map(params![id], library_file_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(files)
+}
+
+/// Result of a database integrity check
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(tag = "status", content = "problems")]
+enum IntegrityReport {
+    Ok,
+    Problems(Vec<String>),
+}
+
+/// Check library.db for corruption.
+/// Runs SQLite's integrity and foreign key checks, and checks the full-text
+/// index still matches the files table. If only the index is broken,
+/// db_rebuild_index can repair it.
+#[tauri::command]
+#[tracing::instrument(skip(state), err)]
+fn db_check_integrity(state: State<DbState>) -> Result<IntegrityReport, CommandError> {
+    state.with_read_conn(|conn| check_integrity(conn).map_err(CommandError::from))
+}
+
+fn check_integrity(conn: &Connection) -> Result<IntegrityReport, rusqlite::Error> {
+    let mut problems = Vec::new();
+
+    // integrity_check returns a single "ok" row when all is well
+    let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+    let rows = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    problems.extend(rows.into_iter().filter(|r| r != "ok"));
+
+    // foreign_key_check returns one row per violation: table, rowid, parent, fkid
+    let mut stmt = conn.prepare("PRAGMA foreign_key_check")?;
+    let violations = stmt
+        .query_map([], |row| {
+            Ok(format!(
+                "Foreign key violation in {} (rowid {:?}) referencing {}",
+                row.get::<_, String>(0)?,
+                row.get::<_, Option<i64>>(1)?,
+                row.get::<_, String>(2)?
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    problems.extend(violations);
+
+    // FTS5 reports a mismatch as an error; a non-zero rank also compares the
+    // index against its external content table (the files table)
+    if let Err(e) = conn.execute(
+        "INSERT INTO files_fts(files_fts, rank) VALUES('integrity-check', 1)",
+        [],
+    ) {
+        problems.push(format!("Search index: {}", e));
     }
 
-    #[cfg(target_os = "macos")]
-    {
-        info.insert("osVersion".to_string(), "macOS".to_string());
+    if problems.is_empty() {
+        Ok(IntegrityReport::Ok)
+    } else {
+        Ok(IntegrityReport::Problems(problems))
     }
+}
 
-    // Monitor info
-    if let Ok(Some(monitor)) = window.current_monitor() {
-        let size = monitor.size();
-        let scale = monitor.scale_factor();
-        info.insert("monitorWidth".to_string(), size.width.to_string());
-        info.insert("monitorHeight".to_string(), size.height.to_string());
-        info.insert("scaleFactor".to_string(), format!("{:.2}", scale));
+/// Close and reopen the database connection. A manual way out if the
+/// automatic recovery in DbState::with_conn hasn't helped.
+#[tauri::command]
+#[tracing::instrument(skip(state), err)]
+fn db_reconnect(state: State<DbState>) -> Result<(), CommandError> {
+    tracing::info!("Reopening the database connection");
+    state.reconnect().map_err(CommandError::Db)
+}
+
+/// Drop and recreate the full-text search index and its triggers, then
+/// repopulate it from the files table.
+#[tauri::command]
+#[tracing::instrument(skip(state), err)]
+fn db_rebuild_index(state: State<DbState>) -> Result<(), CommandError> {
+    state.with_conn(|conn| rebuild_fts_index(conn).map_err(CommandError::from))
+}
+
+fn rebuild_fts_index(conn: &Connection) -> Result<(), rusqlite::Error> {
+    let tx = conn.unchecked_transaction()?;
+    recreate_fts_index(&tx)?;
+    tx.commit()
+}
+
+/// Repopulate the full-text index from the files table in place, keeping
+/// the table and triggers. Quicker than db_rebuild_index and needs no
+/// library scan; enough when only the index contents have drifted.
+#[tauri::command]
+#[tracing::instrument(skip(state), err)]
+fn db_reindex_fts(state: State<DbState>) -> Result<(), CommandError> {
+    state.with_conn(|conn| reindex_fts(conn).map_err(CommandError::from))
+}
+
+fn reindex_fts(conn: &Connection) -> Result<(), rusqlite::Error> {
+    conn.execute("INSERT INTO files_fts(files_fts) VALUES('rebuild')", [])?;
+    Ok(())
+}
+
+/// Merge the full-text index's b-tree segments into one, so searches read
+/// fewer pages. Worth running after large imports or rebuilds.
+#[tauri::command]
+#[tracing::instrument(skip(state), err)]
+fn db_optimize_fts(state: State<DbState>) -> Result<(), CommandError> {
+    state.with_conn(|conn| optimize_fts(conn).map_err(CommandError::from))
+}
+
+fn optimize_fts(conn: &Connection) -> Result<(), rusqlite::Error> {
+    conn.execute("INSERT INTO files_fts(files_fts) VALUES('optimize')", [])?;
+    Ok(())
+}
+
+/// Where library.db lives and how SQLite is using it, for the settings screen
+#[derive(Serialize)]
+struct DatabaseHealth {
+    path: String,
+    /// SQLite journal mode in use, e.g. "wal" or "delete"
+    journal_mode: String,
+    /// File system type of the database folder, when it could be determined
+    filesystem: Option<String>,
+    /// Problems worth showing the user
+    warnings: Vec<String>,
+}
+
+/// Report library.db's location, journal mode and file system, with
+/// warnings for setups known to cause locking problems
+#[tauri::command]
+#[tracing::instrument(skip(state), err)]
+fn database_health(state: State<DbState>) -> Result<DatabaseHealth, CommandError> {
+    state.with_read_conn(|conn| check_database_health(conn).map_err(CommandError::from))
+}
+
+fn check_database_health(conn: &Connection) -> Result<DatabaseHealth, rusqlite::Error> {
+    let journal_mode: String = conn.query_row("PRAGMA journal_mode", [], |row| row.get(0))?;
+    let filesystem = database_filesystem(conn);
+
+    let mut warnings = Vec::new();
+    if let Some(fs) = filesystem.as_deref().filter(|fs| is_network_filesystem(fs)) {
+        warnings.push(format!(
+            "The library database is on a network file system ({}). It is using the slower rollback journal, and locking may still be unreliable; a local folder is recommended.",
+            fs
+        ));
+        if journal_mode.eq_ignore_ascii_case("wal") {
+            warnings.push("Write-ahead logging is enabled on a network file system".to_string());
+        }
     }
 
-    // Config paths
-    if let Ok(config_dir) = get_user_config_dir() {
-        info.insert("configPath".to_string(), config_dir.to_string_lossy().to_string());
+    Ok(DatabaseHealth {
+        path: conn.path().unwrap_or_default().to_string(),
+        journal_mode,
+        filesystem,
+        warnings,
+    })
+}
+
+/// Library health figures for the settings screen
+#[derive(Serialize)]
+struct DbStats {
+    /// Rows in the files table
+    total_files: i64,
+    /// Files fully indexed from disk by a library rebuild (have a content hash)
+    indexed_files: i64,
+    /// Size of library.db in bytes
+    db_size_bytes: i64,
+    /// Documents present in the full-text search index
+    fts_row_count: i64,
+}
+
+/// Size of the main database file in bytes, from SQLite's page count
+fn database_size(conn: &Connection) -> Result<i64, rusqlite::Error> {
+    conn.query_row(
+        "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+        [],
+        |row| row.get(0),
+    )
+}
+
+/// Compact library.db, returning the number of bytes reclaimed.
+/// Checkpoints any WAL first so its pages are included in the rewrite.
+#[tauri::command]
+#[tracing::instrument(skip(state), err)]
+fn db_vacuum(state: State<DbState>) -> Result<i64, CommandError> {
+    state.with_conn(|conn| vacuum_database(conn)).map_err(CommandError::Db)
+}
+
+fn vacuum_database(conn: &Connection) -> Result<i64, String> {
+    // VACUUM fails inside a transaction; refuse clearly rather than with
+    // SQLite's generic error
+    if !conn.is_autocommit() {
+        return Err("Cannot vacuum while a transaction is open".to_string());
     }
 
-    Ok(info)
+    // Returns (busy, log frames, checkpointed frames); works in any journal mode
+    conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))
+        .map_err(|e| e.to_string())?;
+
+    let before = database_size(conn).map_err(|e| e.to_string())?;
+    conn.execute_batch("VACUUM").map_err(|e| e.to_string())?;
+    let after = database_size(conn).map_err(|e| e.to_string())?;
+
+    tracing::info!(before, after, "vacuumed library database");
+    Ok((before - after).max(0))
 }
 
-/// Return the file path passed as a CLI argument, if any.
-/// Called by frontend after init to check if app was launched with a file.
+/// Get library health statistics
 #[tauri::command]
-fn get_cli_file_arg() -> Option<String> {
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() > 1 {
-        let file_path = if args[1].starts_with("~") {
-            if let Some(home) = dirs::home_dir() {
-                args[1].replacen("~", &home.to_string_lossy().as_ref(), 1)
-            } else {
-                args[1].clone()
-            }
-        } else {
-            args[1].clone()
-        };
-        if std::path::Path::new(&file_path).exists() {
-            return Some(file_path);
-        }
+#[tracing::instrument(skip(state), err)]
+fn db_stats(state: State<DbState>) -> Result<DbStats, CommandError> {
+    state.with_read_conn(|conn| database_stats(conn).map_err(CommandError::from))
+}
+
+fn database_stats(conn: &Connection) -> Result<DbStats, rusqlite::Error> {
+    let (total_files, indexed_files): (i64, i64) = conn.query_row(
+        "SELECT COUNT(*), COUNT(content_hash) FROM files",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+
+    // files_fts_docsize holds one row per document in the FTS index
+    let fts_row_count = conn.query_row("SELECT COUNT(*) FROM files_fts_docsize", [], |row| row.get(0))?;
+
+    Ok(DbStats {
+        total_files,
+        indexed_files,
+        db_size_bytes: database_size(conn)?,
+        fts_row_count,
+    })
+}
+
+/// Output format for export_library
+#[derive(Deserialize, Debug, Clone, Copy)]
+enum ExportFormat {
+    Csv,
+    Json,
+}
+
+/// Columns written by export_library, in order
+const EXPORT_COLUMNS: [&str; 9] = [
+    "id",
+    "path",
+    "filename",
+    "title",
+    "summary",
+    "keywords",
+    "modified",
+    "last_opened",
+    "snapshot_count",
+];
+
+/// Quote a CSV field if it contains a delimiter, quote or line break
+/// (RFC 4180: wrap in quotes and double any embedded quotes)
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
     }
-    None
 }
 
-/// Clamp window size to 90% of current monitor dimensions.
-/// Called from JS after page load, when window-state plugin has already restored saved size.
+/// Export the library index to a CSV or JSON file for analysis elsewhere.
+/// Rows are streamed straight from the database to the file, which is
+/// written atomically. Returns the number of records written.
 #[tauri::command]
-fn clamp_window_size(window: tauri::Window) -> Result<(), String> {
-    if let Ok(Some(monitor)) = window.current_monitor() {
-        let screen = monitor.size();
-        let scale = monitor.scale_factor();
-        let max_w = (screen.width as f64 / scale * 0.9) as u32;
-        let max_h = (screen.height as f64 / scale * 0.9) as u32;
+#[tracing::instrument(skip(app_handle, state), err)]
+fn export_library(
+    app_handle: tauri::AppHandle,
+    state: State<DbState>,
+    format: ExportFormat,
+    out_path: String,
+) -> Result<usize, CommandError> {
+    ensure_write_allowed(&app_handle, Path::new(&out_path))?;
+    state.with_conn(|conn| export_library_to(conn, format, Path::new(&out_path)))
+}
 
-        if let Ok(size) = window.outer_size() {
-            let cur_w = (size.width as f64 / scale) as u32;
-            let cur_h = (size.height as f64 / scale) as u32;
-            if cur_w > max_w || cur_h > max_h {
-                let new_w = cur_w.min(max_w);
-                let new_h = cur_h.min(max_h);
-                let _ = window.set_size(tauri::Size::Logical(tauri::LogicalSize::new(new_w as f64, new_h as f64)));
+fn export_library_to(conn: &Connection, format: ExportFormat, out_path: &Path) -> Result<usize, CommandError> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM files WHERE deleted_at IS NULL ORDER BY id",
+        EXPORT_COLUMNS.join(", ")
+    ))?;
+
+    let mut count = 0;
+    // Kept aside so a database failure isn't reported as a write failure
+    let mut db_error = None;
+
+    let written = write_atomic(out_path, |out| {
+        let mut from_db = |e: rusqlite::Error| {
+            let io = std::io::Error::other(e.to_string());
+            db_error = Some(e);
+            io
+        };
+        let mut rows = stmt.query([]).map_err(&mut from_db)?;
+
+        match format {
+            ExportFormat::Csv => writeln!(out, "{}", EXPORT_COLUMNS.join(","))?,
+            ExportFormat::Json => write!(out, "[")?,
+        }
+
+        while let Some(row) = rows.next().map_err(&mut from_db)? {
+            // Every column as JSON, so NULLs and numbers keep their type
+            let values = (0..EXPORT_COLUMNS.len())
+                .map(|i| {
+                    Ok(match row.get_ref(i)? {
+                        rusqlite::types::ValueRef::Null => serde_json::Value::Null,
+                        rusqlite::types::ValueRef::Integer(n) => n.into(),
+                        rusqlite::types::ValueRef::Real(f) => f.into(),
+                        rusqlite::types::ValueRef::Text(t) | rusqlite::types::ValueRef::Blob(t) => {
+                            String::from_utf8_lossy(t).into_owned().into()
+                        }
+                    })
+                })
+                .collect::<Result<Vec<serde_json::Value>, rusqlite::Error>>()
+                .map_err(&mut from_db)?;
+
+            match format {
+                ExportFormat::Csv => {
+                    let fields: Vec<String> = values
+                        .iter()
+                        .map(|v| match v {
+                            serde_json::Value::Null => String::new(),
+                            serde_json::Value::String(s) => csv_field(s),
+                            other => other.to_string(),
+                        })
+                        .collect();
+                    writeln!(out, "{}", fields.join(","))?;
+                }
+                ExportFormat::Json => {
+                    let record: serde_json::Map<String, serde_json::Value> = EXPORT_COLUMNS
+                        .iter()
+                        .map(|c| c.to_string())
+                        .zip(values)
+                        .collect();
+                    if count > 0 {
+                        write!(out, ",")?;
+                    }
+                    write!(out, "\n  ")?;
+                    serde_json::to_writer(&mut *out, &record)?;
+                }
             }
+
+            count += 1;
         }
+
+        if let ExportFormat::Json = format {
+            writeln!(out, "{}]", if count > 0 { "\n" } else { "" })?;
+        }
+
+        Ok(())
+    });
+
+    match (written, db_error) {
+        (Err(_), Some(e)) => Err(CommandError::Db(format!("Failed to read library: {}", e))),
+        (written, _) => written.map(|_| count),
     }
-    Ok(())
 }
 
-/// Finalize and close a ZIP archive.
-/// Must be called after all files are added to produce a valid ZIP.
+/// Copy the whole library database to `out_path`, e.g. to move it to
+/// another machine. Uses SQLite's online backup, so it's consistent even
+/// while the app keeps using the database.
 #[tauri::command]
-fn zip_finalize(state: State<ZipState>, zip_id: String) -> Result<(), String> {
-    let mut map = state.0.lock().map_err(|e| e.to_string())?;
-    let writer_mutex = map.remove(&zip_id)
-        .ok_or_else(|| format!("ZIP archive not found: {}", zip_id))?;
+#[tracing::instrument(skip(app_handle, state), err)]
+fn export_database(app_handle: tauri::AppHandle, state: State<DbState>, out_path: String) -> Result<(), CommandError> {
+    ensure_write_allowed(&app_handle, Path::new(&out_path))?;
+    state.with_conn(|conn| backup_database(conn, Path::new(&out_path)))
+}
+
+/// Back up to a temporary file next to `out_path` and rename it into place,
+/// so a failed export never leaves a partial database behind
+fn backup_database(conn: &Connection, out_path: &Path) -> Result<(), CommandError> {
+    if let Some(parent) = out_path.parent() {
+        fs::create_dir_all(parent).map_err(|e| CommandError::io("Failed to create directories", e))?;
+    }
+
+    let tmp_path = path_with_suffix(out_path, ".tmp");
+    let _ = fs::remove_file(&tmp_path);
 
-    let writer = writer_mutex.into_inner().map_err(|e| e.to_string())?;
+    conn.backup(rusqlite::DatabaseName::Main, &tmp_path, None)
+        .map_err(|e| CommandError::Db(format!("Failed to export database: {}", e)))
+        .and_then(|_| fs::rename(&tmp_path, out_path).map_err(|e| CommandError::io("Failed to export database", e)))
+        .inspect_err(|_| {
+            let _ = fs::remove_file(&tmp_path);
+        })
+}
 
-    writer.finish().map_err(|e| format!("Failed to finalize ZIP: {}", e))?;
+/// Copy everything in the write-ahead log into the database file and
+/// truncate the log, so the database is complete on its own after exit
+fn checkpoint_wal(conn: &Connection) -> Result<(), String> {
+    // Returns (busy, log frames, checkpointed frames); busy means another
+    // connection kept the checkpoint from finishing
+    let busy: i64 = conn
+        .query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |row| row.get(0))
+        .map_err(|e| format!("Failed to checkpoint database: {}", e))?;
 
+    if busy != 0 {
+        return Err("Failed to checkpoint database: database is busy".to_string());
+    }
     Ok(())
 }
 
-// ============================================================================
-// Application Entry Point
-// ============================================================================
-//
-// main() sets up the Tauri application:
-// 1. Initializes the SQLite database
-// 2. Registers plugins (dialogs, clipboard, window state)
-// 3. Sets up the system tray with menu
-// 4. Registers all command handlers
-// 5. Starts the application event loop
-//
-// ============================================================================
+/// Result of a checkpoint, from `PRAGMA wal_checkpoint`
+#[derive(Serialize, Debug, PartialEq)]
+struct WalCheckpoint {
+    /// Whether another connection kept the checkpoint from finishing
+    busy: bool,
+    /// Frames in the write-ahead log
+    log_frames: i64,
+    /// Frames copied into the database file
+    checkpointed_frames: i64,
+}
 
-fn main() {
-    // Initialize the SQLite database for the file library
-    let db = init_database().expect("Failed to initialize database");
+/// Copy as much of the write-ahead log into the database file as can be
+/// done without waiting for other connections. Never blocks readers or
+/// writers, so it's safe to run while the app is in use.
+fn checkpoint_wal_passive(conn: &Connection) -> Result<WalCheckpoint, rusqlite::Error> {
+    // Log frames are -1 when the database isn't in WAL mode
+    conn.query_row("PRAGMA wal_checkpoint(PASSIVE)", [], |row| {
+        Ok(WalCheckpoint {
+            busy: row.get::<_, i64>(0)? != 0,
+            log_frames: row.get::<_, i64>(1)?.max(0),
+            checkpointed_frames: row.get::<_, i64>(2)?.max(0),
+        })
+    })
+}
 
-    tauri::Builder::default()
-        // Make the database connection available to all commands via State<DbState>
-        .manage(DbState(Mutex::new(db)))
-        .manage(ZipState(Mutex::new(HashMap::new())))
-        .plugin(tauri_plugin_shell::init())
-        .plugin(tauri_plugin_dialog::init())
-        .plugin(tauri_plugin_fs::init())
-        .plugin(tauri_plugin_clipboard_manager::init())
-        .plugin(tauri_plugin_window_state::Builder::new().build())
-        .plugin(tauri_plugin_single_instance::init(|app, args, _cwd| {
-            // Second instance launched - show existing window and pass file arg
-            if let Some(window) = app.get_webview_window("main") {
-                let _ = window.show();
-                let _ = window.unminimize();
-                let _ = window.set_focus();
-            }
+/// Checkpoint the write-ahead log now instead of waiting for the periodic
+/// checkpoint (see WalCheckpointer)
+#[tauri::command]
+#[tracing::instrument(skip(state), err)]
+fn force_checkpoint(state: State<DbState>) -> Result<WalCheckpoint, CommandError> {
+    state.with_conn(|conn| checkpoint_wal_passive(conn).map_err(CommandError::from))
+}
 
-            // If second instance was launched with a file argument, emit it
-            if args.len() > 1 {
-                let file_path = if args[1].starts_with("~") {
-                    if let Some(home) = dirs::home_dir() {
-                        args[1].replacen("~", &home.to_string_lossy().as_ref(), 1)
-                    } else {
-                        args[1].clone()
-                    }
-                } else {
-                    args[1].clone()
-                };
-                if std::path::Path::new(&file_path).exists() {
-                    let _ = app.emit("open-file", file_path);
+/// Checkpoints the write-ahead log every `library.checkpointIntervalSeconds`
+/// on a background thread, so a long session doesn't leave a large WAL
+/// behind. Dropping the sender (see stop) ends the thread.
+struct WalCheckpointer(Mutex<Option<std::sync::mpsc::Sender<()>>>);
+
+impl WalCheckpointer {
+    /// Start checkpointing every `interval`; a zero interval disables it
+    fn start(app_handle: tauri::AppHandle, interval: std::time::Duration) -> Self {
+        use std::sync::mpsc::RecvTimeoutError;
+
+        if interval.is_zero() {
+            return WalCheckpointer(Mutex::new(None));
+        }
+
+        let (tx, rx) = std::sync::mpsc::channel::<()>();
+        std::thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = rx.recv_timeout(interval) {
+                let result = app_handle
+                    .state::<DbState>()
+                    .with_conn(|conn| checkpoint_wal_passive(conn).map_err(CommandError::from));
+                match result {
+                    Ok(checkpoint) => tracing::debug!(?checkpoint, "periodic WAL checkpoint"),
+                    Err(e) => tracing::warn!("Periodic checkpoint failed: {}", e),
                 }
             }
-        }))
-        .setup(|app| {
-            // Set window icon
-            if let Some(window) = app.get_webview_window("main") {
-                let window_icon = Image::from_bytes(include_bytes!("../icons/128x128.png"))
-                    .expect("Failed to load window icon");
-                let _ = window.set_icon(window_icon);
-            }
+        });
+        WalCheckpointer(Mutex::new(Some(tx)))
+    }
 
-            // Create tray menu
-            let show_item = MenuItem::with_id(app, "show", "Show SSCE", true, None::<&str>)?;
-            let quit_item = MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?;
-            let menu = Menu::with_items(app, &[&show_item, &quit_item])?;
-
-            // Load tray icon
-            let icon = Image::from_path("icons/tray-icon.png")
-                .unwrap_or_else(|_| Image::from_bytes(include_bytes!("../icons/tray-icon.png")).expect("Failed to load embedded tray icon"));
-
-            // Build the system tray
-            let _tray = TrayIconBuilder::new()
-                .icon(icon)
-                .menu(&menu)
-                .tooltip("SSCE Desktop")
-                .on_menu_event(|app, event| {
-                    match event.id.as_ref() {
-                        "show" => {
-                            if let Some(window) = app.get_webview_window("main") {
-                                let _ = window.show();
-                                let _ = window.set_focus();
-                            }
-                        }
-                        "quit" => {
-                            app.exit(0);
-                        }
-                        _ => {}
-                    }
-                })
-                .on_tray_icon_event(|tray, event| {
-                    if let TrayIconEvent::Click {
-                        button: MouseButton::Left,
-                        button_state: MouseButtonState::Up,
-                        ..
-                    } = event
-                    {
-                        let app = tray.app_handle();
-                        if let Some(window) = app.get_webview_window("main") {
-                            let _ = window.show();
-                            let _ = window.set_focus();
-                        }
-                    }
-                })
-                .build(app)?;
+    /// Stop the background thread (it finishes any checkpoint in progress)
+    fn stop(&self) {
+        if let Ok(mut sender) = self.0.lock() {
+            sender.take();
+        }
+    }
+}
 
-            Ok(())
-        })
-        .on_window_event(|window, event| {
-            // Intercept close event and minimize to tray instead
-            if let tauri::WindowEvent::CloseRequested { api, .. } = event {
-                // Save window state before hiding
-                let _ = window.app_handle().save_window_state(StateFlags::all());
-                let _ = window.hide();
-                api.prevent_close();
-            }
-        })
-        .invoke_handler(tauri::generate_handler![
-            browse_directory,
-            load_image,
-            save_image,
-            load_ssce,
-            save_ssce,
-            save_text_file,
-            get_ssce_thumbnail,
-            get_ssce_metadata,
-            file_exists,
-            save_autosave,
-            delete_autosave,
+/// Replace the library database with one exported by export_database. The
+/// file must pass an integrity check and be from this version of the app
+/// or an older one (older databases are migrated on open).
+#[tauri::command]
+#[tracing::instrument(skip(state), err)]
+fn import_database(state: State<DbState>, in_path: String) -> Result<(), CommandError> {
+    let mut conn = state.lock_for_replacing();
+    import_database_file(&mut conn, Path::new(&in_path), &database_path())
+}
+
+/// Validate `in_path`, copy it next to `db_path`, then close `conn`, swap
+/// the copy in with a rename and reopen. Until the rename the current
+/// database is untouched.
+fn import_database_file(conn: &mut Connection, in_path: &Path, db_path: &Path) -> Result<(), CommandError> {
+    let incoming = validate_database_file(in_path)?;
+
+    let tmp_path = path_with_suffix(db_path, ".import");
+    let _ = fs::remove_file(&tmp_path);
+    incoming
+        .backup(rusqlite::DatabaseName::Main, &tmp_path, None)
+        .map_err(|e| {
+            let _ = fs::remove_file(&tmp_path);
+            CommandError::Db(format!("Failed to copy database: {}", e))
+        })?;
+    drop(incoming);
+
+    // Closing the live connection checkpoints its WAL; the placeholder keeps
+    // `conn` valid while the file is swapped
+    *conn = Connection::open_in_memory()?;
+    for suffix in ["-wal", "-shm"] {
+        let _ = fs::remove_file(path_with_suffix(db_path, suffix));
+    }
+
+    let swapped = fs::rename(&tmp_path, db_path).map_err(|e| CommandError::io("Failed to replace database", e));
+    if swapped.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+
+    // Reopen either way, so a failed swap leaves the old database in use
+    *conn = open_database(db_path).map_err(|e| CommandError::Db(format!("Failed to reopen database: {}", e)))?;
+    swapped?;
+
+    tracing::info!(path = %in_path.display(), "library database imported");
+    Ok(())
+}
+
+/// Outcome of merge_database, counted in rows of the other database
+#[derive(Serialize, Debug, Default, PartialEq)]
+struct MergeSummary {
+    /// Paths this library didn't have
+    added: usize,
+    /// Paths the other database has a newer copy of
+    updated: usize,
+    /// Paths where this library's copy is as new or newer
+    skipped: usize,
+}
+
+/// Combine another library database (e.g. from a second machine) into this
+/// one. Documents only the other has are added; for a path both have, the
+/// copy with the later `modified` wins. Both must be the same schema
+/// version. The search index is rebuilt afterwards.
+#[tauri::command]
+#[tracing::instrument(skip(state), err)]
+fn merge_database(state: State<DbState>, other_db_path: String) -> Result<MergeSummary, CommandError> {
+    state.with_conn(|conn| merge_database_file(conn, Path::new(&other_db_path)))
+}
+
+fn merge_database_file(conn: &Connection, other_path: &Path) -> Result<MergeSummary, CommandError> {
+    let other = validate_database_file(other_path)?;
+    let version: i64 = other.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    drop(other);
+    if version != MIGRATIONS.len() as i64 {
+        return Err(CommandError::UnsupportedVersion(format!(
+            "The database is schema {}, this version needs {}; open it in this version of SSCE Desktop first",
+            version,
+            MIGRATIONS.len()
+        )));
+    }
+
+    // ATTACH can't run inside a transaction, so it wraps the one doing the merge
+    conn.execute("ATTACH DATABASE ?1 AS merge_source", params![other_path.to_string_lossy()])?;
+    let merged = merge_attached_files(conn);
+    if let Err(e) = conn.execute("DETACH DATABASE merge_source", []) {
+        tracing::warn!("Failed to detach merged database: {}", e);
+    }
+    let summary = merged?;
+
+    tracing::info!(path = %other_path.display(), ?summary, "library database merged");
+    Ok(summary)
+}
+
+/// Merge the files table of the database attached as merge_source. Rows
+/// keep their id here, so collection memberships survive an update.
+fn merge_attached_files(conn: &Connection) -> Result<MergeSummary, CommandError> {
+    let columns: Vec<String> = conn
+        .prepare("SELECT name FROM pragma_table_info('files') WHERE name != 'id'")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    let list = columns.join(", ");
+    let from_source = columns.iter().map(|c| format!("o.{}", c)).collect::<Vec<_>>().join(", ");
+    let newer = "o.modified > IFNULL(main.files.modified, '')";
+
+    let tx = conn.unchecked_transaction()?;
+    let count = |sql: &str| tx.query_row(sql, [], |row| row.get::<_, i64>(0)).map(|n| n as usize);
+    let total = count("SELECT COUNT(*) FROM merge_source.files")?;
+    let added = count(
+        "SELECT COUNT(*) FROM merge_source.files o
+         WHERE NOT EXISTS (SELECT 1 FROM main.files WHERE main.files.path = o.path)",
+    )?;
+
+    let updated = tx.execute(
+        &format!(
+            "UPDATE main.files
+             SET ({list}) = (SELECT {from_source} FROM merge_source.files o WHERE o.path = main.files.path)
+             WHERE EXISTS (SELECT 1 FROM merge_source.files o WHERE o.path = main.files.path AND {newer})"
+        ),
+        [],
+    )?;
+    tx.execute(
+        &format!(
+            "INSERT INTO main.files ({list})
+             SELECT {from_source} FROM merge_source.files o
+             WHERE NOT EXISTS (SELECT 1 FROM main.files WHERE main.files.path = o.path)"
+        ),
+        [],
+    )?;
+
+    recreate_fts_index(&tx)?;
+    tx.commit()?;
+
+    Ok(MergeSummary {
+        added,
+        updated,
+        skipped: total - added - updated,
+    })
+}
+
+/// Open a database file read-only and check it's a usable library:
+/// NotFound if there's no such file, Validation if it isn't a library
+/// database and UnsupportedVersion if it's from a newer version of the app
+fn validate_database_file(path: &Path) -> Result<Connection, CommandError> {
+    let invalid = |e: &dyn std::fmt::Display| CommandError::Validation(format!("Not a valid library database: {}", e));
+
+    if !path.is_file() {
+        return Err(CommandError::NotFound(format!("Database not found: {}", path.display())));
+    }
+    let conn = Connection::open_with_flags(path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY).map_err(|e| invalid(&e))?;
+
+    let integrity: String = conn
+        .query_row("PRAGMA integrity_check", [], |row| row.get(0))
+        .map_err(|e| invalid(&e))?;
+    if integrity != "ok" {
+        return Err(invalid(&integrity));
+    }
+
+    let has_files_table: bool = conn
+        .query_row("SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'files')", [], |row| {
+            row.get(0)
+        })
+        .map_err(|e| invalid(&e))?;
+    if !has_files_table {
+        return Err(invalid(&"no files table"));
+    }
+
+    let version: i64 = conn
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .map_err(|e| invalid(&e))?;
+    if version > MIGRATIONS.len() as i64 {
+        return Err(CommandError::UnsupportedVersion(format!(
+            "The database is from a newer version of SSCE Desktop (schema {}, this version supports {})",
+            version,
+            MIGRATIONS.len()
+        )));
+    }
+
+    Ok(conn)
+}
+
+/// A library entry parsed from a .ssce file during a rebuild
+struct ScannedFile {
+    file: LibraryFile,
+    content_hash: String,
+    /// Document text for the full-text index (see extract_index_content)
+    content: String,
+}
+
+/// Default cap on the text indexed per document, in bytes
+const DEFAULT_MAX_INDEXED_CONTENT_BYTES: usize = 64 * 1024;
+
+/// Settings for a library rebuild, read from the `library` section of
+/// defaults.json
+struct RebuildOptions {
+    /// Indexed document text is truncated to this many bytes
+    max_content_bytes: usize,
+    /// Gitignore-style patterns skipped in every library, on top of the
+    /// library's own .ssceignore
+    ignore_patterns: Vec<String>,
+    /// File extensions (without the leading dot) that are indexed
+    extensions: Vec<String>,
+    /// Whether to enter symlinked folders
+    follow_symlinks: bool,
+    /// How long a volume or file check may take before the file is treated
+    /// as unknown rather than missing
+    exists_timeout: std::time::Duration,
+    /// Folder levels below the library a rebuild may enter
+    max_scan_depth: usize,
+    /// Files (of any kind) a rebuild may look at
+    max_scan_files: usize,
+}
+
+/// Default for `library.maxScanDepth`
+const DEFAULT_MAX_SCAN_DEPTH: usize = 32;
+/// Default for `library.maxScanFiles`
+const DEFAULT_MAX_SCAN_FILES: usize = 200_000;
+
+impl Default for RebuildOptions {
+    fn default() -> Self {
+        RebuildOptions::from_config(&Defaults::default())
+    }
+}
+
+impl RebuildOptions {
+    fn from_config(config: &Defaults) -> Self {
+        let library = &config.library;
+        RebuildOptions {
+            max_content_bytes: library.max_indexed_content_bytes as usize,
+            ignore_patterns: library.ignore_patterns.clone(),
+            extensions: indexed_extensions(config),
+            follow_symlinks: library.follow_symlinks,
+            exists_timeout: std::time::Duration::from_millis(library.exists_timeout_ms),
+            max_scan_depth: library.max_scan_depth as usize,
+            max_scan_files: library.max_scan_files as usize,
+        }
+    }
+}
+
+/// Collect the searchable text of a document: snapshot titles and summaries,
+/// and the text of text layers. Truncated to at most `max_bytes` (on a
+/// character boundary) so huge documents don't bloat the index.
+fn extract_index_content(json: &serde_json::Value, max_bytes: usize) -> String {
+    let mut parts: Vec<&str> = Vec::new();
+
+    for snapshot in json["snapshots"].as_array().into_iter().flatten() {
+        let front_matter = &snapshot["frontMatter"];
+        parts.extend(front_matter["title"].as_str());
+        parts.extend(front_matter["summary"].as_str());
+    }
+
+    for layer in json["layers"].as_array().into_iter().flatten() {
+        if layer["type"] == "text" {
+            parts.extend(layer["data"]["text"].as_str());
+        }
+    }
+
+    let mut content = parts
+        .into_iter()
+        .filter(|p| !p.trim().is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    if content.len() > max_bytes {
+        let mut end = max_bytes;
+        while !content.is_char_boundary(end) {
+            end -= 1;
+        }
+        content.truncate(end);
+    }
+
+    content
+}
+
+/// Extensions indexed when defaults.json doesn't list any
+const DEFAULT_INDEXED_EXTENSIONS: &[&str] = &["ssce"];
+
+fn default_indexed_extensions() -> Vec<String> {
+    DEFAULT_INDEXED_EXTENSIONS.iter().map(|e| e.to_string()).collect()
+}
+
+/// The `indexedExtensions` list from defaults.json, lowercased and without
+/// leading dots. Compound extensions like "ssce.gz" are allowed.
+fn indexed_extensions(config: &Defaults) -> Vec<String> {
+    let extensions: Vec<String> = config
+        .indexed_extensions
+        .iter()
+        .map(|e| e.trim().trim_start_matches('.').to_lowercase())
+        .filter(|e| !e.is_empty())
+        .collect();
+
+    if extensions.is_empty() {
+        default_indexed_extensions()
+    } else {
+        extensions
+    }
+}
+
+/// Whether a file name ends in one of `extensions` (case-insensitive)
+fn has_indexed_extension(name: &str, extensions: &[String]) -> bool {
+    let name = name.to_lowercase();
+    extensions.iter().any(|ext| {
+        name.strip_suffix(ext.as_str())
+            .and_then(|stem| stem.strip_suffix('.'))
+            .is_some_and(|base| !base.is_empty())
+    })
+}
+
+/// First bytes of every gzip stream
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Whether the file starts with the gzip header
+fn is_gzipped(path: &Path) -> bool {
+    use std::io::Read;
+
+    let mut prefix = [0u8; GZIP_MAGIC.len()];
+    fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut prefix))
+        .map(|_| prefix == GZIP_MAGIC)
+        .unwrap_or(false)
+}
+
+fn gzip(data: &[u8]) -> Result<Vec<u8>, String> {
+    use std::io::Write;
+
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder
+        .write_all(data)
+        .and_then(|_| encoder.finish())
+        .map_err(|e| format!("Failed to compress data: {}", e))
+}
+
+/// Read a document as text, decompressing it first if it's gzipped
+/// (saved with compression, or a .ssce.gz file). Detection is by content,
+/// not extension.
+fn read_document_text(path: &Path) -> Result<String, CommandError> {
+    document_text(fs::read(path).map_err(|e| CommandError::io("Failed to read file", e))?)
+}
+
+/// A document's text from its file content, decompressing it if gzipped
+fn document_text(bytes: Vec<u8>) -> Result<String, CommandError> {
+    if bytes.starts_with(&GZIP_MAGIC) {
+        let mut content = String::new();
+        std::io::Read::read_to_string(&mut flate2::read::GzDecoder::new(bytes.as_slice()), &mut content).map_err(|e| {
+            CommandError::Parse(format!(
+                "File is compressed but could not be decompressed - it may be truncated or damaged: {}",
+                e
+            ))
+        })?;
+        return Ok(content);
+    }
+
+    String::from_utf8(bytes).map_err(|e| CommandError::Parse(format!("Failed to read file: {}", e)))
+}
+
+/// Name of the per-library ignore file, read from the library root
+const IGNORE_FILE_NAME: &str = ".ssceignore";
+
+/// Build the matcher for files a rebuild should skip: the global patterns
+/// from defaults.json plus the library's .ssceignore, if it has one.
+/// Patterns use gitignore syntax, relative to the library root.
+fn library_ignore(library_path: &Path, global_patterns: &[String]) -> Result<Gitignore, CommandError> {
+    let mut builder = GitignoreBuilder::new(library_path);
+
+    for pattern in global_patterns {
+        builder
+            .add_line(None, pattern)
+            .map_err(|e| CommandError::Validation(format!("Invalid ignore pattern '{}': {}", pattern, e)))?;
+    }
+
+    let ignore_file = library_path.join(IGNORE_FILE_NAME);
+    if ignore_file.is_file() {
+        if let Some(e) = builder.add(&ignore_file) {
+            return Err(CommandError::Validation(format!("Invalid {}: {}", IGNORE_FILE_NAME, e)));
+        }
+    }
+
+    builder.build().map_err(|e| CommandError::Validation(format!("Invalid ignore patterns: {}", e)))
+}
+
+/// Whether an indexed path falls under an ignore pattern. Paths outside the
+/// library are never ignored.
+fn is_ignored(ignore: &Gitignore, path: &Path, is_dir: bool) -> bool {
+    path.starts_with(ignore.path()) && ignore.matched_path_or_any_parents(path, is_dir).is_ignore()
+}
+
+/// Recursively find all files whose name passes `wanted` under a
+/// directory, skipping anything matched by `ignore` (an ignored folder isn't
+/// read at all).
+///
+/// Symlinked folders are only entered with `follow_symlinks`. Every folder
+/// is recorded in `visited` by its canonical path, so a link back up the
+/// tree is entered at most once. Returned paths are canonical, so a file
+/// reached through two links appears once.
+///
+/// Subdirectories are read in parallel on the rayon pool.
+/// Stops descending once `cancel` is set, returning what was found so far.
+fn find_files(
+    dir: &Path,
+    options: &RebuildOptions,
+    ignore: &Gitignore,
+    visited: &Mutex<std::collections::HashSet<std::path::PathBuf>>,
+    cancel: &AtomicBool,
+    wanted: &(dyn Fn(&str) -> bool + Sync),
+) -> Result<Vec<std::path::PathBuf>, String> {
+    let scan = FileScan {
+        options,
+        ignore,
+        visited,
+        cancel,
+        wanted,
+        limits: &ScanLimits::unlimited(),
+        unreadable: None,
+    };
+    scan.find(dir, 0)
+}
+
+/// A limit that stopped a library scan, named as in defaults.json
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(tag = "limit", content = "value", rename_all = "camelCase")]
+enum ScanLimit {
+    MaxScanDepth(usize),
+    MaxScanFiles(usize),
+}
+
+/// How deep and how wide a scan may go, and which limit stopped it. Shared
+/// by the parallel workers of one scan.
+struct ScanLimits {
+    max_depth: usize,
+    max_files: usize,
+    seen_files: std::sync::atomic::AtomicUsize,
+    exceeded: Mutex<Option<ScanLimit>>,
+}
+
+impl ScanLimits {
+    fn new(max_depth: usize, max_files: usize) -> Self {
+        ScanLimits {
+            max_depth,
+            max_files,
+            seen_files: std::sync::atomic::AtomicUsize::new(0),
+            exceeded: Mutex::new(None),
+        }
+    }
+
+    fn unlimited() -> Self {
+        ScanLimits::new(usize::MAX, usize::MAX)
+    }
+
+    /// The rebuild's limits from `library.maxScanDepth` and `maxScanFiles`
+    fn for_rebuild(options: &RebuildOptions) -> Self {
+        ScanLimits::new(options.max_scan_depth, options.max_scan_files)
+    }
+
+    fn exceeded(&self) -> Option<ScanLimit> {
+        self.exceeded.lock().ok().and_then(|exceeded| *exceeded)
+    }
+
+    /// Record the first limit hit; the scan stops once one is
+    fn hit(&self, limit: ScanLimit) {
+        if let Ok(mut exceeded) = self.exceeded.lock() {
+            exceeded.get_or_insert(limit);
+        }
+    }
+
+    /// Count a file seen by the scan; false (and the limit recorded) once
+    /// there are more than max_files
+    fn count_file(&self) -> bool {
+        if self.seen_files.fetch_add(1, Ordering::Relaxed) >= self.max_files {
+            self.hit(ScanLimit::MaxScanFiles(self.max_files));
+            return false;
+        }
+        true
+    }
+}
+
+/// Everything one find_files scan shares between folders
+struct FileScan<'a> {
+    options: &'a RebuildOptions,
+    ignore: &'a Gitignore,
+    visited: &'a Mutex<std::collections::HashSet<std::path::PathBuf>>,
+    cancel: &'a AtomicBool,
+    wanted: &'a (dyn Fn(&str) -> bool + Sync),
+    limits: &'a ScanLimits,
+    /// Where subfolders that can't be read are recorded and skipped; without
+    /// it one unreadable folder fails the whole scan
+    unreadable: Option<&'a Mutex<Vec<RebuildFileError>>>,
+}
+
+impl FileScan<'_> {
+    /// Files under `dir`, which is `depth` levels below where the scan began
+    fn find(&self, dir: &Path, depth: usize) -> Result<Vec<std::path::PathBuf>, String> {
+        use rayon::prelude::*;
+
+        if self.cancel.load(Ordering::Relaxed) || self.limits.exceeded().is_some() {
+            return Ok(Vec::new());
+        }
+
+        let mut files = Vec::new();
+        let mut subdirs = Vec::new();
+
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                self.skip_unreadable(dir, depth, e.to_string())?;
+                return Ok(Vec::new());
+            }
+        };
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    self.skip_unreadable(dir, depth, e.to_string())?;
+                    continue;
+                }
+            };
+            let path = entry.path();
+            let is_symlink = entry.file_type().map(|t| t.is_symlink()).unwrap_or(false);
+            let is_dir = path.is_dir();
+
+            let skipped_link = is_dir && is_symlink && !self.options.follow_symlinks;
+            if self.ignore.matched(&path, is_dir).is_ignore() || skipped_link {
+                continue;
+            }
+
+            if is_dir {
+                if depth >= self.limits.max_depth {
+                    self.limits.hit(ScanLimit::MaxScanDepth(self.limits.max_depth));
+                    return Ok(files);
+                }
+                // Broken links and unreadable folders are skipped
+                let Ok(canonical) = dunce::canonicalize(&path) else { continue };
+                if self.visited.lock().map_err(|e| e.to_string())?.insert(canonical.clone()) {
+                    subdirs.push(canonical);
+                }
+            } else {
+                if !self.limits.count_file() {
+                    return Ok(files);
+                }
+                if path.file_name().is_some_and(|n| (self.wanted)(&n.to_string_lossy())) {
+                    files.extend(dunce::canonicalize(&path).ok());
+                }
+            }
+        }
+
+        let nested = subdirs
+            .par_iter()
+            .map(|d| self.find(d, depth + 1))
+            .collect::<Result<Vec<_>, String>>()?;
+        files.extend(nested.into_iter().flatten());
+
+        Ok(files)
+    }
+
+    /// Record a subfolder that couldn't be read so the scan can go on
+    /// without it. Fails instead for the folder the scan began in, or when
+    /// there's nowhere to record it.
+    fn skip_unreadable(&self, dir: &Path, depth: usize, message: String) -> Result<(), String> {
+        match self.unreadable {
+            Some(unreadable) if depth > 0 => {
+                tracing::warn!(path = %dir.display(), "Skipping unreadable folder: {}", message);
+                unreadable.lock().map_err(|e| e.to_string())?.push(RebuildFileError {
+                    path: dir.to_string_lossy().to_string(),
+                    message,
+                });
+                Ok(())
+            }
+            _ => Err(message),
+        }
+    }
+}
+
+/// Every document in a (canonical) library folder, sorted and without
+/// duplicates. If the scan hits one of `limits`, what was found before it
+/// stopped (check `limits.exceeded()`). With `unreadable`, subfolders that
+/// can't be read are recorded there and skipped rather than failing the scan.
+fn find_library_documents(
+    library_path: &Path,
+    ignore: &Gitignore,
+    options: &RebuildOptions,
+    cancel: &AtomicBool,
+    limits: &ScanLimits,
+    unreadable: Option<&Mutex<Vec<RebuildFileError>>>,
+) -> Result<Vec<std::path::PathBuf>, String> {
+    let visited = Mutex::new(std::collections::HashSet::from([library_path.to_path_buf()]));
+    let is_document = |name: &str| has_indexed_extension(name, &options.extensions);
+    let scan = FileScan {
+        options,
+        ignore,
+        visited: &visited,
+        cancel,
+        wanted: &is_document,
+        limits,
+        unreadable,
+    };
+    let mut paths = scan.find(library_path, 0)?;
+    // The same file can be linked from two folders
+    paths.sort();
+    paths.dedup();
+    Ok(paths)
+}
+
+/// Words of a camelCase or PascalCase filename ("QuarterlyReport2024.ssce"
+/// gives "Quarterly Report 2024"), which the tokenizer can't split itself.
+/// None when there are no such boundaries.
+fn camel_case_words(filename: &str) -> Option<String> {
+    let stem = Path::new(filename).file_stem()?.to_string_lossy();
+    let mut words = String::new();
+    let mut prev: Option<char> = None;
+    for c in stem.chars() {
+        if let Some(p) = prev {
+            let boundary = (p.is_lowercase() && c.is_uppercase())
+                || (p.is_alphabetic() && c.is_numeric())
+                || (p.is_numeric() && c.is_alphabetic());
+            if boundary {
+                words.push(' ');
+            }
+        }
+        words.push(c);
+        prev = Some(c);
+    }
+    (words.len() != stem.len()).then_some(words)
+}
+
+/// Index content with the filename's camelCase words added, so a search
+/// for "report" finds QuarterlyReport.ssce
+fn with_filename_words(content: String, filename: &str) -> String {
+    match camel_case_words(filename) {
+        Some(words) if content.is_empty() => words,
+        Some(words) => format!("{}\n{}", content, words),
+        None => content,
+    }
+}
+
+/// Read and parse a .ssce file (possibly gzipped) into a library entry
+fn scan_ssce_file(path: &Path, options: &RebuildOptions) -> Result<ScannedFile, String> {
+    let content = read_document_text(path)?;
+    let json: serde_json::Value = serde_json::from_str(&content).map_err(|e| e.to_string())?;
+
+    let filename = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let thumbnail = json.get("thumbnail").and_then(|v| v.as_str()).map(String::from);
+    let keywords = json.get("keywords").and_then(|v| {
+        v.as_array().map(|arr| {
+            arr.iter()
+                .filter_map(|k| k.as_str())
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+    });
+
+    let front_matter = json.get("frontMatter");
+    let title = front_matter
+        .and_then(|fm| fm.get("title"))
+        .and_then(|v| v.as_str())
+        .map(String::from);
+    let summary = front_matter
+        .and_then(|fm| fm.get("summary"))
+        .and_then(|v| v.as_str())
+        .map(String::from);
+    let modified = front_matter
+        .and_then(|fm| fm.get("modified"))
+        .and_then(|v| v.as_str())
+        .map(normalize_timestamp);
+    let author = front_matter
+        .and_then(|fm| fm.get("author"))
+        .and_then(|v| v.as_str())
+        .map(str::trim)
+        .filter(|a| !a.is_empty())
+        .map(String::from);
+    let created = front_matter
+        .and_then(|fm| fm.get("created"))
+        .and_then(|v| v.as_str())
+        .map(normalize_timestamp);
+
+    let snapshot_count = json
+        .get("snapshots")
+        .and_then(|v| v.as_array())
+        .map(|arr| arr.len() as i32)
+        .unwrap_or(0);
+
+    Ok(ScannedFile {
+        content_hash: ssce_content_hash(&json),
+        content: with_filename_words(extract_index_content(&json, options.max_content_bytes), &filename),
+        file: LibraryFile {
+            id: None,
+            path: normalize_path(&path.to_string_lossy()),
+            filename,
+            thumbnail,
+            title,
+            summary,
+            keywords,
+            // Only set when the file is actually opened
+            last_opened: None,
+            modified,
+            snapshot_count,
+            size: file_size(path),
+            fuzzy: false,
+            encoded_path: encode_os_path(path.as_os_str()),
+            open_count: 0,
+            author,
+            created,
+        },
+    })
+}
+
+/// Insert or update a library row from a scanned file. Keeps the existing
+/// last_opened, and un-deletes the row if it was soft-deleted.
+const UPSERT_SCANNED_SQL: &str = "INSERT INTO files (path, filename, thumbnail, title, summary, keywords, modified, last_opened, snapshot_count, content_hash, size, content, encoded_path, author, created)
+     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)
+     ON CONFLICT(path) DO UPDATE SET
+         filename = excluded.filename,
+         thumbnail = excluded.thumbnail,
+         title = excluded.title,
+         summary = excluded.summary,
+         keywords = excluded.keywords,
+         modified = excluded.modified,
+         last_opened = COALESCE(files.last_opened, excluded.last_opened),
+         snapshot_count = excluded.snapshot_count,
+         content_hash = excluded.content_hash,
+         size = excluded.size,
+         content = excluded.content,
+         encoded_path = excluded.encoded_path,
+         author = excluded.author,
+         created = excluded.created,
+         deleted_at = NULL";
+
+/// Run a statement prepared from UPSERT_SCANNED_SQL for one file
+fn upsert_scanned(stmt: &mut rusqlite::Statement, scanned: &ScannedFile) -> Result<(), rusqlite::Error> {
+    let file = &scanned.file;
+    stmt.execute(params![
+        file.path,
+        nfc(&file.filename),
+        file.thumbnail,
+        file.title.as_deref().map(nfc),
+        file.summary.as_deref().map(nfc),
+        file.keywords.as_deref().map(normalize_keyword_list),
+        file.modified,
+        file.last_opened,
+        file.snapshot_count,
+        scanned.content_hash,
+        file.size,
+        nfc(&scanned.content),
+        file.encoded_path,
+        file.author.as_deref().map(nfc),
+        file.created,
+    ])?;
+    Ok(())
+}
+
+/// Scan the library folder and index all .ssce files found.
+/// Called via "Rebuild from Library" button in Recent Files dialog.
+/// Extracts metadata (thumbnail, title, keywords) from each file.
+/// Also removes stale entries for files that no longer exist.
+/// Can be stopped with cancel_rebuild. Files that can't be read or parsed
+/// are skipped; the result counts them and says why.
+/// Runs on a blocking thread and only locks the database to write each
+/// batch, so cancel_rebuild and searches are handled while it is in progress.
+/// Fails with AlreadyRunning while another rebuild is in progress; cancel
+/// that one first to start afresh.
+/// A folder deeper than library.maxScanDepth or with more files than
+/// library.maxScanFiles isn't indexed; the result's limit_exceeded says
+/// which limit was hit.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle, cancel, running, queue), err)]
+async fn db_rebuild_from_library(
+    app_handle: tauri::AppHandle,
+    cancel: State<'_, RebuildCancel>,
+    running: State<'_, RebuildRunning>,
+    queue: State<'_, IndexQueue>,
+    library_path: String,
+) -> Result<RebuildResult, CommandError> {
+    // Claimed before clearing cancel, so a turned-away call can't undo a
+    // cancel_rebuild aimed at the running one
+    let _running = running.start()?;
+    cancel.0.store(false, Ordering::Relaxed);
+    let _paused = queue.pause_while()?;
+    let options = RebuildOptions::from_config(&load_defaults_typed(&app_handle));
+    let app = app_handle.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        rebuild_library(&*app.state::<DbState>(), Path::new(&library_path), &app.state::<RebuildCancel>().0, &options)
+    })
+    .await
+    .map_err(|e| CommandError::Io(format!("Library rebuild failed: {}", e)))?
+}
+
+/// Ask an in-progress library rebuild to stop after the current file
+#[tauri::command]
+#[tracing::instrument(skip(cancel))]
+fn cancel_rebuild(cancel: State<RebuildCancel>) {
+    cancel.0.store(true, Ordering::Relaxed);
+}
+
+/// Most per-file errors a rebuild reports; the rest are only counted
+const MAX_REBUILD_ERRORS: usize = 100;
+
+/// Result of a library rebuild
+#[derive(Serialize, Debug, Default, PartialEq)]
+struct RebuildResult {
+    indexed: i32,
+    /// Files that couldn't be read or parsed, and were left out
+    skipped: usize,
+    /// Why, for the first MAX_REBUILD_ERRORS of them, along with subfolders
+    /// that couldn't be read and were skipped
+    errors: Vec<RebuildFileError>,
+    /// Set when the library folder was too deep or had too many files to
+    /// scan (library.maxScanDepth / maxScanFiles); nothing was changed
+    limit_exceeded: Option<ScanLimit>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct RebuildFileError {
+    path: String,
+    message: String,
+}
+
+/// Files are discovered and parsed on the rayon pool; this thread is the only
+/// writer and applies the upserts REBUILD_BATCH at a time, each batch in its
+/// own transaction, since SQLite serializes writes anyway. A file that can't
+/// be read or parsed is skipped and reported; any other failure stops the
+/// rebuild, keeping the batches already written (each upsert is complete on
+/// its own, so running the rebuild again picks up where it left off).
+///
+/// `cancel` is checked between files. A cancelled rebuild writes the files
+/// indexed so far but skips the stale entry cleanup, since the scan didn't
+/// see the whole library.
+fn rebuild_library<D: LibraryDb + ?Sized>(
+    db: &D,
+    library_path: &Path,
+    cancel: &AtomicBool,
+    options: &RebuildOptions,
+) -> Result<RebuildResult, CommandError> {
+    use rayon::prelude::*;
+
+    if !library_path.exists() {
+        return Err(CommandError::NotFound(format!("Library path does not exist: {}", library_path.display())));
+    }
+
+    // Canonical, so the paths found below it are too
+    let library_path =
+        &dunce::canonicalize(library_path).map_err(|e| CommandError::io("Failed to resolve library path", e))?;
+    let ignore = library_ignore(library_path, &options.ignore_patterns)?;
+    let limits = ScanLimits::for_rebuild(options);
+    let unreadable = Mutex::new(Vec::new());
+    let paths = find_library_documents(library_path, &ignore, options, cancel, &limits, Some(&unreadable))
+        .map_err(|e| CommandError::Io(format!("Failed to scan library: {}", e)))?;
+
+    // A folder this big is more likely the wrong one (e.g. the home folder)
+    // than a library, so nothing is indexed or pruned
+    if let Some(limit) = limits.exceeded() {
+        tracing::warn!(path = %library_path.display(), ?limit, "library rebuild stopped at a scan limit");
+        return Ok(RebuildResult {
+            limit_exceeded: Some(limit),
+            ..RebuildResult::default()
+        });
+    }
+
+    let mut result = RebuildResult::default();
+    let unreadable = unreadable.into_inner().map_err(|e| CommandError::Io(e.to_string()))?;
+    result.errors.extend(unreadable.into_iter().take(MAX_REBUILD_ERRORS));
+
+    std::thread::scope(|scope| -> Result<(), CommandError> {
+        // Bounded so parsed files (with thumbnails) don't pile up in memory
+        // if the writer falls behind
+        let (sender, receiver) = std::sync::mpsc::sync_channel(64);
+
+        scope.spawn(move || {
+            // A send error means the writer stopped early; skip remaining files
+            paths
+                .par_iter()
+                .try_for_each_with(sender, |s, path| s.send((path.clone(), scan_ssce_file(path, options))).map_err(|_| ()))
+                .ok();
+        });
+
+        let mut batch = Vec::with_capacity(REBUILD_BATCH);
+
+        // Returning early drops the receiver, which stops the workers
+        for (path, scanned) in receiver {
+            if cancel.load(Ordering::Relaxed) {
+                break;
+            }
+
+            match scanned {
+                Ok(scanned) => {
+                    batch.push(scanned);
+                    if batch.len() >= REBUILD_BATCH {
+                        result.indexed += db.run(|conn| upsert_scanned_batch(conn, &batch)).map_err(CommandError::Db)?;
+                        batch.clear();
+                    }
+                }
+                Err(message) => {
+                    tracing::warn!(path = %path.display(), "Skipping unreadable document: {}", message);
+                    result.skipped += 1;
+                    if result.errors.len() < MAX_REBUILD_ERRORS {
+                        result.errors.push(RebuildFileError {
+                            path: path.to_string_lossy().to_string(),
+                            message,
+                        });
+                    }
+                }
+            }
+        }
+
+        if !batch.is_empty() {
+            result.indexed += db.run(|conn| upsert_scanned_batch(conn, &batch)).map_err(CommandError::Db)?;
+        }
+        Ok(())
+    })?;
+
+    if cancel.load(Ordering::Relaxed) {
+        tracing::info!(indexed = result.indexed, skipped = result.skipped, "library rebuild cancelled");
+        return Ok(result);
+    }
+
+    // Clean up stale entries (files in DB that no longer exist, or that are
+    // now ignored)
+    prune_missing(db, Some(&ignore), options.exists_timeout)?;
+
+    Ok(result)
+}
+
+/// Most files a rebuild writes in one transaction (and one hold of the
+/// database lock)
+const REBUILD_BATCH: usize = 200;
+
+/// Write a batch of scanned files in one transaction
+fn upsert_scanned_batch(conn: &Connection, batch: &[ScannedFile]) -> Result<i32, String> {
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    {
+        let mut stmt = tx.prepare(UPSERT_SCANNED_SQL).map_err(|e| e.to_string())?;
+        for scanned in batch {
+            upsert_scanned(&mut stmt, scanned).map_err(|e| e.to_string())?;
+        }
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(batch.len() as i32)
+}
+
+/// The library database as long-running jobs (rebuild, prune, snapshot
+/// count checks) see it: the app's DbState, locked only for each call to
+/// `run`, or a bare connection in tests
+trait LibraryDb {
+    fn run<T, E, F>(&self, op: F) -> Result<T, E>
+    where
+        E: DbError,
+        F: FnMut(&Connection) -> Result<T, E>;
+}
+
+impl LibraryDb for Connection {
+    fn run<T, E, F>(&self, mut op: F) -> Result<T, E>
+    where
+        E: DbError,
+        F: FnMut(&Connection) -> Result<T, E>,
+    {
+        op(self)
+    }
+}
+
+impl LibraryDb for DbState {
+    /// Not retried after a reconnect: `op` usually writes
+    fn run<T, E, F>(&self, mut op: F) -> Result<T, E>
+    where
+        E: DbError,
+        F: FnMut(&Connection) -> Result<T, E>,
+    {
+        self.with_conn(|conn| op(conn))
+    }
+}
+
+/// How long the index worker keeps collecting jobs after the first one
+/// arrives, so a burst of saves is written in one transaction
+const INDEX_BATCH_WINDOW: std::time::Duration = std::time::Duration::from_millis(250);
+
+/// Most jobs the index worker writes in one transaction
+const INDEX_BATCH_MAX: usize = 200;
+
+/// Single-file indexing jobs for the background worker (see queue_index).
+/// The worker parses files without the database lock and only takes it to
+/// write each batch, so searches stay responsive while indexing runs.
+///
+/// While paused (see pause_library_watch), queued paths are held back
+/// instead of sent, each path once, and released together on resume.
+struct IndexQueue {
+    sender: std::sync::mpsc::Sender<String>,
+    pending: Arc<std::sync::atomic::AtomicUsize>,
+    last_error: Arc<Mutex<Option<String>>>,
+    paused: Mutex<IndexPause>,
+}
+
+/// Pause state of the index queue. Pauses nest: the queue runs again once
+/// every pause has been resumed.
+#[derive(Default)]
+struct IndexPause {
+    depth: usize,
+    held: std::collections::BTreeSet<String>,
+}
+
+/// Result of index_queue_status
+#[derive(Debug, Serialize)]
+struct IndexQueueStatus {
+    /// Jobs queued or in the batch being written
+    pending: usize,
+    /// Most recent failure (unreadable file or failed write), if any
+    last_error: Option<String>,
+    paused: bool,
+    /// Paths queued while paused, waiting for resume
+    held: usize,
+}
+
+/// What the worker does with one queued path
+enum IndexJob {
+    Upsert(Box<ScannedFile>),
+    Remove(String),
+}
+
+impl IndexQueue {
+    fn new() -> (Self, std::sync::mpsc::Receiver<String>) {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let queue = IndexQueue {
+            sender,
+            pending: Arc::new(std::sync::atomic::AtomicUsize::new(0)),
+            last_error: Arc::new(Mutex::new(None)),
+            paused: Mutex::new(IndexPause::default()),
+        };
+        (queue, receiver)
+    }
+
+    fn push(&self, path: String) -> Result<(), CommandError> {
+        {
+            let mut paused = self.lock_pause()?;
+            if paused.depth > 0 {
+                paused.held.insert(path);
+                return Ok(());
+            }
+        }
+        self.send(path)
+    }
+
+    fn send(&self, path: String) -> Result<(), CommandError> {
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        self.sender.send(path).map_err(|_| {
+            self.pending.fetch_sub(1, Ordering::SeqCst);
+            CommandError::Validation("Index worker is not running".into())
+        })
+    }
+
+    fn lock_pause(&self) -> Result<std::sync::MutexGuard<'_, IndexPause>, CommandError> {
+        self.paused.lock().map_err(|e| CommandError::Validation(format!("Index queue lock poisoned: {}", e)))
+    }
+
+    fn pause(&self) -> Result<(), CommandError> {
+        self.lock_pause()?.depth += 1;
+        Ok(())
+    }
+
+    /// Undo one pause. When the last one is resumed, the held paths go to
+    /// the worker in one go; returns how many.
+    fn resume(&self) -> Result<usize, CommandError> {
+        let held = {
+            let mut paused = self.lock_pause()?;
+            paused.depth = paused.depth.saturating_sub(1);
+            if paused.depth > 0 {
+                return Ok(0);
+            }
+            std::mem::take(&mut paused.held)
+        };
+        let count = held.len();
+        for path in held {
+            self.send(path)?;
+        }
+        Ok(count)
+    }
+
+    /// Pause until the returned guard is dropped
+    fn pause_while(&self) -> Result<IndexPauseGuard<'_>, CommandError> {
+        self.pause()?;
+        Ok(IndexPauseGuard(self))
+    }
+
+    fn status(&self) -> IndexQueueStatus {
+        let (paused, held) = self.paused.lock().map(|p| (p.depth > 0, p.held.len())).unwrap_or_default();
+        IndexQueueStatus {
+            pending: self.pending.load(Ordering::SeqCst),
+            last_error: self.last_error.lock().ok().and_then(|e| e.clone()),
+            paused,
+            held,
+        }
+    }
+}
+
+/// Keeps the index queue paused for the length of a bulk operation
+struct IndexPauseGuard<'a>(&'a IndexQueue);
+
+impl Drop for IndexPauseGuard<'_> {
+    fn drop(&mut self) {
+        if let Err(e) = self.0.resume() {
+            tracing::warn!("Failed to resume index queue: {}", e);
+        }
+    }
+}
+
+/// Wait for the next job, then gather whatever else arrives within
+/// INDEX_BATCH_WINDOW (up to INDEX_BATCH_MAX). A path queued twice in the
+/// window is only indexed once. None once the queue is dropped.
+fn next_index_batch(receiver: &std::sync::mpsc::Receiver<String>) -> Option<(Vec<String>, usize)> {
+    let first = receiver.recv().ok()?;
+    let deadline = std::time::Instant::now() + INDEX_BATCH_WINDOW;
+    let mut received = vec![first];
+
+    while received.len() < INDEX_BATCH_MAX {
+        let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+        match receiver.recv_timeout(remaining) {
+            Ok(path) => received.push(path),
+            Err(_) => break,
+        }
+    }
+
+    let count = received.len();
+    let mut seen = std::collections::HashSet::new();
+    let paths = received.into_iter().filter(|path| seen.insert(normalize_path(path))).collect();
+    Some((paths, count))
+}
+
+/// Upsert if the file is there when the worker gets to it, remove it from
+/// the library otherwise (deleted or moved away since it was queued)
+fn index_job(path: &str, options: &RebuildOptions) -> Result<IndexJob, String> {
+    let resolved = resolve_path(path);
+    let file = Path::new(&resolved);
+    if file.is_file() {
+        scan_ssce_file(file, options)
+            .map(|scanned| IndexJob::Upsert(Box::new(scanned)))
+            .map_err(|e| format!("Failed to index {}: {}", path, e))
+    } else {
+        Ok(IndexJob::Remove(normalize_path(&resolved)))
+    }
+}
+
+/// Write a batch of prepared jobs in one transaction
+fn apply_index_jobs(conn: &Connection, jobs: &[IndexJob]) -> Result<usize, String> {
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    {
+        let mut upsert = tx.prepare(UPSERT_SCANNED_SQL).map_err(|e| e.to_string())?;
+        let mut remove = tx.prepare("DELETE FROM files WHERE path = ?1").map_err(|e| e.to_string())?;
+        for job in jobs {
+            match job {
+                IndexJob::Upsert(scanned) => upsert_scanned(&mut upsert, scanned),
+                IndexJob::Remove(path) => remove.execute(params![path]).map(|_| ()),
+            }
+            .map_err(|e| e.to_string())?;
+        }
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(jobs.len())
+}
+
+/// Start the background index worker. It runs until the app exits.
+fn start_index_worker(app_handle: tauri::AppHandle) -> IndexQueue {
+    let (queue, receiver) = IndexQueue::new();
+    let pending = queue.pending.clone();
+    let last_error = queue.last_error.clone();
+
+    std::thread::spawn(move || {
+        while let Some((paths, count)) = next_index_batch(&receiver) {
+            let options = RebuildOptions::from_config(&load_defaults_typed(&app_handle));
+            let mut errors = Vec::new();
+            let jobs: Vec<IndexJob> = paths
+                .iter()
+                .filter_map(|path| index_job(path, &options).map_err(|e| errors.push(e)).ok())
+                .collect();
+
+            if let Err(e) = app_handle.state::<DbState>().with_conn(|conn| apply_index_jobs(conn, &jobs)) {
+                errors.push(format!("Failed to write index batch: {}", e));
+            }
+            pending.fetch_sub(count, Ordering::SeqCst);
+
+            for e in &errors {
+                tracing::warn!("{}", e);
+            }
+            if let (Some(e), Ok(mut last)) = (errors.pop(), last_error.lock()) {
+                *last = Some(e);
+            }
+        }
+    });
+
+    queue
+}
+
+/// Queue one document to be (re)indexed in the background, or removed from
+/// the library if it no longer exists by the time the worker reaches it.
+/// Returns immediately; see index_queue_status for progress.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle, queue), err)]
+fn queue_index(app_handle: tauri::AppHandle, queue: State<IndexQueue>, path: String) -> Result<(), CommandError> {
+    if !has_indexed_extension(&path, &indexed_extensions(&load_defaults_typed(&app_handle))) {
+        return Err(CommandError::Validation(format!("Not an indexed document type: {}", path)));
+    }
+    queue.push(path)
+}
+
+/// Hold back background indexing (queue_index) while the library is being
+/// changed in bulk. Paths queued meanwhile are kept, each once, and indexed
+/// together by resume_library_watch. Pauses nest, so call resume once per
+/// pause. Rebuild, folder import and thumbnail regeneration pause on their
+/// own for as long as they run.
+#[tauri::command]
+#[tracing::instrument(skip(queue), err)]
+fn pause_library_watch(queue: State<IndexQueue>) -> Result<(), CommandError> {
+    queue.pause()
+}
+
+/// Undo pause_library_watch. Returns the number of held paths sent for
+/// indexing (0 while another pause is still in effect).
+#[tauri::command]
+#[tracing::instrument(skip(queue), err)]
+fn resume_library_watch(queue: State<IndexQueue>) -> Result<usize, CommandError> {
+    queue.resume()
+}
+
+/// Jobs still waiting for the background index worker, and its last error
+#[tauri::command]
+#[tracing::instrument(skip(queue))]
+fn index_queue_status(queue: State<IndexQueue>) -> IndexQueueStatus {
+    queue.status()
+}
+
+/// How long to wait for a volume or file to answer before treating it as
+/// unreachable (a hung network mount can block a stat for minutes), unless
+/// library.existsTimeoutMs says otherwise
+const VOLUME_CHECK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// The mount a path lives on, as far as can be told from the path alone:
+/// the drive or UNC share on Windows, and on Unix the folder under the
+/// usual mount points (/media/<user>/<disk>, /mnt/<disk>, /Volumes/<disk>,
+/// /net/<host>). Anything else is on the root file system.
+fn volume_root(path: &Path) -> std::path::PathBuf {
+    use std::path::Component;
+
+    let components: Vec<Component> = path.components().collect();
+    if let Some(Component::Prefix(prefix)) = components.first() {
+        let mut root = std::path::PathBuf::from(prefix.as_os_str());
+        root.push(std::path::MAIN_SEPARATOR_STR);
+        return root;
+    }
+
+    let names: Vec<&std::ffi::OsStr> = components
+        .iter()
+        .filter_map(|c| match c {
+            Component::Normal(name) => Some(*name),
+            _ => None,
+        })
+        .collect();
+    let depth = match names.first().and_then(|n| n.to_str()) {
+        Some("mnt" | "Volumes" | "net") => 2,
+        Some("media") => 3,
+        Some("run") if names.get(1).is_some_and(|n| *n == "media") => 4,
+        _ => 0,
+    };
+
+    let mut root = std::path::PathBuf::from("/");
+    if names.len() > depth {
+        root.extend(&names[..depth]);
+    }
+    root
+}
+
+/// Run a blocking file system check on its own thread, giving up after
+/// `timeout`. Returns None if it didn't finish in time; the thread is left
+/// to finish (or hang) on its own.
+fn check_with_timeout<T, F>(check: F, timeout: std::time::Duration) -> Option<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = sender.send(check());
+    });
+    receiver.recv_timeout(timeout).ok()
+}
+
+/// Whether a volume root is mounted and answers within `timeout`, so a
+/// hung mount only costs the timeout
+fn volume_reachable(root: &Path, timeout: std::time::Duration) -> bool {
+    let root = root.to_path_buf();
+    check_with_timeout(move || root.is_dir(), timeout).unwrap_or(false)
+}
+
+/// Whether `path` exists, or None if the check didn't answer within `timeout`
+fn path_exists_within(path: &Path, timeout: std::time::Duration) -> Option<bool> {
+    let path = path.to_path_buf();
+    check_with_timeout(move || path.exists(), timeout)
+}
+
+/// Delete rows for files that no longer exist (and, with `ignore`, files
+/// the ignore rules now exclude). Rows on a volume that is unmounted or not
+/// answering, or whose file doesn't answer within `timeout`, are left
+/// alone - the files are probably still there. After one timeout the rest
+/// of that volume is treated as not answering, so a hung mount costs the
+/// timeout (and a stuck thread) once rather than once per row.
+/// The files are checked without holding the database lock.
+/// Returns the number of rows removed.
+fn prune_missing<D: LibraryDb + ?Sized>(
+    db: &D,
+    ignore: Option<&Gitignore>,
+    timeout: std::time::Duration,
+) -> Result<i32, CommandError> {
+    let mut volumes: HashMap<std::path::PathBuf, bool> = HashMap::new();
+
+    prune_missing_with(db, ignore, |path| {
+        let root = volume_root(path);
+        let reachable = *volumes
+            .entry(root.clone())
+            .or_insert_with_key(|root| volume_reachable(root, timeout));
+        if !reachable {
+            return None;
+        }
+        let exists = path_exists_within(path, timeout);
+        if exists.is_none() {
+            tracing::warn!(volume = %root.display(), "Volume stopped answering, keeping the rest of its entries");
+            volumes.insert(root, false);
+        }
+        exists
+    })
+}
+
+/// prune_missing with the existence check supplied: `exists` returns None
+/// when it can't tell, and the row is kept
+fn prune_missing_with<D, F>(db: &D, ignore: Option<&Gitignore>, mut exists: F) -> Result<i32, CommandError>
+where
+    D: LibraryDb + ?Sized,
+    F: FnMut(&Path) -> Option<bool>,
+{
+    let rows: Vec<(i64, String, Option<String>)> = db.run(|conn| {
+        conn.prepare("SELECT id, path, encoded_path FROM files")
+            .and_then(|mut stmt| {
+                stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+                    .collect::<Result<_, _>>()
+            })
+            .map_err(|e| e.to_string())
+    })
+    .map_err(CommandError::Db)?;
+
+    let mut stale_ids = Vec::new();
+
+    for (id, path, encoded_path) in rows {
+        // A lossy path wouldn't be found, so check the real one; a row whose
+        // real path can't be decoded can't be checked, so it's kept
+        let path = match encoded_path.map(|encoded| fs_path(&encoded)) {
+            Some(Ok(path)) => path,
+            Some(Err(e)) => {
+                tracing::warn!(id, "Keeping library entry with an unreadable path: {}", e);
+                continue;
+            }
+            None => std::path::PathBuf::from(path),
+        };
+        let path = path.as_path();
+        let stale = if ignore.is_some_and(|ignore| is_ignored(ignore, path, false)) {
+            true
+        } else {
+            match exists(path) {
+                Some(exists) => !exists,
+                None => continue,
+            }
+        };
+
+        if stale {
+            stale_ids.push(id);
+        }
+    }
+
+    let pruned = db.run(|conn| {
+        let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+        let mut pruned = 0;
+        {
+            let mut stmt = tx.prepare("DELETE FROM files WHERE id = ?1").map_err(|e| e.to_string())?;
+            for id in &stale_ids {
+                pruned += stmt.execute(params![id]).map_err(|e| e.to_string())? as i32;
+            }
+        }
+        tx.commit().map_err(|e| e.to_string())?;
+        Ok(pruned)
+    })
+    .map_err(CommandError::Db)?;
+
+    if pruned > 0 {
+        tracing::info!(pruned, "removed library entries for missing files");
+    }
+    Ok(pruned)
+}
+
+/// Remove library entries for files that have been deleted or moved away
+/// outside the app. Cheaper than a rebuild: nothing is rescanned.
+/// Returns the number of entries removed. The files are checked on a
+/// blocking thread, since a slow network share can take a while to answer.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle), err)]
+async fn db_prune_missing(app_handle: tauri::AppHandle) -> Result<i32, CommandError> {
+    let timeout = RebuildOptions::from_config(&load_defaults_typed(&app_handle)).exists_timeout;
+    let app = app_handle.clone();
+    tauri::async_runtime::spawn_blocking(move || prune_missing(&*app.state::<DbState>(), None, timeout))
+        .await
+        .map_err(|e| CommandError::Io(format!("Pruning the library failed: {}", e)))?
+}
+
+/// Total on-disk size of the library
+#[derive(Serialize)]
+struct LibrarySize {
+    total_bytes: i64,
+    file_count: i64,
+}
+
+/// Get the total size and number of files in the library.
+/// Files whose size is unknown count towards file_count but not total_bytes.
+#[tauri::command]
+#[tracing::instrument(skip(state), err)]
+fn db_library_size(state: State<DbState>) -> Result<LibrarySize, CommandError> {
+    state.with_read_conn(|conn| library_size(conn).map_err(CommandError::from))
+}
+
+fn library_size(conn: &Connection) -> Result<LibrarySize, rusqlite::Error> {
+    conn.query_row(
+        "SELECT COALESCE(SUM(size), 0), COUNT(*) FROM files WHERE deleted_at IS NULL",
+        [],
+        |row| {
+            Ok(LibrarySize {
+                total_bytes: row.get(0)?,
+                file_count: row.get(1)?,
+            })
+        },
+    )
+}
+
+/// Files and bytes in one disk usage category
+#[derive(Serialize, Debug, Default, PartialEq)]
+struct UsageTotals {
+    files: u64,
+    bytes: u64,
+}
+
+/// A file in the largest-files list of library_disk_usage
+#[derive(Serialize, Debug)]
+struct LargeFile {
+    path: String,
+    size: u64,
+}
+
+/// Where the space under the library folder goes, from the files on disk
+/// (indexed or not)
+#[derive(Serialize, Debug, Default)]
+struct DiskUsage {
+    /// Documents (the configured indexedExtensions)
+    documents: UsageTotals,
+    images: UsageTotals,
+    /// `.bak` backups
+    backups: UsageTotals,
+    other: UsageTotals,
+    total_bytes: u64,
+    /// Biggest files first
+    largest: Vec<LargeFile>,
+    cancelled: bool,
+}
+
+/// Progress of library_disk_usage, emitted as "disk-usage-progress"
+#[derive(Serialize, Clone, Debug)]
+struct DiskUsageProgress {
+    done: usize,
+    total: usize,
+}
+
+/// How many of the largest files library_disk_usage lists by default
+const DEFAULT_LARGEST_FILES: usize = 20;
+
+/// Files sized between progress events
+const DISK_USAGE_PROGRESS_EVERY: usize = 500;
+
+/// Break down the space used under the library folder by kind of file, with
+/// the `largest` (default 20) biggest files. Unlike db_library_size this
+/// counts everything on disk, including backups and files the index skips.
+/// Stopped by cancel_library_disk_usage; reports "disk-usage-progress" as
+/// it goes. Fails with AlreadyRunning while another count is in progress.
+/// The walk runs on a blocking thread.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle), err)]
+async fn library_disk_usage(
+    app_handle: tauri::AppHandle,
+    library_path: String,
+    largest: Option<usize>,
+) -> Result<DiskUsage, CommandError> {
+    let app = app_handle.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let slot = app.state::<DiskUsageSlot>();
+        let _running = slot.0.start()?;
+        let options = RebuildOptions::from_config(&load_defaults_typed(&app));
+        let largest = largest.unwrap_or(DEFAULT_LARGEST_FILES);
+        disk_usage(Path::new(&library_path), &options, largest, &slot.0.cancel, |done, total| {
+            let _ = app.emit("disk-usage-progress", DiskUsageProgress { done, total });
+        })
+    })
+    .await
+    .map_err(|e| CommandError::Io(format!("Disk usage count failed: {}", e)))?
+}
+
+/// Ask an in-progress library_disk_usage to stop
+#[tauri::command]
+#[tracing::instrument(skip(slot))]
+fn cancel_library_disk_usage(slot: State<DiskUsageSlot>) {
+    slot.0.cancel();
+}
+
+fn disk_usage<F>(
+    library_path: &Path,
+    options: &RebuildOptions,
+    largest: usize,
+    cancel: &AtomicBool,
+    mut progress: F,
+) -> Result<DiskUsage, CommandError>
+where
+    F: FnMut(usize, usize),
+{
+    if !library_path.is_dir() {
+        return Err(CommandError::NotFound(format!("Library path does not exist: {}", library_path.display())));
+    }
+
+    // Everything counts here, including what the index ignores (like *.bak)
+    let library_path = &dunce::canonicalize(library_path).map_err(|e| CommandError::io("Failed to resolve library path", e))?;
+    let visited = Mutex::new(std::collections::HashSet::from([library_path.clone()]));
+    let paths = find_files(library_path, options, &Gitignore::empty(), &visited, cancel, &|_| true)
+        .map_err(CommandError::Io)?;
+
+    let mut usage = DiskUsage::default();
+    let mut sized: Vec<LargeFile> = Vec::new();
+    let total = paths.len();
+
+    for (index, path) in paths.iter().enumerate() {
+        if cancel.load(Ordering::Relaxed) {
+            break;
+        }
+
+        // Files removed mid-scan are skipped
+        if let Ok(metadata) = fs::metadata(path) {
+            let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+            let totals = if has_indexed_extension(&name, &options.extensions) {
+                &mut usage.documents
+            } else if FileFilter::Images.matches(&name) {
+                &mut usage.images
+            } else if name.to_lowercase().ends_with(".bak") {
+                &mut usage.backups
+            } else {
+                &mut usage.other
+            };
+            totals.files += 1;
+            totals.bytes += metadata.len();
+            usage.total_bytes += metadata.len();
+            sized.push(LargeFile {
+                path: path.to_string_lossy().to_string(),
+                size: metadata.len(),
+            });
+        }
+
+        if (index + 1) % DISK_USAGE_PROGRESS_EVERY == 0 || index + 1 == total {
+            progress(index + 1, total);
+        }
+    }
+
+    sized.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+    sized.truncate(largest);
+    usage.largest = sized;
+    usage.cancelled = cancel.load(Ordering::Relaxed);
+    Ok(usage)
+}
+
+/// Files copied between backup-library-progress events
+const LIBRARY_BACKUP_PROGRESS_EVERY: usize = 100;
+
+/// Outcome of backup_library
+#[derive(Serialize, Debug, Default)]
+struct LibraryBackup {
+    /// The new backup folder (empty if cancelled)
+    path: String,
+    /// Files copied from the library because they were new or changed
+    copied: usize,
+    /// Files unchanged since the previous backup, linked from it instead
+    skipped: usize,
+    /// Bytes copied from the library
+    bytes: u64,
+    cancelled: bool,
+}
+
+/// Progress of backup_library, emitted as "backup-library-progress"
+#[derive(Serialize, Clone, Debug)]
+struct LibraryBackupProgress {
+    done: usize,
+    total: usize,
+}
+
+/// Copy the library folder (paths.library) into a new timestamped folder
+/// under `dest_dir`. Files whose size and modified time match the previous
+/// backup are hard-linked from it rather than copied again, and only the
+/// newest `libraryBackups.maxCount` backups are kept. Stopped by
+/// cancel_backup_library, which discards the partial backup; reports
+/// "backup-library-progress" as it goes. Fails with AlreadyRunning while
+/// another backup is in progress. The copy runs on a blocking thread.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle), err)]
+async fn backup_library(app_handle: tauri::AppHandle, dest_dir: String) -> Result<LibraryBackup, CommandError> {
+    let app = app_handle.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let slot = app.state::<LibraryBackupSlot>();
+        let _running = slot.0.start()?;
+        let library_path =
+            library_root(&app).ok_or_else(|| CommandError::NotFound("No library folder is configured".to_string()))?;
+        ensure_write_allowed(&app, Path::new(&dest_dir))?;
+        let config = load_defaults_typed(&app);
+        let keep = config.library_backups.max_count as usize;
+        let options = RebuildOptions::from_config(&config);
+        let backup = LibraryBackupJob {
+            library_path: &library_path,
+            dest_dir: Path::new(&dest_dir),
+            keep,
+            options: &options,
+        };
+        backup.run(chrono::Utc::now(), &slot.0.cancel, |done, total| {
+            let _ = app.emit("backup-library-progress", LibraryBackupProgress { done, total });
+        })
+    })
+    .await
+    .map_err(|e| CommandError::Io(format!("Library backup failed: {}", e)))?
+}
+
+/// Ask an in-progress backup_library to stop; the partial backup is removed
+#[tauri::command]
+#[tracing::instrument(skip(slot))]
+fn cancel_backup_library(slot: State<LibraryBackupSlot>) {
+    slot.0.cancel();
+}
+
+/// One run of backup_library
+struct LibraryBackupJob<'a> {
+    library_path: &'a Path,
+    dest_dir: &'a Path,
+    keep: usize,
+    options: &'a RebuildOptions,
+}
+
+impl LibraryBackupJob<'_> {
+    /// Backups are written under a ".partial" name and renamed when done, so
+    /// a cancelled or failed run never counts as the previous backup
+    fn run<F>(
+        &self,
+        now: chrono::DateTime<chrono::Utc>,
+        cancel: &AtomicBool,
+        mut progress: F,
+    ) -> Result<LibraryBackup, CommandError>
+    where
+        F: FnMut(usize, usize),
+    {
+        if !self.library_path.is_dir() {
+            return Err(CommandError::NotFound(format!(
+                "Library path does not exist: {}",
+                self.library_path.display()
+            )));
+        }
+
+        let library_path = &dunce::canonicalize(self.library_path)
+            .map_err(|e| CommandError::io("Failed to resolve library path", e))?;
+        fs::create_dir_all(self.dest_dir).map_err(|e| CommandError::io("Failed to create backup folder", e))?;
+        let dest_dir = &dunce::canonicalize(self.dest_dir)
+            .map_err(|e| CommandError::io("Failed to resolve backup folder", e))?;
+
+        // A backup folder inside the library is never backed up itself
+        let visited = Mutex::new(std::collections::HashSet::from([library_path.clone(), dest_dir.clone()]));
+        let paths = find_files(library_path, self.options, &Gitignore::empty(), &visited, cancel, &|_| true)
+            .map_err(CommandError::Io)?;
+
+        let previous = list_library_backups(dest_dir).into_iter().next();
+        let name = format!("library-{}", now.format("%Y%m%d-%H%M%S%.3f"));
+        let target = dest_dir.join(&name);
+        let partial = dest_dir.join(format!("{}.partial", name));
+
+        let mut result = LibraryBackup::default();
+        let total = paths.len();
+        let copied = paths.iter().enumerate().try_for_each(|(index, path)| {
+            if cancel.load(Ordering::Relaxed) {
+                return Ok(());
+            }
+            // Files reached through a followed link outside the library
+            // have no place in the mirror
+            if let Ok(relative) = path.strip_prefix(library_path) {
+                back_up_file(path, relative, previous.as_deref(), &partial, &mut result)?;
+            }
+            if (index + 1) % LIBRARY_BACKUP_PROGRESS_EVERY == 0 || index + 1 == total {
+                progress(index + 1, total);
+            }
+            Ok::<(), CommandError>(())
+        });
+
+        if let Err(e) = copied {
+            let _ = fs::remove_dir_all(&partial);
+            return Err(e);
+        }
+        if cancel.load(Ordering::Relaxed) {
+            let _ = fs::remove_dir_all(&partial);
+            return Ok(LibraryBackup { cancelled: true, ..result });
+        }
+
+        // An empty library still gets an empty backup folder
+        fs::create_dir_all(&partial).map_err(|e| CommandError::io("Failed to create backup folder", e))?;
+        fs::rename(&partial, &target).map_err(|e| CommandError::io("Failed to finish backup", e))?;
+        result.path = target.to_string_lossy().to_string();
+
+        for stale in list_library_backups(dest_dir).into_iter().skip(self.keep.max(1)) {
+            if let Err(e) = fs::remove_dir_all(&stale) {
+                tracing::warn!(path = %stale.display(), "Failed to remove old library backup: {}", e);
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+/// Mirror one library file at `relative` under `backup`. A file the same
+/// size and age as in `previous` is linked from there (or copied from
+/// there where links aren't supported); anything else is copied from the
+/// library with its modified time kept for the next comparison.
+fn back_up_file(
+    source: &Path,
+    relative: &Path,
+    previous: Option<&Path>,
+    backup: &Path,
+    result: &mut LibraryBackup,
+) -> Result<(), CommandError> {
+    // Files removed mid-backup are skipped
+    let Ok(metadata) = fs::metadata(source) else {
+        return Ok(());
+    };
+    let target = backup.join(relative);
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent).map_err(|e| CommandError::io("Failed to create backup folder", e))?;
+    }
+
+    let unchanged = previous.map(|p| p.join(relative)).filter(|old| {
+        fs::metadata(old).is_ok_and(|m| m.len() == metadata.len() && mtime_secs(&m) == mtime_secs(&metadata))
+    });
+    if let Some(old) = unchanged {
+        if fs::hard_link(&old, &target).is_err() {
+            fs::copy(&old, &target).map_err(|e| CommandError::io("Failed to copy from previous backup", e))?;
+        }
+        result.skipped += 1;
+        return Ok(());
+    }
+
+    fs::copy(source, &target).map_err(|e| CommandError::io("Failed to back up file", e))?;
+    if let Ok(modified) = metadata.modified() {
+        let kept = fs::File::options().write(true).open(&target).and_then(|f| f.set_modified(modified));
+        if let Err(e) = kept {
+            tracing::warn!(path = %target.display(), "Failed to keep modified time in backup: {}", e);
+        }
+    }
+    result.copied += 1;
+    result.bytes += metadata.len();
+    Ok(())
+}
+
+/// Finished library backups in `dest_dir`, newest first
+fn list_library_backups(dest_dir: &Path) -> Vec<std::path::PathBuf> {
+    let Ok(entries) = fs::read_dir(dest_dir) else {
+        return Vec::new();
+    };
+
+    let mut backups: Vec<std::path::PathBuf> = entries
+        .flatten()
+        .filter(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            name.starts_with("library-") && !name.ends_with(".partial") && entry.path().is_dir()
+        })
+        .map(|entry| entry.path())
+        .collect();
+
+    // Names are timestamps, so they sort in the order backups were taken
+    backups.sort_by(|a, b| b.file_name().cmp(&a.file_name()));
+    backups
+}
+
+/// A set of library documents with identical content
+#[derive(Serialize)]
+struct DuplicateGroup {
+    content_hash: String,
+    paths: Vec<String>,
+}
+
+/// Serialize JSON with object keys sorted at every level, so two documents
+/// that differ only in key order produce the same string.
+fn canonical_json_string(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Object(map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            let fields: Vec<String> = keys
+                .into_iter()
+                .map(|k| {
+                    format!(
+                        "{}:{}",
+                        serde_json::Value::String(k.clone()),
+                        canonical_json_string(&map[k])
+                    )
+                })
+                .collect();
+            format!("{{{}}}", fields.join(","))
+        }
+        serde_json::Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(canonical_json_string).collect();
+            format!("[{}]", items.join(","))
+        }
+        other => other.to_string(),
+    }
+}
+
+/// Copy of a JSON value with object keys sorted at every level
+fn sorted_json(value: &serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(map) => {
+            let mut entries: Vec<(&String, &serde_json::Value)> = map.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            serde_json::Value::Object(entries.into_iter().map(|(k, v)| (k.clone(), sorted_json(v))).collect())
+        }
+        serde_json::Value::Array(items) => serde_json::Value::Array(items.iter().map(sorted_json).collect()),
+        other => other.clone(),
+    }
+}
+
+/// Re-serialize a document with sorted keys and two-space indentation, so
+/// logically identical documents are saved as identical bytes
+fn canonicalize_ssce(content: &str) -> Result<String, serde_json::Error> {
+    let value: serde_json::Value = serde_json::from_str(content)?;
+    serde_json::to_string_pretty(&sorted_json(&value))
+}
+
+/// SHA-256 of a document's meaningful content, as lowercase hex.
+/// Derived and volatile fields (thumbnail, keywords, created/modified
+/// timestamps) are removed first, so re-saving a document or saving a copy
+/// under another name doesn't change the hash.
+fn ssce_content_hash(json: &serde_json::Value) -> String {
+    use sha2::{Digest, Sha256};
+
+    let mut content = json.clone();
+    if let Some(obj) = content.as_object_mut() {
+        obj.remove("thumbnail");
+        obj.remove("keywords");
+        if let Some(fm) = obj.get_mut("frontMatter").and_then(|fm| fm.as_object_mut()) {
+            fm.remove("created");
+            fm.remove("modified");
+        }
+    }
+
+    let digest = Sha256::digest(canonical_json_string(&content).as_bytes());
+    format!("{:x}", digest)
+}
+
+/// Find documents in the library that share the same content hash.
+/// Hashes are populated by db_rebuild_from_library.
+#[tauri::command]
+#[tracing::instrument(skip(state), err)]
+fn db_find_duplicates(state: State<DbState>) -> Result<Vec<DuplicateGroup>, CommandError> {
+    state.with_read_conn(|conn| find_duplicates(conn).map_err(CommandError::from))
+}
+
+/// Group paths by content hash, keeping only hashes shared by 2+ files
+fn find_duplicates(conn: &Connection) -> Result<Vec<DuplicateGroup>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT content_hash, path FROM files
+         WHERE deleted_at IS NULL AND content_hash IN (
+             SELECT content_hash FROM files
+             WHERE content_hash IS NOT NULL AND deleted_at IS NULL
+             GROUP BY content_hash
+             HAVING COUNT(*) > 1
+         )
+         ORDER BY content_hash, path",
+    )?;
+
+    let rows = stmt
+        .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut groups: Vec<DuplicateGroup> = Vec::new();
+    for (content_hash, path) in rows {
+        match groups.last_mut() {
+            Some(group) if group.content_hash == content_hash => group.paths.push(path),
+            _ => groups.push(DuplicateGroup {
+                content_hash,
+                paths: vec![path],
+            }),
+        }
+    }
+
+    Ok(groups)
+}
+
+// ============================================================================
+// File System Commands
+// ============================================================================
+//
+// These commands provide file system access to the JavaScript frontend.
+// They handle reading/writing images and .ssce project files.
+//
+// ============================================================================
+
+/// Represents a file or directory entry for directory listings
+#[derive(Serialize, Clone)]
+struct FileEntry {
+    name: String,
+    /// Path relative to the browsed directory, using '/' separators.
+    /// Same as `name` unless listing recursively.
+    path: String,
+    is_dir: bool,
+    size: u64,
+    /// Last modified time as unix seconds (0 if unavailable)
+    mtime: u64,
+    /// Full path in encode_os_path form when the name isn't valid UTF-8
+    /// (`name` then only serves for display)
+    encoded_path: Option<String>,
+}
+
+/// Ordering for browse_directory results. Directories always come first.
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+enum BrowseSort {
+    /// Alphabetical, case-insensitive
+    #[default]
+    Name,
+    /// Largest first
+    Size,
+    /// Most recently modified first
+    Modified,
+}
+
+/// Which files browse_directory includes (directories are always included)
+enum FileFilter {
+    All,
+    Ssce,
+    Images,
+    /// Case-insensitive globs matched against the file name
+    Patterns(globset::GlobSet),
+}
+
+impl FileFilter {
+    /// Parse a filter string: one of the keywords "all", "ssce" or "images",
+    /// or a comma-separated list of extensions and globs such as
+    /// "*.ssce,md,report_*". Items containing `*` or `?` are globs; anything
+    /// else is an extension (with or without the leading dot).
+    fn parse(filter: &str) -> Result<FileFilter, String> {
+        match filter.trim() {
+            "" | "all" => return Ok(FileFilter::All),
+            "ssce" => return Ok(FileFilter::Ssce),
+            "images" => return Ok(FileFilter::Images),
+            _ => {}
+        }
+
+        let mut builder = globset::GlobSetBuilder::new();
+        for item in filter.split(',').map(str::trim).filter(|i| !i.is_empty()) {
+            let pattern = if item.contains(['*', '?']) {
+                item.to_string()
+            } else {
+                format!("*.{}", item.trim_start_matches('.'))
+            };
+            let glob = globset::GlobBuilder::new(&pattern)
+                .case_insensitive(true)
+                .build()
+                .map_err(|e| format!("Invalid filter pattern '{}': {}", item, e))?;
+            builder.add(glob);
+        }
+
+        builder
+            .build()
+            .map(FileFilter::Patterns)
+            .map_err(|e| format!("Invalid filter: {}", e))
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        let lower_name = name.to_lowercase();
+        match self {
+            FileFilter::All => true,
+            FileFilter::Ssce => lower_name.ends_with(".ssce"),
+            FileFilter::Images => {
+                lower_name.ends_with(".png")
+                    || lower_name.ends_with(".jpg")
+                    || lower_name.ends_with(".jpeg")
+                    || lower_name.ends_with(".gif")
+                    || lower_name.ends_with(".webp")
+                    || lower_name.ends_with(".bmp")
+            }
+            FileFilter::Patterns(set) => set.is_match(name),
+        }
+    }
+}
+
+/// Modification time of a file as unix seconds, or 0 if it can't be read
+fn mtime_secs(metadata: &fs::Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Browse a directory and return list of files/directories.
+/// Used for custom file browser dialogs (not currently used - native dialogs preferred).
+/// Filters: "all", "ssce", "images", or a comma-separated list of extensions
+/// and globs (e.g. "*.ssce,md,report_*"), matched case-insensitively.
+/// With `recursive`, subdirectories are listed too (up to `max_depth` levels
+/// below `dir`, unlimited if None), each directory followed by its contents.
+/// `sort` orders entries within each directory (default Name).
+/// Each successful browse is recorded in the recent folders list.
+#[tauri::command]
+#[tracing::instrument(skip(state), err)]
+fn browse_directory(
+    state: State<DbState>,
+    dir: String,
+    filter: String,
+    recursive: bool,
+    max_depth: Option<u32>,
+    sort: Option<BrowseSort>,
+) -> Result<Vec<FileEntry>, CommandError> {
+    let entries = list_directory(dir.clone(), filter.clone(), recursive, max_depth, sort)?;
+
+    // Recent folders are a convenience - never fail the listing because of them
+    let recorded = state.with_conn(|conn| {
+        record_folder_visit(conn, &dir)?;
+        record_browse_state(conn, &dir, &filter)
+    });
+    if let Err(e) = recorded {
+        tracing::warn!("Failed to record recent folder: {}", e);
+    }
+
+    Ok(entries)
+}
+
+fn list_directory(
+    dir: String,
+    filter: String,
+    recursive: bool,
+    max_depth: Option<u32>,
+    sort: Option<BrowseSort>,
+) -> Result<Vec<FileEntry>, CommandError> {
+    let path = Path::new(&dir);
+
+    if !path.exists() {
+        return Err(CommandError::NotFound(format!("Directory does not exist: {}", dir)));
+    }
+
+    if !path.is_dir() {
+        return Err(CommandError::Validation(format!("Path is not a directory: {}", dir)));
+    }
+
+    let max_depth = if recursive { max_depth } else { Some(0) };
+
+    // Canonical paths of directories already listed, so a symlink pointing
+    // back up the tree can't cause infinite recursion
+    let mut visited = std::collections::HashSet::new();
+    if let Ok(canonical) = path.canonicalize() {
+        visited.insert(canonical);
+    }
+
+    let mut entries: Vec<FileEntry> = Vec::new();
+    let options = BrowseOptions {
+        max_depth,
+        filter: FileFilter::parse(&filter).map_err(CommandError::Validation)?,
+        sort: sort.unwrap_or_default(),
+    };
+    browse_into(path, "", 0, &options, &mut visited, &mut entries).map_err(CommandError::Io)?;
+
+    Ok(entries)
+}
+
+/// Entries per directory-batch event when the caller doesn't specify
+const DEFAULT_BROWSE_BATCH_SIZE: usize = 500;
+
+/// Payload of the "directory-batch" event
+#[derive(Serialize, Clone)]
+struct DirectoryBatch {
+    dir: String,
+    entries: Vec<FileEntry>,
+}
+
+/// Payload of the "directory-done" event
+#[derive(Serialize, Clone)]
+struct DirectoryDone {
+    dir: String,
+    total: usize,
+}
+
+/// List a single directory (non-recursive) progressively, for folders too
+/// large for browse_directory. Entries arrive as "directory-batch" events -
+/// all folders first, then files by name - followed by "directory-done".
+/// Returns the number of entries sent. The folder is read on a blocking
+/// thread; the database is only locked afterwards, to record the visit.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle), err)]
+async fn browse_directory_stream(
+    app_handle: tauri::AppHandle,
+    dir: String,
+    filter: String,
+    batch_size: Option<usize>,
+) -> Result<usize, CommandError> {
+    let batch_size = batch_size.unwrap_or(DEFAULT_BROWSE_BATCH_SIZE).max(1);
+    let file_filter = FileFilter::parse(&filter).map_err(CommandError::Validation)?;
+    let app = app_handle.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let total = stream_directory(Path::new(&dir), &file_filter, batch_size, |entries| {
+            let _ = app.emit("directory-batch", DirectoryBatch { dir: dir.clone(), entries });
+        })
+        .map_err(CommandError::Io)?;
+        let _ = app.emit("directory-done", DirectoryDone { dir: dir.clone(), total });
+
+        let recorded = app.state::<DbState>().with_conn(|conn| {
+            record_folder_visit(conn, &dir)?;
+            record_browse_state(conn, &dir, &filter)
+        });
+        if let Err(e) = recorded {
+            tracing::warn!("Failed to record recent folder: {}", e);
+        }
+
+        Ok(total)
+    })
+    .await
+    .map_err(|e| CommandError::Io(format!("Listing the folder failed: {}", e)))?
+}
+
+/// The first pass only reads names and file types, which is cheap, so the
+/// folders can be sent straight away. Files are stat'ed (the slow part) in
+/// a second pass and sent a batch at a time.
+fn stream_directory<F>(dir: &Path, filter: &FileFilter, batch_size: usize, mut send: F) -> Result<usize, String>
+where
+    F: FnMut(Vec<FileEntry>),
+{
+    if !dir.is_dir() {
+        return Err(format!("Directory does not exist: {}", dir.display()));
+    }
+
+    let mut dirs: Vec<(String, std::path::PathBuf)> = Vec::new();
+    let mut files: Vec<(String, std::path::PathBuf)> = Vec::new();
+
+    let read_dir = fs::read_dir(dir).map_err(|e| format!("Failed to read directory: {}", e))?;
+    for entry in read_dir {
+        let entry = entry.map_err(|e| format!("Failed to read entry: {}", e))?;
+        let name = entry.file_name().to_string_lossy().to_string();
+
+        // Skip hidden files (starting with .)
+        if name.starts_with('.') {
+            continue;
+        }
+
+        // Symlinks need a stat to tell whether they point at a folder
+        let is_dir = match entry.file_type() {
+            Ok(file_type) if file_type.is_symlink() => entry.path().is_dir(),
+            Ok(file_type) => file_type.is_dir(),
+            Err(_) => false,
+        };
+
+        if is_dir {
+            dirs.push((name, entry.path()));
+        } else if filter.matches(&name) {
+            files.push((name, entry.path()));
+        }
+    }
+
+    let by_name = |a: &(String, std::path::PathBuf), b: &(String, std::path::PathBuf)| {
+        a.0.to_lowercase().cmp(&b.0.to_lowercase())
+    };
+    dirs.sort_by(by_name);
+    files.sort_by(by_name);
+
+    let dir_count = dirs.len();
+    let mut total = 0;
+
+    for (index, chunk) in dirs.into_iter().chain(files).collect::<Vec<_>>().chunks(batch_size).enumerate() {
+        let entries: Vec<FileEntry> = chunk
+            .iter()
+            .enumerate()
+            .filter_map(|(offset, (name, path))| {
+                let is_dir = index * batch_size + offset < dir_count;
+                // A file removed since the first pass is simply left out
+                let metadata = fs::metadata(path).ok()?;
+                Some(FileEntry {
+                    name: name.clone(),
+                    path: name.clone(),
+                    is_dir,
+                    size: if is_dir { 0 } else { metadata.len() },
+                    mtime: mtime_secs(&metadata),
+                    encoded_path: encode_os_path(path.as_os_str()),
+                })
+            })
+            .collect();
+
+        total += entries.len();
+        send(entries);
+    }
+
+    Ok(total)
+}
+
+/// A folder from the recent folders list
+#[derive(Serialize, Debug)]
+struct RecentFolder {
+    path: String,
+    last_visited: String,
+    visit_count: i64,
+}
+
+fn record_folder_visit(conn: &Connection, dir: &str) -> Result<(), rusqlite::Error> {
+    let now = now_timestamp();
+    conn.prepare_cached(
+        "INSERT INTO recent_folders (path, last_visited) VALUES (?1, ?2)
+         ON CONFLICT(path) DO UPDATE SET
+             last_visited = excluded.last_visited,
+             visit_count = visit_count + 1",
+    )?
+    .execute(params![normalize_path(dir), now])?;
+    Ok(())
+}
+
+/// Recently browsed folders, most recent first (ties go to the most
+/// visited). Folders that no longer exist are left out.
+#[tauri::command]
+#[tracing::instrument(skip(state), err)]
+fn db_recent_folders(state: State<DbState>, limit: i32) -> Result<Vec<RecentFolder>, CommandError> {
+    state.with_read_conn(|conn| recent_folders(conn, limit).map_err(CommandError::from))
+}
+
+fn recent_folders(conn: &Connection, limit: i32) -> Result<Vec<RecentFolder>, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT path, last_visited, visit_count FROM recent_folders
+         ORDER BY last_visited DESC, visit_count DESC",
+    )?;
+
+    let folders = stmt
+        .query_map([], |row| {
+            Ok(RecentFolder {
+                path: row.get(0)?,
+                last_visited: row.get(1)?,
+                visit_count: row.get(2)?,
+            })
+        })?
+        .filter(|folder| folder.as_ref().map_or(true, |f| Path::new(&f.path).is_dir()))
+        .take(clamp_limit(Some(limit)) as usize)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(folders)
+}
+
+/// Forget all recently browsed folders
+#[tauri::command]
+#[tracing::instrument(skip(state), err)]
+fn db_clear_recent_folders(state: State<DbState>) -> Result<(), CommandError> {
+    state.with_conn(|conn| {
+        conn.execute("DELETE FROM recent_folders", [])?;
+        Ok(())
+    })
+}
+
+/// Settings shared by every level of a browse_directory listing
+struct BrowseOptions {
+    max_depth: Option<u32>,
+    filter: FileFilter,
+    sort: BrowseSort,
+}
+
+/// List one directory level into `entries`, recursing into subdirectories
+/// while `depth` is within `max_depth`
+fn browse_into(
+    dir: &Path,
+    prefix: &str,
+    depth: u32,
+    options: &BrowseOptions,
+    visited: &mut std::collections::HashSet<std::path::PathBuf>,
+    entries: &mut Vec<FileEntry>,
+) -> Result<(), String> {
+    let mut level: Vec<(FileEntry, std::path::PathBuf)> = Vec::new();
+
+    let read_dir = fs::read_dir(dir).map_err(|e| format!("Failed to read directory: {}", e))?;
+
+    for entry in read_dir {
+        let entry = entry.map_err(|e| format!("Failed to read entry: {}", e))?;
+        let name = entry.file_name().to_string_lossy().to_string();
+
+        // Skip hidden files (starting with .)
+        if name.starts_with('.') {
+            continue;
+        }
+
+        // Follow symlinks so linked folders show as folders; fall back to the
+        // link itself if it is broken
+        let metadata = fs::metadata(entry.path())
+            .or_else(|_| entry.metadata())
+            .map_err(|e| format!("Failed to get metadata: {}", e))?;
+
+        let is_dir = metadata.is_dir();
+        let size = if is_dir { 0 } else { metadata.len() };
+        let mtime = mtime_secs(&metadata);
+
+        // Apply filter for files (directories always included)
+        if !is_dir && !options.filter.matches(&name) {
+            continue;
+        }
+
+        let path = if prefix.is_empty() {
+            name.clone()
+        } else {
+            format!("{}/{}", prefix, name)
+        };
+
+        let encoded_path = encode_os_path(entry.path().as_os_str());
+        level.push((FileEntry { name, path, is_dir, size, mtime, encoded_path }, entry.path()));
+    }
+
+    // Sort: directories first, then by the chosen key, then alphabetically
+    level.sort_by(|(a, _), (b, _)| {
+        let by_name = || a.name.to_lowercase().cmp(&b.name.to_lowercase());
+        match (a.is_dir, b.is_dir) {
+            (true, false) => std::cmp::Ordering::Less,
+            (false, true) => std::cmp::Ordering::Greater,
+            _ => match options.sort {
+                BrowseSort::Name => by_name(),
+                BrowseSort::Size => b.size.cmp(&a.size).then_with(by_name),
+                BrowseSort::Modified => b.mtime.cmp(&a.mtime).then_with(by_name),
+            },
+        }
+    });
+
+    let descend = options.max_depth.map(|max| depth < max).unwrap_or(true);
+
+    for (entry, full_path) in level {
+        let child_prefix = entry.path.clone();
+        let is_dir = entry.is_dir;
+        entries.push(entry);
+
+        if is_dir && descend {
+            let first_visit = full_path
+                .canonicalize()
+                .map(|canonical| visited.insert(canonical))
+                .unwrap_or(false);
+            if first_visit {
+                browse_into(&full_path, &child_prefix, depth + 1, options, visited, entries)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Largest image file load_image, save_image and thumbnailing accept when
+/// defaults.json doesn't say
+const DEFAULT_MAX_IMAGE_BYTES: u64 = 200 * 1024 * 1024;
+/// Longest image edge, in pixels, accepted by default
+const DEFAULT_MAX_IMAGE_DIMENSION: u32 = 50_000;
+/// Most pixels (width x height) accepted by default; decoded as RGBA this
+/// is 600 MB
+const DEFAULT_MAX_IMAGE_PIXELS: u64 = 150_000_000;
+
+/// Size limits checked before an image is read in full or decoded, so a
+/// huge (or malicious) image is turned away with TooLarge instead of
+/// exhausting memory. From `images` in defaults.json.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ImageLimits {
+    max_bytes: u64,
+    max_dimension: u32,
+    max_pixels: u64,
+}
+
+impl Default for ImageLimits {
+    fn default() -> Self {
+        ImageLimits::from_config(&Defaults::default())
+    }
+}
+
+impl ImageLimits {
+    fn from_config(config: &Defaults) -> Self {
+        ImageLimits {
+            max_bytes: config.images.max_file_bytes,
+            max_dimension: config.images.max_dimension,
+            max_pixels: config.images.max_pixels,
+        }
+    }
+
+    fn check_bytes(&self, len: u64) -> Result<(), CommandError> {
+        if len > self.max_bytes {
+            return Err(CommandError::TooLarge(format!(
+                "Image is {} bytes, more than the limit of {}",
+                len, self.max_bytes
+            )));
+        }
+        Ok(())
+    }
+
+    fn check_dimensions(&self, width: u32, height: u32) -> Result<(), CommandError> {
+        if width.max(height) > self.max_dimension || width as u64 * height as u64 > self.max_pixels {
+            return Err(CommandError::TooLarge(format!(
+                "Image is {}x{} pixels, more than the limit of {} per side or {} in total",
+                width, height, self.max_dimension, self.max_pixels
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Check an image file against `limits` without reading it in full: its
+/// size first, then the dimensions from its header. Formats the image
+/// crate doesn't recognise (e.g. SVG) only have their size checked.
+fn check_image_file(path: &Path, limits: &ImageLimits) -> Result<(), CommandError> {
+    let len = fs::metadata(path).map_err(|e| CommandError::io("Failed to read file", e))?.len();
+    limits.check_bytes(len)?;
+
+    let reader = image::ImageReader::open(path)
+        .map_err(|e| CommandError::io("Failed to read file", e))?
+        .with_guessed_format()
+        .map_err(|e| CommandError::io("Failed to read file", e))?;
+    if reader.format().is_some() {
+        if let Ok((width, height)) = reader.into_dimensions() {
+            limits.check_dimensions(width, height)?;
+        }
+    }
+    Ok(())
+}
+
+/// Decode an image file, refusing ones over `limits` before decoding
+fn decode_image_file(path: &Path, limits: &ImageLimits) -> Result<image::DynamicImage, CommandError> {
+    check_image_file(path, limits)?;
+    image::open(path).map_err(|e| CommandError::Parse(format!("Failed to decode image: {}", e)))
+}
+
+/// Decode an image from memory, refusing ones over `limits` before decoding
+fn decode_image_bytes(bytes: &[u8], limits: &ImageLimits) -> Result<image::DynamicImage, CommandError> {
+    limits.check_bytes(bytes.len() as u64)?;
+    let reader = image::ImageReader::new(std::io::Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(|e| CommandError::io("Failed to read image", e))?;
+    let (width, height) = reader
+        .into_dimensions()
+        .map_err(|e| CommandError::Parse(format!("Failed to decode image: {}", e)))?;
+    limits.check_dimensions(width, height)?;
+    image::load_from_memory(bytes).map_err(|e| CommandError::Parse(format!("Failed to decode image: {}", e)))
+}
+
+/// Load an image file and return as base64-encoded data URL.
+/// The data URL format (data:image/png;base64,...) can be used directly
+/// as an <img> src or drawn onto a canvas.
+/// With `placeholder`, the image is checked to decode and a missing or
+/// unreadable one gets a placeholder tile instead of an error (see LoadedImage).
+/// Images over the `images` limits in defaults.json fail with TooLarge (or
+/// get the placeholder).
+#[tauri::command]
+#[tracing::instrument(skip(app_handle), err)]
+fn load_image(
+    app_handle: tauri::AppHandle,
+    path: String,
+    placeholder: Option<bool>,
+) -> Result<LoadedImage, CommandError> {
+    let file_path = &fs_path(&path)?;
+    let limits = ImageLimits::from_config(&load_defaults_typed(&app_handle));
+
+    if !placeholder.unwrap_or(false) {
+        if !file_path.exists() {
+            return Err(CommandError::NotFound(format!("File does not exist: {}", file_path.display())));
+        }
+        check_image_file(file_path, &limits)?;
+        return Ok(LoadedImage::DataUrl(image_data_url(file_path)?));
+    }
+
+    let mut image = or_placeholder(checked_image_data_url(file_path, &limits), file_path, PLACEHOLDER_IMAGE_SIZE);
+    image.mislabeled =
+        !image.placeholder && data_url_mime(&image.data_url).is_some_and(|mime| is_mislabeled(file_path, mime));
+    Ok(LoadedImage::WithFallback(image))
+}
+
+/// Like image_data_url, but fails unless the image's header decodes and
+/// the image is within `limits`
+fn checked_image_data_url(file_path: &Path, limits: &ImageLimits) -> Result<String, CommandError> {
+    let len = fs::metadata(file_path).map_err(|e| CommandError::io("Failed to read file", e))?.len();
+    limits.check_bytes(len)?;
+    let (width, height) = image::ImageReader::open(file_path)
+        .map_err(|e| CommandError::io("Failed to read file", e))?
+        .with_guessed_format()
+        .map_err(|e| CommandError::io("Failed to read file", e))?
+        .into_dimensions()
+        .map_err(|e| CommandError::Parse(format!("Failed to decode image: {}", e)))?;
+    limits.check_dimensions(width, height)?;
+    image_data_url(file_path)
+}
+
+/// Side of the placeholder tile load_image returns, in pixels
+const PLACEHOLDER_IMAGE_SIZE: u32 = 256;
+
+/// What load_image and load_thumbnail return: just the data URL normally,
+/// or when called with `placeholder`, the data URL with whether it is a
+/// placeholder tile and the error that made it one
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(untagged)]
+enum LoadedImage {
+    DataUrl(String),
+    WithFallback(ImageLoad),
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct ImageLoad {
+    data_url: String,
+    placeholder: bool,
+    error: Option<String>,
+    /// The file's content is a different format than its extension says
+    /// (e.g. a JPEG named .png); the data URL has the real type
+    mislabeled: bool,
+}
+
+/// The loaded data URL, or a placeholder tile for `path` carrying the error
+fn or_placeholder(loaded: Result<String, CommandError>, path: &Path, size: u32) -> ImageLoad {
+    match loaded {
+        Ok(data_url) => ImageLoad {
+            data_url,
+            placeholder: false,
+            error: None,
+            mislabeled: false,
+        },
+        Err(e) => {
+            tracing::debug!(path = %path.display(), "Showing placeholder: {}", e);
+            ImageLoad {
+                data_url: placeholder_data_url(path, size),
+                placeholder: true,
+                error: Some(e.to_string()),
+                mislabeled: false,
+            }
+        }
+    }
+}
+
+/// A grey `size` x `size` tile labelled with the file's extension (or "?"
+/// when it has none), as an SVG data URL
+fn placeholder_data_url(path: &Path, size: u32) -> String {
+    // Only letters and digits, so nothing needs escaping in the SVG
+    let label: String = path
+        .extension()
+        .map(|e| e.to_string_lossy().chars().filter(char::is_ascii_alphanumeric).take(5).collect::<String>())
+        .filter(|e| !e.is_empty())
+        .map_or_else(|| "?".to_string(), |e| e.to_uppercase());
+    let svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{size}\" height=\"{size}\" viewBox=\"0 0 100 100\">\
+         <rect width=\"100\" height=\"100\" fill=\"#374151\"/>\
+         <rect x=\"8\" y=\"8\" width=\"84\" height=\"84\" fill=\"none\" stroke=\"#6b7280\" stroke-width=\"2\" stroke-dasharray=\"6 4\"/>\
+         <text x=\"50\" y=\"58\" font-family=\"sans-serif\" font-size=\"20\" font-weight=\"bold\" fill=\"#9ca3af\" text-anchor=\"middle\">{label}</text>\
+         </svg>"
+    );
+    format!("data:image/svg+xml;base64,{}", STANDARD.encode(svg))
+}
+
+/// Read an image file as a base64 data URL, with the MIME type taken from
+/// its extension
+fn image_data_url(file_path: &Path) -> Result<String, CommandError> {
+    let data = fs::read(file_path).map_err(|e| CommandError::io("Failed to read file", e))?;
+
+    let mime_type = image_mime(&data, file_path);
+    if is_mislabeled(file_path, mime_type) {
+        tracing::warn!(path = %file_path.display(), "Image content is {}, not what its extension says", mime_type);
+    }
+
+    let base64_data = STANDARD.encode(&data);
+    Ok(format!("data:{};base64,{}", mime_type, base64_data))
+}
+
+/// MIME type an image's extension claims, if it's one we know
+fn extension_mime(path: &Path) -> Option<&'static str> {
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+    match extension.as_str() {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "webp" => Some("image/webp"),
+        "bmp" => Some("image/bmp"),
+        _ => None,
+    }
+}
+
+/// MIME type of image data, sniffed from its leading bytes. The extension
+/// is only used when the bytes aren't a format we recognise.
+fn image_mime(data: &[u8], path: &Path) -> &'static str {
+    image::guess_format(data)
+        .ok()
+        .map(|format| format.to_mime_type())
+        .or_else(|| extension_mime(path))
+        .unwrap_or("application/octet-stream")
+}
+
+/// Whether `path`'s extension names a different format than `mime`
+fn is_mislabeled(path: &Path, mime: &str) -> bool {
+    extension_mime(path).is_some_and(|claimed| claimed != mime)
+}
+
+/// Free space kept in reserve when checking a save will fit, so the disk
+/// isn't filled to the last byte
+const DISK_SPACE_HEADROOM_BYTES: u64 = 10 * 1024 * 1024;
+
+/// Check there is room on the target volume for `needed` bytes (plus
+/// headroom) before writing, so a nearly-full disk gives a clear error
+/// instead of failing partway through the write.
+/// If free space can't be determined the check is skipped.
+fn ensure_disk_space(path: &Path, needed: u64) -> Result<(), CommandError> {
+    // The file and its parent directories may not exist yet
+    let existing = match path.ancestors().skip(1).find(|p| p.is_dir()) {
+        Some(dir) => dir,
+        None => return Ok(()),
+    };
+
+    let available = match fs2::available_space(existing) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::debug!(path = %existing.display(), error = %e, "couldn't read free disk space");
+            return Ok(());
+        }
+    };
+
+    if needed.saturating_add(DISK_SPACE_HEADROOM_BYTES) > available {
+        let mb = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+        return Err(CommandError::Io(format!(
+            "Not enough disk space to save {}: {:.1} MB needed, {:.1} MB free",
+            path.display(),
+            mb(needed),
+            mb(available)
+        )));
+    }
+
+    Ok(())
+}
+
+/// Carry an existing file's permissions, ownership and access time over to
+/// its replacement. Ownership can only be kept where the process is allowed
+/// to chown, so that part is best effort. The modify time is left as the
+/// time of the save.
+fn copy_file_attributes(original: &fs::Metadata, replacement: &fs::File) -> std::io::Result<()> {
+    replacement.set_permissions(original.permissions())?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let _ = std::os::unix::fs::fchown(replacement, Some(original.uid()), Some(original.gid()));
+    }
+
+    if let Ok(accessed) = original.accessed() {
+        let _ = replacement.set_times(fs::FileTimes::new().set_accessed(accessed));
+    }
+    Ok(())
+}
+
+/// Write a file atomically: the content goes to a temporary file next to the
+/// destination, which is synced and then renamed over it. Readers never see a
+/// half-written file, and a failed write leaves any existing file untouched.
+/// An existing file keeps its permissions (see copy_file_attributes); a new
+/// one gets the platform default.
+/// Creates parent directories if they don't exist.
+fn write_atomic<F>(path: &Path, write: F) -> Result<(), CommandError>
+where
+    F: FnOnce(&mut std::io::BufWriter<fs::File>) -> std::io::Result<()>,
+{
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| CommandError::io("Failed to create directories", e))?;
+    }
+
+    try_write_atomic(path, write).map_err(|e| CommandError::io("Failed to write file", e))
+}
+
+/// write_atomic of `bytes`, retrying transient failures per `retry`
+fn write_atomic_with_retry(path: &Path, bytes: &[u8], retry: &RetryPolicy) -> Result<(), CommandError> {
+    if let Some(parent) = path.parent() {
+        retry
+            .run(|| fs::create_dir_all(parent))
+            .map_err(|e| CommandError::io("Failed to create directories", e))?;
+    }
+
+    retry
+        .run(|| try_write_atomic(path, |w| w.write_all(bytes)))
+        .map_err(|e| CommandError::io("Failed to write file", e))
+}
+
+/// One attempt at write_atomic's temporary-file-and-rename; the temporary
+/// file is removed again if any step fails
+fn try_write_atomic<F>(path: &Path, write: F) -> std::io::Result<()>
+where
+    F: FnOnce(&mut std::io::BufWriter<fs::File>) -> std::io::Result<()>,
+{
+    let tmp_path = path_with_suffix(path, ".tmp");
+    let original = fs::metadata(path).ok();
+
+    let result = (|| {
+        let mut writer = std::io::BufWriter::new(fs::File::create(&tmp_path)?);
+        write(&mut writer)?;
+        let file = writer.into_inner().map_err(|e| e.into_error())?;
+        if let Some(original) = &original {
+            copy_file_attributes(original, &file)?;
+        }
+        file.sync_all()?;
+        fs::rename(&tmp_path, path)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+/// How document loads and saves retry transient I/O failures, which are
+/// common on network shares. From `fileRetry` in defaults.json; the default
+/// of zero attempts means no retries.
+#[derive(Debug, Clone, PartialEq)]
+struct RetryPolicy {
+    /// Retries after the first failure
+    attempts: u32,
+    /// Wait before the first retry; doubled for each one after that
+    initial_delay: std::time::Duration,
+    /// Upper bound on the wait between retries
+    max_delay: std::time::Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::from_config(&Defaults::default())
+    }
+}
+
+impl RetryPolicy {
+    fn from_config(config: &Defaults) -> Self {
+        let configured = &config.file_retry;
+        RetryPolicy {
+            attempts: configured.attempts,
+            initial_delay: std::time::Duration::from_millis(configured.initial_delay_ms),
+            max_delay: std::time::Duration::from_millis(configured.max_delay_ms),
+        }
+    }
+
+    /// Run `op`, retrying it with exponential backoff while it fails with a
+    /// transient error. Other errors, and the last transient one, are returned.
+    fn run<T>(&self, mut op: impl FnMut() -> std::io::Result<T>) -> std::io::Result<T> {
+        let mut delay = self.initial_delay;
+        let mut retries = 0;
+        loop {
+            match op() {
+                Err(e) if retries < self.attempts && is_transient_io_error(&e) => {
+                    retries += 1;
+                    tracing::warn!(error = %e, retry = retries, "Transient file error, retrying");
+                    std::thread::sleep(delay);
+                    delay = (delay * 2).min(self.max_delay);
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Whether an I/O error is worth retrying: timeouts, busy or temporarily
+/// unavailable resources, and dropped network connections. Missing files
+/// and permission errors fail straight away.
+fn is_transient_io_error(error: &std::io::Error) -> bool {
+    use std::io::ErrorKind;
+
+    matches!(
+        error.kind(),
+        ErrorKind::TimedOut
+            | ErrorKind::WouldBlock
+            | ErrorKind::Interrupted
+            | ErrorKind::ResourceBusy
+            | ErrorKind::StaleNetworkFileHandle
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::NetworkDown
+    )
+}
+
+/// Format and size of an image written by save_image
+#[derive(Serialize, Debug, PartialEq)]
+struct SavedImage {
+    format: String,
+    width: u32,
+    height: u32,
+}
+
+/// The MIME type a data URL declares ("data:image/png;base64,..." -> "image/png")
+fn data_url_mime(data: &str) -> Option<&str> {
+    let header = data.strip_prefix("data:")?.split(',').next()?;
+    header.split(';').next().filter(|mime| !mime.is_empty())
+}
+
+/// Check that decoded bytes are an image this app can read, and that they
+/// are the type a data URL declared. Only the image header is parsed.
+fn inspect_image(bytes: &[u8], declared_mime: Option<&str>) -> Result<SavedImage, CommandError> {
+    let format =
+        image::guess_format(bytes).map_err(|_| CommandError::Parse("Data is not a recognised image".to_string()))?;
+    if let Some(mime) = declared_mime {
+        if image::ImageFormat::from_mime_type(mime) != Some(format) {
+            return Err(CommandError::Validation(format!(
+                "Image type mismatch: data URL says {} but the data is {}",
+                mime,
+                format.to_mime_type()
+            )));
+        }
+    }
+
+    let (width, height) = image::ImageReader::with_format(std::io::Cursor::new(bytes), format)
+        .into_dimensions()
+        .map_err(|e| CommandError::Parse(format!("Invalid image data: {}", e)))?;
+
+    Ok(SavedImage {
+        format: format.extensions_str()[0].to_string(),
+        width,
+        height,
+    })
+}
+
+/// Save base64-encoded image data to a file.
+/// Accepts data URL format (strips the "data:image/png;base64," prefix).
+/// The bytes must be a readable image of the declared type; they are
+/// written to a temp file and renamed into place, so a failed save never
+/// leaves a broken image behind. Creates parent directories if needed.
+/// Images over the `images` limits in defaults.json fail with TooLarge.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle, data), err)]
+fn save_image(app_handle: tauri::AppHandle, path: String, data: String) -> Result<SavedImage, CommandError> {
+    ensure_write_allowed(&app_handle, Path::new(&path))?;
+    let config = load_defaults_typed(&app_handle);
+    write_image(path, data, &RetryPolicy::from_config(&config), &ImageLimits::from_config(&config))
+}
+
+fn write_image(
+    path: String,
+    data: String,
+    retry: &RetryPolicy,
+    limits: &ImageLimits,
+) -> Result<SavedImage, CommandError> {
+    // Base64 is 4 characters for every 3 bytes, so an oversized payload is
+    // refused before it's decoded
+    limits.check_bytes(data.len() as u64 / 4 * 3)?;
+    let decoded = decode_data_url(&data)?;
+    limits.check_bytes(decoded.len() as u64)?;
+    let image = inspect_image(&decoded, data_url_mime(&data))?;
+    limits.check_dimensions(image.width, image.height)?;
+
+    ensure_disk_space(Path::new(&path), decoded.len() as u64)?;
+    write_atomic_with_retry(Path::new(&path), &decoded, retry)?;
+
+    Ok(image)
+}
+
+/// Error from save_clipboard_image, tagged like DirectoryError so the
+/// frontend can tell an empty clipboard from a failed write
+#[derive(Serialize, Debug)]
+#[serde(tag = "kind", content = "message")]
+enum ClipboardImageError {
+    NoImage(String),
+    Io(String),
+}
+
+/// Where save_clipboard_image wrote the image, and its size in pixels
+#[derive(Serialize, Debug)]
+struct SavedClipboardImage {
+    path: String,
+    width: u32,
+    height: u32,
+}
+
+/// Save the image currently on the clipboard as a PNG file
+#[tauri::command]
+#[tracing::instrument(skip(app_handle), err(Debug))]
+fn save_clipboard_image(app_handle: tauri::AppHandle, path: String) -> Result<SavedClipboardImage, ClipboardImageError> {
+    use tauri_plugin_clipboard_manager::ClipboardExt;
+
+    ensure_write_allowed(&app_handle, Path::new(&path)).map_err(|e| ClipboardImageError::Io(e.to_string()))?;
+
+    let image = app_handle
+        .clipboard()
+        .read_image()
+        .map_err(|e| ClipboardImageError::NoImage(format!("No image in clipboard: {}", e)))?;
+    if image.width() == 0 || image.height() == 0 {
+        return Err(ClipboardImageError::NoImage("No image in clipboard".to_string()));
+    }
+
+    save_rgba_png(Path::new(&path), image.rgba().to_vec(), image.width(), image.height())
+        .map_err(ClipboardImageError::Io)?;
+
+    Ok(SavedClipboardImage {
+        path,
+        width: image.width(),
+        height: image.height(),
+    })
+}
+
+/// Encode raw RGBA pixels as PNG and write them atomically
+fn save_rgba_png(path: &Path, rgba: Vec<u8>, width: u32, height: u32) -> Result<(), String> {
+    let image = image::RgbaImage::from_raw(width, height, rgba)
+        .ok_or_else(|| format!("Image data doesn't match its size ({}x{})", width, height))?;
+
+    let mut png = Vec::new();
+    image
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .map_err(|e| format!("Failed to encode PNG: {}", e))?;
+
+    ensure_disk_space(path, png.len() as u64)?;
+
+    // Create parent directories if they don't exist
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to create directories: {}", e))?;
+    }
+
+    write_atomic(path, |writer| writer.write_all(&png)).map_err(|e| e.to_string())
+}
+
+/// Load a .ssce JSON file and return its contents
+#[tauri::command]
+#[tracing::instrument(skip(app_handle), err)]
+fn load_ssce(app_handle: tauri::AppHandle, path: String) -> Result<String, CommandError> {
+    read_ssce(&path, &RetryPolicy::from_config(&load_defaults_typed(&app_handle)))
+}
+
+fn read_ssce(path: &str, retry: &RetryPolicy) -> Result<String, CommandError> {
+    let file_path = &fs_path(path)?;
+
+    if !file_path.exists() {
+        return Err(CommandError::NotFound(format!("File does not exist: {}", file_path.display())));
+    }
+
+    if is_encrypted_ssce(file_path) {
+        return Err(CommandError::Validation(format!(
+            "File is encrypted - a passphrase is needed to open it: {}",
+            file_path.display()
+        )));
+    }
+
+    let bytes = retry
+        .run(|| fs::read(file_path))
+        .map_err(|e| CommandError::io("Failed to read file", e))?;
+    document_text(bytes)
+}
+
+/// Files smaller than this are parsed whole by load_ssce_header; streaming
+/// only pays off once snapshot images dominate the file
+const HEADER_STREAM_MIN_BYTES: u64 = 1024 * 1024;
+
+/// The parts of a .ssce file the library grid needs. Everything else
+/// (layers, snapshots) is skipped while parsing, never held in memory.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct SsceHeader {
+    #[serde(default, alias = "schemaVersion")]
+    version: Option<String>,
+    #[serde(default)]
+    front_matter: Option<serde_json::Value>,
+    #[serde(default)]
+    thumbnail: Option<String>,
+}
+
+/// Fills an SsceHeader from the top-level object, stopping as soon as all
+/// of its fields have been read. The app writes them before `layers` and
+/// `snapshots`, so for its own files parsing ends within the first few KB;
+/// other files are skipped through to the end.
+struct SsceHeaderVisitor<'a> {
+    header: &'a mut SsceHeader,
+    complete: &'a mut bool,
+}
+
+impl<'de> serde::de::Visitor<'de> for SsceHeaderVisitor<'_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a .ssce JSON object")
+    }
+
+    fn visit_map<A: serde::de::MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let (mut version, mut front_matter, mut thumbnail) = (false, false, false);
+
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "version" | "schemaVersion" => (self.header.version, version) = (map.next_value()?, true),
+                "frontMatter" => (self.header.front_matter, front_matter) = (map.next_value()?, true),
+                "thumbnail" => (self.header.thumbnail, thumbnail) = (map.next_value()?, true),
+                _ => {
+                    map.next_value::<serde::de::IgnoredAny>()?;
+                }
+            }
+
+            if version && front_matter && thumbnail {
+                *self.complete = true;
+                return Ok(());
+            }
+        }
+
+        *self.complete = true;
+        Ok(())
+    }
+}
+
+/// Read a document's version, front matter and thumbnail without loading
+/// its snapshot images. Large files are parsed straight from disk (through
+/// the gzip decoder if compressed) and reading stops once the header
+/// fields are found; small ones are simply read whole.
+fn read_ssce_header(path: &Path) -> Result<SsceHeader, CommandError> {
+    use serde::Deserializer;
+
+    if is_encrypted_ssce(path) {
+        return Err(CommandError::Validation(format!("File is encrypted: {}", path.display())));
+    }
+
+    let size = fs::metadata(path).map_err(|e| CommandError::io("Failed to read file", e))?.len();
+    if size < HEADER_STREAM_MIN_BYTES {
+        return serde_json::from_str(&read_document_text(path)?)
+            .map_err(|e| CommandError::Parse(format!("Failed to parse JSON: {}", e)));
+    }
+
+    let file = std::io::BufReader::new(fs::File::open(path).map_err(|e| CommandError::io("Failed to read file", e))?);
+    let reader: Box<dyn std::io::Read> = if is_gzipped(path) {
+        Box::new(std::io::BufReader::new(flate2::read::GzDecoder::new(file)))
+    } else {
+        Box::new(file)
+    };
+
+    let mut header = SsceHeader::default();
+    let mut complete = false;
+    let parsed = serde_json::Deserializer::from_reader(reader).deserialize_map(SsceHeaderVisitor {
+        header: &mut header,
+        complete: &mut complete,
+    });
+
+    // Stopping early leaves the object unterminated, which the parser reports
+    // as an error - the header is still good if every field was read
+    match parsed {
+        Err(e) if !complete => Err(CommandError::Parse(format!("Failed to parse JSON: {}", e))),
+        _ => Ok(header),
+    }
+}
+
+/// Load just the version, front matter and thumbnail of a .ssce file, for
+/// listings that don't need the snapshots
+#[tauri::command]
+#[tracing::instrument(err)]
+fn load_ssce_header(path: String) -> Result<SsceHeader, CommandError> {
+    let file_path = &fs_path(&path)?;
+
+    if !file_path.exists() {
+        return Err(CommandError::NotFound(format!("File does not exist: {}", file_path.display())));
+    }
+
+    read_ssce_header(file_path)
+}
+
+/// Error from save_ssce, tagged like DirectoryError so the frontend can
+/// tell a read-only location (offer Save As) from a failed write
+#[derive(Serialize, Debug)]
+#[serde(tag = "kind", content = "message")]
+enum SaveError {
+    ReadOnly(String),
+    OutsideRoot(String),
+    Io(String),
+    /// `canonical` was requested but the data isn't valid JSON
+    Invalid(String),
+}
+
+/// Whether the effective user may write to `path` (or, for a new file, to
+/// the folder it would be created in)
+#[cfg(unix)]
+fn can_write(path: &Path) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+
+    let Ok(c_path) = std::ffi::CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    // SAFETY: c_path is a valid NUL-terminated string for the whole call
+    unsafe { libc::access(c_path.as_ptr(), libc::W_OK) == 0 }
+}
+
+/// Whether the effective user may write to `path` (or, for a new file, to
+/// the folder it would be created in)
+#[cfg(not(unix))]
+fn can_write(path: &Path) -> bool {
+    if path.is_dir() {
+        // Folder ACLs can only be checked by trying
+        let probe = path.join(format!(".ssce-write-test-{}", std::process::id()));
+        let created = fs::OpenOptions::new().write(true).create_new(true).open(&probe).is_ok();
+        let _ = fs::remove_file(&probe);
+        created
+    } else {
+        // Opening for append checks the ACLs without touching the content
+        fs::OpenOptions::new().append(true).open(path).is_ok()
+    }
+}
+
+/// Why a document at `path` can't be saved, or None if it can.
+/// The folder has to be writable too, since lock files and new documents
+/// are created alongside. Missing folders are checked at the nearest
+/// existing ancestor, which is where save_ssce will create them.
+fn write_blocker(path: &Path) -> Option<String> {
+    if let Ok(meta) = fs::metadata(path) {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+
+            // SAFETY: geteuid has no preconditions and cannot fail
+            let euid = unsafe { libc::geteuid() };
+            if meta.uid() != euid && !can_write(path) {
+                return Some(format!("File is owned by another user: {}", path.display()));
+            }
+        }
+
+        if meta.permissions().readonly() {
+            return Some(format!("File is read-only: {}", path.display()));
+        }
+        if !can_write(path) {
+            return Some(format!("No permission to write the file: {}", path.display()));
+        }
+    }
+
+    let folder = path.ancestors().skip(1).find(|p| p.is_dir())?;
+    if !can_write(folder) {
+        return Some(format!("No permission to write to the folder: {}", folder.display()));
+    }
+
+    None
+}
+
+/// Whether a document can be saved back to `path`, so the UI can disable
+/// editing before the user makes changes they can't keep
+#[tauri::command]
+fn file_writable(path: String) -> bool {
+    write_blocker(Path::new(&path)).is_none()
+}
+
+/// Save JSON data to a .ssce file. With `compress` the JSON is gzipped;
+/// every reader detects that from the content, so the file name can stay
+/// the same (or use .ssce.gz).
+/// With `canonical` the JSON is rewritten with sorted keys and standard
+/// formatting first (see canonicalize_ssce), so saves of the same document
+/// are byte-identical and diff cleanly.
+/// Fails with ReadOnly before writing anything if the file or its folder
+/// can't be written, and with OutsideRoot if it isn't in an allowed root.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle, data), err(Debug))]
+fn save_ssce(
+    app_handle: tauri::AppHandle,
+    path: String,
+    data: String,
+    compress: Option<bool>,
+    canonical: Option<bool>,
+) -> Result<(), SaveError> {
+    ensure_write_allowed(&app_handle, Path::new(&path)).map_err(|e| SaveError::OutsideRoot(e.to_string()))?;
+    let retry = RetryPolicy::from_config(&load_defaults_typed(&app_handle));
+    let data = if canonical.unwrap_or(false) {
+        canonicalize_ssce(&data).map_err(|e| SaveError::Invalid(format!("Invalid document JSON: {}", e)))?
+    } else {
+        data
+    };
+    write_ssce(path.clone(), data, compress, &retry)?;
+    app_handle.state::<DocumentWatchers>().record_write(&path);
+    Ok(())
+}
+
+fn write_ssce(path: String, data: String, compress: Option<bool>, retry: &RetryPolicy) -> Result<(), SaveError> {
+    if let Some(reason) = write_blocker(Path::new(&path)) {
+        return Err(SaveError::ReadOnly(reason));
+    }
+
+    ensure_disk_space(Path::new(&path), data.len() as u64).map_err(|e| SaveError::Io(e.to_string()))?;
+
+    let bytes = if compress.unwrap_or(false) {
+        gzip(data.as_bytes()).map_err(SaveError::Io)?
+    } else {
+        data.into_bytes()
+    };
+
+    write_atomic_with_retry(Path::new(&path), &bytes, retry).map_err(|e| SaveError::Io(e.to_string()))
+}
+
+/// Prefix identifying an encrypted .ssce file. The layout is
+/// magic | Argon2id memory (KiB), iterations, parallelism (u32 LE each) |
+/// Argon2 salt | XChaCha20-Poly1305 nonce | ciphertext
+const ENCRYPTED_SSCE_MAGIC: &[u8] = b"SSCEENC2";
+/// Prefix of encrypted files from before the header recorded the key
+/// derivation parameters: magic | salt | nonce | ciphertext, with the key
+/// derived using LEGACY_KDF
+const ENCRYPTED_SSCE_MAGIC_V1: &[u8] = b"SSCEENC1";
+const ENCRYPTION_SALT_LEN: usize = 16;
+const ENCRYPTION_NONCE_LEN: usize = 24;
+
+/// Argon2id cost used for new encrypted files
+const ENCRYPTION_KDF: KdfParams = KdfParams { memory_kib: 19 * 1024, iterations: 2, parallelism: 1 };
+/// The argon2 crate's defaults when SSCEENC1 files were written
+const LEGACY_KDF: KdfParams = KdfParams { memory_kib: 19 * 1024, iterations: 2, parallelism: 1 };
+/// How far past ENCRYPTION_KDF a file's header may go. Room to raise the
+/// cost for new files later, while a crafted header can only cost a few
+/// times a normal open.
+const MAX_KDF_FACTOR: u32 = 4;
+/// Most memory a file's header may ask the key derivation for, so a
+/// crafted file can't exhaust it
+const MAX_KDF_MEMORY_KIB: u32 = ENCRYPTION_KDF.memory_kib * MAX_KDF_FACTOR;
+/// Most Argon2 passes a header may ask for, so a crafted file can't hang
+/// the loader
+const MAX_KDF_ITERATIONS: u32 = ENCRYPTION_KDF.iterations * MAX_KDF_FACTOR;
+/// Most Argon2 lanes a header may ask for
+const MAX_KDF_PARALLELISM: u32 = ENCRYPTION_KDF.parallelism * MAX_KDF_FACTOR;
+
+/// Argon2id parameters, stored in each encrypted file so changing the
+/// defaults never makes existing files undecryptable
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct KdfParams {
+    memory_kib: u32,
+    iterations: u32,
+    parallelism: u32,
+}
+
+impl KdfParams {
+    const ENCODED_LEN: usize = 12;
+
+    fn to_bytes(self) -> [u8; Self::ENCODED_LEN] {
+        let mut bytes = [0u8; Self::ENCODED_LEN];
+        bytes[..4].copy_from_slice(&self.memory_kib.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.iterations.to_le_bytes());
+        bytes[8..].copy_from_slice(&self.parallelism.to_le_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        let word = |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        KdfParams { memory_kib: word(0), iterations: word(4), parallelism: word(8) }
+    }
+
+    fn derive_key(self, passphrase: &str, salt: &[u8]) -> Result<chacha20poly1305::Key, String> {
+        if self.memory_kib > MAX_KDF_MEMORY_KIB {
+            return Err(format!("Key derivation asks for too much memory ({} KiB)", self.memory_kib));
+        }
+        if self.iterations > MAX_KDF_ITERATIONS {
+            return Err(format!("Key derivation asks for too many iterations ({})", self.iterations));
+        }
+        if self.parallelism > MAX_KDF_PARALLELISM {
+            return Err(format!("Key derivation asks for too many lanes ({})", self.parallelism));
+        }
+        let params = argon2::Params::new(self.memory_kib, self.iterations, self.parallelism, None)
+            .map_err(|e| format!("Invalid key derivation parameters: {}", e))?;
+        let argon2 = argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params);
+
+        let mut key = chacha20poly1305::Key::default();
+        argon2
+            .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .map_err(|e| format!("Failed to derive key: {}", e))?;
+        Ok(key)
+    }
+}
+
+/// Whether the file starts with an encrypted .ssce header
+fn is_encrypted_ssce(path: &Path) -> bool {
+    use std::io::Read;
+
+    let mut prefix = [0u8; ENCRYPTED_SSCE_MAGIC.len()];
+    fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut prefix))
+        .map(|_| prefix == ENCRYPTED_SSCE_MAGIC || prefix == ENCRYPTED_SSCE_MAGIC_V1)
+        .unwrap_or(false)
+}
+
+fn encrypt_ssce(data: &[u8], passphrase: &str) -> Result<Vec<u8>, String> {
+    encrypt_ssce_with(data, passphrase, ENCRYPTION_KDF)
+}
+
+fn encrypt_ssce_with(data: &[u8], passphrase: &str, kdf: KdfParams) -> Result<Vec<u8>, String> {
+    use chacha20poly1305::aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng};
+    use chacha20poly1305::XChaCha20Poly1305;
+
+    let mut salt = [0u8; ENCRYPTION_SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let cipher = XChaCha20Poly1305::new(&kdf.derive_key(passphrase, &salt)?);
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+    let ciphertext = cipher
+        .encrypt(&nonce, data)
+        .map_err(|_| "Failed to encrypt file".to_string())?;
+
+    let mut out = Vec::with_capacity(
+        ENCRYPTED_SSCE_MAGIC.len() + KdfParams::ENCODED_LEN + salt.len() + nonce.len() + ciphertext.len(),
+    );
+    out.extend_from_slice(ENCRYPTED_SSCE_MAGIC);
+    out.extend_from_slice(&kdf.to_bytes());
+    out.extend_from_slice(&salt);
+    out.extend_from_slice(&nonce);
+    out.extend_from_slice(&ciphertext);
+    Ok(out)
+}
+
+fn decrypt_ssce(bytes: &[u8], passphrase: &str) -> Result<String, CommandError> {
+    use chacha20poly1305::aead::{Aead, KeyInit};
+    use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+
+    let truncated = || CommandError::Parse("Encrypted file is truncated".to_string());
+    let (kdf, body) = if let Some(body) = bytes.strip_prefix(ENCRYPTED_SSCE_MAGIC) {
+        if body.len() < KdfParams::ENCODED_LEN {
+            return Err(truncated());
+        }
+        let (params, body) = body.split_at(KdfParams::ENCODED_LEN);
+        (KdfParams::from_bytes(params), body)
+    } else if let Some(body) = bytes.strip_prefix(ENCRYPTED_SSCE_MAGIC_V1) {
+        (LEGACY_KDF, body)
+    } else {
+        return Err(CommandError::Validation("File is not an encrypted .ssce file".to_string()));
+    };
+    if body.len() < ENCRYPTION_SALT_LEN + ENCRYPTION_NONCE_LEN {
+        return Err(truncated());
+    }
+
+    let (salt, rest) = body.split_at(ENCRYPTION_SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(ENCRYPTION_NONCE_LEN);
+    let cipher = XChaCha20Poly1305::new(&kdf.derive_key(passphrase, salt).map_err(CommandError::Validation)?);
+
+    // The AEAD tag covers the whole payload, so a wrong passphrase and a
+    // tampered file both end up here
+    let plaintext = cipher
+        .decrypt(XNonce::from_slice(nonce), ciphertext)
+        .map_err(|_| CommandError::Validation("Wrong passphrase or the file is damaged".to_string()))?;
+
+    String::from_utf8(plaintext).map_err(|e| CommandError::Parse(format!("Decrypted file is not valid text: {}", e)))
+}
+
+/// Save JSON data to a .ssce file encrypted with a passphrase
+#[tauri::command]
+#[tracing::instrument(skip(data, passphrase), err)]
+fn save_ssce_encrypted(
+    app_handle: tauri::AppHandle,
+    path: String,
+    data: String,
+    passphrase: String,
+) -> Result<(), CommandError> {
+    ensure_write_allowed(&app_handle, &fs_path(&path)?)?;
+    let retry = RetryPolicy::from_config(&load_defaults_typed(&app_handle));
+    write_ssce_encrypted(path.clone(), data, passphrase, &retry)?;
+    app_handle.state::<DocumentWatchers>().record_write(&path);
+    Ok(())
+}
+
+fn write_ssce_encrypted(
+    path: String,
+    data: String,
+    passphrase: String,
+    retry: &RetryPolicy,
+) -> Result<(), CommandError> {
+    if passphrase.is_empty() {
+        return Err(CommandError::Validation("Passphrase must not be empty".to_string()));
+    }
+
+    let file_path = &fs_path(&path)?;
+    let encrypted = encrypt_ssce(data.as_bytes(), &passphrase).map_err(CommandError::Io)?;
+    ensure_disk_space(file_path, encrypted.len() as u64)?;
+
+    write_atomic_with_retry(file_path, &encrypted, retry)
+}
+
+/// Load and decrypt a .ssce file saved with `save_ssce_encrypted`
+#[tauri::command]
+#[tracing::instrument(skip(passphrase), err)]
+fn load_ssce_encrypted(path: String, passphrase: String) -> Result<String, CommandError> {
+    read_ssce_encrypted(&path, &passphrase)
+}
+
+fn read_ssce_encrypted(path: &str, passphrase: &str) -> Result<String, CommandError> {
+    let file_path = &fs_path(path)?;
+
+    if !file_path.exists() {
+        return Err(CommandError::NotFound(format!("File does not exist: {}", file_path.display())));
+    }
+
+    let bytes = fs::read(file_path).map_err(|e| CommandError::io("Failed to read file", e))?;
+    decrypt_ssce(&bytes, passphrase)
+}
+
+/// Save text content to a file (for HTML export, etc.)
+#[tauri::command]
+#[tracing::instrument(skip(app_handle, content), err)]
+fn save_text_file(app_handle: tauri::AppHandle, path: String, content: String) -> Result<(), CommandError> {
+    let file_path = Path::new(&path);
+    ensure_write_allowed(&app_handle, file_path)?;
+    ensure_disk_space(file_path, content.len() as u64)?;
+
+    write_atomic(file_path, |writer| writer.write_all(content.as_bytes()))
+}
+
+/// Metadata extracted from a .ssce file
+#[derive(Serialize)]
+struct SsceMetadata {
+    thumbnail: Option<String>,
+    snapshot_count: u32,
+    /// The document's format version; None if it has none or is encrypted
+    version: Option<String>,
+}
+
+/// Just the snapshot array and version of a .ssce file, with each snapshot
+/// skipped rather than parsed
+#[derive(Deserialize)]
+struct SnapshotCount {
+    #[serde(default)]
+    snapshots: Vec<serde::de::IgnoredAny>,
+    #[serde(default, alias = "schemaVersion")]
+    version: Option<String>,
+}
+
+/// Extract the snapshot count, and with `include_thumbnail` the thumbnail,
+/// from a .ssce file. The thumbnail is left out by default to keep the
+/// payload small; grids should use get_ssce_thumbnail_cached for it.
+#[tauri::command]
+#[tracing::instrument(err)]
+fn get_ssce_metadata(path: String, include_thumbnail: Option<bool>) -> Result<SsceMetadata, CommandError> {
+    let file_path = Path::new(&path);
+
+    // Encrypted files can't be read without the passphrase
+    if !file_path.exists() || is_encrypted_ssce(file_path) {
+        return Ok(SsceMetadata {
+            thumbnail: None,
+            snapshot_count: 0,
+            version: None,
+        });
+    }
+
+    let content = read_document_text(file_path)?;
+
+    if !include_thumbnail.unwrap_or(false) {
+        let counted: SnapshotCount =
+            serde_json::from_str(&content).map_err(|e| CommandError::Parse(format!("Failed to parse JSON: {}", e)))?;
+        return Ok(SsceMetadata {
+            thumbnail: None,
+            snapshot_count: counted.snapshots.len() as u32,
+            version: counted.version,
+        });
+    }
+
+    // Parse JSON
+    let json: serde_json::Value = serde_json::from_str(&content)
+        .map_err(|e| CommandError::Parse(format!("Failed to parse JSON: {}", e)))?;
+
+    // Get thumbnail field if it exists, otherwise derive one from a snapshot
+    let thumbnail = json.get("thumbnail")
+        .and_then(|t| t.as_str())
+        .map(|s| s.to_string())
+        .or_else(|| derive_ssce_thumbnail(&json));
+
+    // Get snapshot count
+    let snapshot_count = json.get("snapshots")
+        .and_then(|s| s.as_array())
+        .map(|arr| arr.len() as u32)
+        .unwrap_or(0);
+
+    Ok(SsceMetadata {
+        thumbnail,
+        snapshot_count,
+        version: document_version(&json).map(str::to_string),
+    })
+}
+
+/// A library row whose snapshot_count no longer matches its document
+#[derive(Serialize, Debug, PartialEq)]
+struct SnapshotCountMismatch {
+    path: String,
+    stored: i64,
+    actual: u32,
+}
+
+/// Outcome of db_verify_snapshot_counts
+#[derive(Serialize, Debug, Default, PartialEq)]
+struct SnapshotCountReport {
+    checked: usize,
+    mismatches: Vec<SnapshotCountMismatch>,
+    /// Documents that couldn't be parsed; their rows are left alone
+    unreadable: usize,
+    /// Whether the mismatched rows were updated
+    fixed: bool,
+}
+
+/// Progress of db_verify_snapshot_counts, emitted as
+/// "verify-snapshot-counts-progress"
+#[derive(Serialize, Clone, Debug)]
+struct SnapshotCountProgress {
+    done: usize,
+    total: usize,
+}
+
+/// Documents checked between verify-snapshot-counts-progress events
+const SNAPSHOT_COUNT_PROGRESS_EVERY: usize = 100;
+
+/// Compare the stored snapshot_count of every indexed document under
+/// `library_path` with its file, e.g. after edits made outside the app.
+/// With `auto_fix` the drifted rows are corrected in one transaction.
+/// Missing and encrypted documents are skipped.
+/// The documents are read on a blocking thread without holding the
+/// database lock; it's only taken to list the rows and to apply the fixes.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle), err)]
+async fn db_verify_snapshot_counts(
+    app_handle: tauri::AppHandle,
+    library_path: String,
+    auto_fix: Option<bool>,
+) -> Result<SnapshotCountReport, CommandError> {
+    let library_path = resolve_path(&library_path);
+    let app = app_handle.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        verify_snapshot_counts(&*app.state::<DbState>(), &library_path, auto_fix.unwrap_or(false), |done, total| {
+            let _ = app.emit("verify-snapshot-counts-progress", SnapshotCountProgress { done, total });
+        })
+    })
+    .await
+    .map_err(|e| CommandError::Io(format!("Snapshot count check failed: {}", e)))?
+}
+
+fn verify_snapshot_counts<D, F>(
+    db: &D,
+    library_path: &str,
+    auto_fix: bool,
+    mut progress: F,
+) -> Result<SnapshotCountReport, CommandError>
+where
+    D: LibraryDb + ?Sized,
+    F: FnMut(usize, usize),
+{
+    let prefix = folder_prefix(library_path);
+    let rows = db.run(|conn| -> Result<Vec<(String, i64)>, CommandError> {
+        Ok(conn
+            .prepare(
+                "SELECT path, snapshot_count FROM files
+                 WHERE deleted_at IS NULL AND path LIKE ?1 ESCAPE '\\' AND substr(path, 1, length(?2)) = ?2
+                 ORDER BY path",
+            )?
+            .query_map(params![like_prefix_pattern(&prefix), prefix], |row| {
+                Ok((row.get(0)?, row.get::<_, Option<i64>>(1)?.unwrap_or(0)))
+            })?
+            .collect::<Result<_, _>>()?)
+    })?;
+
+    let total = rows.len();
+    let mut report = SnapshotCountReport::default();
+    for (index, (path, stored)) in rows.into_iter().enumerate() {
+        let file_path = Path::new(&path);
+        if file_path.is_file() && !is_encrypted_ssce(file_path) {
+            match get_ssce_metadata(path.clone(), None) {
+                Ok(metadata) => {
+                    report.checked += 1;
+                    if i64::from(metadata.snapshot_count) != stored {
+                        report.mismatches.push(SnapshotCountMismatch { path, stored, actual: metadata.snapshot_count });
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!(path = %path, "Failed to read snapshot count: {}", e);
+                    report.unreadable += 1;
+                }
+            }
+        }
+        if (index + 1) % SNAPSHOT_COUNT_PROGRESS_EVERY == 0 || index + 1 == total {
+            progress(index + 1, total);
+        }
+    }
+
+    if auto_fix && !report.mismatches.is_empty() {
+        db.run(|conn| -> Result<(), CommandError> {
+            let tx = conn.unchecked_transaction()?;
+            {
+                let mut update = tx.prepare_cached("UPDATE files SET snapshot_count = ?1 WHERE path = ?2")?;
+                for mismatch in &report.mismatches {
+                    update.execute(params![mismatch.actual, mismatch.path])?;
+                }
+            }
+            tx.commit()?;
+            Ok(())
+        })?;
+        report.fixed = true;
+    }
+
+    Ok(report)
+}
+
+/// Counts describing a .ssce document's content
+#[derive(Serialize, Debug, Default, PartialEq)]
+struct SsceStats {
+    snapshot_count: u32,
+    /// Whitespace-separated words in the title, summary, snapshot titles
+    /// and summaries, and text layers
+    word_count: usize,
+    /// Image layers
+    image_count: u32,
+    /// Characters in the same text as word_count
+    character_count: usize,
+    /// Part of the document couldn't be read, so the counts are incomplete
+    partial: bool,
+}
+
+/// Word, character, snapshot and image counts for a .ssce file. A
+/// malformed document isn't an error: whatever could be counted is
+/// returned with `partial` set. Encrypted documents return zero counts,
+/// also marked partial.
+#[tauri::command]
+#[tracing::instrument(err)]
+fn get_ssce_stats(path: String) -> Result<SsceStats, CommandError> {
+    let file_path = Path::new(&path);
+
+    if !file_path.exists() {
+        return Err(CommandError::NotFound(format!("File does not exist: {}", path)));
+    }
+
+    if is_encrypted_ssce(file_path) {
+        return Ok(SsceStats { partial: true, ..Default::default() });
+    }
+
+    let content = read_document_text(file_path)?;
+    match serde_json::from_str::<serde_json::Value>(&content) {
+        Ok(json) => Ok(ssce_stats(&json)),
+        Err(e) => {
+            tracing::warn!(path = %path, "Failed to parse JSON for stats: {}", e);
+            Ok(SsceStats { partial: true, ..Default::default() })
+        }
+    }
+}
+
+fn ssce_stats(json: &serde_json::Value) -> SsceStats {
+    let mut stats = SsceStats::default();
+    let mut count_text = |value: &serde_json::Value, partial: &mut bool| match value {
+        serde_json::Value::String(text) => {
+            stats.word_count += text.split_whitespace().count();
+            stats.character_count += text.chars().count();
+        }
+        serde_json::Value::Null => {}
+        _ => *partial = true,
+    };
+    let mut partial = !json.is_object();
+
+    count_text(&json["frontMatter"]["title"], &mut partial);
+    count_text(&json["frontMatter"]["summary"], &mut partial);
+
+    match &json["snapshots"] {
+        serde_json::Value::Array(snapshots) => {
+            for snapshot in snapshots {
+                if !snapshot.is_object() {
+                    partial = true;
+                    continue;
+                }
+                count_text(&snapshot["frontMatter"]["title"], &mut partial);
+                count_text(&snapshot["frontMatter"]["summary"], &mut partial);
+            }
+            stats.snapshot_count = snapshots.len() as u32;
+        }
+        serde_json::Value::Null => {}
+        _ => partial = true,
+    }
+
+    let mut image_count = 0;
+    match &json["layers"] {
+        serde_json::Value::Array(layers) => {
+            for layer in layers {
+                match layer["type"].as_str() {
+                    Some("text") => count_text(&layer["data"]["text"], &mut partial),
+                    Some("image") => image_count += 1,
+                    Some(_) => {}
+                    None => partial = true,
+                }
+            }
+        }
+        serde_json::Value::Null => {}
+        _ => partial = true,
+    }
+
+    SsceStats { image_count, partial, ..stats }
+}
+
+/// Extract thumbnail from a .ssce file (legacy, kept for compatibility)
+/// Returns the thumbnail data URL if present, or null if not found
+#[tauri::command]
+#[tracing::instrument(err)]
+fn get_ssce_thumbnail(path: String) -> Result<Option<String>, CommandError> {
+    let file_path = Path::new(&path);
+
+    if !file_path.exists() || is_encrypted_ssce(file_path) {
+        return Ok(None);
+    }
+
+    Ok(read_ssce_header(file_path)?.thumbnail)
+}
+
+/// The .ssce format version this build writes (matches FORMAT_VERSION in
+/// ssce-format.js)
+const SSCE_FORMAT_VERSION: &str = "1.1";
+
+/// One upgrade step: from version, to version, and the transform
+type SsceMigrationStep = (&'static str, &'static str, fn(&mut serde_json::Value));
+
+/// Upgrades between consecutive format versions, oldest first. Each one
+/// edits the parsed document in place; migrate_ssce runs them in order.
+const SSCE_MIGRATIONS: &[SsceMigrationStep] = &[("1.0", "1.1", migrate_1_0_to_1_1)];
+
+/// 1.1 added the embedded thumbnail and the search keywords. The thumbnail
+/// is derived from the first snapshot when there is one; keywords start
+/// empty and are filled in the next time the app saves the document.
+fn migrate_1_0_to_1_1(json: &mut serde_json::Value) {
+    let thumbnail = derive_ssce_thumbnail(json);
+    if let Some(obj) = json.as_object_mut() {
+        obj.entry("thumbnail")
+            .or_insert_with(|| thumbnail.map_or(serde_json::Value::Null, serde_json::Value::String));
+        obj.entry("keywords").or_insert_with(|| serde_json::Value::Array(Vec::new()));
+    }
+}
+
+/// A document's version field (`version`, or `schemaVersion` in files
+/// written by other tools)
+fn document_version(json: &serde_json::Value) -> Option<&str> {
+    json.get("version")
+        .or_else(|| json.get("schemaVersion"))
+        .and_then(|v| v.as_str())
+}
+
+/// "1.1" -> (1, 1); a missing minor part counts as 0
+fn parse_format_version(version: &str) -> Option<(u32, u32)> {
+    let mut parts = version.trim().splitn(2, '.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next().map_or(Some(0), |m| m.parse().ok())?;
+    Some((major, minor))
+}
+
+/// Read a document's format version without loading its snapshots.
+/// Returns None if the file has no version field.
+#[tauri::command]
+#[tracing::instrument(err)]
+fn get_ssce_version(path: String) -> Result<Option<String>, CommandError> {
+    let file_path = Path::new(&path);
+
+    if !file_path.exists() {
+        return Err(CommandError::NotFound(format!("File does not exist: {}", path)));
+    }
+
+    Ok(read_ssce_header(file_path)?.version)
+}
+
+/// Outcome of migrate_ssce
+#[derive(Serialize, Debug, PartialEq)]
+struct SsceMigration {
+    from: String,
+    to: String,
+    /// False if the document was already at the target version
+    migrated: bool,
+}
+
+/// Upgrade a .ssce file in place to `to_version` (default: the current
+/// format). The original is kept as a `.bak` next to it and the upgraded
+/// document is written atomically, compressed if the original was.
+/// A document without a version field is treated as 1.0. Versions newer
+/// than this build, unknown versions and downgrades fail with
+/// UnsupportedVersion.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle), err)]
+fn migrate_ssce(
+    app_handle: tauri::AppHandle,
+    path: String,
+    to_version: Option<String>,
+) -> Result<SsceMigration, CommandError> {
+    ensure_write_allowed(&app_handle, Path::new(&path))?;
+    let retry = RetryPolicy::from_config(&load_defaults_typed(&app_handle));
+    let migration = migrate_ssce_file(Path::new(&path), to_version.as_deref().unwrap_or(SSCE_FORMAT_VERSION), &retry)?;
+    if migration.migrated {
+        // Our own write, not an outside change to warn the editor about
+        app_handle.state::<DocumentWatchers>().record_write(&path);
+    }
+    Ok(migration)
+}
+
+fn migrate_ssce_file(path: &Path, to_version: &str, retry: &RetryPolicy) -> Result<SsceMigration, CommandError> {
+    if !path.is_file() {
+        return Err(CommandError::NotFound(format!("File does not exist: {}", path.display())));
+    }
+    if is_encrypted_ssce(path) {
+        return Err(CommandError::Validation(format!("File is encrypted: {}", path.display())));
+    }
+
+    let mut json: serde_json::Value = serde_json::from_str(&read_document_text(path)?)
+        .map_err(|e| CommandError::Parse(format!("Failed to parse JSON: {}", e)))?;
+    let from = document_version(&json).unwrap_or("1.0").to_string();
+
+    let known = |version: &str| {
+        version == SSCE_FORMAT_VERSION || SSCE_MIGRATIONS.iter().any(|(from, _, _)| *from == version)
+    };
+    let (Some(from_parsed), Some(to_parsed)) = (parse_format_version(&from), parse_format_version(to_version)) else {
+        return Err(CommandError::UnsupportedVersion(format!(
+            "Unknown format version: {} -> {}",
+            from, to_version
+        )));
+    };
+    if !known(&from) || !known(to_version) {
+        return Err(CommandError::UnsupportedVersion(format!(
+            "Can't migrate from version {} to {} (this build writes {})",
+            from, to_version, SSCE_FORMAT_VERSION
+        )));
+    }
+    if from_parsed > to_parsed {
+        return Err(CommandError::UnsupportedVersion(format!(
+            "Won't downgrade a version {} document to {}",
+            from, to_version
+        )));
+    }
+    if from_parsed == to_parsed {
+        return Ok(SsceMigration { to: from.clone(), from, migrated: false });
+    }
+
+    let mut version = from.clone();
+    while version != to_version {
+        let (_, next, migrate) = SSCE_MIGRATIONS
+            .iter()
+            .find(|(step_from, _, _)| *step_from == version)
+            .ok_or_else(|| CommandError::UnsupportedVersion(format!("No migration from version {}", version)))?;
+        migrate(&mut json);
+        version = next.to_string();
+    }
+
+    if let Some(obj) = json.as_object_mut() {
+        obj.remove("schemaVersion");
+        obj.insert("version".to_string(), serde_json::Value::String(version.clone()));
+    }
+
+    fs::copy(path, path_with_suffix(path, ".bak")).map_err(|e| CommandError::io("Failed to back up file", e))?;
+    // Same layout as the frontend's JSON.stringify(data, null, 2)
+    let data = serde_json::to_string_pretty(&json)?;
+    write_ssce(path.to_string_lossy().to_string(), data, Some(is_gzipped(path)), retry).map_err(|e| match e {
+        SaveError::ReadOnly(message) | SaveError::OutsideRoot(message) => CommandError::Permission(message),
+        SaveError::Io(message) => CommandError::Io(message),
+        SaveError::Invalid(message) => CommandError::Parse(message),
+    })?;
+
+    Ok(SsceMigration { from, to: version, migrated: true })
+}
+
+/// Check if a file exists
+#[tauri::command]
+#[tracing::instrument]
+fn file_exists(path: String) -> bool {
+    Path::new(&path).exists()
+}
+
+/// Check if a file exists, giving up after `ms` milliseconds. Returns null
+/// if the check didn't answer in time (e.g. a hung network mount).
+#[tauri::command]
+#[tracing::instrument]
+async fn file_exists_timeout(path: String, ms: u64) -> Option<bool> {
+    path_exists_within(Path::new(&path), std::time::Duration::from_millis(ms))
+}
+
+/// Size, modification time and content hash of a file, for noticing that
+/// another app changed it
+#[derive(Serialize, Debug, Clone, PartialEq)]
+struct FileFingerprint {
+    size: u64,
+    /// Unix milliseconds
+    mtime: u64,
+    /// Hex SHA-256 of the content; None when hashing was skipped
+    sha256: Option<String>,
+}
+
+/// Fingerprint a file so the editor can tell, at save time, whether it was
+/// changed on disk since it was opened. With `hash` false (default true)
+/// the content isn't read and only size and mtime are compared.
+#[tauri::command]
+#[tracing::instrument(err)]
+fn file_fingerprint(path: String, hash: Option<bool>) -> Result<FileFingerprint, CommandError> {
+    fingerprint_file(Path::new(&path), hash.unwrap_or(true))
+}
+
+fn fingerprint_file(path: &Path, hash: bool) -> Result<FileFingerprint, CommandError> {
+    let metadata = fs::metadata(path).map_err(|e| CommandError::io("Failed to read file", e))?;
+    if !metadata.is_file() {
+        return Err(CommandError::Validation(format!("Not a file: {}", path.display())));
+    }
+
+    let mtime = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_millis() as u64);
+
+    let sha256 = if hash { Some(hash_file(path)?) } else { None };
+
+    Ok(FileFingerprint {
+        size: metadata.len(),
+        mtime,
+        sha256,
+    })
+}
+
+/// Buffer size for hash_file reads
+const HASH_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Hex SHA-256 of a file's raw bytes. The file is streamed through the
+/// hasher in fixed-size chunks, so memory use doesn't grow with its size.
+fn hash_file(path: &Path) -> Result<String, CommandError> {
+    use sha2::{Digest, Sha256};
+    use std::io::Read;
+
+    let mut file = fs::File::open(path).map_err(|e| CommandError::io("Failed to open file", e))?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; HASH_BUFFER_SIZE];
+    loop {
+        let n = match file.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(CommandError::io("Failed to read file", e)),
+        };
+        hasher.update(&buffer[..n]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// How long an open document must be quiet after a change before its
+/// content is compared. Saves often come as several events (write, rename).
+const DOCUMENT_WATCH_DEBOUNCE: std::time::Duration = std::time::Duration::from_millis(300);
+
+/// Payload of "document-externally-modified"
+#[derive(Serialize, Debug, Clone, PartialEq)]
+struct DocumentChange {
+    path: String,
+    /// The file is gone (deleted, or moved away)
+    deleted: bool,
+}
+
+/// A notify watch on one open document (see watch_document)
+struct DocumentWatch {
+    /// The watch ends when this is dropped
+    _watcher: notify::RecommendedWatcher,
+    /// Hash of the content last seen on disk or written by the app; None
+    /// while the file doesn't exist
+    known: Arc<Mutex<Option<String>>>,
+}
+
+impl DocumentWatch {
+    /// Watch `path`, calling `on_change` when its content differs from what
+    /// was last seen. The folder is watched rather than the file, because
+    /// saves that rename a temp file over the original would end a watch on
+    /// the file itself.
+    fn start(path: &Path, on_change: impl Fn(DocumentChange) + Send + 'static) -> Result<Self, CommandError> {
+        use notify::Watcher;
+
+        let known = Arc::new(Mutex::new(Some(hash_file(path)?)));
+        let name = path
+            .file_name()
+            .ok_or_else(|| CommandError::Validation(format!("Not a file: {}", path.display())))?
+            .to_os_string();
+        let folder = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => std::path::PathBuf::from("."),
+        };
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            if let Ok(event) = event {
+                let _ = tx.send(event);
+            }
+        })
+        .map_err(|e| CommandError::Io(format!("Failed to start document watcher: {}", e)))?;
+        watcher
+            .watch(&folder, notify::RecursiveMode::NonRecursive)
+            .map_err(|e| CommandError::Io(format!("Failed to watch {}: {}", folder.display(), e)))?;
+
+        let path = path.to_path_buf();
+        let thread_known = Arc::clone(&known);
+        std::thread::spawn(move || {
+            // Ends when the watcher (and with it the sender) is dropped
+            while let Ok(event) = rx.recv() {
+                let ours = !event.kind.is_access() && event.paths.iter().any(|p| p.file_name() == Some(name.as_os_str()));
+                if !ours {
+                    continue;
+                }
+                while rx.recv_timeout(DOCUMENT_WATCH_DEBOUNCE).is_ok() {}
+
+                let current = hash_file(&path).ok();
+                let Ok(mut known) = thread_known.lock() else {
+                    return;
+                };
+                if *known != current {
+                    *known = current.clone();
+                    drop(known);
+                    on_change(DocumentChange {
+                        path: path.to_string_lossy().to_string(),
+                        deleted: current.is_none(),
+                    });
+                }
+            }
+        });
+
+        Ok(DocumentWatch { _watcher: watcher, known })
+    }
+
+    /// Take the file's current content as seen, so the events from a save
+    /// the app just made aren't reported
+    fn record_write(&self, path: &Path) {
+        if let Ok(mut known) = self.known.lock() {
+            *known = hash_file(path).ok();
+        }
+    }
+}
+
+/// Documents watched by watch_document, by normalize_path form
+struct DocumentWatchers(Mutex<HashMap<String, DocumentWatch>>);
+
+impl DocumentWatchers {
+    /// Called after the app writes a document, so a watch on it ignores
+    /// that write
+    fn record_write(&self, path: &str) {
+        if let Ok(watchers) = self.0.lock() {
+            if let Some(watch) = watchers.get(&normalize_path(path)) {
+                watch.record_write(Path::new(path));
+            }
+        }
+    }
+}
+
+/// Watch an open document for changes made by other apps, emitting
+/// "document-externally-modified" (a DocumentChange) when its content
+/// changes on disk. Saves made through save_ssce aren't reported.
+/// Watching a document that is already watched starts the watch afresh.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle, watchers), err)]
+fn watch_document(
+    app_handle: tauri::AppHandle,
+    watchers: State<DocumentWatchers>,
+    path: String,
+) -> Result<(), CommandError> {
+    let watch = DocumentWatch::start(Path::new(&path), move |change| {
+        tracing::info!("{} changed on disk", change.path);
+        let _ = app_handle.emit("document-externally-modified", change);
+    })?;
+    watchers
+        .0
+        .lock()
+        .map_err(|_| CommandError::Io("Document watchers lock poisoned".to_string()))?
+        .insert(normalize_path(&path), watch);
+    Ok(())
+}
+
+/// Stop watching a document. Returns false if it wasn't being watched.
+#[tauri::command]
+#[tracing::instrument(skip(watchers), err)]
+fn unwatch_document(watchers: State<DocumentWatchers>, path: String) -> Result<bool, CommandError> {
+    Ok(watchers
+        .0
+        .lock()
+        .map_err(|_| CommandError::Io("Document watchers lock poisoned".to_string()))?
+        .remove(&normalize_path(&path))
+        .is_some())
+}
+
+/// The folder containing `path`, canonical (symlinked folders resolved), for
+/// the app's own file browser to open. Also emits navigate-to-folder with
+/// it to the calling window. Fails if the path is a file system root.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle, window), err)]
+fn get_parent_directory(
+    app_handle: tauri::AppHandle,
+    window: tauri::WebviewWindow,
+    path: String,
+) -> Result<String, CommandError> {
+    let parent = parent_directory(Path::new(&path))?.to_string_lossy().to_string();
+    app_handle
+        .emit_to(window.label(), "navigate-to-folder", &parent)
+        .map_err(|e| CommandError::Io(format!("Failed to emit navigate-to-folder: {}", e)))?;
+    Ok(parent)
+}
+
+fn parent_directory(path: &Path) -> Result<std::path::PathBuf, CommandError> {
+    // components() ignores a trailing separator, so "/a/b/" has parent "/a"
+    let parent = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .ok_or_else(|| CommandError::Validation(format!("Path has no parent folder: {}", path.display())))?;
+
+    dunce::canonicalize(parent).map_err(|e| CommandError::io("Failed to resolve parent folder", e))
+}
+
+/// Marks a path argument as raw OS bytes rather than text (see encode_os_path)
+const ENCODED_PATH_PREFIX: &str = "raw:";
+
+/// Round-trippable form of a path that isn't valid UTF-8, which the lossy
+/// display string can't reopen: "raw:" then base64 of the OS bytes (UTF-16
+/// code units on Windows). None for ordinary paths, which are passed as-is.
+fn encode_os_path(path: &std::ffi::OsStr) -> Option<String> {
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+
+    if path.to_str().is_some() {
+        return None;
+    }
+
+    #[cfg(unix)]
+    let bytes = std::os::unix::ffi::OsStrExt::as_bytes(path).to_vec();
+    #[cfg(windows)]
+    let bytes: Vec<u8> = std::os::windows::ffi::OsStrExt::encode_wide(path)
+        .flat_map(u16::to_le_bytes)
+        .collect();
+
+    Some(format!("{}{}", ENCODED_PATH_PREFIX, URL_SAFE_NO_PAD.encode(bytes)))
+}
+
+/// Recover the exact path from encode_os_path's form
+fn decode_os_path(encoded: &str) -> Result<std::ffi::OsString, CommandError> {
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+
+    let payload = encoded
+        .strip_prefix(ENCODED_PATH_PREFIX)
+        .ok_or_else(|| CommandError::Validation(format!("Not an encoded path: {}", encoded)))?;
+    let bytes = URL_SAFE_NO_PAD
+        .decode(payload)
+        .map_err(|e| CommandError::Parse(format!("Invalid encoded path: {}", e)))?;
+
+    #[cfg(unix)]
+    return Ok(std::os::unix::ffi::OsStringExt::from_vec(bytes));
+    #[cfg(windows)]
+    {
+        if bytes.len() % 2 != 0 {
+            return Err(CommandError::Parse("Invalid encoded path: odd byte count".into()));
+        }
+        let wide: Vec<u16> = bytes.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+        Ok(std::os::windows::ffi::OsStringExt::from_wide(&wide))
+    }
+}
+
+/// A path argument from the frontend: either an ordinary path or the
+/// encoded form of one that isn't valid UTF-8
+fn fs_path(arg: &str) -> Result<std::path::PathBuf, CommandError> {
+    if arg.starts_with(ENCODED_PATH_PREFIX) {
+        decode_os_path(arg).map(std::path::PathBuf::from)
+    } else {
+        Ok(std::path::PathBuf::from(arg))
+    }
+}
+
+/// Result of decode_path
+#[derive(Serialize, Debug)]
+struct DecodedPath {
+    /// Readable form, with invalid sequences replaced by U+FFFD
+    display: String,
+    exists: bool,
+}
+
+/// Show an encoded path (from a listing or the library) to the user.
+/// Pass the encoded form itself to commands that open the file.
+#[tauri::command]
+#[tracing::instrument(err)]
+fn decode_path(encoded: String) -> Result<DecodedPath, CommandError> {
+    let path = std::path::PathBuf::from(decode_os_path(&encoded)?);
+    Ok(DecodedPath {
+        display: path.to_string_lossy().to_string(),
+        exists: path.exists(),
+    })
+}
+
+/// Append a suffix to a full path (e.g. "doc.ssce" + ".bak" -> "doc.ssce.bak")
+fn path_with_suffix(path: &Path, suffix: &str) -> std::path::PathBuf {
+    let mut os_string = path.as_os_str().to_os_string();
+    os_string.push(suffix);
+    std::path::PathBuf::from(os_string)
+}
+
+/// Sidecar files that belong to a document and should follow it when it is
+/// moved or renamed. Currently the `.bak` backup written next to the document.
+fn sidecar_paths(path: &Path) -> Vec<std::path::PathBuf> {
+    vec![path_with_suffix(path, ".bak")]
+}
+
+/// Check that a file is inside the configured library folder before it is
+/// deleted, so a bad path from the frontend can't remove arbitrary files
+fn ensure_in_library(app_handle: &tauri::AppHandle, path: &Path) -> Result<(), CommandError> {
+    let root = library_root(app_handle)
+        .ok_or_else(|| CommandError::Validation("Library path is not configured".to_string()))?;
+
+    if !is_within_root(path, std::slice::from_ref(&root)) {
+        return Err(CommandError::Permission(format!(
+            "Refusing to delete a file outside the library folder ({}): {}",
+            root.display(),
+            path.display()
+        )));
+    }
+
+    Ok(())
+}
+
+/// The library folder from defaults.json (paths.library), if configured
+fn library_root(app_handle: &tauri::AppHandle) -> Option<std::path::PathBuf> {
+    Some(load_defaults_typed(app_handle).paths.library.clone())
+        .filter(|r| !r.trim().is_empty())
+        .map(std::path::PathBuf::from)
+}
+
+/// Folders that write and delete commands may touch: the library, the
+/// default open/save folders, the autosave folder, Downloads, and any
+/// folders listed in paths.extraRoots
+fn allowed_roots(config: &Defaults) -> Vec<std::path::PathBuf> {
+    let paths = &config.paths;
+    let mut roots: Vec<std::path::PathBuf> = [&paths.library, &paths.default_image_load, &paths.default_image_save]
+        .into_iter()
+        .chain(&paths.extra_roots)
+        .filter(|root| !root.trim().is_empty())
+        .map(std::path::PathBuf::from)
+        .collect();
+
+    // Autosaves go to tempDirectory, or the fallback when it isn't writable
+    roots.extend(configured_autosave_dir(config));
+    roots.push(fallback_autosave_dir());
+
+    roots.extend(dirs::download_dir());
+    roots
+}
+
+/// Canonical form of a path that may not exist yet: the nearest existing
+/// ancestor is canonicalized and the missing components are appended
+fn resolve_for_write(path: &Path) -> Option<std::path::PathBuf> {
+    let mut existing = path;
+    let mut missing = Vec::new();
+    loop {
+        if let Ok(canonical) = existing.canonicalize() {
+            return Some(missing.iter().rev().fold(canonical, |acc, name| acc.join(name)));
+        }
+        missing.push(existing.file_name()?);
+        existing = existing.parent()?;
+    }
+}
+
+/// Whether `path` is inside one of `roots`. Relative paths and paths with
+/// `..` components are rejected outright; the rest are compared in
+/// canonical form so symlinks can't be used to escape.
+fn is_within_root(path: &Path, roots: &[std::path::PathBuf]) -> bool {
+    if !path.is_absolute() || path.components().any(|c| c == std::path::Component::ParentDir) {
+        return false;
+    }
+    let Some(path) = resolve_for_write(path) else {
+        return false;
+    };
+
+    roots
+        .iter()
+        .filter_map(|root| resolve_for_write(root))
+        .any(|root| path.starts_with(root))
+}
+
+/// Check that a write or delete targets a file inside the allowed roots,
+/// or in a folder the user chose this run (see WriteGrants).
+/// Reads aren't checked.
+fn ensure_write_allowed(app_handle: &tauri::AppHandle, path: &Path) -> Result<(), CommandError> {
+    let mut roots = allowed_roots(&load_defaults_typed(app_handle));
+    if let Some(grants) = app_handle.try_state::<WriteGrants>() {
+        roots.extend(grants.folders());
+    }
+    check_within_roots(path, &roots)
+}
+
+/// Folders the user picked this run, writable even outside the configured
+/// roots: the folder of a file chosen in a save or open dialog, or a folder
+/// chosen in a folder dialog. Loading a file never grants anything, so a
+/// read outside the roots doesn't make its folder writable.
+struct WriteGrants(Mutex<Vec<std::path::PathBuf>>);
+
+impl WriteGrants {
+    fn grant(&self, folder: &Path) {
+        if let Ok(mut folders) = self.0.lock() {
+            if !folders.iter().any(|f| f == folder) {
+                folders.push(folder.to_path_buf());
+            }
+        }
+    }
+
+    /// Grant the folder containing `file`
+    fn grant_folder_of(&self, file: &Path) {
+        if let Some(folder) = file.parent().filter(|f| !f.as_os_str().is_empty()) {
+            self.grant(folder);
+        }
+    }
+
+    fn folders(&self) -> Vec<std::path::PathBuf> {
+        self.0.lock().map(|folders| folders.clone()).unwrap_or_default()
+    }
+}
+
+/// A file type filter in a file dialog
+#[derive(Deserialize, Debug)]
+struct DialogFilter {
+    name: String,
+    extensions: Vec<String>,
+}
+
+/// Options for the file dialogs. `default_path` is a folder, or for a save
+/// dialog may also be a full path whose file name is suggested.
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct FileDialogOptions {
+    title: Option<String>,
+    default_path: Option<String>,
+    #[serde(default)]
+    filters: Vec<DialogFilter>,
+    #[serde(default)]
+    multiple: bool,
+}
+
+impl FileDialogOptions {
+    fn builder(self, app_handle: &tauri::AppHandle) -> tauri_plugin_dialog::FileDialogBuilder<tauri::Wry> {
+        use tauri_plugin_dialog::DialogExt;
+
+        let mut dialog = app_handle.dialog().file();
+        if let Some(title) = self.title {
+            dialog = dialog.set_title(title);
+        }
+        if let Some(default_path) = self.default_path.filter(|p| !p.is_empty()) {
+            let default_path = Path::new(&default_path);
+            if default_path.is_dir() {
+                dialog = dialog.set_directory(default_path);
+            } else {
+                if let Some(folder) = default_path.parent() {
+                    dialog = dialog.set_directory(folder);
+                }
+                if let Some(name) = default_path.file_name() {
+                    dialog = dialog.set_file_name(name.to_string_lossy());
+                }
+            }
+        }
+        for filter in &self.filters {
+            let extensions: Vec<&str> = filter.extensions.iter().map(String::as_str).collect();
+            dialog = dialog.add_filter(&filter.name, &extensions);
+        }
+        dialog
+    }
+}
+
+/// Show a dialog on a blocking thread (they block until closed) and return
+/// the chosen paths
+async fn run_file_dialog<F>(pick: F) -> Result<Vec<std::path::PathBuf>, CommandError>
+where
+    F: FnOnce() -> Option<Vec<tauri_plugin_dialog::FilePath>> + Send + 'static,
+{
+    let picked = tauri::async_runtime::spawn_blocking(pick)
+        .await
+        .map_err(|e| CommandError::Io(format!("File dialog failed: {}", e)))?;
+    picked
+        .unwrap_or_default()
+        .into_iter()
+        .map(|path| path.into_path().map_err(|e| CommandError::Validation(format!("Not a local file: {}", e))))
+        .collect()
+}
+
+fn path_strings(paths: &[std::path::PathBuf]) -> Vec<String> {
+    paths.iter().map(|p| p.to_string_lossy().to_string()).collect()
+}
+
+/// Native save dialog. The chosen file's folder becomes writable for the
+/// rest of the run. Returns None if cancelled.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle, grants), err)]
+async fn show_save_dialog(
+    app_handle: tauri::AppHandle,
+    grants: State<'_, WriteGrants>,
+    options: FileDialogOptions,
+) -> Result<Option<String>, CommandError> {
+    let dialog = options.builder(&app_handle);
+    let picked = run_file_dialog(move || dialog.blocking_save_file().map(|path| vec![path])).await?;
+    for path in &picked {
+        grants.grant_folder_of(path);
+    }
+    Ok(path_strings(&picked).into_iter().next())
+}
+
+/// Native open dialog, for one file or (with `multiple`) several. The
+/// chosen files' folders become writable for the rest of the run.
+/// Returns an empty list if cancelled.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle, grants), err)]
+async fn show_open_dialog(
+    app_handle: tauri::AppHandle,
+    grants: State<'_, WriteGrants>,
+    options: FileDialogOptions,
+) -> Result<Vec<String>, CommandError> {
+    let multiple = options.multiple;
+    let dialog = options.builder(&app_handle);
+    let picked = run_file_dialog(move || {
+        if multiple {
+            dialog.blocking_pick_files()
+        } else {
+            dialog.blocking_pick_file().map(|path| vec![path])
+        }
+    })
+    .await?;
+    for path in &picked {
+        grants.grant_folder_of(path);
+    }
+    Ok(path_strings(&picked))
+}
+
+/// Native folder picker. The chosen folder becomes writable for the rest of
+/// the run. Returns None if cancelled.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle, grants), err)]
+async fn show_folder_dialog(
+    app_handle: tauri::AppHandle,
+    grants: State<'_, WriteGrants>,
+    options: FileDialogOptions,
+) -> Result<Option<String>, CommandError> {
+    let dialog = options.builder(&app_handle);
+    let picked = run_file_dialog(move || dialog.blocking_pick_folder().map(|path| vec![path])).await?;
+    for folder in &picked {
+        grants.grant(folder);
+    }
+    Ok(path_strings(&picked).into_iter().next())
+}
+
+fn check_within_roots(path: &Path, roots: &[std::path::PathBuf]) -> Result<(), CommandError> {
+    if is_within_root(path, roots) {
+        return Ok(());
+    }
+
+    Err(CommandError::Permission(format!(
+        "Refusing to write outside the library, autosave, and Downloads folders \
+         (choose it in a file dialog, or add it to paths.extraRoots): {}",
+        path.display()
+    )))
+}
+
+/// Error from the directory commands, tagged so the frontend can tell the
+/// cases apart: `{ kind: "NotEmpty", message: "..." }`
+#[derive(Serialize, Debug)]
+#[serde(tag = "kind", content = "message")]
+enum DirectoryError {
+    AlreadyExists(String),
+    NotFound(String),
+    NotADirectory(String),
+    NotEmpty(String),
+    OutsideRoot(String),
+    Io(String),
+}
+
+/// Create a folder, including any missing parents.
+/// Fails if something already exists at the path.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle), err(Debug))]
+fn create_directory(app_handle: tauri::AppHandle, path: String) -> Result<(), DirectoryError> {
+    ensure_write_allowed(&app_handle, Path::new(&path)).map_err(|e| DirectoryError::OutsideRoot(e.to_string()))?;
+    make_directory(path)
+}
+
+fn make_directory(path: String) -> Result<(), DirectoryError> {
+    let dir = Path::new(&path);
+
+    if dir.exists() {
+        return Err(DirectoryError::AlreadyExists(format!("Already exists: {}", path)));
+    }
+
+    fs::create_dir_all(dir).map_err(|e| DirectoryError::Io(format!("Failed to create folder: {}", e)))
+}
+
+/// Remove a folder inside the library folder. Without `recursive` only an
+/// empty folder is removed; with it, the whole tree is deleted.
+/// The library folder itself can't be removed.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle), err(Debug))]
+fn remove_directory(app_handle: tauri::AppHandle, path: String, recursive: bool) -> Result<(), DirectoryError> {
+    let root = library_root(&app_handle)
+        .ok_or_else(|| DirectoryError::OutsideRoot("Library path is not configured".to_string()))?;
+    remove_directory_within(Path::new(&path), &root, recursive)
+}
+
+fn remove_directory_within(dir: &Path, root: &Path, recursive: bool) -> Result<(), DirectoryError> {
+    if !dir.exists() {
+        return Err(DirectoryError::NotFound(format!("Folder does not exist: {}", dir.display())));
+    }
+
+    if !dir.is_dir() {
+        return Err(DirectoryError::NotADirectory(format!("Not a folder: {}", dir.display())));
+    }
+
+    let is_root = dir.canonicalize().ok() == root.canonicalize().ok();
+    if is_root || !is_within_root(dir, &[root.to_path_buf()]) {
+        return Err(DirectoryError::OutsideRoot(format!(
+            "Refusing to remove a folder outside the library folder ({}): {}",
+            root.display(),
+            dir.display()
+        )));
+    }
+
+    if recursive {
+        return fs::remove_dir_all(dir).map_err(|e| DirectoryError::Io(format!("Failed to remove folder: {}", e)));
+    }
+
+    let is_empty = fs::read_dir(dir)
+        .map_err(|e| DirectoryError::Io(format!("Failed to read folder: {}", e)))?
+        .next()
+        .is_none();
+    if !is_empty {
+        return Err(DirectoryError::NotEmpty(format!("Folder is not empty: {}", dir.display())));
+    }
+
+    fs::remove_dir(dir).map_err(|e| DirectoryError::Io(format!("Failed to remove folder: {}", e)))
+}
+
+/// Hide a file's library entry without removing the row. Saving or
+/// rebuilding the same path again brings it back.
+fn soft_delete_file(conn: &Connection, path: &str) -> Result<(), rusqlite::Error> {
+    let now = now_timestamp();
+    conn.execute(
+        "UPDATE files SET deleted_at = ?1 WHERE path = ?2",
+        params![now, normalize_path(path)],
+    )?;
+    Ok(())
+}
+
+/// Send a document in the library folder to the OS recycle bin (so it can
+/// be recovered) and soft-delete its library entry. Sidecar files go too.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle, state), err)]
+fn trash_file(app_handle: tauri::AppHandle, state: State<DbState>, path: String) -> Result<(), CommandError> {
+    let path = resolve_path(&path);
+    let file_path = Path::new(&path);
+    if !file_path.exists() {
+        return Err(CommandError::NotFound(format!("File does not exist: {}", path)));
+    }
+    ensure_in_library(&app_handle, file_path)?;
+
+    trash::delete(file_path).map_err(|e| CommandError::Io(format!("Failed to move file to trash: {}", e)))?;
+
+    for sidecar in sidecar_paths(file_path) {
+        if sidecar.exists() {
+            let _ = trash::delete(&sidecar);
+        }
+    }
+
+    state.with_conn(|conn| soft_delete_file(conn, &path).map_err(CommandError::from))
+}
+
+/// Permanently delete a document in the library folder, bypassing the
+/// recycle bin, and remove its library entry. Sidecar files go too.
+/// Only for when the user has explicitly asked for a hard delete.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle, state), err)]
+fn trash_file_permanent(app_handle: tauri::AppHandle, state: State<DbState>, path: String) -> Result<(), CommandError> {
+    let path = resolve_path(&path);
+    let file_path = Path::new(&path);
+    if !file_path.exists() {
+        return Err(CommandError::NotFound(format!("File does not exist: {}", path)));
+    }
+    ensure_in_library(&app_handle, file_path)?;
+
+    fs::remove_file(file_path).map_err(|e| CommandError::io("Failed to delete file", e))?;
+
+    for sidecar in sidecar_paths(file_path) {
+        let _ = fs::remove_file(&sidecar);
+    }
+
+    state.with_conn(|conn| {
+        conn.execute("DELETE FROM files WHERE path = ?1", params![normalize_path(&path)])?;
+
+        Ok(())
+    })
+}
+
+/// Duplicate a document and add the copy to the library.
+/// The copy's library entry is built from the copied file, so it keeps the
+/// title, summary and keywords but starts with no last_opened.
+/// An existing destination is only replaced when `overwrite` is true.
+/// Returns the path of the copy.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle, state), err)]
+fn copy_file(
+    app_handle: tauri::AppHandle,
+    state: State<DbState>,
+    src_path: String,
+    dest_path: String,
+    overwrite: bool,
+) -> Result<String, CommandError> {
+    ensure_write_allowed(&app_handle, Path::new(&dest_path))?;
+    let options = RebuildOptions::from_config(&load_defaults_typed(&app_handle));
+    state.with_conn(|conn| copy_document(conn, Path::new(&src_path), Path::new(&dest_path), overwrite, &options))
+}
+
+fn copy_document(
+    conn: &Connection,
+    src: &Path,
+    dest: &Path,
+    overwrite: bool,
+    options: &RebuildOptions,
+) -> Result<String, CommandError> {
+    if !src.is_file() {
+        return Err(CommandError::NotFound(format!("File does not exist: {}", src.display())));
+    }
+
+    if dest.exists() {
+        if !overwrite {
+            return Err(CommandError::Validation(format!("Destination already exists: {}", dest.display())));
+        }
+        if dest.canonicalize().ok() == src.canonicalize().ok() {
+            return Err(CommandError::Validation("Source and destination are the same file".to_string()));
+        }
+    }
+
+    ensure_disk_space(dest, file_size(src).unwrap_or(0) as u64)?;
+
+    // Create parent directories if they don't exist
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent).map_err(|e| CommandError::io("Failed to create directories", e))?;
+    }
+
+    fs::copy(src, dest).map_err(|e| CommandError::io("Failed to copy file", e))?;
+
+    let scanned = scan_ssce_file(dest, options).map_err(CommandError::Parse)?;
+    let mut stmt = conn.prepare(UPSERT_SCANNED_SQL)?;
+    upsert_scanned(&mut stmt, &scanned)?;
+
+    // An overwritten destination may have been opened before; the copy hasn't
+    conn.execute(
+        "UPDATE files SET last_opened = NULL WHERE path = ?1",
+        params![scanned.file.path],
+    )?;
+
+    Ok(dest.to_string_lossy().to_string())
+}
+
+/// The library entry stored for `path` (in normalize_path form)
+fn library_file_at(conn: &Connection, path: &str) -> Result<Option<LibraryFile>, rusqlite::Error> {
+    conn.query_row(
+        "SELECT id, path, filename, thumbnail, title, summary, keywords, modified, last_opened, snapshot_count, size, encoded_path, open_count, author, created
+         FROM files WHERE path = ?1",
+        params![path],
+        library_file_from_row,
+    )
+    .optional()
+}
+
+/// What happened to one document brought in by import_document/import_folder
+enum ImportResult {
+    Imported(Box<LibraryFile>),
+    /// Identical content is already in the library at this path
+    Duplicate(String),
+}
+
+/// Copy a document into `dest_dir` and index it. Skipped as a duplicate
+/// when the library already holds the same content (see ssce_content_hash).
+/// The copy is written atomically; an existing file of the same name is
+/// only replaced with `force`. A copy without an embedded thumbnail gets
+/// one derived from its first snapshot.
+/// The database is only locked for the duplicate check and to add the
+/// copy; reading, copying and parsing happen without it.
+fn import_one<D: LibraryDb + ?Sized>(
+    db: &D,
+    src: &Path,
+    dest_dir: &Path,
+    force: bool,
+    options: &RebuildOptions,
+) -> Result<ImportResult, CommandError> {
+    if !src.is_file() {
+        return Err(CommandError::NotFound(format!("File does not exist: {}", src.display())));
+    }
+    let name = src
+        .file_name()
+        .ok_or_else(|| CommandError::Validation(format!("Not a file: {}", src.display())))?;
+    if !has_indexed_extension(&name.to_string_lossy(), &options.extensions) {
+        return Err(CommandError::Validation(format!("Not a document: {}", src.display())));
+    }
+
+    let incoming = scan_ssce_file(src, options).map_err(CommandError::Parse)?;
+    let existing = db.run(|conn| -> Result<Option<String>, CommandError> {
+        Ok(conn
+            .query_row(
+                "SELECT path FROM files WHERE content_hash = ?1 AND deleted_at IS NULL LIMIT 1",
+                params![incoming.content_hash],
+                |row| row.get(0),
+            )
+            .optional()?)
+    })?;
+    if let Some(existing) = existing {
+        return Ok(ImportResult::Duplicate(existing));
+    }
+
+    let dest = dest_dir.join(name);
+    if dest.exists() && !force {
+        return Err(CommandError::Validation(format!("Destination already exists: {}", dest.display())));
+    }
+
+    let bytes = fs::read(src).map_err(|e| CommandError::io("Failed to read file", e))?;
+    ensure_disk_space(&dest, bytes.len() as u64)?;
+    write_atomic(&dest, |w| w.write_all(&bytes))?;
+
+    if let Err(e) = regenerate_thumbnail(&dest) {
+        tracing::warn!(path = %dest.display(), "Failed to derive thumbnail: {}", e);
+    }
+
+    let scanned = scan_ssce_file(&dest, options).map_err(CommandError::Parse)?;
+    let file = db.run(|conn| -> Result<Option<LibraryFile>, CommandError> {
+        upsert_scanned(&mut conn.prepare(UPSERT_SCANNED_SQL)?, &scanned)?;
+        Ok(library_file_at(conn, &scanned.file.path)?)
+    })?
+    .ok_or_else(|| CommandError::Db(format!("Imported file missing from library: {}", scanned.file.path)))?;
+    Ok(ImportResult::Imported(Box::new(file)))
+}
+
+/// Copy a document from outside the library into `dest_dir` and add it to
+/// the index, returning its library entry. Fails if the library already has
+/// a document with the same content, or if `dest_dir` already has a file of
+/// that name (unless `force`).
+#[tauri::command]
+#[tracing::instrument(skip(app_handle, state), err)]
+fn import_document(
+    app_handle: tauri::AppHandle,
+    state: State<DbState>,
+    src_path: String,
+    dest_dir: String,
+    force: Option<bool>,
+) -> Result<LibraryFile, CommandError> {
+    let src = Path::new(&src_path);
+    let dest_dir = Path::new(&dest_dir);
+    ensure_write_allowed(&app_handle, &dest_dir.join(src.file_name().unwrap_or_default()))?;
+    let options = RebuildOptions::from_config(&load_defaults_typed(&app_handle));
+
+    match import_one(&*state, src, dest_dir, force.unwrap_or(false), &options)? {
+        ImportResult::Imported(file) => Ok(*file),
+        ImportResult::Duplicate(existing) => Err(CommandError::Validation(format!(
+            "Already in the library as {}",
+            existing
+        ))),
+    }
+}
+
+/// A document import_folder (or export_zip) left out, and why
+#[derive(Serialize, Debug)]
+struct ImportSkip {
+    path: String,
+    reason: String,
+}
+
+/// Result of import_folder
+#[derive(Serialize, Default)]
+struct FolderImport {
+    imported: Vec<LibraryFile>,
+    /// Same content already in the library (`reason` is the existing path)
+    duplicates: Vec<ImportSkip>,
+    failed: Vec<ImportSkip>,
+}
+
+/// Progress of import_folder, emitted as "import-progress" after each document
+#[derive(Serialize, Clone, Debug)]
+struct ImportProgress {
+    done: usize,
+    total: usize,
+}
+
+/// Import every document under `src_dir` (recursively) into `dest_dir`,
+/// keeping the subfolder layout. Duplicates and failures are reported per
+/// file rather than stopping the import.
+/// Runs on a blocking thread (see import_one for when it locks the database).
+#[tauri::command]
+#[tracing::instrument(skip(app_handle, queue), err)]
+async fn import_folder(
+    app_handle: tauri::AppHandle,
+    queue: State<'_, IndexQueue>,
+    src_dir: String,
+    dest_dir: String,
+    force: Option<bool>,
+) -> Result<FolderImport, CommandError> {
+    ensure_write_allowed(&app_handle, Path::new(&dest_dir))?;
+    let options = RebuildOptions::from_config(&load_defaults_typed(&app_handle));
+    let _paused = queue.pause_while()?;
+
+    let app = app_handle.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let state = app.state::<DbState>();
+        let (src_dir, dest_dir) = (Path::new(&src_dir), Path::new(&dest_dir));
+        import_documents(&state, src_dir, dest_dir, force.unwrap_or(false), &options, |done, total| {
+            let _ = app.emit("import-progress", ImportProgress { done, total });
+        })
+    })
+    .await
+    .map_err(|e| CommandError::Io(format!("Folder import failed: {}", e)))?
+}
+
+fn import_documents<F>(
+    state: &DbState,
+    src_dir: &Path,
+    dest_dir: &Path,
+    force: bool,
+    options: &RebuildOptions,
+    mut progress: F,
+) -> Result<FolderImport, CommandError>
+where
+    F: FnMut(usize, usize),
+{
+    if !src_dir.is_dir() {
+        return Err(CommandError::NotFound(format!("Folder does not exist: {}", src_dir.display())));
+    }
+
+    let src_dir = &dunce::canonicalize(src_dir).map_err(|e| CommandError::io("Failed to resolve folder", e))?;
+    let ignore = library_ignore(src_dir, &options.ignore_patterns)?;
+    let cancel = AtomicBool::new(false);
+    let paths = find_library_documents(src_dir, &ignore, options, &cancel, &ScanLimits::unlimited(), None)
+        .map_err(CommandError::Io)?;
+
+    let mut result = FolderImport::default();
+    for (index, path) in paths.iter().enumerate() {
+        // Same layout below dest_dir as below src_dir
+        let target_dir = path
+            .parent()
+            .and_then(|parent| parent.strip_prefix(src_dir).ok())
+            .map_or_else(|| dest_dir.to_path_buf(), |relative| dest_dir.join(relative));
+
+        // Each document is committed on its own, so a failure doesn't undo
+        // earlier imports
+        let skip = |reason: String| ImportSkip {
+            path: path.to_string_lossy().to_string(),
+            reason,
+        };
+        match import_one(state, path, &target_dir, force, options) {
+            Ok(ImportResult::Imported(file)) => result.imported.push(*file),
+            Ok(ImportResult::Duplicate(existing)) => result.duplicates.push(skip(existing)),
+            Err(e) => result.failed.push(skip(e.to_string())),
+        }
+
+        progress(index + 1, paths.len());
+    }
+
+    tracing::info!(
+        imported = result.imported.len(),
+        duplicates = result.duplicates.len(),
+        failed = result.failed.len(),
+        "folder import finished"
+    );
+    Ok(result)
+}
+
+/// Move or rename a document and update its library entry in one step.
+/// Fails if the destination already exists on disk or in the library.
+/// The DB update runs in a transaction that is only committed once the file
+/// has been renamed, so a failure in either step leaves both unchanged.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle, state), err)]
+fn move_file(
+    app_handle: tauri::AppHandle,
+    state: State<DbState>,
+    old_path: String,
+    new_path: String,
+) -> Result<(), CommandError> {
+    ensure_write_allowed(&app_handle, Path::new(&old_path))?;
+    ensure_write_allowed(&app_handle, Path::new(&new_path))?;
+    let (old_path, new_path) = (resolve_path(&old_path), resolve_path(&new_path));
+    state.with_conn(|conn| {
+        let old = Path::new(&old_path);
+        let new = Path::new(&new_path);
+
+        if !old.exists() {
+            return Err(CommandError::NotFound(format!("File does not exist: {}", old_path)));
+        }
+
+        if new.exists() {
+            return Err(CommandError::Validation(format!("Destination already exists: {}", new_path)));
+        }
+
+        let in_library: bool = conn
+            .query_row(
+                "SELECT EXISTS(SELECT 1 FROM files WHERE path = ?1)",
+                params![normalize_path(&new_path)],
+                |row| row.get(0),
+            )?;
+        if in_library {
+            return Err(CommandError::Validation(format!(
+                "Destination is already in the library: {}",
+                new_path
+            )));
+        }
+
+        // Create parent directories if they don't exist
+        if let Some(parent) = new.parent() {
+            fs::create_dir_all(parent).map_err(|e| CommandError::io("Failed to create directories", e))?;
+        }
+
+        let filename = new
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let tx = conn.transaction()?;
+        tx.execute(
+            "UPDATE files SET path = ?1, filename = ?2 WHERE path = ?3",
+            params![normalize_path(&new_path), filename, normalize_path(&old_path)],
+        )?;
+
+        // If the rename fails the transaction is dropped and rolled back
+        fs::rename(old, new).map_err(|e| CommandError::io("Failed to move file", e))?;
+
+        if let Err(e) = tx.commit() {
+            // Put the file back so disk and library still agree
+            let _ = fs::rename(new, old);
+            return Err(CommandError::Db(format!("Failed to update library: {}", e)));
+        }
+
+        // Sidecars are moved best-effort - the document itself has already moved
+        for (from, to) in sidecar_paths(old).into_iter().zip(sidecar_paths(new)) {
+            if from.exists() && !to.exists() {
+                let _ = fs::rename(&from, &to);
+            }
+        }
+
+        Ok(())
+    })
+}
+
+/// One move for move_files
+#[derive(Deserialize, Debug, Clone)]
+struct FileMove {
+    from: String,
+    to: String,
+}
+
+/// Outcome of one move in move_files
+#[derive(Serialize, Debug, PartialEq)]
+struct MoveResult {
+    from: String,
+    to: String,
+    moved: bool,
+    /// Why this move failed, or that it was undone because another did
+    error: Option<String>,
+}
+
+/// Move several documents at once, all or none: every destination is
+/// checked to be free before anything moves, the library rows are updated
+/// in one transaction, and if a rename or the commit fails the files
+/// already moved are put back (best-effort). Returns one result per move,
+/// in order.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle, state), err)]
+fn move_files(
+    app_handle: tauri::AppHandle,
+    state: State<DbState>,
+    moves: Vec<FileMove>,
+) -> Result<Vec<MoveResult>, CommandError> {
+    for file_move in &moves {
+        ensure_write_allowed(&app_handle, Path::new(&file_move.from))?;
+        ensure_write_allowed(&app_handle, Path::new(&file_move.to))?;
+    }
+    let resolved: Vec<FileMove> = moves
+        .iter()
+        .map(|m| FileMove { from: resolve_path(&m.from), to: resolve_path(&m.to) })
+        .collect();
+    let results = state.with_conn(|conn| move_documents(conn, &resolved))?;
+
+    // Report each move by the paths the caller gave
+    Ok(results
+        .into_iter()
+        .zip(moves)
+        .map(|(result, file_move)| MoveResult { from: file_move.from, to: file_move.to, ..result })
+        .collect())
+}
+
+/// Why `file_move` can't be made, if anything, before any file is touched
+fn move_problem(conn: &Connection, file_move: &FileMove, moves: &[FileMove]) -> Result<Option<String>, CommandError> {
+    let (from, to) = (Path::new(&file_move.from), Path::new(&file_move.to));
+    let same = |a: &str, b: &str| normalize_path(a) == normalize_path(b);
+
+    if !from.exists() {
+        return Ok(Some(format!("File does not exist: {}", file_move.from)));
+    }
+    if to.exists() {
+        return Ok(Some(format!("Destination already exists: {}", file_move.to)));
+    }
+    if moves.iter().filter(|m| same(&m.to, &file_move.to)).count() > 1 {
+        return Ok(Some(format!("More than one file is moving to {}", file_move.to)));
+    }
+    if moves.iter().filter(|m| same(&m.from, &file_move.from)).count() > 1 {
+        return Ok(Some(format!("File is moved more than once: {}", file_move.from)));
+    }
+    let in_library: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM files WHERE path = ?1)",
+        params![normalize_path(&file_move.to)],
+        |row| row.get(0),
+    )?;
+    if in_library {
+        return Ok(Some(format!("Destination is already in the library: {}", file_move.to)));
+    }
+    Ok(None)
+}
+
+fn move_documents(conn: &mut Connection, moves: &[FileMove]) -> Result<Vec<MoveResult>, CommandError> {
+    let result = |file_move: &FileMove, moved: bool, error: Option<String>| MoveResult {
+        from: file_move.from.clone(),
+        to: file_move.to.clone(),
+        moved,
+        error,
+    };
+    // Every move fails with its own error, or with `other` if it was fine
+    let all_failed = |errors: Vec<Option<String>>, other: &str| -> Vec<MoveResult> {
+        moves
+            .iter()
+            .zip(errors)
+            .map(|(m, e)| result(m, false, Some(e.unwrap_or_else(|| other.to_string()))))
+            .collect()
+    };
+
+    let problems = moves
+        .iter()
+        .map(|m| move_problem(conn, m, moves))
+        .collect::<Result<Vec<_>, _>>()?;
+    if problems.iter().any(Option::is_some) {
+        return Ok(all_failed(problems, "Not moved: another move in the batch can't be made"));
+    }
+
+    let tx = conn.transaction()?;
+    {
+        let mut update = tx.prepare("UPDATE files SET path = ?1, filename = ?2 WHERE path = ?3")?;
+        for file_move in moves {
+            let filename = Path::new(&file_move.to)
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default();
+            update.execute(params![normalize_path(&file_move.to), filename, normalize_path(&file_move.from)])?;
+        }
+    }
+
+    // Put back the first `count` files, newest move first
+    let undo = |count: usize| {
+        for file_move in moves[..count].iter().rev() {
+            if let Err(e) = fs::rename(&file_move.to, &file_move.from) {
+                tracing::error!(from = %file_move.to, to = %file_move.from, "Failed to undo move: {}", e);
+            }
+        }
+    };
+
+    for (index, file_move) in moves.iter().enumerate() {
+        let to = Path::new(&file_move.to);
+        let renamed = to
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::rename(&file_move.from, to));
+        if let Err(e) = renamed {
+            // Dropping the transaction rolls the library back
+            undo(index);
+            let mut errors = vec![None; moves.len()];
+            errors[index] = Some(CommandError::io("Failed to move file", e).to_string());
+            return Ok(all_failed(errors, "Not moved: another move in the batch failed"));
+        }
+    }
+
+    if let Err(e) = tx.commit() {
+        undo(moves.len());
+        let error = format!("Failed to update library: {}", e);
+        return Ok(all_failed(vec![Some(error); moves.len()], ""));
+    }
+
+    // Sidecars are moved best-effort - the documents themselves have moved
+    for file_move in moves {
+        let (old, new) = (Path::new(&file_move.from), Path::new(&file_move.to));
+        for (from, to) in sidecar_paths(old).into_iter().zip(sidecar_paths(new)) {
+            if from.exists() && !to.exists() {
+                let _ = fs::rename(&from, &to);
+            }
+        }
+    }
+
+    Ok(moves.iter().map(|m| result(m, true, None)).collect())
+}
+
+/// Locks older than this are assumed to be left behind by a crash and can be
+/// taken over
+const LOCK_STALE_AFTER_SECS: i64 = 12 * 60 * 60;
+
+/// Contents of a `<document>.lock` file
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct FileLock {
+    pid: u32,
+    acquired: String,
+}
+
+/// Take the advisory lock on a document before opening it for editing.
+/// Fails with the holder's PID and time if another instance has a fresh lock.
+/// Re-acquiring a lock this process already holds refreshes it.
+#[tauri::command]
+#[tracing::instrument(skip(held), err)]
+fn acquire_file_lock(held: State<HeldLocks>, path: String) -> Result<(), CommandError> {
+    acquire_lock(Path::new(&path), std::process::id(), chrono::Utc::now())?;
+    if let Ok(mut held) = held.0.lock() {
+        held.insert(std::path::PathBuf::from(path));
+    }
+    Ok(())
+}
+
+/// Release the advisory lock on a document. Locks held by other processes
+/// are left alone.
+#[tauri::command]
+#[tracing::instrument(skip(held), err)]
+fn release_file_lock(held: State<HeldLocks>, path: String) -> Result<(), CommandError> {
+    if let Ok(mut held) = held.0.lock() {
+        held.remove(Path::new(&path));
+    }
+    release_lock(Path::new(&path), std::process::id())
+}
+
+/// Documents this process has locked, so the locks can be released on exit
+/// even if the frontend never gets the chance
+struct HeldLocks(Mutex<std::collections::HashSet<std::path::PathBuf>>);
+
+fn lock_path(path: &Path) -> std::path::PathBuf {
+    path_with_suffix(path, ".lock")
+}
+
+fn read_lock(lock_file: &Path) -> Option<FileLock> {
+    let contents = fs::read_to_string(lock_file).ok()?;
+    serde_json::from_str(&contents).ok()
+}
+
+fn acquire_lock(path: &Path, pid: u32, now: chrono::DateTime<chrono::Utc>) -> Result<(), CommandError> {
+    let lock_file = lock_path(path);
+    let lock = FileLock {
+        pid,
+        acquired: now.format(DB_TIMESTAMP_FORMAT).to_string(),
+    };
+    let contents = serde_json::to_string(&lock)?;
+
+    // create_new makes the check-and-create atomic when there is no lock yet
+    match fs::OpenOptions::new().write(true).create_new(true).open(&lock_file) {
+        Ok(mut file) => {
+            return file
+                .write_all(contents.as_bytes())
+                .map_err(|e| CommandError::io("Failed to write lock file", e));
+        }
+        Err(e) if e.kind() != std::io::ErrorKind::AlreadyExists => {
+            return Err(CommandError::io("Failed to create lock file", e));
+        }
+        Err(_) => {}
+    }
+
+    // An unreadable lock file is treated as stale
+    if let Some(holder) = read_lock(&lock_file) {
+        let age = chrono::DateTime::parse_from_rfc3339(&holder.acquired)
+            .map(|acquired| now.signed_duration_since(acquired).num_seconds())
+            .unwrap_or(i64::MAX);
+
+        if holder.pid != pid && age < LOCK_STALE_AFTER_SECS {
+            return Err(CommandError::Permission(format!(
+                "Document is already open in another instance (PID {}, since {})",
+                holder.pid, holder.acquired
+            )));
+        }
+    }
+
+    fs::write(&lock_file, contents).map_err(|e| CommandError::io("Failed to write lock file", e))
+}
+
+fn release_lock(path: &Path, pid: u32) -> Result<(), CommandError> {
+    let lock_file = lock_path(path);
+
+    match read_lock(&lock_file) {
+        Some(holder) if holder.pid != pid => Ok(()),
+        _ if !lock_file.exists() => Ok(()),
+        _ => fs::remove_file(&lock_file).map_err(|e| CommandError::io("Failed to remove lock file", e)),
+    }
+}
+
+// ============================================================================
+// Thumbnail Cache
+// ============================================================================
+//
+// Thumbnails for the file browser are rendered once and kept as PNG files in
+// <config dir>/thumbnails. Cache keys include the source file's mtime and
+// size, so editing a file simply misses the old entry; stale entries age out
+// through eviction. When the cache grows past its size budget (defaults.json
+// thumbnails.cacheMaxBytes) the least recently used entries are removed -
+// reading an entry bumps its mtime, which is what eviction orders by.
+//
+// ============================================================================
+
+/// Cache size budget used when defaults.json doesn't set one
+const DEFAULT_THUMBNAIL_CACHE_BYTES: u64 = 200 * 1024 * 1024;
+
+/// Longest edge of a thumbnail, matching the thumbnails embedded in .ssce files
+const DEFAULT_THUMBNAIL_MAX_DIM: u32 = 150;
+
+/// JPEG quality used when load_thumbnail isn't given one
+const DEFAULT_THUMBNAIL_QUALITY: u8 = 80;
+
+/// Image format for load_thumbnail
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum ThumbnailFormat {
+    #[default]
+    Png,
+    /// Lossless; the bundled WebP encoder has no lossy mode
+    Webp,
+    Jpeg,
+    /// No AVIF encoder is built in, so this is served as PNG
+    Avif,
+}
+
+impl ThumbnailFormat {
+    /// The format actually produced: the requested one if its encoder is
+    /// built in, else PNG
+    fn encoded(self) -> Self {
+        match self {
+            ThumbnailFormat::Avif => ThumbnailFormat::Png,
+            other => other,
+        }
+    }
+
+    fn image_format(self) -> image::ImageFormat {
+        match self.encoded() {
+            ThumbnailFormat::Webp => image::ImageFormat::WebP,
+            ThumbnailFormat::Jpeg => image::ImageFormat::Jpeg,
+            ThumbnailFormat::Png | ThumbnailFormat::Avif => image::ImageFormat::Png,
+        }
+    }
+
+    fn mime(self) -> &'static str {
+        self.image_format().to_mime_type()
+    }
+}
+
+/// How a thumbnail is encoded
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ThumbnailEncoding {
+    format: ThumbnailFormat,
+    /// 1-100, used by lossy formats only
+    quality: u8,
+}
+
+impl ThumbnailEncoding {
+    const PNG: ThumbnailEncoding = ThumbnailEncoding {
+        format: ThumbnailFormat::Png,
+        quality: 0,
+    };
+
+    /// The encoding a request gets: unavailable formats become PNG, and
+    /// quality only matters (and is only kept) for lossy ones
+    fn new(format: ThumbnailFormat, quality: Option<u8>) -> Self {
+        let format = format.encoded();
+        let quality = match format {
+            ThumbnailFormat::Jpeg => quality.unwrap_or(DEFAULT_THUMBNAIL_QUALITY).clamp(1, 100),
+            _ => 0,
+        };
+        ThumbnailEncoding { format, quality }
+    }
+}
+
+struct ThumbnailCache {
+    dir: std::path::PathBuf,
+    max_bytes: u64,
+    /// Sources over these limits aren't decoded
+    image_limits: ImageLimits,
+    /// One lock per key being generated, so concurrent requests for the same
+    /// thumbnail wait for the first instead of rendering it again
+    in_flight: Mutex<HashMap<String, Arc<Mutex<()>>>>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct ThumbnailCacheStats {
+    entries: usize,
+    bytes: u64,
+}
+
+impl ThumbnailCache {
+    fn new(dir: std::path::PathBuf, max_bytes: u64) -> Self {
+        ThumbnailCache {
+            dir,
+            max_bytes,
+            image_limits: ImageLimits::default(),
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn from_config(config: &Defaults) -> Self {
+        let max_bytes = config.thumbnails.cache_max_bytes;
+        let config_dir = get_user_config_dir().unwrap_or_else(|_| std::env::temp_dir().join("ssce-desktop"));
+        ThumbnailCache {
+            image_limits: ImageLimits::from_config(config),
+            ..ThumbnailCache::new(config_dir.join("thumbnails"), max_bytes)
+        }
+    }
+
+    /// Cache key for a PNG thumbnail of `path`, or None if the file can't be read
+    fn key(path: &Path, max_dim: u32) -> Option<String> {
+        ThumbnailCache::key_for(path, max_dim, ThumbnailEncoding::PNG)
+    }
+
+    /// Cache key for a thumbnail of `path` in the given encoding. Entries
+    /// keep the .png extension whatever their format; the key tells them apart.
+    fn key_for(path: &Path, max_dim: u32, encoding: ThumbnailEncoding) -> Option<String> {
+        use sha2::{Digest, Sha256};
+
+        let metadata = fs::metadata(path).ok()?;
+        let mtime = metadata
+            .modified()
+            .ok()?
+            .duration_since(std::time::UNIX_EPOCH)
+            .ok()?
+            .as_nanos();
+
+        let mut hasher = Sha256::new();
+        hasher.update(normalize_path(&path.to_string_lossy()).as_bytes());
+        hasher.update(format!("|{}|{}|{}", mtime, metadata.len(), max_dim).as_bytes());
+        // PNG keys are unchanged from before formats were added, so existing
+        // entries stay valid
+        if encoding != ThumbnailEncoding::PNG {
+            hasher.update(format!("|{:?}|{}", encoding.format, encoding.quality).as_bytes());
+        }
+        Some(format!("{:x}", hasher.finalize()))
+    }
+
+    fn entry_path(&self, key: &str) -> std::path::PathBuf {
+        self.dir.join(format!("{}.png", key))
+    }
+
+    /// File next to an entry holding the path of the file it was rendered
+    /// from, so entries for deleted documents can be found
+    fn source_path(&self, entry: &Path) -> std::path::PathBuf {
+        entry.with_extension("src")
+    }
+
+    /// Delete an entry and its source record
+    fn remove_entry(&self, entry: &Path) -> Result<(), CommandError> {
+        fs::remove_file(entry).map_err(|e| CommandError::io("Failed to remove cache entry", e))?;
+        let _ = fs::remove_file(self.source_path(entry));
+        Ok(())
+    }
+
+    /// Read an entry and mark it as recently used
+    fn read(&self, key: &str) -> Option<Vec<u8>> {
+        let path = self.entry_path(key);
+        let bytes = fs::read(&path).ok()?;
+        if let Ok(file) = fs::File::options().write(true).open(&path) {
+            let _ = file.set_modified(std::time::SystemTime::now());
+        }
+        Some(bytes)
+    }
+
+    /// Return the cached entry for `key`, generating and storing it on a miss.
+    /// Failing to write the cache isn't an error - the thumbnail is still returned.
+    fn get_or_generate<F>(&self, key: &str, source: &Path, generate: F) -> Result<Vec<u8>, String>
+    where
+        F: FnOnce() -> Result<Vec<u8>, String>,
+    {
+        if let Some(bytes) = self.read(key) {
+            return Ok(bytes);
+        }
+
+        let slot = {
+            let mut in_flight = self.in_flight.lock().map_err(|e| e.to_string())?;
+            in_flight.entry(key.to_string()).or_default().clone()
+        };
+
+        let result = {
+            let _generating = slot.lock().unwrap_or_else(|e| e.into_inner());
+
+            // Someone else may have generated it while we waited
+            match self.read(key) {
+                Some(bytes) => Ok(bytes),
+                None => generate().inspect(|bytes| {
+                    let stored = fs::create_dir_all(&self.dir)
+                        .map_err(CommandError::from)
+                        .and_then(|_| write_atomic(&self.entry_path(key), |w| w.write_all(bytes)))
+                        .and_then(|_| {
+                            let source = source.to_string_lossy();
+                            write_atomic(&self.source_path(&self.entry_path(key)), |w| w.write_all(source.as_bytes()))
+                        });
+                    match stored {
+                        Ok(()) => self.evict_to_budget(),
+                        Err(e) => tracing::warn!("Failed to cache thumbnail: {}", e),
+                    }
+                }),
+            }
+        };
+
+        if let Ok(mut in_flight) = self.in_flight.lock() {
+            in_flight.remove(key);
+        }
+
+        result
+    }
+
+    /// Cached entries with their size and last-used time
+    fn entries(&self) -> Vec<(std::path::PathBuf, u64, std::time::SystemTime)> {
+        let Ok(read_dir) = fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+
+        read_dir
+            .flatten()
+            .filter(|entry| entry.path().extension().is_some_and(|e| e == "png"))
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok()?;
+                let used = metadata.modified().unwrap_or(std::time::UNIX_EPOCH);
+                Some((entry.path(), metadata.len(), used))
+            })
+            .collect()
+    }
+
+    /// Remove least recently used entries until the cache fits its budget
+    fn evict_to_budget(&self) {
+        let mut entries = self.entries();
+        let mut total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+        if total <= self.max_bytes {
+            return;
+        }
+
+        entries.sort_by_key(|(_, _, used)| *used);
+        for (path, size, _) in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            if self.remove_entry(&path).is_ok() {
+                total -= size;
+            }
+        }
+    }
+
+    fn stats(&self) -> ThumbnailCacheStats {
+        let entries = self.entries();
+        ThumbnailCacheStats {
+            entries: entries.len(),
+            bytes: entries.iter().map(|(_, size, _)| size).sum(),
+        }
+    }
+
+    fn clear(&self) -> Result<(), String> {
+        for (path, _, _) in self.entries() {
+            self.remove_entry(&path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Decode the base64 payload of a data URL (or bare base64 string)
+fn decode_data_url(data: &str) -> Result<Vec<u8>, CommandError> {
+    let base64_data = data.split_once(',').map_or(data, |(_, payload)| payload);
+    STANDARD
+        .decode(base64_data)
+        .map_err(|e| CommandError::Parse(format!("Failed to decode base64: {}", e)))
+}
+
+/// Scale an image to fit within `max_dim` and encode it as PNG
+fn encode_thumbnail(source: &image::DynamicImage, max_dim: u32) -> Result<Vec<u8>, String> {
+    encode_thumbnail_as(source, max_dim, ThumbnailEncoding::PNG)
+}
+
+/// Scale an image to fit within `max_dim` and encode it as `encoding` says
+fn encode_thumbnail_as(
+    source: &image::DynamicImage,
+    max_dim: u32,
+    encoding: ThumbnailEncoding,
+) -> Result<Vec<u8>, String> {
+    let thumbnail = source.thumbnail(max_dim, max_dim);
+    let mut bytes = Vec::new();
+    let written = match encoding.format.encoded() {
+        ThumbnailFormat::Jpeg => {
+            // JPEG has no alpha channel
+            let encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut bytes, encoding.quality);
+            image::DynamicImage::ImageRgb8(thumbnail.to_rgb8()).write_with_encoder(encoder)
+        }
+        format => thumbnail.write_to(&mut std::io::Cursor::new(&mut bytes), format.image_format()),
+    };
+    written.map_err(|e| format!("Failed to encode thumbnail: {}", e))?;
+    Ok(bytes)
+}
+
+/// The image of the first snapshot in a .ssce document that decodes within
+/// `limits`
+fn first_snapshot_image(json: &serde_json::Value, limits: &ImageLimits) -> Option<image::DynamicImage> {
+    json["snapshots"]
+        .as_array()?
+        .iter()
+        .filter_map(|snapshot| snapshot["image"].as_str())
+        .find_map(|data| decode_image_bytes(&decode_data_url(data).ok()?, limits).ok())
+}
+
+/// Thumbnail data URL rendered from the document's first usable snapshot,
+/// for older files saved without an embedded thumbnail. Runs where no
+/// config is at hand (migrations, metadata reads), so the default
+/// ImageLimits apply.
+fn derive_ssce_thumbnail(json: &serde_json::Value) -> Option<String> {
+    let png = encode_thumbnail(&first_snapshot_image(json, &ImageLimits::default())?, DEFAULT_THUMBNAIL_MAX_DIM).ok()?;
+    Some(format!("data:image/png;base64,{}", STANDARD.encode(png)))
+}
+
+/// The image a .ssce file's thumbnail is made from: the embedded thumbnail
+/// (read without loading the snapshots), else the first snapshot.
+/// None if the document has neither.
+fn ssce_thumbnail_source(path: &Path, limits: &ImageLimits) -> Result<Option<image::DynamicImage>, String> {
+    if let Some(embedded) = read_ssce_header(path)?.thumbnail {
+        return Ok(Some(decode_image_bytes(&decode_data_url(&embedded)?, limits)?));
+    }
+
+    let content = read_document_text(path)?;
+    let json: serde_json::Value =
+        serde_json::from_str(&content).map_err(|e| format!("Failed to parse JSON: {}", e))?;
+    Ok(first_snapshot_image(&json, limits))
+}
+
+/// Render a thumbnail no larger than `max_dim` on either edge.
+/// For .ssce files this scales the embedded thumbnail, or the first
+/// snapshot if there isn't one.
+fn render_thumbnail(
+    path: &Path,
+    max_dim: u32,
+    encoding: ThumbnailEncoding,
+    limits: &ImageLimits,
+) -> Result<Vec<u8>, String> {
+    let source = if is_ssce_path(&path.to_string_lossy()) {
+        ssce_thumbnail_source(path, limits)?
+            .ok_or_else(|| format!("No thumbnail or snapshot image in {}", path.display()))?
+    } else {
+        decode_image_file(path, limits)?
+    };
+
+    encode_thumbnail_as(&source, max_dim, encoding)
+}
+
+/// Thumbnail of a .ssce file as a PNG data URL, served from the on-disk
+/// thumbnail cache. Returns None for encrypted documents and documents
+/// with no thumbnail or snapshot to make one from. Reading and rendering
+/// run on a blocking thread.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle), err)]
+async fn get_ssce_thumbnail_cached(app_handle: tauri::AppHandle, path: String) -> Result<Option<String>, CommandError> {
+    let app = app_handle.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let file_path = Path::new(&path);
+
+        if !file_path.exists() || is_encrypted_ssce(file_path) {
+            return Ok(None);
+        }
+
+        let key = ThumbnailCache::key(file_path, DEFAULT_THUMBNAIL_MAX_DIM)
+            .ok_or_else(|| CommandError::NotFound(format!("File does not exist: {}", path)))?;
+
+        // Nothing is cached for a document without an image, so it's checked
+        // again next time (it may have gained a snapshot by then)
+        let mut no_image = false;
+        let cache = app.state::<ThumbnailCache>();
+        let limits = &cache.image_limits;
+        let rendered = cache.get_or_generate(&key, file_path, || match ssce_thumbnail_source(file_path, limits)? {
+            Some(source) => encode_thumbnail(&source, DEFAULT_THUMBNAIL_MAX_DIM),
+            None => {
+                no_image = true;
+                Err(format!("No thumbnail or snapshot image in {}", path))
+            }
+        });
+
+        match rendered {
+            Ok(png) => Ok(Some(format!("data:image/png;base64,{}", STANDARD.encode(png)))),
+            Err(_) if no_image => Ok(None),
+            Err(e) => Err(CommandError::Io(e)),
+        }
+    })
+    .await
+    .map_err(|e| CommandError::Io(format!("Loading thumbnail failed: {}", e)))?
+}
+
+/// Thumbnail for a .ssce file: the embedded one if present, otherwise one
+/// derived from the first snapshot. With `write_back`, a derived thumbnail
+/// is saved into the file so it doesn't have to be derived again.
+/// Returns None when the document has neither.
+#[tauri::command]
+#[tracing::instrument(err)]
+fn generate_ssce_thumbnail(path: String, write_back: Option<bool>) -> Result<Option<String>, CommandError> {
+    let file_path = Path::new(&path);
+
+    if !file_path.exists() || is_encrypted_ssce(file_path) {
+        return Ok(None);
+    }
+
+    let content = read_document_text(file_path)?;
+    let mut json: serde_json::Value =
+        serde_json::from_str(&content).map_err(|e| CommandError::Parse(format!("Failed to parse JSON: {}", e)))?;
+
+    if let Some(embedded) = json["thumbnail"].as_str() {
+        return Ok(Some(embedded.to_string()));
+    }
+
+    let Some(thumbnail) = derive_ssce_thumbnail(&json) else {
+        return Ok(None);
+    };
+
+    if write_back.unwrap_or(false) {
+        write_thumbnail_back(file_path, &mut json, &thumbnail).map_err(CommandError::Io)?;
+    }
+
+    Ok(Some(thumbnail))
+}
+
+/// Store `thumbnail` in the document and save it over `path`
+fn write_thumbnail_back(path: &Path, json: &mut serde_json::Value, thumbnail: &str) -> Result<(), String> {
+    if let Some(obj) = json.as_object_mut() {
+        obj.insert("thumbnail".to_string(), serde_json::Value::String(thumbnail.to_string()));
+    }
+    // Same layout as the frontend's JSON.stringify(data, null, 2)
+    let data = serde_json::to_string_pretty(json).map_err(|e| e.to_string())?;
+    // Keep a compressed file compressed
+    write_ssce(path.to_string_lossy().to_string(), data, Some(is_gzipped(path)), &RetryPolicy::default()).map_err(|e| match e {
+        SaveError::ReadOnly(message)
+        | SaveError::OutsideRoot(message)
+        | SaveError::Io(message)
+        | SaveError::Invalid(message) => message,
+    })
+}
+
+/// Outcome of regenerate_thumbnails
+#[derive(Serialize, Debug, Default, PartialEq)]
+struct ThumbnailRegeneration {
+    /// Documents that got a thumbnail derived from a snapshot
+    generated: usize,
+    /// Documents that already had one, have no snapshot, or are encrypted
+    skipped: usize,
+    failed: usize,
+    cancelled: bool,
+}
+
+/// Progress of regenerate_thumbnails, emitted as
+/// "regenerate-thumbnails-progress" after each document
+#[derive(Serialize, Clone, Debug)]
+struct ThumbnailRegenerationProgress {
+    done: usize,
+    total: usize,
+}
+
+/// Give every document in the library that lacks an embedded thumbnail one
+/// derived from its first snapshot, written back into the file. Honors the
+/// rebuild's ignore patterns and extensions, and is stopped by
+/// cancel_regenerate_thumbnails. Fails with AlreadyRunning while another
+/// regeneration is in progress. Runs on a blocking thread.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle), err)]
+async fn regenerate_thumbnails(
+    app_handle: tauri::AppHandle,
+    library_path: String,
+) -> Result<ThumbnailRegeneration, CommandError> {
+    let app = app_handle.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let slot = app.state::<ThumbnailRegenerationSlot>();
+        let _running = slot.0.start()?;
+        let queue = app.state::<IndexQueue>();
+        let _paused = queue.pause_while()?;
+        let options = RebuildOptions::from_config(&load_defaults_typed(&app));
+        regenerate_library_thumbnails(Path::new(&library_path), &options, &slot.0.cancel, |done, total| {
+            let _ = app.emit("regenerate-thumbnails-progress", ThumbnailRegenerationProgress { done, total });
+        })
+        .map_err(CommandError::Io)
+    })
+    .await
+    .map_err(|e| CommandError::Io(format!("Thumbnail regeneration failed: {}", e)))?
+}
+
+/// Ask an in-progress regenerate_thumbnails to stop after the current document
+#[tauri::command]
+#[tracing::instrument(skip(slot))]
+fn cancel_regenerate_thumbnails(slot: State<ThumbnailRegenerationSlot>) {
+    slot.0.cancel();
+}
+
+fn regenerate_library_thumbnails<F>(
+    library_path: &Path,
+    options: &RebuildOptions,
+    cancel: &AtomicBool,
+    mut progress: F,
+) -> Result<ThumbnailRegeneration, String>
+where
+    F: FnMut(usize, usize),
+{
+    if !library_path.exists() {
+        return Err(format!("Library path does not exist: {}", library_path.display()));
+    }
+
+    let library_path = &dunce::canonicalize(library_path).map_err(|e| e.to_string())?;
+    let ignore = library_ignore(library_path, &options.ignore_patterns)?;
+    let paths = find_library_documents(library_path, &ignore, options, cancel, &ScanLimits::unlimited(), None)?;
+
+    let mut result = ThumbnailRegeneration::default();
+
+    for (index, path) in paths.iter().enumerate() {
+        if cancel.load(Ordering::Relaxed) {
+            break;
+        }
+
+        match regenerate_thumbnail(path) {
+            Ok(true) => result.generated += 1,
+            Ok(false) => result.skipped += 1,
+            Err(e) => {
+                tracing::warn!(path = %path.display(), "Failed to regenerate thumbnail: {}", e);
+                result.failed += 1;
+            }
+        }
+
+        progress(index + 1, paths.len());
+    }
+
+    result.cancelled = cancel.load(Ordering::Relaxed);
+    tracing::info!(?result, "thumbnail regeneration finished");
+    Ok(result)
+}
+
+/// Derive and save a thumbnail for one document. Returns false when it was
+/// left alone.
+fn regenerate_thumbnail(path: &Path) -> Result<bool, String> {
+    if is_encrypted_ssce(path) {
+        return Ok(false);
+    }
+
+    let content = read_document_text(path)?;
+    let mut json: serde_json::Value =
+        serde_json::from_str(&content).map_err(|e| format!("Failed to parse JSON: {}", e))?;
+
+    if json["thumbnail"].is_string() {
+        return Ok(false);
+    }
+
+    let Some(thumbnail) = derive_ssce_thumbnail(&json) else {
+        return Ok(false);
+    };
+
+    write_thumbnail_back(path, &mut json, &thumbnail)?;
+    Ok(true)
+}
+
+/// A sidecar file left behind by a document that was deleted or moved
+#[derive(Serialize, Debug, PartialEq)]
+struct OrphanedSidecar {
+    path: String,
+    /// "backup" (a `<doc>.bak`) or "thumbnail" (a cache entry)
+    kind: &'static str,
+    /// The document it belonged to, which no longer exists
+    base: String,
+}
+
+/// Progress of find_orphaned_sidecars, emitted as "orphan-scan-progress"
+#[derive(Serialize, Clone, Debug)]
+struct OrphanScanProgress {
+    done: usize,
+    total: usize,
+}
+
+/// How many sidecars are checked between progress events
+const ORPHAN_PROGRESS_EVERY: usize = 200;
+
+/// Every `.bak` file under `dir` (symlinked folders aren't followed)
+fn find_backup_files(dir: &Path) -> Vec<std::path::PathBuf> {
+    let mut found = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        let Ok(read_dir) = fs::read_dir(&dir) else { continue };
+        for entry in read_dir.flatten() {
+            let Ok(file_type) = entry.file_type() else { continue };
+            let path = entry.path();
+            if file_type.is_dir() {
+                pending.push(path);
+            } else if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("bak")) {
+                found.push(path);
+            }
+        }
+    }
+
+    found.sort();
+    found
+}
+
+/// If `path` is a sidecar whose document is gone, that document's path.
+/// None for anything else - including a sidecar we can't trace back to
+/// its document, such as a cache entry written before sources were recorded.
+fn orphan_base(path: &Path, cache: &ThumbnailCache) -> Option<(&'static str, std::path::PathBuf)> {
+    let (kind, base) = if path.parent() == Some(cache.dir.as_path()) {
+        ("thumbnail", std::path::PathBuf::from(fs::read_to_string(cache.source_path(path)).ok()?))
+    } else if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("bak")) {
+        ("backup", path.with_extension(""))
+    } else {
+        return None;
+    };
+
+    // A base we can't stat (permissions, unmounted volume) might still exist
+    match fs::symlink_metadata(&base) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Some((kind, base)),
+        _ => None,
+    }
+}
+
+/// Look for `.bak` files under the library folder and thumbnail cache entries
+/// whose document no longer exists. Nothing is deleted; pass the ones to
+/// remove to clean_orphaned_sidecars. The scan runs on a blocking thread.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle), err)]
+async fn find_orphaned_sidecars(
+    app_handle: tauri::AppHandle,
+    library_path: String,
+) -> Result<Vec<OrphanedSidecar>, CommandError> {
+    let app = app_handle.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        orphaned_sidecars(Path::new(&library_path), &app.state::<ThumbnailCache>(), |done, total| {
+            let _ = app.emit("orphan-scan-progress", OrphanScanProgress { done, total });
+        })
+    })
+    .await
+    .map_err(|e| CommandError::Io(format!("Orphan scan failed: {}", e)))?
+}
+
+fn orphaned_sidecars<F>(library_path: &Path, cache: &ThumbnailCache, mut progress: F) -> Result<Vec<OrphanedSidecar>, CommandError>
+where
+    F: FnMut(usize, usize),
+{
+    if !library_path.is_dir() {
+        return Err(CommandError::NotFound(format!("Library path does not exist: {}", library_path.display())));
+    }
+
+    let mut candidates = find_backup_files(library_path);
+    candidates.extend(cache.entries().into_iter().map(|(path, _, _)| path));
+
+    let total = candidates.len();
+    let mut orphans = Vec::new();
+    for (index, path) in candidates.iter().enumerate() {
+        if let Some((kind, base)) = orphan_base(path, cache) {
+            orphans.push(OrphanedSidecar {
+                path: path.to_string_lossy().to_string(),
+                kind,
+                base: base.to_string_lossy().to_string(),
+            });
+        }
+        if (index + 1) % ORPHAN_PROGRESS_EVERY == 0 || index + 1 == total {
+            progress(index + 1, total);
+        }
+    }
+
+    Ok(orphans)
+}
+
+/// Delete sidecars reported by find_orphaned_sidecars. Each one is checked
+/// again first and left alone if its document has reappeared (or it isn't a
+/// sidecar at all). Returns the number removed.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle, cache), err)]
+fn clean_orphaned_sidecars(
+    app_handle: tauri::AppHandle,
+    cache: State<ThumbnailCache>,
+    paths: Vec<String>,
+) -> Result<usize, CommandError> {
+    let roots = allowed_roots(&load_defaults_typed(&app_handle));
+    remove_orphaned_sidecars(&paths, &cache, &roots)
+}
+
+fn remove_orphaned_sidecars(
+    paths: &[String],
+    cache: &ThumbnailCache,
+    roots: &[std::path::PathBuf],
+) -> Result<usize, CommandError> {
+    let mut removed = 0;
+    for path in paths.iter().map(Path::new) {
+        match orphan_base(path, cache) {
+            Some(("thumbnail", _)) => cache.remove_entry(path)?,
+            Some(_) => {
+                check_within_roots(path, roots)?;
+                fs::remove_file(path).map_err(|e| CommandError::io("Failed to remove backup", e))?;
+            }
+            None => {
+                tracing::info!(path = %path.display(), "Not an orphaned sidecar, left in place");
+                continue;
+            }
+        }
+        removed += 1;
+    }
+    Ok(removed)
+}
+
+/// Thumbnail of an image or .ssce file as a data URL, served from the
+/// on-disk cache when the file hasn't changed since it was last rendered.
+/// `max_dim` defaults to the thumbnailMaxDim setting.
+/// `format` defaults to PNG; `quality` (1-100, default 80) applies to JPEG.
+/// A format whose encoder isn't built in (AVIF) is served as PNG, so the
+/// data URL's MIME type says which format was actually returned.
+/// With `placeholder`, a file that can't be rendered gets a placeholder
+/// tile instead of an error (see LoadedImage). Rendering runs on a
+/// blocking thread.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle), err)]
+async fn load_thumbnail(
+    app_handle: tauri::AppHandle,
+    path: String,
+    max_dim: Option<u32>,
+    placeholder: Option<bool>,
+    format: Option<ThumbnailFormat>,
+    quality: Option<u8>,
+) -> Result<LoadedImage, CommandError> {
+    let app = app_handle.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let max_dim = match max_dim {
+            Some(dim) => dim,
+            None => default_thumbnail_max_dim(&app, &app.state::<DbState>())?,
+        };
+        let file_path = &fs_path(&path)?;
+        let encoding = ThumbnailEncoding::new(format.unwrap_or_default(), quality);
+        let loaded = thumbnail_data_url(&app.state::<ThumbnailCache>(), file_path, max_dim, encoding);
+        if placeholder.unwrap_or(false) {
+            Ok(LoadedImage::WithFallback(or_placeholder(loaded, file_path, max_dim)))
+        } else {
+            loaded.map(LoadedImage::DataUrl)
+        }
+    })
+    .await
+    .map_err(|e| CommandError::Io(format!("Loading thumbnail failed: {}", e)))?
+}
+
+/// The thumbnailMaxDim setting
+fn default_thumbnail_max_dim(app_handle: &tauri::AppHandle, state: &DbState) -> Result<u32, CommandError> {
+    let config = load_defaults_typed(app_handle);
+    Ok(state
+        .with_read_conn(|conn| read_setting(conn, &config, "thumbnailMaxDim"))?
+        .as_u64()
+        .map_or(DEFAULT_THUMBNAIL_MAX_DIM, |dim| dim as u32))
+}
+
+fn thumbnail_data_url(
+    cache: &ThumbnailCache,
+    path: &Path,
+    max_dim: u32,
+    encoding: ThumbnailEncoding,
+) -> Result<String, CommandError> {
+    let max_dim = max_dim.max(1);
+    let key = ThumbnailCache::key_for(path, max_dim, encoding)
+        .ok_or_else(|| CommandError::NotFound(format!("File does not exist: {}", path.display())))?;
+    // Checked here as well as in render_thumbnail so the caller gets
+    // TooLarge rather than a rendering error
+    if !is_ssce_path(&path.to_string_lossy()) {
+        check_image_file(path, &cache.image_limits)?;
+    }
+
+    let bytes = cache
+        .get_or_generate(&key, path, || render_thumbnail(path, max_dim, encoding, &cache.image_limits))
+        .map_err(CommandError::Io)?;
+    Ok(format!("data:{};base64,{}", encoding.format.mime(), STANDARD.encode(bytes)))
+}
+
+/// One entry of a load_thumbnails_batch request
+#[derive(Deserialize, Debug, Clone)]
+struct ThumbnailRequest {
+    path: String,
+    max_dim: Option<u32>,
+}
+
+/// One entry of a load_thumbnails_batch result: the data URL, or why it
+/// couldn't be produced
+#[derive(Serialize, Debug, PartialEq)]
+struct BatchThumbnail {
+    thumbnail: Option<String>,
+    error: Option<CommandError>,
+}
+
+/// Thumbnails for many files at once, keyed by path. They are rendered in
+/// parallel on a pool of thumbnails.batchConcurrency threads (kept small so
+/// spinning disks aren't thrashed) and served from the on-disk cache like
+/// load_thumbnail. A file that fails gets an error entry; the rest of the
+/// batch is unaffected. If a path is requested twice the last entry wins.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle, requests), fields(count = requests.len()), err)]
+async fn load_thumbnails_batch(
+    app_handle: tauri::AppHandle,
+    requests: Vec<ThumbnailRequest>,
+) -> Result<HashMap<String, BatchThumbnail>, CommandError> {
+    let app = app_handle.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let default_dim = if requests.iter().any(|r| r.max_dim.is_none()) {
+            default_thumbnail_max_dim(&app, &app.state::<DbState>())?
+        } else {
+            DEFAULT_THUMBNAIL_MAX_DIM
+        };
+        let concurrency = load_defaults_typed(&app).thumbnails.batch_concurrency as usize;
+        let pool = app.state::<ThumbnailPool>().get(concurrency)?;
+
+        Ok(load_thumbnails(&app.state::<ThumbnailCache>(), &requests, default_dim, &pool))
+    })
+    .await
+    .map_err(|e| CommandError::Io(format!("Loading thumbnails failed: {}", e)))?
+}
+
+/// Worker threads for load_thumbnails_batch, kept between batches. Rebuilt
+/// only when thumbnails.batchConcurrency changes.
+#[derive(Default)]
+struct ThumbnailPool(Mutex<Option<(usize, Arc<rayon::ThreadPool>)>>);
+
+impl ThumbnailPool {
+    fn get(&self, concurrency: usize) -> Result<Arc<rayon::ThreadPool>, CommandError> {
+        let concurrency = concurrency.max(1);
+        let mut cached = self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some((threads, pool)) = cached.as_ref() {
+            if *threads == concurrency {
+                return Ok(pool.clone());
+            }
+        }
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(concurrency)
+            .thread_name(|i| format!("thumbnail-{}", i))
+            .build()
+            .map_err(|e| CommandError::Io(format!("Failed to start thumbnail workers: {}", e)))?;
+        let pool = Arc::new(pool);
+        *cached = Some((concurrency, pool.clone()));
+        Ok(pool)
+    }
+}
+
+fn load_thumbnails(
+    cache: &ThumbnailCache,
+    requests: &[ThumbnailRequest],
+    default_dim: u32,
+    pool: &rayon::ThreadPool,
+) -> HashMap<String, BatchThumbnail> {
+    use rayon::prelude::*;
+
+    let results: Vec<(String, BatchThumbnail)> = pool.install(|| {
+        requests
+            .par_iter()
+            .map(|request| {
+                let result = thumbnail_data_url(
+                    cache,
+                    Path::new(&request.path),
+                    request.max_dim.unwrap_or(default_dim),
+                    ThumbnailEncoding::PNG,
+                );
+                let entry = match result {
+                    Ok(thumbnail) => BatchThumbnail { thumbnail: Some(thumbnail), error: None },
+                    Err(e) => BatchThumbnail { thumbnail: None, error: Some(e) },
+                };
+                (request.path.clone(), entry)
+            })
+            .collect()
+    });
+
+    results.into_iter().collect()
+}
+
+/// Remove every cached thumbnail
+#[tauri::command]
+#[tracing::instrument(skip(cache), err)]
+fn clear_thumbnail_cache(cache: State<ThumbnailCache>) -> Result<(), CommandError> {
+    cache.clear().map_err(CommandError::Io)
+}
+
+/// Number of cached thumbnails and their total size in bytes
+#[tauri::command]
+#[tracing::instrument(skip(cache))]
+fn thumbnail_cache_stats(cache: State<ThumbnailCache>) -> ThumbnailCacheStats {
+    cache.stats()
+}
+
+// ============================================================================
+// Autosave Commands
+// ============================================================================
+//
+// Autosave provides crash recovery by periodically saving work to temp files.
+// Files are stored in ~/.ssce-temp/ (Linux) or %APPDATA%/.ssce-temp (Windows).
+// On startup, the app checks for recovery files and prompts to restore.
+//
+// ============================================================================
+
+/// Autosave file entry with metadata for listing recovery files
+#[derive(Serialize)]
+struct AutosaveEntry {
+    name: String,
+    path: String,
+    mtime: u64,
+}
+
+/// Folder (under the user config directory) used for autosaves when the
+/// configured one can't be written to
+const FALLBACK_AUTOSAVE_DIR: &str = "autosave";
+
+/// The autosave folder from `autosave.tempDirectory`: `~` is expanded and a
+/// relative path is taken from the home directory. Defaults to ~/.ssce-temp.
+fn configured_autosave_dir(config: &Defaults) -> Option<std::path::PathBuf> {
+    home_relative_dir(&config.autosave.temp_directory)
+}
+
+/// `configured` with `~` expanded, and taken from the home directory when
+/// relative. None when it's relative and there is no home directory.
+fn home_relative_dir(configured: &str) -> Option<std::path::PathBuf> {
+    let home = dirs::home_dir();
+    let expanded = match home.as_deref().map(|h| h.to_string_lossy().to_string()) {
+        Some(home) => expand_home_prefix(configured, &home).unwrap_or_else(|| configured.to_string()),
+        None => configured.to_string(),
+    };
+
+    let dir = std::path::PathBuf::from(expanded);
+    if dir.is_absolute() {
+        Some(dir)
+    } else {
+        home.map(|home| home.join(dir))
+    }
+}
+
+/// Where autosaves go when the configured folder is unusable
+fn fallback_autosave_dir() -> std::path::PathBuf {
+    get_user_config_dir()
+        .unwrap_or_else(|_| std::env::temp_dir().join("ssce-desktop"))
+        .join(FALLBACK_AUTOSAVE_DIR)
+}
+
+/// Result of resolve_autosave_dir
+#[derive(Serialize, Debug)]
+struct AutosaveDir {
+    path: String,
+    /// True when the configured folder couldn't be used
+    fallback: bool,
+    /// Why the configured folder was passed over
+    reason: Option<String>,
+}
+
+/// Create `dir` if needed and prove a file can be written in it
+fn check_dir_writable(dir: &Path) -> Result<(), String> {
+    fs::create_dir_all(dir).map_err(|e| format!("cannot create {}: {}", dir.display(), e))?;
+    let probe = dir.join(format!(".ssce-write-test-{}", std::process::id()));
+    fs::write(&probe, b"").map_err(|e| format!("cannot write to {}: {}", dir.display(), e))?;
+    let _ = fs::remove_file(&probe);
+    Ok(())
+}
+
+/// The configured autosave folder if it can be written to, else the
+/// fallback under the config directory
+fn resolve_autosave_directory(
+    configured: Option<std::path::PathBuf>,
+    fallback: &Path,
+) -> Result<AutosaveDir, CommandError> {
+    let reason = match configured {
+        Some(dir) => match check_dir_writable(&dir) {
+            Ok(()) => {
+                return Ok(AutosaveDir {
+                    path: dir.to_string_lossy().to_string(),
+                    fallback: false,
+                    reason: None,
+                })
+            }
+            Err(e) => e,
+        },
+        None => "no home directory to resolve autosave.tempDirectory against".to_string(),
+    };
+
+    tracing::warn!(reason = %reason, "Autosave folder unusable, falling back to {}", fallback.display());
+    check_dir_writable(fallback)
+        .map_err(|e| CommandError::Permission(format!("No writable autosave folder ({}; fallback {})", reason, e)))?;
+    Ok(AutosaveDir {
+        path: fallback.to_string_lossy().to_string(),
+        fallback: true,
+        reason: Some(reason),
+    })
+}
+
+/// Pick the folder autosaves should go to: `autosave.tempDirectory` from
+/// defaults.json when it's writable, otherwise a folder under the config
+/// directory. Says which was chosen and why.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle), err)]
+fn resolve_autosave_dir(app_handle: tauri::AppHandle) -> Result<AutosaveDir, CommandError> {
+    resolve_autosave_directory(configured_autosave_dir(&load_defaults_typed(&app_handle)), &fallback_autosave_dir())
+}
+
+/// Companion of an autosave file holding the path of the document it was
+/// made from (see list_autosaves_for)
+fn autosave_origin_path(autosave: &Path) -> std::path::PathBuf {
+    path_with_suffix(autosave, ".origin")
+}
+
+/// The document an autosave was made from, if save_autosave was told
+fn autosave_origin(autosave: &Path) -> Option<String> {
+    fs::read_to_string(autosave_origin_path(autosave))
+        .ok()
+        .map(|origin| origin.trim().to_string())
+        .filter(|origin| !origin.is_empty())
+}
+
+/// Delete an autosave and its origin companion
+fn remove_autosave(autosave: &Path) -> Result<(), CommandError> {
+    fs::remove_file(autosave).map_err(|e| CommandError::io("Failed to delete autosave file", e))?;
+    let _ = fs::remove_file(autosave_origin_path(autosave));
+    Ok(())
+}
+
+/// Save autosave data to a temp file
+/// Creates the directory if it doesn't exist. Without a `directory` the
+/// folder is chosen by resolve_autosave_dir; a relative one is taken from home. `original_path` is the saved
+/// document being edited, if any; it is recorded next to the autosave so
+/// list_autosaves_for can find it.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle, data), err)]
+fn save_autosave(
+    app_handle: tauri::AppHandle,
+    data: String,
+    filename: String,
+    directory: Option<String>,
+    original_path: Option<String>,
+) -> Result<String, CommandError> {
+    // A relative folder (e.g. ".ssce-temp" from print preview) is taken from home
+    let directory = match directory {
+        Some(directory) => home_relative_dir(&directory)
+            .ok_or_else(|| CommandError::NotFound(format!("No home directory to resolve {} against", directory)))?,
+        None => std::path::PathBuf::from(resolve_autosave_dir(app_handle.clone())?.path),
+    };
+    let dir_path = directory.as_path();
+    ensure_write_allowed(&app_handle, &dir_path.join(&filename))?;
+
+    // Create directory if it doesn't exist
+    if !dir_path.exists() {
+        fs::create_dir_all(dir_path)
+            .map_err(|e| CommandError::io("Failed to create autosave directory", e))?;
+    }
+
+    let file_path = dir_path.join(&filename);
+    let full_path = file_path.to_string_lossy().to_string();
+
+    write_atomic(&file_path, |writer| writer.write_all(data.as_bytes()))?;
+    record_autosave_origin(&file_path, original_path.as_deref())?;
+
+    Ok(full_path)
+}
+
+fn record_autosave_origin(autosave: &Path, original_path: Option<&str>) -> Result<(), CommandError> {
+    let origin_path = autosave_origin_path(autosave);
+    match original_path {
+        Some(original) => fs::write(&origin_path, normalize_path(original))
+            .map_err(|e| CommandError::io("Failed to write autosave origin", e)),
+        // An untitled document; don't leave a link to whatever was here before
+        None => match fs::remove_file(&origin_path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(CommandError::io("Failed to remove autosave origin", e))
+            }
+            _ => Ok(()),
+        },
+    }
+}
+
+/// Delete an autosave temp file
+#[tauri::command]
+#[tracing::instrument(skip(app_handle), err)]
+fn delete_autosave(app_handle: tauri::AppHandle, path: String) -> Result<(), CommandError> {
+    let file_path = Path::new(&path);
+    ensure_write_allowed(&app_handle, file_path)?;
+
+    if file_path.exists() {
+        remove_autosave(file_path)?;
+    }
+
+    Ok(())
+}
+
+/// How many folder levels below the autosave directory a recursive listing
+/// descends
+const MAX_AUTOSAVE_DEPTH: usize = 8;
+
+/// List autosave files in a directory
+/// Returns files with an indexed extension (see `indexedExtensions`),
+/// sorted by modification time (newest first).
+/// With `recursive`, subfolders (e.g. one per project) are searched too,
+/// skipping hidden ones and stopping MAX_AUTOSAVE_DEPTH levels down.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle), err)]
+fn list_autosave_files(
+    app_handle: tauri::AppHandle,
+    directory: String,
+    recursive: Option<bool>,
+) -> Result<Vec<AutosaveEntry>, CommandError> {
+    let depth = if recursive.unwrap_or(false) { MAX_AUTOSAVE_DEPTH } else { 0 };
+    list_autosave_entries(&directory, &indexed_extensions(&load_defaults_typed(&app_handle)), depth)
+}
+
+/// Autosave files in `directory` and, up to `depth` levels down, its
+/// non-hidden subfolders
+fn list_autosave_entries(directory: &str, extensions: &[String], depth: usize) -> Result<Vec<AutosaveEntry>, CommandError> {
+    let dir_path = Path::new(directory);
+
+    if !dir_path.exists() {
+        // Directory doesn't exist, no recovery files
+        return Ok(Vec::new());
+    }
+
+    if !dir_path.is_dir() {
+        return Err(CommandError::Validation(format!("Path is not a directory: {}", directory)));
+    }
+
+    let mut entries: Vec<AutosaveEntry> = Vec::new();
+    collect_autosave_entries(dir_path, extensions, depth, &mut entries)?;
+
+    // Sort by modification time, newest first
+    entries.sort_by(|a, b| b.mtime.cmp(&a.mtime));
+
+    Ok(entries)
+}
+
+fn collect_autosave_entries(
+    dir_path: &Path,
+    extensions: &[String],
+    depth: usize,
+    entries: &mut Vec<AutosaveEntry>,
+) -> Result<(), CommandError> {
+    let read_dir = fs::read_dir(dir_path).map_err(|e| CommandError::io("Failed to read autosave directory", e))?;
+
+    for entry in read_dir {
+        let entry = entry.map_err(|e| CommandError::io("Failed to read entry", e))?;
+        let metadata = entry.metadata().map_err(|e| CommandError::io("Failed to get metadata", e))?;
+        let name = entry.file_name().to_string_lossy().to_string();
+
+        // Subfolders are only searched when recursing, and never hidden
+        // ones (the same rule browse_directory uses). Symlinked folders
+        // aren't followed, so a link loop can't recurse.
+        if metadata.is_dir() {
+            if depth > 0 && !name.starts_with('.') {
+                collect_autosave_entries(&entry.path(), extensions, depth - 1, entries)?;
+            }
+            continue;
+        }
+
+        if !has_indexed_extension(&name, extensions) {
+            continue;
+        }
+
+        // Get modification time as unix timestamp
+        let mtime = metadata
+            .modified()
+            .map_err(|e| CommandError::io("Failed to get mtime", e))?
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        let path = entry.path().to_string_lossy().to_string();
+
+        entries.push(AutosaveEntry { name, path, mtime });
+    }
+
+    Ok(())
+}
+
+/// The document an autosave belongs to: the `<name>` of the
+/// `autosave_<session>_<name>.ssce` files written by autosave.js, or the
+/// file stem for anything named otherwise
+fn autosave_document(name: &str) -> String {
+    let stem = Path::new(name).file_stem().map_or_else(|| name.to_string(), |s| s.to_string_lossy().to_string());
+    stem.strip_prefix("autosave_")
+        .and_then(|rest| rest.split_once('_'))
+        .map_or_else(|| stem.clone(), |(_, document)| document.to_string())
+}
+
+/// Result of compact_autosaves for one document
+#[derive(Serialize, Debug, PartialEq)]
+struct AutosaveCompaction {
+    document: String,
+    kept: usize,
+    removed: usize,
+}
+
+/// Remove autosaves that add nothing: for each document, versions whose
+/// content (ignoring timestamps, see ssce_content_hash) matches the next
+/// newer version are deleted. The newest version, every distinct
+/// checkpoint, and anything that can't be parsed are kept. Unlike the
+/// age/count pruning, every file is read.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle), err)]
+fn compact_autosaves(app_handle: tauri::AppHandle, directory: String) -> Result<Vec<AutosaveCompaction>, CommandError> {
+    ensure_write_allowed(&app_handle, Path::new(&directory))?;
+    compact_autosave_dir(&directory, &indexed_extensions(&load_defaults_typed(&app_handle)))
+}
+
+fn compact_autosave_dir(directory: &str, extensions: &[String]) -> Result<Vec<AutosaveCompaction>, CommandError> {
+    let mut documents: std::collections::BTreeMap<String, Vec<AutosaveEntry>> = std::collections::BTreeMap::new();
+    for entry in list_autosave_entries(directory, extensions, 0)? {
+        documents.entry(autosave_document(&entry.name)).or_default().push(entry);
+    }
+
+    let mut results = Vec::new();
+    for (document, mut versions) in documents {
+        // Newest first; the name breaks ties between saves in the same second
+        versions.sort_by(|a, b| b.mtime.cmp(&a.mtime).then_with(|| b.name.cmp(&a.name)));
+
+        let mut newer_hash: Option<String> = None;
+        let mut result = AutosaveCompaction { document, kept: 0, removed: 0 };
+        for version in &versions {
+            let hash = fs::read_to_string(&version.path)
+                .ok()
+                .and_then(|text| serde_json::from_str::<serde_json::Value>(&text).ok())
+                .map(|json| ssce_content_hash(&json));
+
+            if hash.is_some() && hash == newer_hash {
+                remove_autosave(Path::new(&version.path))?;
+                result.removed += 1;
+            } else {
+                result.kept += 1;
+            }
+            newer_hash = hash;
+        }
+
+        tracing::info!(document = %result.document, kept = result.kept, removed = result.removed, "compacted autosaves");
+        results.push(result);
+    }
+
+    Ok(results)
+}
+
+/// One autosave of a document, from list_autosaves_for
+#[derive(Serialize, Debug)]
+struct AutosaveVersion {
+    name: String,
+    path: String,
+    mtime: u64,
+    /// What differs from the saved document, e.g. "2 more layers, title changed"
+    changes: String,
+}
+
+/// The autosaves made from `original_path`, newest first, each with a
+/// short summary of how it differs from the document as saved. Autosaves
+/// are matched by the origin save_autosave recorded, or for older ones
+/// without it, by the `autosave_<session>_<name>` filename convention.
+/// Without a `directory` the folder is chosen by resolve_autosave_dir.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle), err)]
+fn list_autosaves_for(
+    app_handle: tauri::AppHandle,
+    original_path: String,
+    directory: Option<String>,
+) -> Result<Vec<AutosaveVersion>, CommandError> {
+    let directory = match directory {
+        Some(directory) => directory,
+        None => resolve_autosave_dir(app_handle.clone())?.path,
+    };
+    autosaves_for(&directory, Path::new(&original_path), &indexed_extensions(&load_defaults_typed(&app_handle)))
+}
+
+fn autosaves_for(directory: &str, original: &Path, extensions: &[String]) -> Result<Vec<AutosaveVersion>, CommandError> {
+    let key = normalize_path(&original.to_string_lossy());
+    let stem = original.file_stem().map(|s| s.to_string_lossy().to_string());
+    let original_json = read_document_text(original)
+        .map_err(|e| e.to_string())
+        .and_then(|text| serde_json::from_str::<serde_json::Value>(&text).map_err(|e| e.to_string()));
+
+    let mut versions = Vec::new();
+    // list_autosave_entries already sorts newest first
+    for entry in list_autosave_entries(directory, extensions, 0)? {
+        let belongs = match autosave_origin(Path::new(&entry.path)) {
+            Some(origin) => origin == key,
+            None => stem.as_deref() == Some(autosave_document(&entry.name).as_str()),
+        };
+        if !belongs {
+            continue;
+        }
+
+        let changes = match (&original_json, read_document_text(Path::new(&entry.path))) {
+            (Err(e), _) => format!("original unreadable: {}", e),
+            (_, Err(e)) => format!("autosave unreadable: {}", e),
+            (Ok(original), Ok(text)) => match serde_json::from_str(&text) {
+                Ok(autosave) => autosave_changes(original, &autosave),
+                Err(e) => format!("autosave unreadable: {}", e),
+            },
+        };
+        versions.push(AutosaveVersion {
+            name: entry.name,
+            path: entry.path,
+            mtime: entry.mtime,
+            changes,
+        });
+    }
+
+    Ok(versions)
+}
+
+/// A short description of how `autosave` differs from `original`
+fn autosave_changes(original: &serde_json::Value, autosave: &serde_json::Value) -> String {
+    if ssce_content_hash(original) == ssce_content_hash(autosave) {
+        return "no changes".to_string();
+    }
+
+    let count = |json: &serde_json::Value, key: &str| json[key].as_array().map_or(0, |a| a.len() as i64);
+    let mut changes = Vec::new();
+    for (key, singular) in [("layers", "layer"), ("snapshots", "snapshot")] {
+        let (before, after) = (count(original, key), count(autosave, key));
+        let diff = after - before;
+        let noun = if diff.abs() == 1 { singular } else { key };
+        if diff > 0 {
+            changes.push(format!("{} more {}", diff, noun));
+        } else if diff < 0 {
+            changes.push(format!("{} fewer {}", -diff, noun));
+        } else if original[key] != autosave[key] {
+            changes.push(format!("{} edited", key));
+        }
+    }
+    if original["frontMatter"]["title"] != autosave["frontMatter"]["title"] {
+        changes.push("title changed".to_string());
+    }
+    if original["canvas"] != autosave["canvas"] {
+        changes.push("canvas resized".to_string());
+    }
+
+    if changes.is_empty() {
+        "edited".to_string()
+    } else {
+        changes.join(", ")
+    }
+}
+
+/// Startup recovery check: the autosave files left in `directory`, plus a
+/// system notification when there are any. Silent when there's nothing to
+/// recover.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle), err)]
+fn check_recovery(app_handle: tauri::AppHandle, directory: String) -> Result<Vec<AutosaveEntry>, CommandError> {
+    use tauri_plugin_notification::NotificationExt;
+
+    let entries = list_autosave_files(app_handle.clone(), directory, None)?;
+
+    if !entries.is_empty() {
+        // The entries are still returned if the notification can't be shown
+        if let Err(e) = app_handle
+            .notification()
+            .builder()
+            .title("SSCE Desktop")
+            .body(recovery_message(entries.len()))
+            .show()
+        {
+            tracing::warn!("Failed to show recovery notification: {}", e);
+        }
+    }
+
+    Ok(entries)
+}
+
+fn recovery_message(count: usize) -> String {
+    match count {
+        1 => "1 document can be recovered".to_string(),
+        n => format!("{} documents can be recovered", n),
+    }
+}
+
+/// Get the user's home directory
+#[tauri::command]
+#[tracing::instrument(err)]
+fn get_home_dir() -> Result<String, CommandError> {
+    dirs::home_dir()
+        .map(|p| p.to_string_lossy().to_string())
+        .ok_or_else(|| CommandError::NotFound("Could not determine home directory".to_string()))
+}
+
+/// Get the user's downloads directory
+#[tauri::command]
+#[tracing::instrument(err)]
+fn get_downloads_dir() -> Result<String, CommandError> {
+    dirs::download_dir()
+        .map(|p| p.to_string_lossy().to_string())
+        .ok_or_else(|| CommandError::NotFound("Could not determine downloads directory".to_string()))
+}
+
+// ============================================================================
+// Configuration Commands
+// ============================================================================
+//
+// Configuration is loaded from defaults.json which contains:
+// - Tool defaults (colours, sizes, line styles)
+// - File paths (library folder, default open/save directories)
+// - UI settings (colour palette, presets)
+//
+// User customizations are saved to ~/.config/ssce-desktop/defaults.json
+// and deep-merged over the bundled defaults, so user values win but new
+// bundled keys still show up.
+//
+// ============================================================================
+
+/// Environment settings returned to JavaScript (build info only)
+#[derive(Serialize)]
+struct EnvConfig {
+    show_build_timestamp: bool,
+    build_timestamp: Option<String>,
+    /// App version from Cargo.toml
+    version: String,
+    /// Short commit hash, if the build ran inside a git checkout
+    git_hash: Option<String>,
+    tauri_version: String,
+    /// Target triple the binary was compiled for, e.g. x86_64-unknown-linux-gnu
+    target: Option<String>,
+}
+
+/// Read build info settings.
+/// Returns the build timestamp for display in the window title/footer, and
+/// the version, commit and target for the About dialog. The hash and
+/// target are baked in by build.rs.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle), err)]
+fn get_env_config(app_handle: tauri::AppHandle) -> Result<EnvConfig, CommandError> {
+    // Check if we should show build timestamp (defaults to true)
+    // Can be disabled via SHOW_BUILD_TIMESTAMP=false in environment
+    let show_build_timestamp = std::env::var("SHOW_BUILD_TIMESTAMP")
+        .map(|v| v.to_lowercase() != "false")
+        .unwrap_or(true);
+
+    // Get build timestamp from build-time.txt file (written by build-and-install.sh)
+    let build_timestamp = if show_build_timestamp {
+        // Try multiple locations for build-time.txt
+        let mut time_str: Option<String> = None;
+
+        // 1. Development path (relative to src-tauri directory)
+        let dev_path = Path::new("../src/config/build-time.txt");
+        if dev_path.exists() {
+            time_str = fs::read_to_string(dev_path).ok().map(|s| s.trim().to_string());
+        }
+
+        // 2. Production path (bundled with app)
+        if time_str.is_none() {
+            if let Ok(resource_dir) = app_handle.path().resource_dir() {
+                let resource_path = resource_dir.join("config/build-time.txt");
+                if resource_path.exists() {
+                    time_str = fs::read_to_string(&resource_path).ok().map(|s| s.trim().to_string());
+                }
+            }
+        }
+
+        // 3. Linux-specific production path
+        #[cfg(target_os = "linux")]
+        if time_str.is_none() {
+            let linux_path = Path::new("/usr/lib/SSCE Desktop/config/build-time.txt");
+            if linux_path.exists() {
+                time_str = fs::read_to_string(linux_path).ok().map(|s| s.trim().to_string());
+            }
+        }
+
+        time_str
+    } else {
+        None
+    };
+
+    Ok(EnvConfig {
+        show_build_timestamp,
+        build_timestamp,
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        git_hash: option_env!("GIT_HASH").filter(|h| !h.is_empty()).map(str::to_string),
+        tauri_version: tauri::VERSION.to_string(),
+        target: option_env!("BUILD_TARGET").map(str::to_string),
+    })
+}
+
+/// Get the user config directory path for SSCE
+/// Returns ~/.config/ssce-desktop on Linux, AppData on Windows
+fn get_user_config_dir() -> Result<std::path::PathBuf, CommandError> {
+    dirs::config_dir()
+        .map(|p| p.join("ssce-desktop"))
+        .ok_or_else(|| CommandError::NotFound("Could not determine config directory".to_string()))
+}
+
+/// Which defaults.json get_defaults_config used
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+enum ConfigSource {
+    /// The user's own defaults.json, merged over the bundled one
+    User,
+    /// src/config/defaults.json in a development checkout
+    Dev,
+    /// The copy bundled in the app's resources
+    Bundled,
+    /// The deb package's copy under /usr/lib
+    LinuxSystem,
+}
+
+/// The active config and where it came from
+#[derive(Serialize, Debug)]
+struct DefaultsConfig {
+    /// JSON text of the merged config, with paths expanded
+    config: String,
+    source: ConfigSource,
+    /// The file `source` refers to
+    path: String,
+}
+
+/// Load the defaults.json configuration file
+/// The bundled defaults are loaded first and the user's config is deep-merged
+/// on top, so new default keys appear for everyone without losing customizations.
+/// Bundled priority: dev config > bundled resource > Linux system path
+/// Expands ~/, $HOME and ${HOME} prefixes in all string values
+#[tauri::command]
+#[tracing::instrument(skip(app_handle), err)]
+fn get_defaults_config(app_handle: tauri::AppHandle) -> Result<DefaultsConfig, CommandError> {
+    let user_path = get_user_config_dir().ok().map(|dir| dir.join("defaults.json"));
+    load_defaults_config(&bundled_defaults_candidates(&app_handle), user_path.as_deref())
+}
+
+/// Result of get_active_config_source
+#[derive(Serialize, Debug)]
+struct ActiveConfigSource {
+    source: ConfigSource,
+    path: String,
+}
+
+/// Which defaults.json is in effect and its path, without the config itself
+#[tauri::command]
+#[tracing::instrument(skip(app_handle), err)]
+fn get_active_config_source(app_handle: tauri::AppHandle) -> Result<ActiveConfigSource, CommandError> {
+    let loaded = get_defaults_config(app_handle)?;
+    Ok(ActiveConfigSource {
+        source: loaded.source,
+        path: loaded.path,
+    })
+}
+
+fn load_defaults_config(
+    bundled_candidates: &[(ConfigSource, std::path::PathBuf)],
+    user_path: Option<&Path>,
+) -> Result<DefaultsConfig, CommandError> {
+    let bundled = read_bundled_defaults(bundled_candidates)?;
+    let user = match user_path {
+        Some(path) => read_user_defaults(path)?.map(|config| (config, path.to_path_buf())),
+        None => None,
+    };
+
+    let (config, source, path) = match (bundled, user) {
+        (Some((mut bundled, _, _)), Some((user, user_path))) => {
+            deep_merge(&mut bundled, user);
+            (bundled, ConfigSource::User, user_path)
+        }
+        (Some((bundled, source, path)), None) => (bundled, source, path),
+        (None, Some((user, user_path))) => (user, ConfigSource::User, user_path),
+        // Fallback: return error to trigger frontend fallback
+        (None, None) => return Err(CommandError::NotFound("defaults.json not found in any config paths".to_string())),
+    };
+
+    // Reject configs with type errors before they reach the frontend
+    let problems = validate_defaults(&config);
+    if problems.iter().any(|p| p.severity == ProblemSeverity::Error) {
+        return Err(CommandError::Validation(format_config_problems(&problems)));
+    }
+
+    Ok(DefaultsConfig {
+        config: expand_paths_in_config(config).map_err(CommandError::Parse)?,
+        source,
+        path: path.to_string_lossy().to_string(),
+    })
+}
+
+/// Read and parse the user's customized defaults.json, if one has been saved
+fn read_user_defaults(user_config_path: &Path) -> Result<Option<serde_json::Value>, CommandError> {
+    if !user_config_path.exists() {
+        return Ok(None);
+    }
+
+    let json_str = fs::read_to_string(user_config_path)
+        .map_err(|e| CommandError::io("Failed to read user defaults.json", e))?;
+    let config = serde_json::from_str(&json_str)
+        .map_err(|e| CommandError::Parse(format!("Failed to parse user defaults.json: {}", e)))?;
+
+    Ok(Some(config))
+}
+
+/// Places the defaults.json that ships with the app may be, in priority order
+fn bundled_defaults_candidates(app_handle: &tauri::AppHandle) -> Vec<(ConfigSource, std::path::PathBuf)> {
+    let mut candidates = Vec::new();
+
+    // Development path (relative to src-tauri directory)
+    candidates.push((ConfigSource::Dev, std::path::PathBuf::from("../src/config/defaults.json")));
+
+    // Production path (bundled with app) using Tauri v2 API
+    if let Ok(resource_dir) = app_handle.path().resource_dir() {
+        candidates.push((ConfigSource::Bundled, resource_dir.join("config/defaults.json")));
+    }
+
+    // Linux-specific production path (deb package location)
+    #[cfg(target_os = "linux")]
+    candidates.push((
+        ConfigSource::LinuxSystem,
+        std::path::PathBuf::from("/usr/lib/SSCE Desktop/config/defaults.json"),
+    ));
+
+    candidates
+}
+
+/// Read and parse the first of `candidates` that exists, with its source
+/// and path
+fn read_bundled_defaults(
+    candidates: &[(ConfigSource, std::path::PathBuf)],
+) -> Result<Option<(serde_json::Value, ConfigSource, std::path::PathBuf)>, CommandError> {
+    for (source, path) in candidates {
+        if path.exists() {
+            let json_str = fs::read_to_string(path)
+                .map_err(|e| CommandError::io("Failed to read defaults.json", e))?;
+            let config = serde_json::from_str(&json_str)
+                .map_err(|e| CommandError::Parse(format!("Failed to parse defaults.json: {}", e)))?;
+            return Ok(Some((config, *source, path.clone())));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Recursively merge `overlay` into `base`.
+/// Objects are merged key by key; scalars and arrays from `overlay` replace
+/// whatever `base` had at that key.
+fn deep_merge(base: &mut serde_json::Value, overlay: serde_json::Value) {
+    match (base, overlay) {
+        (serde_json::Value::Object(base_map), serde_json::Value::Object(overlay_map)) => {
+            for (key, value) in overlay_map {
+                deep_merge(base_map.entry(key).or_insert(serde_json::Value::Null), value);
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// How serious a config problem is.
+/// Errors block the config from being saved/applied; warnings are informational.
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+enum ProblemSeverity {
+    Error,
+    Warning,
+}
+
+/// A single problem found while validating defaults.json
+#[derive(Serialize, Debug)]
+struct ConfigProblem {
+    /// Dotted key path, e.g. "paths.defaultImageLoad" (empty for whole-file problems)
+    key: String,
+    message: String,
+    severity: ProblemSeverity,
+}
+
+/// Expected JSON type for a known defaults.json key
+#[derive(Clone, Copy)]
+enum ConfigType {
+    String,
+    Bool,
+    Number,
+    PositiveInt,
+    NonNegativeInt,
+    Object,
+    Array,
+}
+
+impl ConfigType {
+    /// Whether `value` has this type, and how to describe the type in errors
+    fn check(self, value: &serde_json::Value) -> (bool, &'static str) {
+        match self {
+            ConfigType::String => (value.is_string(), "a string"),
+            ConfigType::Bool => (value.is_boolean(), "true or false"),
+            ConfigType::Number => (value.is_number(), "a number"),
+            ConfigType::PositiveInt => (value.as_u64().map(|n| n > 0).unwrap_or(false), "a positive integer"),
+            ConfigType::NonNegativeInt => (value.is_u64(), "a non-negative integer"),
+            ConfigType::Object => (value.is_object(), "an object"),
+            ConfigType::Array => (value.is_array(), "an array"),
+        }
+    }
+}
+
+/// Known defaults.json keys (dotted paths) and their expected types.
+/// Keys not listed here are not type-checked. Top-level keys that don't
+/// appear here produce a warning so newer configs still load in older builds.
+const CONFIG_SCHEMA: &[(&str, ConfigType)] = &[
+    ("paths", ConfigType::Object),
+    ("paths.defaultImageLoad", ConfigType::String),
+    ("paths.defaultImageSave", ConfigType::String),
+    ("paths.library", ConfigType::String),
+    ("paths.extraRoots", ConfigType::Array),
+    ("tools", ConfigType::Object),
+    ("palette", ConfigType::Object),
+    ("palette.colours", ConfigType::Array),
+    ("canvas", ConfigType::Object),
+    ("canvas.defaultWidth", ConfigType::PositiveInt),
+    ("canvas.defaultHeight", ConfigType::PositiveInt),
+    ("lineWidthPresets", ConfigType::Object),
+    ("dashGapMultipliers", ConfigType::Object),
+    ("textSizes", ConfigType::Object),
+    ("textLineHeight", ConfigType::Number),
+    ("arrowheadStyles", ConfigType::Object),
+    ("symbols", ConfigType::Array),
+    ("steps", ConfigType::Array),
+    ("resizeLimits", ConfigType::Object),
+    ("resizeLimits.warning", ConfigType::PositiveInt),
+    ("resizeLimits.error", ConfigType::PositiveInt),
+    ("autosave", ConfigType::Object),
+    ("autosave.enabled", ConfigType::Bool),
+    ("autosave.inactivitySeconds", ConfigType::PositiveInt),
+    ("autosave.tempDirectory", ConfigType::String),
+    ("autosave.snapshotReminderEdits", ConfigType::NonNegativeInt),
+    ("smartGuides", ConfigType::Object),
+    ("recentFiles", ConfigType::Object),
+    ("recentFiles.maxCount", ConfigType::PositiveInt),
+    ("configBackups", ConfigType::Object),
+    ("configBackups.maxCount", ConfigType::PositiveInt),
+    ("libraryBackups", ConfigType::Object),
+    ("libraryBackups.maxCount", ConfigType::PositiveInt),
+    ("toast", ConfigType::Object),
+    ("toast.durationMs", ConfigType::PositiveInt),
+    ("toast.errorDurationMs", ConfigType::PositiveInt),
+    ("user", ConfigType::Object),
+    ("print", ConfigType::Object),
+    ("preferredBrowser", ConfigType::String),
+    ("openWithApps", ConfigType::Array),
+    ("logLevel", ConfigType::String),
+    ("theme", ConfigType::String),
+    ("globalHotkey", ConfigType::String),
+    ("closeToTray", ConfigType::Bool),
+    ("images", ConfigType::Object),
+    ("images.maxFileBytes", ConfigType::PositiveInt),
+    ("images.maxDimension", ConfigType::PositiveInt),
+    ("images.maxPixels", ConfigType::PositiveInt),
+    ("fileRetry", ConfigType::Object),
+    ("fileRetry.attempts", ConfigType::NonNegativeInt),
+    ("fileRetry.initialDelayMs", ConfigType::PositiveInt),
+    ("fileRetry.maxDelayMs", ConfigType::PositiveInt),
+    ("showTrayIcon", ConfigType::Bool),
+    ("indexedExtensions", ConfigType::Array),
+    ("library", ConfigType::Object),
+    ("library.maxIndexedContentBytes", ConfigType::PositiveInt),
+    ("library.ignorePatterns", ConfigType::Array),
+    ("library.followSymlinks", ConfigType::Bool),
+    ("library.existsTimeoutMs", ConfigType::PositiveInt),
+    ("library.checkpointIntervalSeconds", ConfigType::NonNegativeInt),
+    ("library.maxScanDepth", ConfigType::NonNegativeInt),
+    ("library.maxScanFiles", ConfigType::PositiveInt),
+    ("thumbnails", ConfigType::Object),
+    ("thumbnails.cacheMaxBytes", ConfigType::PositiveInt),
+    ("thumbnails.batchConcurrency", ConfigType::PositiveInt),
+    ("search", ConfigType::Object),
+    ("search.rankWeights", ConfigType::Object),
+    ("search.rankWeights.filename", ConfigType::Number),
+    ("search.rankWeights.title", ConfigType::Number),
+    ("search.rankWeights.summary", ConfigType::Number),
+    ("search.rankWeights.keywords", ConfigType::Number),
+    ("search.rankWeights.content", ConfigType::Number),
+    ("search.rankWeights.author", ConfigType::Number),
+    ("search.tokenizer", ConfigType::Object),
+    ("search.tokenizer.removeDiacritics", ConfigType::Number),
+    ("search.tokenizer.tokenChars", ConfigType::String),
+    ("search.tokenizer.separators", ConfigType::String),
+];
+
+/// Validate a parsed defaults.json against CONFIG_SCHEMA.
+/// Missing keys are fine (the bundled defaults or frontend fallbacks fill them in).
+fn validate_defaults(config: &serde_json::Value) -> Vec<ConfigProblem> {
+    let mut problems = Vec::new();
+
+    let root = match config.as_object() {
+        Some(obj) => obj,
+        None => {
+            problems.push(ConfigProblem {
+                key: String::new(),
+                message: "defaults.json must contain a JSON object".to_string(),
+                severity: ProblemSeverity::Error,
+            });
+            return problems;
+        }
+    };
+
+    // Type-check every known key that is present
+    for (key, expected) in CONFIG_SCHEMA {
+        let pointer = format!("/{}", key.replace('.', "/"));
+        let value = match config.pointer(&pointer) {
+            Some(v) => v,
+            None => continue,
+        };
+
+        let (valid, description) = expected.check(value);
+
+        if !valid {
+            problems.push(ConfigProblem {
+                key: key.to_string(),
+                message: format!("must be {} (found {})", description, value),
+                severity: ProblemSeverity::Error,
+            });
+        }
+    }
+
+    // Unknown top-level keys are only a warning, for forward compatibility
+    for key in root.keys() {
+        let known = CONFIG_SCHEMA.iter().any(|(k, _)| k == key);
+        if !known {
+            problems.push(ConfigProblem {
+                key: key.clone(),
+                message: "unknown setting (ignored)".to_string(),
+                severity: ProblemSeverity::Warning,
+            });
+        }
+    }
+
+    problems
+}
+
+/// Format config problems as readable text for error messages (one per line)
+fn format_config_problems(problems: &[ConfigProblem]) -> String {
+    let lines: Vec<String> = problems
+        .iter()
+        .filter(|p| p.severity == ProblemSeverity::Error)
+        .map(|p| {
+            if p.key.is_empty() {
+                p.message.clone()
+            } else {
+                format!("{}: {}", p.key, p.message)
+            }
+        })
+        .collect();
+
+    format!("Invalid defaults.json:\n{}", lines.join("\n"))
+}
+
+/// Expand home-directory references in every string value of the config JSON.
+/// Walks the whole tree (not just the "paths" section) so nested settings
+/// like autosave or library folders are expanded too.
+fn expand_paths_in_config(mut config: serde_json::Value) -> Result<String, String> {
+    let home_dir = dirs::home_dir()
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    expand_paths_in_value(&mut config, &home_dir);
+
+    // Serialize back to JSON
+    serde_json::to_string(&config)
+        .map_err(|e| format!("Failed to serialize config: {}", e))
+}
+
+/// Recursively expand home-directory prefixes in all strings within a JSON value
+fn expand_paths_in_value(value: &mut serde_json::Value, home_dir: &str) {
+    match value {
+        serde_json::Value::String(s) => {
+            if let Some(expanded) = expand_home_prefix(s, home_dir) {
+                *s = expanded;
+            }
+        }
+        serde_json::Value::Array(items) => {
+            for item in items {
+                expand_paths_in_value(item, home_dir);
+            }
+        }
+        serde_json::Value::Object(map) => {
+            for (_key, item) in map.iter_mut() {
+                expand_paths_in_value(item, home_dir);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Expand a leading `~/`, `$HOME` or `${HOME}` to the home directory.
+/// Only a prefix is expanded - occurrences later in the string are left alone.
+/// Returns None if the string doesn't start with a home reference.
+fn expand_home_prefix(s: &str, home_dir: &str) -> Option<String> {
+    if let Some(rest) = s.strip_prefix("~/") {
+        return Some(format!("{}/{}", home_dir, rest));
+    }
+
+    for var in ["${HOME}", "$HOME"] {
+        if let Some(rest) = s.strip_prefix(var) {
+            // "$HOMEDIR" is a different variable, so require a separator or end
+            if rest.is_empty() || rest.starts_with('/') || rest.starts_with('\\') {
+                return Some(format!("{}{}", home_dir, rest));
+            }
+        }
+    }
+
+    None
+}
+
+/// Folder under the user config directory holding copies of defaults.json
+const CONFIG_BACKUP_DIR: &str = "config-backups";
+
+/// A saved copy of defaults.json, as listed by list_config_backups
+#[derive(Serialize, Debug)]
+struct ConfigBackup {
+    name: String,
+    size: u64,
+    /// Last modified time as unix seconds (0 if unavailable)
+    mtime: u64,
+}
+
+/// Copy the current defaults.json in `config_dir` to a timestamped backup,
+/// then delete all but the newest `keep` backups. Does nothing if there is
+/// no config yet. Backup names sort in the order they were taken.
+fn backup_config(config_dir: &Path, keep: usize, now: chrono::DateTime<chrono::Utc>) -> Result<(), CommandError> {
+    let config_path = config_dir.join("defaults.json");
+    if !config_path.exists() {
+        return Ok(());
+    }
+
+    let backup_dir = config_dir.join(CONFIG_BACKUP_DIR);
+    fs::create_dir_all(&backup_dir).map_err(|e| CommandError::io("Failed to create backup folder", e))?;
+
+    let name = format!("defaults-{}.json", now.format("%Y%m%d-%H%M%S%.3f"));
+    fs::copy(&config_path, backup_dir.join(&name))
+        .map_err(|e| CommandError::io("Failed to back up defaults.json", e))?;
+
+    for stale in list_config_backups_in(config_dir).into_iter().skip(keep.max(1)) {
+        if let Err(e) = fs::remove_file(backup_dir.join(&stale.name)) {
+            tracing::warn!("Failed to remove old config backup {}: {}", stale.name, e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Config backups in `config_dir`, newest first
+fn list_config_backups_in(config_dir: &Path) -> Vec<ConfigBackup> {
+    let Ok(entries) = fs::read_dir(config_dir.join(CONFIG_BACKUP_DIR)) else {
+        return Vec::new();
+    };
+
+    let mut backups: Vec<ConfigBackup> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !(name.starts_with("defaults-") && name.ends_with(".json")) {
+                return None;
+            }
+            let meta = entry.metadata().ok()?;
+            let mtime = meta
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            Some(ConfigBackup { name, size: meta.len(), mtime })
+        })
+        .collect();
+
+    backups.sort_by(|a, b| b.name.cmp(&a.name));
+    backups
+}
+
+/// Saved copies of defaults.json, newest first
+#[tauri::command]
+#[tracing::instrument(err)]
+fn list_config_backups() -> Result<Vec<ConfigBackup>, CommandError> {
+    Ok(list_config_backups_in(&get_user_config_dir()?))
+}
+
+/// Replace defaults.json with one of its backups. The config being replaced
+/// is backed up first, so a restore can itself be undone.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle), err)]
+fn restore_config_backup(app_handle: tauri::AppHandle, name: String) -> Result<String, CommandError> {
+    let config_dir = get_user_config_dir()?;
+    let data = read_config_backup(&config_dir, &name)?;
+    save_defaults_config(app_handle, data)
+}
+
+/// Content of a named backup. Names are checked against the listing so a
+/// crafted name can't reach outside the backup folder.
+fn read_config_backup(config_dir: &Path, name: &str) -> Result<String, CommandError> {
+    if !list_config_backups_in(config_dir).iter().any(|b| b.name == name) {
+        return Err(CommandError::NotFound(format!("Config backup not found: {}", name)));
+    }
+
+    fs::read_to_string(config_dir.join(CONFIG_BACKUP_DIR).join(name))
+        .map_err(|e| CommandError::io("Failed to read config backup", e))
+}
+
+/// Save defaults.json to user config directory
+/// This allows user customization without modifying bundled files.
+/// The previous file is kept in config-backups/ (the newest
+/// `configBackups.maxCount` copies are retained).
+#[tauri::command]
+#[tracing::instrument(skip(app_handle, data), err)]
+fn save_defaults_config(app_handle: tauri::AppHandle, data: String) -> Result<String, CommandError> {
+    // Validate JSON before saving
+    let config: serde_json::Value = serde_json::from_str(&data)
+        .map_err(|e| CommandError::Parse(format!("Invalid JSON: {}", e)))?;
+
+    // Validate known keys and types (warnings are allowed through)
+    let problems = validate_defaults(&config);
+    if problems.iter().any(|p| p.severity == ProblemSeverity::Error) {
+        return Err(CommandError::Validation(format_config_problems(&problems)));
+    }
+
+    let user_config_dir = get_user_config_dir()?;
+
+    // Create config directory if it doesn't exist
+    if !user_config_dir.exists() {
+        fs::create_dir_all(&user_config_dir)
+            .map_err(|e| CommandError::io("Failed to create config directory", e))?;
+    }
+
+    let keep = load_defaults_typed(&app_handle).config_backups.max_count as usize;
+    backup_config(&user_config_dir, keep, chrono::Utc::now())?;
+
+    let config_path = user_config_dir.join("defaults.json");
+    let config_path_str = config_path.to_string_lossy().to_string();
+
+    // Atomic, so a crash mid-write can't leave a truncated config behind
+    write_atomic(&config_path, |writer| writer.write_all(data.as_bytes()))?;
+    if let Some(cache) = app_handle.try_state::<ConfigCache>() {
+        cache.clear();
+    }
+
+    Ok(config_path_str)
+}
+
+/// Check defaults.json content without saving it.
+/// Returns every problem found (errors and warnings) so the Settings UI can
+/// show them all at once. An empty list means the config is valid.
+#[tauri::command]
+#[tracing::instrument(skip(data))]
+fn validate_defaults_config(data: String) -> Vec<ConfigProblem> {
+    match serde_json::from_str::<serde_json::Value>(&data) {
+        Ok(config) => validate_defaults(&config),
+        Err(e) => vec![ConfigProblem {
+            key: String::new(),
+            message: format!("Invalid JSON: {}", e),
+            severity: ProblemSeverity::Error,
+        }],
+    }
+}
+
+/// An editor setting stored in the settings table
+struct SettingSpec {
+    key: &'static str,
+    kind: ConfigType,
+    /// defaults.json value used until the setting is first set
+    from_config: Option<fn(&Defaults) -> serde_json::Value>,
+    /// JSON value used when neither the table nor defaults.json has one
+    default: &'static str,
+}
+
+/// Settings readable and writable with get_setting / set_setting
+const SETTINGS: &[SettingSpec] = &[
+    SettingSpec {
+        key: "autosaveIntervalSeconds",
+        kind: ConfigType::PositiveInt,
+        from_config: Some(|config| config.autosave.inactivity_seconds.into()),
+        default: "30",
+    },
+    SettingSpec {
+        key: "maxAutosavesPerDocument",
+        kind: ConfigType::PositiveInt,
+        from_config: None,
+        default: "5",
+    },
+    SettingSpec {
+        key: "thumbnailMaxDim",
+        kind: ConfigType::PositiveInt,
+        from_config: None,
+        default: "150",
+    },
+];
+
+fn setting_spec(key: &str) -> Result<&'static SettingSpec, CommandError> {
+    SETTINGS
+        .iter()
+        .find(|spec| spec.key == key)
+        .ok_or_else(|| CommandError::Validation(format!("Unknown setting: {}", key)))
+}
+
+/// Current value of an editor setting: the stored value, else the matching
+/// defaults.json value, else the built-in default
+#[tauri::command]
+#[tracing::instrument(skip(app_handle, state), err)]
+fn get_setting(app_handle: tauri::AppHandle, state: State<DbState>, key: String) -> Result<serde_json::Value, CommandError> {
+    state.with_read_conn(|conn| read_setting(conn, &load_defaults_typed(&app_handle), &key))
+}
+
+/// Store an editor setting. The value must have the setting's type.
+#[tauri::command]
+#[tracing::instrument(skip(state), err)]
+fn set_setting(state: State<DbState>, key: String, value: serde_json::Value) -> Result<(), CommandError> {
+    state.with_conn(|conn| write_setting(conn, &key, &value))
+}
+
+fn read_setting(conn: &Connection, config: &Defaults, key: &str) -> Result<serde_json::Value, CommandError> {
+    let spec = setting_spec(key)?;
+
+    let stored: Option<String> = conn
+        .prepare_cached("SELECT value FROM settings WHERE key = ?1")
+        .and_then(|mut stmt| stmt.query_row(params![key], |row| row.get(0)).optional())?;
+
+    // A stored or configured value of the wrong type (e.g. from an older
+    // build) is skipped rather than returned
+    let valid = |value: &serde_json::Value| spec.kind.check(value).0;
+    let from_config = spec.from_config.map(|value| value(config)).filter(valid);
+
+    Ok(stored
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .filter(valid)
+        .or(from_config)
+        .unwrap_or_else(|| serde_json::from_str(spec.default).expect("setting defaults are valid JSON")))
+}
+
+fn write_setting(conn: &Connection, key: &str, value: &serde_json::Value) -> Result<(), CommandError> {
+    let spec = setting_spec(key)?;
+
+    let (valid, description) = spec.kind.check(value);
+    if !valid {
+        return Err(CommandError::Validation(format!("{} must be {} (found {})", key, description, value)));
+    }
+
+    conn.execute(
+        "INSERT INTO settings (key, value) VALUES (?1, ?2)
+         ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+        params![key, value.to_string()],
+    )?;
+
+    Ok(())
+}
+
+/// Settings keys holding the file browser's last folder and filter. They
+/// live in the settings table but aren't user-editable, so they are kept
+/// out of SETTINGS.
+const LAST_BROWSE_DIRECTORY_KEY: &str = "lastBrowseDirectory";
+const LAST_BROWSE_FILTER_KEY: &str = "lastBrowseFilter";
+
+/// Filter used when nothing has been browsed yet
+const DEFAULT_BROWSE_FILTER: &str = "all";
+
+/// Where the file browser was last left, for restoring it on startup
+#[derive(Serialize, Debug, PartialEq)]
+struct LastBrowseState {
+    directory: Option<String>,
+    filter: String,
+}
+
+fn record_browse_state(conn: &Connection, dir: &str, filter: &str) -> Result<(), CommandError> {
+    let mut stmt = conn.prepare_cached(
+        "INSERT INTO settings (key, value) VALUES (?1, ?2)
+         ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+    )?;
+    stmt.execute(params![LAST_BROWSE_DIRECTORY_KEY, serde_json::Value::from(normalize_path(dir)).to_string()])?;
+    stmt.execute(params![LAST_BROWSE_FILTER_KEY, serde_json::Value::from(filter).to_string()])?;
+    Ok(())
+}
+
+/// The folder and filter of the last browse_directory call. A folder that
+/// no longer exists is replaced by paths.defaultImageLoad (or null if that
+/// isn't set either).
+#[tauri::command]
+#[tracing::instrument(skip(app_handle, state), err)]
+fn get_last_browse_state(app_handle: tauri::AppHandle, state: State<DbState>) -> Result<LastBrowseState, CommandError> {
+    let config = load_defaults_typed(&app_handle);
+    let fallback = Some(config.paths.default_image_load.as_str()).filter(|dir| !dir.trim().is_empty());
+    state.with_read_conn(|conn| last_browse_state(conn, fallback))
+}
+
+fn last_browse_state(conn: &Connection, fallback_dir: Option<&str>) -> Result<LastBrowseState, CommandError> {
+    let mut stmt = conn.prepare_cached("SELECT value FROM settings WHERE key = ?1")?;
+    let mut stored = |key: &str| -> Result<Option<String>, CommandError> {
+        let value: Option<String> = stmt.query_row(params![key], |row| row.get(0)).optional()?;
+        Ok(value
+            .and_then(|v| serde_json::from_str::<serde_json::Value>(&v).ok())
+            .and_then(|v| v.as_str().map(str::to_string)))
+    };
+
+    let directory = stored(LAST_BROWSE_DIRECTORY_KEY)?
+        .filter(|dir| Path::new(dir).is_dir())
+        .or_else(|| fallback_dir.map(str::to_string));
+    let filter = stored(LAST_BROWSE_FILTER_KEY)?.unwrap_or_else(|| DEFAULT_BROWSE_FILTER.to_string());
+
+    Ok(LastBrowseState { directory, filter })
+}
+
+/// Get the path where user config would be saved
+#[tauri::command]
+#[tracing::instrument(err)]
+fn get_user_config_path() -> Result<String, CommandError> {
+    let user_config_dir = get_user_config_dir()?;
+    let config_path = user_config_dir.join("defaults.json");
+    Ok(config_path.to_string_lossy().to_string())
+}
+
+/// The app-level settings from defaults.json, typed, with the bundled
+/// default filled in for any key that's missing. Editor settings (tools,
+/// palette, canvas, search tuning, ...) and unknown keys are kept as they
+/// are in `extra`, so nothing is lost on a round trip.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, rename_all = "camelCase")]
+struct Defaults {
+    paths: PathSettings,
+    autosave: AutosaveSettings,
+    recent_files: MaxCountSettings,
+    config_backups: MaxCountSettings,
+    library_backups: MaxCountSettings,
+    library: LibrarySettings,
+    thumbnails: ThumbnailSettings,
+    file_retry: FileRetrySettings,
+    images: ImageSettings,
+    indexed_extensions: Vec<String>,
+    global_hotkey: String,
+    close_to_tray: bool,
+    show_tray_icon: bool,
+    log_level: String,
+    preferred_browser: String,
+    /// Applications open_path_with may start
+    open_with_apps: Vec<String>,
+    /// "system", "light" or "dark"
+    theme: String,
+    #[serde(flatten)]
+    extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Default for Defaults {
+    fn default() -> Self {
+        Defaults {
+            paths: PathSettings::default(),
+            autosave: AutosaveSettings::default(),
+            recent_files: MaxCountSettings { max_count: 20, extra: Default::default() },
+            config_backups: MaxCountSettings { max_count: 5, extra: Default::default() },
+            library_backups: MaxCountSettings { max_count: 5, extra: Default::default() },
+            library: LibrarySettings::default(),
+            thumbnails: ThumbnailSettings::default(),
+            file_retry: FileRetrySettings::default(),
+            images: ImageSettings::default(),
+            indexed_extensions: default_indexed_extensions(),
+            global_hotkey: DEFAULT_GLOBAL_HOTKEY.to_string(),
+            close_to_tray: true,
+            show_tray_icon: true,
+            log_level: "info".to_string(),
+            preferred_browser: String::new(),
+            open_with_apps: Vec::new(),
+            theme: "system".to_string(),
+            extra: Default::default(),
+        }
+    }
+}
+
+impl Defaults {
+    /// A section of defaults.json kept in `extra` (e.g. "search"), or Null
+    /// if the config doesn't have it
+    fn section(&self, key: &str) -> &serde_json::Value {
+        self.extra.get(key).unwrap_or(&serde_json::Value::Null)
+    }
+}
+
+/// `paths` in defaults.json
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, rename_all = "camelCase")]
+struct PathSettings {
+    default_image_load: String,
+    default_image_save: String,
+    library: String,
+    extra_roots: Vec<String>,
+    #[serde(flatten)]
+    extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Default for PathSettings {
+    fn default() -> Self {
+        PathSettings {
+            default_image_load: "~/Pictures/ssce".to_string(),
+            default_image_save: "~/Pictures/ssce-out".to_string(),
+            library: "~/Pictures/ssce-library".to_string(),
+            extra_roots: Vec::new(),
+            extra: Default::default(),
+        }
+    }
+}
+
+/// `autosave` in defaults.json
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, rename_all = "camelCase")]
+struct AutosaveSettings {
+    enabled: bool,
+    inactivity_seconds: u64,
+    temp_directory: String,
+    snapshot_reminder_edits: u64,
+    #[serde(flatten)]
+    extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Default for AutosaveSettings {
+    fn default() -> Self {
+        AutosaveSettings {
+            enabled: true,
+            inactivity_seconds: 30,
+            temp_directory: ".ssce-temp".to_string(),
+            snapshot_reminder_edits: 10,
+            extra: Default::default(),
+        }
+    }
+}
+
+/// `recentFiles`, `configBackups` and `libraryBackups` in defaults.json
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct MaxCountSettings {
+    max_count: u32,
+    #[serde(flatten)]
+    extra: serde_json::Map<String, serde_json::Value>,
+}
+
+/// `library` in defaults.json
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, rename_all = "camelCase")]
+struct LibrarySettings {
+    max_indexed_content_bytes: u64,
+    ignore_patterns: Vec<String>,
+    follow_symlinks: bool,
+    exists_timeout_ms: u64,
+    /// Seconds between background WAL checkpoints; 0 turns them off
+    checkpoint_interval_seconds: u64,
+    max_scan_depth: u64,
+    max_scan_files: u64,
+    #[serde(flatten)]
+    extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Default for LibrarySettings {
+    fn default() -> Self {
+        LibrarySettings {
+            max_indexed_content_bytes: DEFAULT_MAX_INDEXED_CONTENT_BYTES as u64,
+            ignore_patterns: vec!["*.bak".to_string()],
+            follow_symlinks: false,
+            exists_timeout_ms: VOLUME_CHECK_TIMEOUT.as_millis() as u64,
+            checkpoint_interval_seconds: 300,
+            max_scan_depth: DEFAULT_MAX_SCAN_DEPTH as u64,
+            max_scan_files: DEFAULT_MAX_SCAN_FILES as u64,
+            extra: Default::default(),
+        }
+    }
+}
+
+/// `thumbnails` in defaults.json
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, rename_all = "camelCase")]
+struct ThumbnailSettings {
+    cache_max_bytes: u64,
+    batch_concurrency: u32,
+    #[serde(flatten)]
+    extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Default for ThumbnailSettings {
+    fn default() -> Self {
+        ThumbnailSettings {
+            cache_max_bytes: DEFAULT_THUMBNAIL_CACHE_BYTES,
+            batch_concurrency: 4,
+            extra: Default::default(),
+        }
+    }
+}
+
+/// `fileRetry` in defaults.json (see RetryPolicy)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, rename_all = "camelCase")]
+struct FileRetrySettings {
+    attempts: u32,
+    initial_delay_ms: u64,
+    max_delay_ms: u64,
+    #[serde(flatten)]
+    extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Default for FileRetrySettings {
+    fn default() -> Self {
+        FileRetrySettings {
+            attempts: 0,
+            initial_delay_ms: 100,
+            max_delay_ms: 2000,
+            extra: Default::default(),
+        }
+    }
+}
+
+/// `images` in defaults.json (see ImageLimits)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, rename_all = "camelCase")]
+struct ImageSettings {
+    max_file_bytes: u64,
+    max_dimension: u32,
+    max_pixels: u64,
+    #[serde(flatten)]
+    extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Default for ImageSettings {
+    fn default() -> Self {
+        ImageSettings {
+            max_file_bytes: DEFAULT_MAX_IMAGE_BYTES,
+            max_dimension: DEFAULT_MAX_IMAGE_DIMENSION,
+            max_pixels: DEFAULT_MAX_IMAGE_PIXELS,
+            extra: Default::default(),
+        }
+    }
+}
+
+/// The active config as Defaults. Type errors are already rejected by
+/// get_defaults_config's validation, so this only fails on values out of
+/// range for their field.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle), err)]
+fn get_defaults_typed(app_handle: tauri::AppHandle) -> Result<Defaults, CommandError> {
+    let config: serde_json::Value = serde_json::from_str(&get_defaults_config(app_handle)?.config)?;
+    typed_defaults(config)
+}
+
+fn typed_defaults(config: serde_json::Value) -> Result<Defaults, CommandError> {
+    serde_json::from_value(config).map_err(|e| CommandError::Validation(format!("Invalid config: {}", e)))
+}
+
+/// The active config (merged, with paths expanded) as Defaults, for
+/// commands to read their settings from. The built-in defaults if no config
+/// could be read. Kept in ConfigCache between calls.
+fn load_defaults_typed(app_handle: &tauri::AppHandle) -> Arc<Defaults> {
+    let load = || defaults_or_builtin(get_defaults_typed(app_handle.clone()));
+    match app_handle.try_state::<ConfigCache>() {
+        Some(cache) => cache.get_or_load(load),
+        None => Arc::new(load()),
+    }
+}
+
+/// The loaded config, or Defaults::default() with `~` expanded as a loaded
+/// config's paths are. Left as `~/...` the library and roots would be
+/// relative paths, and every write and delete would be refused.
+fn defaults_or_builtin(loaded: Result<Defaults, CommandError>) -> Defaults {
+    loaded.unwrap_or_else(|e| {
+        tracing::warn!("Using built-in defaults: {}", e);
+        let home_dir = dirs::home_dir()
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let Ok(mut config) = serde_json::to_value(Defaults::default()) else {
+            return Defaults::default();
+        };
+        expand_paths_in_value(&mut config, &home_dir);
+        typed_defaults(config).unwrap_or_default()
+    })
+}
+
+/// The config as load_defaults_typed last read it, so commands called often
+/// (e.g. search as the user types) don't reread and revalidate defaults.json
+/// each time. Cleared when the file is saved from the app or changes on
+/// disk (see reload_defaults).
+#[derive(Default)]
+struct ConfigCache(Mutex<Option<Arc<Defaults>>>);
+
+impl ConfigCache {
+    fn get_or_load(&self, load: impl FnOnce() -> Defaults) -> Arc<Defaults> {
+        let mut cached = self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        cached.get_or_insert_with(|| Arc::new(load())).clone()
+    }
+
+    fn clear(&self) {
+        *self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = None;
+    }
+}
+
+/// How long the config folder must be quiet before defaults.json is
+/// reloaded. Editors often save in several steps (truncate, write, rename).
+const CONFIG_RELOAD_DEBOUNCE: std::time::Duration = std::time::Duration::from_millis(300);
+
+/// Whether a file system event may have changed the user's defaults.json
+fn is_config_change(event: &notify::Event) -> bool {
+    !event.kind.is_access()
+        && event
+            .paths
+            .iter()
+            .any(|path| path.file_name().is_some_and(|name| name == "defaults.json"))
+}
+
+/// Re-read the config and emit "defaults-changed" with the merged, expanded
+/// result. An invalid file is logged and otherwise ignored, so the app keeps
+/// the last good config until the file is fixed.
+fn reload_defaults(app_handle: &tauri::AppHandle) {
+    if let Some(cache) = app_handle.try_state::<ConfigCache>() {
+        cache.clear();
+    }
+    let config = get_defaults_config(app_handle.clone())
+        .and_then(|loaded| serde_json::from_str::<serde_json::Value>(&loaded.config).map_err(CommandError::from));
+
+    match config {
+        Ok(config) => {
+            tracing::info!("defaults.json changed, reloading");
+            let _ = app_handle.emit("defaults-changed", config);
+        }
+        Err(e) => tracing::warn!("Ignoring changed defaults.json: {}", e),
+    }
+}
+
+/// Watch the user's defaults.json for edits made outside the app.
+/// The folder is watched rather than the file, because editors that save
+/// by renaming a temp file over the original would end a watch on the file.
+fn watch_user_config(app_handle: tauri::AppHandle) -> Result<(), String> {
+    use notify::Watcher;
+
+    let config_dir = get_user_config_dir()?;
+    fs::create_dir_all(&config_dir).map_err(|e| format!("Failed to create config directory: {}", e))?;
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        if let Ok(event) = event {
+            let _ = tx.send(event);
+        }
+    })
+    .map_err(|e| format!("Failed to start config watcher: {}", e))?;
+    watcher
+        .watch(&config_dir, notify::RecursiveMode::NonRecursive)
+        .map_err(|e| format!("Failed to watch {}: {}", config_dir.display(), e))?;
+
+    std::thread::spawn(move || {
+        // The watcher stops when dropped, so it lives as long as this thread
+        let _watcher = watcher;
+        while let Ok(event) = rx.recv() {
+            if !is_config_change(&event) {
+                continue;
+            }
+            while rx.recv_timeout(CONFIG_RELOAD_DEBOUNCE).is_ok() {}
+            reload_defaults(&app_handle);
+        }
+    });
+
+    Ok(())
+}
+
+/// Try to launch a program with the given arguments.
+/// Records the program name in `tried` so failures can report what was attempted.
+fn try_spawn(program: &str, args: &[&str], tried: &mut Vec<String>) -> bool {
+    spawn_program(program, args, tried).is_some()
+}
+
+/// A program started to open a file outside the app
+#[derive(Serialize, Debug)]
+struct ExternalLaunch {
+    program: String,
+    /// Process id of the launched program. Launchers such as xdg-open or
+    /// `cmd /C start` hand the file on and exit, so this may not be the
+    /// process that ends up showing it.
+    pid: u32,
+}
+
+/// try_spawn, keeping what was started
+fn spawn_program(program: &str, args: &[&str], tried: &mut Vec<String>) -> Option<ExternalLaunch> {
+    tried.push(program.to_string());
+    let child = std::process::Command::new(program).args(args).spawn().ok()?;
+    Some(ExternalLaunch {
+        program: program.to_string(),
+        pid: child.id(),
+    })
+}
+
+/// Open a file in the default browser
+/// Tries `preferredBrowser` from defaults.json first, then the platform defaults.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle), err)]
+fn open_in_default_app(app_handle: tauri::AppHandle, path: String) -> Result<(), CommandError> {
+    launch_in_browser(&app_handle, path).map(|_| ())
+}
+
+/// Result of open_and_register
+#[derive(Serialize, Debug)]
+struct RegisteredOpen {
+    launch: ExternalLaunch,
+    /// Whether a library document had the open recorded against it
+    tracked: bool,
+}
+
+/// Open a file in the browser like open_in_default_app, and count it as an
+/// open of `source_path` (the document it was exported from), or of `path`
+/// itself if no source is given. That keeps exported HTML viewed outside
+/// the app in the recent and most-used lists.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle, state), err)]
+fn open_and_register(
+    app_handle: tauri::AppHandle,
+    state: State<DbState>,
+    path: String,
+    source_path: Option<String>,
+) -> Result<RegisteredOpen, CommandError> {
+    let document = resolve_path(source_path.as_deref().unwrap_or(&path));
+    let launch = launch_in_browser(&app_handle, path)?;
+
+    // The file is already open by now, so a failure here only loses the record
+    let now = now_timestamp();
+    let tracked = state
+        .with_conn(|conn| register_external_open(conn, &document, &now).map_err(CommandError::from))
+        .unwrap_or_else(|e| {
+            tracing::warn!(path = %document, "Failed to record external open: {}", e);
+            false
+        });
+
+    Ok(RegisteredOpen { launch, tracked })
+}
+
+/// record_open for a file opened outside the app; false if `path` isn't in
+/// the library
+fn register_external_open(conn: &Connection, path: &str, timestamp: &str) -> Result<bool, rusqlite::Error> {
+    record_open(conn, path, timestamp)?;
+    Ok(conn.changes() > 0)
+}
+
+fn launch_in_browser(app_handle: &tauri::AppHandle, path: String) -> Result<ExternalLaunch, CommandError> {
+    // Convert to file:// URL
+    let url = if path.starts_with("file://") {
+        path
+    } else {
+        format!("file://{}", path)
+    };
+
+    // User's browser of choice (executable name or full path), if configured
+    let preferred_browser = Some(load_defaults_typed(app_handle).preferred_browser.trim().to_string())
+        .filter(|s| !s.is_empty());
+
+    let mut tried: Vec<String> = Vec::new();
+
+    if let Some(browser) = preferred_browser {
+        if let Some(launch) = spawn_program(&browser, &[&url], &mut tried) {
+            return Ok(launch);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        // Try browsers directly before xdg-open to avoid text editor association
+        let browsers = [
+            "google-chrome",
+            "google-chrome-stable",
+            "chromium",
+            "chromium-browser",
+            "firefox",
+        ];
+
+        for browser in browsers {
+            if let Some(launch) = spawn_program(browser, &[&url], &mut tried) {
+                return Ok(launch);
+            }
+        }
+
+        // Last resort: whatever the desktop has associated with the file
+        if let Some(launch) = spawn_program("xdg-open", &[&url], &mut tried) {
+            return Ok(launch);
+        }
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        if let Some(launch) = spawn_program("cmd", &["/C", "start", "", &url], &mut tried) {
+            return Ok(launch);
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        if let Some(launch) = spawn_program("open", &[&url], &mut tried) {
+            return Ok(launch);
+        }
+    }
+
+    Err(CommandError::NotFound(format!(
+        "No browser found (tried: {}). Install Chrome, Chromium, or Firefox, or set preferredBrowser in defaults.json.",
+        tried.join(", ")
+    )))
+}
+
+/// Open a file with a named application (an executable name on the PATH or
+/// a full path), e.g. an image editor for exported PNGs. On macOS an
+/// application name such as "Preview" also works. Only applications listed
+/// in `openWithApps` in defaults.json can be started; others fail with
+/// Permission. Without `app` this is open_in_default_app.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle), err)]
+fn open_path_with(app_handle: tauri::AppHandle, path: String, app: Option<String>) -> Result<(), CommandError> {
+    let app = match app.as_deref().map(str::trim).filter(|a| !a.is_empty()) {
+        Some(app) => app.to_string(),
+        None => return open_in_default_app(app_handle, path),
+    };
+    ensure_open_with_allowed(&load_defaults_typed(&app_handle), &app)?;
+
+    let spawned = std::process::Command::new(&app).arg(&path).spawn();
+    match spawned {
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => open_with_app_bundle(&app, &path),
+        Err(e) => Err(CommandError::io(&format!("Failed to start {}", app), e)),
+    }
+}
+
+/// Refuse an application that isn't listed in `openWithApps`
+fn ensure_open_with_allowed(config: &Defaults, app: &str) -> Result<(), CommandError> {
+    if config.open_with_apps.iter().any(|allowed| allowed.trim() == app) {
+        return Ok(());
+    }
+    Err(CommandError::Permission(format!("{} is not listed in openWithApps in defaults.json", app)))
+}
+
+/// macOS applications are usually named rather than run by path, so look
+/// the name up with `open -a`, which fails if no such application exists
+#[cfg(target_os = "macos")]
+fn open_with_app_bundle(app: &str, path: &str) -> Result<(), CommandError> {
+    let status = std::process::Command::new("open")
+        .args(["-a", app, path])
+        .status()
+        .map_err(|e| CommandError::io(&format!("Failed to start {}", app), e))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(CommandError::NotFound(format!("Application not found: {}", app)))
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+fn open_with_app_bundle(app: &str, _path: &str) -> Result<(), CommandError> {
+    Err(CommandError::NotFound(format!("Application not found: {}", app)))
+}
+
+/// Show a folder in the system file manager
+fn open_in_file_manager(dir: &Path) -> Result<(), CommandError> {
+    let dir = dir.to_string_lossy();
+    let mut tried: Vec<String> = Vec::new();
+
+    #[cfg(target_os = "linux")]
+    if try_spawn("xdg-open", &[&dir], &mut tried) || try_spawn("gio", &["open", &dir], &mut tried) {
+        return Ok(());
+    }
+
+    #[cfg(target_os = "windows")]
+    if try_spawn("explorer", &[&dir], &mut tried) {
+        return Ok(());
+    }
+
+    #[cfg(target_os = "macos")]
+    if try_spawn("open", &[&dir], &mut tried) {
+        return Ok(());
+    }
+
+    Err(CommandError::NotFound(format!("No file manager found (tried: {})", tried.join(", "))))
+}
+
+/// Open the app's config folder (defaults.json, the library database and
+/// logs) in the file manager, creating it if needed. Handy for bug reports.
+#[tauri::command]
+#[tracing::instrument(err)]
+fn open_config_dir() -> Result<(), CommandError> {
+    let dir = get_user_config_dir()?;
+    fs::create_dir_all(&dir).map_err(|e| CommandError::io("Failed to create config directory", e))?;
+    open_in_file_manager(&dir)
+}
+
+/// Path of the app's config folder, for display
+#[tauri::command]
+#[tracing::instrument(err)]
+fn get_config_dir_path() -> Result<String, CommandError> {
+    get_user_config_dir().map(|dir| dir.to_string_lossy().to_string())
+}
+
+// ============================================================================
+// Document Export Commands
+// ============================================================================
+//
+// Render a single .ssce document as a standalone file. The HTML export has
+// the same layout as the frontend's snapshot viewer export, but is built
+// here so that images referenced by path can be inlined as data URLs.
+//
+// ============================================================================
+
+/// Stylesheet for exported HTML, matching the snapshot viewer export
+const EXPORT_HTML_STYLE: &str = "
+    * { box-sizing: border-box; }
+    body { margin: 0; padding: 20px; font-family: system-ui, -apple-system, sans-serif; background: #111827; color: #e5e7eb; min-height: 100vh; }
+    .container { max-width: 1200px; margin: 0 auto; }
+    .header { text-align: center; padding-bottom: 1.5rem; margin-bottom: 2rem; border-bottom: 1px solid #374151; }
+    .header h1 { font-size: 1.5rem; font-weight: 600; margin: 0 0 0.25rem 0; color: #f3f4f6; }
+    .header p { font-size: 0.875rem; color: #6b7280; margin: 0; }
+    .snapshot { margin-bottom: 3rem; background: #1f2937; border-radius: 0.5rem; overflow: hidden; }
+    .snapshot-header { padding: 0.5rem 1rem; background: #374151; border-bottom: 1px solid #4b5563; }
+    .snapshot-number { font-size: 0.875rem; color: #9ca3af; }
+    .image-container { padding: 1rem; text-align: center; background: #111827; }
+    .image-container img { max-width: 100%; height: auto; border-radius: 0.25rem; }
+    .missing-image { padding: 3rem 1rem; border: 2px dashed #4b5563; border-radius: 0.25rem; color: #9ca3af; }
+    .metadata { padding: 1rem; border-top: 1px solid #374151; }
+    .metadata h3 { font-size: 1.125rem; font-weight: 600; margin: 0 0 0.5rem 0; color: #f3f4f6; }
+    .metadata .summary { font-size: 0.875rem; color: #9ca3af; margin: 0 0 0.5rem 0; white-space: pre-wrap; }
+    .metadata .date { font-size: 0.75rem; color: #6b7280; margin: 0; }
+    .footer { text-align: center; padding-top: 1.5rem; margin-top: 2rem; border-top: 1px solid #374151; font-size: 0.75rem; color: #4b5563; }
+";
+
+/// Result of export_html
+#[derive(Serialize, Debug)]
+struct HtmlExport {
+    path: String,
+    /// Images read from disk and embedded as data URLs
+    images_inlined: usize,
+    /// Referenced images that couldn't be read (shown as placeholders)
+    images_missing: usize,
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Format an ISO date like the frontend's en-GB display ("5 Jan 2025, 14:30")
+fn format_display_date(iso: &str) -> String {
+    chrono::DateTime::parse_from_rfc3339(iso)
+        .map(|dt| dt.with_timezone(&chrono::Local).format("%-d %b %Y, %H:%M").to_string())
+        .unwrap_or_else(|_| iso.to_string())
+}
+
+/// A snapshot's data URL if it is exactly `data:image/<type>;base64,<base64>`
+/// and decodes; anything else could break out of the `src` attribute
+fn embeddable_data_url(source: &str) -> Option<&str> {
+    let (header, payload) = source.strip_prefix("data:image/")?.split_once(',')?;
+    let subtype = header.strip_suffix(";base64")?;
+    let subtype_ok = !subtype.is_empty()
+        && subtype.bytes().all(|b| b.is_ascii_alphanumeric() || matches!(b, b'.' | b'+' | b'-'));
+    let payload_ok = payload.bytes().all(|b| b.is_ascii_alphanumeric() || matches!(b, b'+' | b'/' | b'='));
+    (subtype_ok && payload_ok && decode_data_url(source).is_ok()).then_some(source)
+}
+
+/// Render a .ssce document as a self-contained HTML page. Image sources that
+/// aren't already data URLs are read from disk (relative paths resolve
+/// against `base_dir`) if they are images within `limits` inside `base_dir`;
+/// anything else becomes a visible placeholder.
+fn render_ssce_html(
+    json: &serde_json::Value,
+    base_dir: &Path,
+    fallback_title: &str,
+    limits: &ImageLimits,
+) -> (String, usize, usize) {
+    let front_matter = &json["frontMatter"];
+    let title = front_matter["title"]
+        .as_str()
+        .filter(|t| !t.is_empty())
+        .unwrap_or(fallback_title);
+    let snapshots = json["snapshots"].as_array().map(Vec::as_slice).unwrap_or_default();
+
+    let mut inlined = 0;
+    let mut missing = 0;
+    let mut blocks = String::new();
+
+    for (index, snapshot) in snapshots.iter().enumerate() {
+        let fm = &snapshot["frontMatter"];
+        let snapshot_title = fm["title"]
+            .as_str()
+            .filter(|t| !t.is_empty())
+            .map(String::from)
+            .unwrap_or_else(|| format!("Snapshot {}", snapshot["id"]));
+
+        let source = snapshot["image"].as_str().unwrap_or_default();
+        let image = if source.starts_with("data:") {
+            match embeddable_data_url(source) {
+                Some(data_url) => format!("<img src=\"{}\" alt=\"{}\" />", data_url, escape_html(&snapshot_title)),
+                None => {
+                    missing += 1;
+                    "<div class=\"missing-image\">Image not found: invalid embedded image</div>".to_string()
+                }
+            }
+        } else {
+            let embedded = document_image_path(source, base_dir)
+                .ok_or_else(|| CommandError::Permission(format!("Image is outside the document folder: {}", source)))
+                .and_then(|path| checked_image_data_url(&path, limits));
+            match embedded {
+                Ok(data_url) => {
+                    inlined += 1;
+                    format!("<img src=\"{}\" alt=\"{}\" />", data_url, escape_html(&snapshot_title))
+                }
+                _ => {
+                    missing += 1;
+                    format!("<div class=\"missing-image\">Image not found: {}</div>", escape_html(source))
+                }
+            }
+        };
+
+        let summary = fm["summary"]
+            .as_str()
+            .filter(|s| !s.is_empty())
+            .map(|s| format!("<p class=\"summary\">{}</p>", escape_html(s)))
+            .unwrap_or_default();
+        let date = fm["created"]
+            .as_str()
+            .map(|d| format!("<p class=\"date\">{}</p>", escape_html(&format_display_date(d))))
+            .unwrap_or_default();
+
+        blocks.push_str(&format!(
+            "
+    <div class=\"snapshot\">
+      <div class=\"snapshot-header\">
+        <span class=\"snapshot-number\">{} of {}</span>
+      </div>
+      <div class=\"image-container\">
+        {}
+      </div>
+      <div class=\"metadata\">
+        <h3>{}</h3>
+        {}
+        {}
+      </div>
+    </div>",
+            index + 1,
+            snapshots.len(),
+            image,
+            escape_html(&snapshot_title),
+            summary,
+            date
+        ));
+    }
+
+    let html = format!(
+        "<!DOCTYPE html>
+<html lang=\"en\">
+<head>
+  <meta charset=\"UTF-8\">
+  <meta name=\"viewport\" content=\"width=device-width, initial-scale=1.0\">
+  <title>{title} - Snapshots</title>
+  <style>{style}</style>
+</head>
+<body>
+  <div class=\"container\">
+    <div class=\"header\">
+      <h1>{title}</h1>
+      <p>{count} snapshot{plural}</p>
+    </div>
+    {blocks}
+    <div class=\"footer\">
+      Created with SSCE - Simple Screen Capture Editor
+    </div>
+  </div>
+</body>
+</html>",
+        title = escape_html(title),
+        style = EXPORT_HTML_STYLE,
+        count = snapshots.len(),
+        plural = if snapshots.len() == 1 { "" } else { "s" },
+        blocks = blocks,
+    );
+
+    (html, inlined, missing)
+}
+
+/// File referenced by a snapshot image that isn't a data URL. Relative paths
+/// resolve against the document's folder.
+fn snapshot_image_path(source: &str, base_dir: &Path) -> std::path::PathBuf {
+    base_dir.join(source.strip_prefix("file://").unwrap_or(source))
+}
+
+/// Canonical path of a snapshot image file, if it exists inside `base_dir`.
+/// A document can name any path, so anything outside its folder (absolute
+/// paths, `..`, symlinks out) is refused rather than read into an export.
+fn document_image_path(source: &str, base_dir: &Path) -> Option<std::path::PathBuf> {
+    if source.is_empty() {
+        return None;
+    }
+    let base_dir = dunce::canonicalize(base_dir).ok()?;
+    let path = dunce::canonicalize(snapshot_image_path(source, &base_dir)).ok()?;
+    (path.starts_with(&base_dir) && path.is_file()).then_some(path)
+}
+
+/// Read a .ssce document and its folder, for the document exports
+fn read_ssce_for_export(ssce_path: &Path) -> Result<(serde_json::Value, std::path::PathBuf, String), CommandError> {
+    if is_encrypted_ssce(ssce_path) {
+        return Err(CommandError::Validation(format!("File is encrypted: {}", ssce_path.display())));
+    }
+
+    let content = read_document_text(ssce_path)?;
+    let json: serde_json::Value = serde_json::from_str(&content)
+        .map_err(|e| CommandError::Parse(format!("Failed to parse JSON: {}", e)))?;
+    let base_dir = ssce_path.parent().map(Path::to_path_buf).unwrap_or_default();
+    let stem = ssce_path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "Snapshots".to_string());
+
+    Ok((json, base_dir, stem))
+}
+
+/// Export a .ssce document as a single self-contained HTML file
+#[tauri::command]
+#[tracing::instrument(skip(app_handle), err)]
+fn export_html(app_handle: tauri::AppHandle, ssce_path: String, out_path: String) -> Result<HtmlExport, CommandError> {
+    ensure_write_allowed(&app_handle, Path::new(&out_path))?;
+    write_html_export(ssce_path, out_path, &ImageLimits::from_config(&load_defaults_typed(&app_handle)))
+}
+
+fn write_html_export(ssce_path: String, out_path: String, limits: &ImageLimits) -> Result<HtmlExport, CommandError> {
+    let (json, base_dir, stem) = read_ssce_for_export(Path::new(&ssce_path))?;
+    let (html, images_inlined, images_missing) = render_ssce_html(&json, &base_dir, &stem, limits);
+
+    let out = Path::new(&out_path);
+    ensure_disk_space(out, html.len() as u64)?;
+    if let Some(parent) = out.parent() {
+        fs::create_dir_all(parent).map_err(|e| CommandError::io("Failed to create directories", e))?;
+    }
+    write_atomic(out, |writer| writer.write_all(html.as_bytes()))?;
+
+    Ok(HtmlExport {
+        path: out_path,
+        images_inlined,
+        images_missing,
+    })
+}
+
+/// Paper size for export_pdf
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+enum PageSize {
+    #[default]
+    A4,
+    A3,
+    Letter,
+    Legal,
+}
+
+impl PageSize {
+    /// Portrait width and height in points
+    fn points(self) -> (f32, f32) {
+        match self {
+            PageSize::A4 => (595.0, 842.0),
+            PageSize::A3 => (842.0, 1191.0),
+            PageSize::Letter => (612.0, 792.0),
+            PageSize::Legal => (612.0, 1008.0),
+        }
+    }
+}
+
+const DEFAULT_PDF_MARGIN_MM: f32 = 15.0;
+const POINTS_PER_MM: f32 = 72.0 / 25.4;
+
+/// Progress of export_pdf, emitted as "export-pdf-progress" after each page
+#[derive(Serialize, Clone, Debug)]
+struct PdfExportProgress {
+    page: usize,
+    total: usize,
+}
+
+/// One page of an exported PDF: a snapshot, or the document itself when it
+/// has no snapshots
+struct PdfPage {
+    title: String,
+    summary: String,
+    image: Result<image::DynamicImage, String>,
+}
+
+fn pdf_pages(json: &serde_json::Value, base_dir: &Path, fallback_title: &str, limits: &ImageLimits) -> Vec<PdfPage> {
+    let load = |source: &str| -> Result<image::DynamicImage, String> {
+        let bytes = if source.starts_with("data:") {
+            decode_data_url(source)?
+        } else {
+            document_image_path(source, base_dir)
+                .and_then(|path| fs::read(path).ok())
+                .ok_or_else(|| format!("Image not found: {}", source))?
+        };
+        Ok(decode_image_bytes(&bytes, limits)?)
+    };
+
+    let snapshots = json["snapshots"].as_array().map(Vec::as_slice).unwrap_or_default();
+    if snapshots.is_empty() {
+        let front_matter = &json["frontMatter"];
+        return vec![PdfPage {
+            title: front_matter["title"]
+                .as_str()
+                .filter(|t| !t.is_empty())
+                .unwrap_or(fallback_title)
+                .to_string(),
+            summary: front_matter["summary"].as_str().unwrap_or_default().to_string(),
+            image: json["thumbnail"]
+                .as_str()
+                .ok_or_else(|| "Document has no snapshots".to_string())
+                .and_then(load),
+        }];
+    }
+
+    snapshots
+        .iter()
+        .map(|snapshot| {
+            let fm = &snapshot["frontMatter"];
+            PdfPage {
+                title: fm["title"]
+                    .as_str()
+                    .filter(|t| !t.is_empty())
+                    .map(String::from)
+                    .unwrap_or_else(|| format!("Snapshot {}", snapshot["id"])),
+                summary: fm["summary"].as_str().unwrap_or_default().to_string(),
+                image: load(snapshot["image"].as_str().unwrap_or_default()),
+            }
+        })
+        .collect()
+}
+
+/// Text for the standard Helvetica font, which only covers Latin-1
+fn pdf_text(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| u8::try_from(u32::from(c)).unwrap_or(b'?'))
+        .collect()
+}
+
+/// Greedy word wrap using Helvetica's average glyph width (about half the
+/// font size) - close enough for titles and summaries
+fn wrap_text(text: &str, font_size: f32, width: f32) -> Vec<String> {
+    let max_chars = ((width / (font_size * 0.5)) as usize).max(1);
+    let mut lines = Vec::new();
+
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > max_chars {
+                lines.push(std::mem::take(&mut line));
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(word);
+        }
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// Lay the pages out as a PDF: title and summary at the top of each page,
+/// the image scaled to fill the rest. `progress` is called after each page.
+fn render_pdf<F>(pages: &[PdfPage], page_size: PageSize, margin_mm: f32, mut progress: F) -> Result<Vec<u8>, CommandError>
+where
+    F: FnMut(usize, usize),
+{
+    use pdf_writer::{Content, Filter, Finish, Name, Pdf, Rect, Ref, Str};
+
+    let (page_width, page_height) = page_size.points();
+    let margin = margin_mm.max(0.0) * POINTS_PER_MM;
+    let content_width = page_width - 2.0 * margin;
+    if content_width <= 0.0 || page_height - 2.0 * margin <= 0.0 {
+        return Err(CommandError::Validation(format!("Margin of {}mm leaves no room on the page", margin_mm)));
+    }
+
+    let mut pdf = Pdf::new();
+    let catalog_id = Ref::new(1);
+    let page_tree_id = Ref::new(2);
+    let font_id = Ref::new(3);
+    let font_name = Name(b"F1");
+    let image_name = Name(b"Im1");
+
+    // Each page needs three objects: the page, its content stream and its image
+    let page_ids: Vec<Ref> = (0..pages.len()).map(|i| Ref::new(4 + 3 * i as i32)).collect();
+
+    pdf.catalog(catalog_id).pages(page_tree_id);
+    pdf.pages(page_tree_id).kids(page_ids.iter().copied()).count(pages.len() as i32);
+    pdf.type1_font(font_id)
+        .base_font(Name(b"Helvetica"))
+        .encoding_predefined(Name(b"WinAnsiEncoding"));
+
+    for (index, page) in pages.iter().enumerate() {
+        let page_id = page_ids[index];
+        let content_id = Ref::new(page_id.get() + 1);
+        let image_id = Ref::new(page_id.get() + 2);
+
+        let mut content = Content::new();
+        let mut y = page_height - margin;
+
+        let text_lines = |content: &mut Content, text: &str, size: f32, gray: f32, y: &mut f32| {
+            for line in wrap_text(text, size, content_width) {
+                *y -= size * 1.3;
+                content
+                    .begin_text()
+                    .set_fill_gray(gray)
+                    .set_font(font_name, size)
+                    .next_line(margin, *y)
+                    .show(Str(&pdf_text(&line)))
+                    .end_text();
+            }
+        };
+        text_lines(&mut content, &page.title, 16.0, 0.0, &mut y);
+        if !page.summary.is_empty() {
+            text_lines(&mut content, &page.summary, 10.0, 0.35, &mut y);
+        }
+        y -= 12.0;
+
+        let image_box_height = y - margin;
+        let mut drew_image = false;
+        match &page.image {
+            Ok(image) if image_box_height > 0.0 => {
+                // JPEG can be embedded as-is (DCTDecode); transparency is
+                // flattened onto white first
+                let mut flattened = image::RgbImage::from_pixel(image.width(), image.height(), image::Rgb([255, 255, 255]));
+                for (x, py, pixel) in image.to_rgba8().enumerate_pixels() {
+                    let alpha = pixel[3] as u32;
+                    let blend = |c: u8| ((c as u32 * alpha + 255 * (255 - alpha)) / 255) as u8;
+                    flattened.put_pixel(x, py, image::Rgb([blend(pixel[0]), blend(pixel[1]), blend(pixel[2])]));
+                }
+                let mut jpeg = Vec::new();
+                image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, 90)
+                    .encode_image(&flattened)
+                    .map_err(|e| CommandError::Io(format!("Failed to encode image: {}", e)))?;
+
+                let mut xobject = pdf.image_xobject(image_id, &jpeg);
+                xobject.filter(Filter::DctDecode);
+                xobject.width(image.width() as i32);
+                xobject.height(image.height() as i32);
+                xobject.color_space().device_rgb();
+                xobject.bits_per_component(8);
+                xobject.finish();
+
+                let scale = (content_width / image.width() as f32).min(image_box_height / image.height() as f32);
+                let (w, h) = (image.width() as f32 * scale, image.height() as f32 * scale);
+                content
+                    .save_state()
+                    .transform([w, 0.0, 0.0, h, margin + (content_width - w) / 2.0, y - h])
+                    .x_object(image_name)
+                    .restore_state();
+                drew_image = true;
+            }
+            Ok(_) => {}
+            Err(e) => text_lines(&mut content, e, 10.0, 0.5, &mut y),
+        }
+
+        let mut pdf_page = pdf.page(page_id);
+        pdf_page.media_box(Rect::new(0.0, 0.0, page_width, page_height));
+        pdf_page.parent(page_tree_id);
+        pdf_page.contents(content_id);
+        let mut resources = pdf_page.resources();
+        resources.fonts().pair(font_name, font_id);
+        // Only an image that was written; a squeezed-out one has no object
+        if drew_image {
+            resources.x_objects().pair(image_name, image_id);
+        }
+        resources.finish();
+        pdf_page.finish();
+
+        pdf.stream(content_id, &content.finish());
+        progress(index + 1, pages.len());
+    }
+
+    Ok(pdf.finish())
+}
+
+fn export_pdf_to<F>(
+    ssce_path: &Path,
+    out_path: &Path,
+    page_size: PageSize,
+    margin_mm: f32,
+    limits: &ImageLimits,
+    progress: F,
+) -> Result<usize, CommandError>
+where
+    F: FnMut(usize, usize),
+{
+    let (json, base_dir, stem) = read_ssce_for_export(ssce_path)?;
+    let pages = pdf_pages(&json, &base_dir, &stem, limits);
+    let pdf = render_pdf(&pages, page_size, margin_mm, progress)?;
+
+    ensure_disk_space(out_path, pdf.len() as u64)?;
+    if let Some(parent) = out_path.parent() {
+        fs::create_dir_all(parent).map_err(|e| CommandError::io("Failed to create directories", e))?;
+    }
+    write_atomic(out_path, |writer| writer.write_all(&pdf))?;
+
+    Ok(pages.len())
+}
+
+/// Export a .ssce document as a PDF, one snapshot per page.
+/// Defaults to A4 with 15mm margins. Emits "export-pdf-progress" as pages
+/// are rendered; returns the output path. Rendering runs on a blocking thread.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle), err)]
+async fn export_pdf(
+    app_handle: tauri::AppHandle,
+    ssce_path: String,
+    out_path: String,
+    page_size: Option<PageSize>,
+    margin_mm: Option<f32>,
+) -> Result<String, CommandError> {
+    ensure_write_allowed(&app_handle, Path::new(&out_path))?;
+    let app = app_handle.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        export_pdf_to(
+            Path::new(&ssce_path),
+            Path::new(&out_path),
+            page_size.unwrap_or_default(),
+            margin_mm.unwrap_or(DEFAULT_PDF_MARGIN_MM),
+            &ImageLimits::from_config(&load_defaults_typed(&app)),
+            |page, total| {
+                let _ = app.emit("export-pdf-progress", PdfExportProgress { page, total });
+            },
+        )?;
+        Ok(out_path)
+    })
+    .await
+    .map_err(|e| CommandError::Io(format!("PDF export failed: {}", e)))?
+}
+
+/// Error returned when the system has no default printer to send a job to
+const NO_PRINTER_ERROR: &str = "No printer is configured. Add a printer in the system settings and set it as the default.";
+
+/// Browsers able to print a page to PDF without a window, tried after
+/// `preferredBrowser`. Firefox has no headless print, so only Chromium builds.
+#[cfg(not(target_os = "windows"))]
+const HEADLESS_PRINT_BROWSERS: &[&str] = &[
+    "google-chrome",
+    "google-chrome-stable",
+    "chromium",
+    "chromium-browser",
+    "/Applications/Google Chrome.app/Contents/MacOS/Google Chrome",
+    "/Applications/Chromium.app/Contents/MacOS/Chromium",
+];
+
+/// Default printer name from `lpstat -d` output.
+/// CUPS prints "system default destination: NAME", or
+/// "no system default destination" when none is set.
+#[cfg_attr(target_os = "windows", allow(dead_code))]
+fn parse_default_printer(lpstat_output: &str) -> Option<String> {
+    lpstat_output
+        .lines()
+        .find_map(|line| line.split_once("system default destination:"))
+        .map(|(_, name)| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+}
+
+/// Name of the system default printer, or NO_PRINTER_ERROR
+fn default_printer() -> Result<String, CommandError> {
+    #[cfg(target_os = "windows")]
+    let printer = std::process::Command::new("powershell")
+        .args([
+            "-NoProfile",
+            "-Command",
+            "(Get-CimInstance Win32_Printer -Filter 'Default=TRUE').Name",
+        ])
+        .output()
+        .ok()
+        .map(|out| String::from_utf8_lossy(&out.stdout).trim().to_string())
+        .filter(|name| !name.is_empty());
+
+    #[cfg(not(target_os = "windows"))]
+    let printer = std::process::Command::new("lpstat")
+        .arg("-d")
+        .output()
+        .ok()
+        .and_then(|out| parse_default_printer(&String::from_utf8_lossy(&out.stdout)));
+
+    printer.ok_or_else(|| CommandError::NotFound(NO_PRINTER_ERROR.to_string()))
+}
+
+/// Print an HTML file through the shell "print" verb, which hands it to
+/// the registered HTML handler and shows its print dialog
+#[cfg(target_os = "windows")]
+fn print_html_file(_app_handle: &tauri::AppHandle, html_path: &Path, _ssce_path: &Path, _printer: &str) -> Result<(), String> {
+    let file = html_path.to_string_lossy().replace('\'', "''");
+    let status = std::process::Command::new("powershell")
+        .args([
+            "-NoProfile",
+            "-Command",
+            &format!("Start-Process -FilePath '{}' -Verb Print -Wait", file),
+        ])
+        .status()
+        .map_err(|e| format!("Failed to start print: {}", e))?;
+
+    if !status.success() {
+        return Err(format!("Print failed ({})", status));
+    }
+    Ok(())
+}
+
+/// Print an HTML file by rendering it to PDF with a headless Chromium and
+/// sending that to the default printer with `lp`. Falls back to the
+/// built-in PDF export when no Chromium browser is installed.
+#[cfg(not(target_os = "windows"))]
+fn print_html_file(app_handle: &tauri::AppHandle, html_path: &Path, ssce_path: &Path, printer: &str) -> Result<(), String> {
+    let pdf_path = html_path.with_extension("pdf");
+    let result = render_print_pdf(app_handle, html_path, ssce_path, &pdf_path).and_then(|_| {
+        let output = std::process::Command::new("lp")
+            .arg("-d")
+            .arg(printer)
+            .arg(&pdf_path)
+            .output()
+            .map_err(|e| format!("Failed to run lp: {}", e))?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(format!("Print failed: {}", String::from_utf8_lossy(&output.stderr).trim()))
+        }
+    });
+
+    let _ = fs::remove_file(&pdf_path);
+    result
+}
+
+/// Render the print HTML to `pdf_path`, preferring a headless Chromium so
+/// the printout matches the HTML export
+#[cfg(not(target_os = "windows"))]
+fn render_print_pdf(app_handle: &tauri::AppHandle, html_path: &Path, ssce_path: &Path, pdf_path: &Path) -> Result<(), String> {
+    let preferred_browser = Some(load_defaults_typed(app_handle).preferred_browser.trim().to_string())
+        .filter(|s| !s.is_empty());
+
+    let url = format!("file://{}", html_path.display());
+    let print_to = format!("--print-to-pdf={}", pdf_path.display());
+    let browsers = preferred_browser
+        .iter()
+        .map(String::as_str)
+        .chain(HEADLESS_PRINT_BROWSERS.iter().copied());
+
+    for browser in browsers {
+        let ran = std::process::Command::new(browser)
+            .args(["--headless", "--disable-gpu", "--no-pdf-header-footer", &print_to, &url])
+            .output();
+        if matches!(ran, Ok(ref out) if out.status.success()) && pdf_path.exists() {
+            return Ok(());
+        }
+    }
+
+    tracing::info!("No headless browser printed the page, using built-in PDF export");
+    let limits = ImageLimits::from_config(&load_defaults_typed(app_handle));
+    export_pdf_to(ssce_path, pdf_path, PageSize::default(), DEFAULT_PDF_MARGIN_MM, &limits, |_, _| {})
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+/// Print a .ssce document on the default printer.
+/// Renders the same HTML as export_html into a temporary file, prints it,
+/// and removes the temporary file afterwards. Runs on a blocking thread, as
+/// rendering and the print tools can take a while.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle), err)]
+async fn print_document(app_handle: tauri::AppHandle, ssce_path: String) -> Result<(), CommandError> {
+    let app = app_handle.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let ssce = Path::new(&ssce_path);
+        let (json, base_dir, stem) = read_ssce_for_export(ssce)?;
+        let printer = default_printer()?;
+        let limits = ImageLimits::from_config(&load_defaults_typed(&app));
+        let (html, _, _) = render_ssce_html(&json, &base_dir, &stem, &limits);
+
+        let html_path = std::env::temp_dir().join(format!(
+            "ssce-print-{}-{}.html",
+            std::process::id(),
+            chrono::Utc::now().timestamp_millis()
+        ));
+        fs::write(&html_path, html).map_err(|e| CommandError::io("Failed to write print file", e))?;
+
+        let result = print_html_file(&app, &html_path, ssce, &printer);
+        let _ = fs::remove_file(&html_path);
+        result.map_err(CommandError::Io)
+    })
+    .await
+    .map_err(|e| CommandError::Io(format!("Printing failed: {}", e)))?
+}
+
+// ============================================================================
+// Bulk Export Commands
+// ============================================================================
+//
+// Commands for the Bulk Export / Backup feature. Allows exporting multiple
+// .ssce files to PNG/JPEG images, optionally into a ZIP archive.
+//
+// ============================================================================
+
+/// File info returned when scanning a directory for .ssce files
+#[derive(Serialize)]
+struct SsceFileInfo {
+    name: String,
+    path: String,
+    date: Option<String>,
+    size: u64,
+}
+
+/// Summary of files per month for the date filter UI
+#[derive(Serialize)]
+struct MonthSummary {
+    month: String,
+    count: u32,
+}
+
+/// Parse a YYYY-MM-DD date from a filename
+fn parse_date_from_filename(filename: &str) -> Option<String> {
+    // Look for YYYY-MM-DD pattern
+    let bytes = filename.as_bytes();
+    for i in 0..filename.len().saturating_sub(9) {
+        if bytes.get(i + 4) == Some(&b'-')
+            && bytes.get(i + 7) == Some(&b'-')
+            && filename[i..i + 4].chars().all(|c| c.is_ascii_digit())
+            && filename[i + 5..i + 7].chars().all(|c| c.is_ascii_digit())
+            && filename[i + 8..i + 10].chars().all(|c| c.is_ascii_digit())
+        {
+            let date = &filename[i..i + 10];
+            let month: u32 = filename[i + 5..i + 7].parse().unwrap_or(0);
+            let day: u32 = filename[i + 8..i + 10].parse().unwrap_or(0);
+            if month >= 1 && month <= 12 && day >= 1 && day <= 31 {
+                return Some(date.to_string());
+            }
+        }
+    }
+    None
+}
+
+/// List all .ssce files in a directory with parsed date metadata
+#[tauri::command]
+#[tracing::instrument(err)]
+fn list_ssce_files(directory: String) -> Result<Vec<SsceFileInfo>, CommandError> {
+    let dir_path = Path::new(&directory);
+
+    if !dir_path.exists() {
+        return Ok(Vec::new());
+    }
+
+    if !dir_path.is_dir() {
+        return Err(CommandError::Validation(format!("Path is not a directory: {}", directory)));
+    }
+
+    let mut entries: Vec<SsceFileInfo> = Vec::new();
+
+    let read_dir = fs::read_dir(dir_path)
+        .map_err(|e| CommandError::io("Failed to read directory", e))?;
+
+    for entry in read_dir {
+        let entry = entry.map_err(|e| CommandError::io("Failed to read entry", e))?;
+        let metadata = entry.metadata().map_err(|e| CommandError::io("Failed to get metadata", e))?;
+
+        if metadata.is_dir() {
+            continue;
+        }
+
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !name.to_lowercase().ends_with(".ssce") {
+            continue;
+        }
+
+        let path = entry.path().to_string_lossy().to_string();
+        let date = parse_date_from_filename(&name);
+        let size = metadata.len();
+
+        entries.push(SsceFileInfo { name, path, date, size });
+    }
+
+    // Sort by name
+    entries.sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()));
+
+    Ok(entries)
+}
+
+/// Get a summary of .ssce file counts grouped by month
+#[tauri::command]
+#[tracing::instrument(err)]
+fn get_monthly_summary(directory: String) -> Result<Vec<MonthSummary>, CommandError> {
+    let files = list_ssce_files(directory)?;
+    let mut counts: HashMap<String, u32> = HashMap::new();
+
+    for file in &files {
+        if let Some(ref date) = file.date {
+            // Extract YYYY-MM from YYYY-MM-DD
+            let month = &date[..7];
+            *counts.entry(month.to_string()).or_insert(0) += 1;
+        }
+    }
+
+    let mut summaries: Vec<MonthSummary> = counts
+        .into_iter()
+        .map(|(month, count)| MonthSummary { month, count })
+        .collect();
+
+    // Sort by month descending (most recent first)
+    summaries.sort_by(|a, b| b.month.cmp(&a.month));
+
+    Ok(summaries)
+}
+
+/// Save an exported image (base64 data URL) to a file path.
+/// Reuses the same pattern as save_image but explicitly for bulk export.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle, data), err)]
+fn save_exported_image(app_handle: tauri::AppHandle, path: String, data: String) -> Result<(), CommandError> {
+    let file_path = Path::new(&path);
+    ensure_write_allowed(&app_handle, file_path)?;
+
+    let decoded = decode_data_url(&data)?;
+    ensure_disk_space(file_path, decoded.len() as u64)?;
+
+    write_atomic(file_path, |writer| writer.write_all(&decoded))
+}
+
+/// Create a new ZIP archive and return an ID for subsequent operations.
+/// The archive stays open until zip_finalize is called.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle, state), err)]
+fn zip_create(app_handle: tauri::AppHandle, state: State<ZipState>, output_path: String) -> Result<String, CommandError> {
+    ensure_write_allowed(&app_handle, Path::new(&output_path))?;
+    // Create parent directories if needed
+    if let Some(parent) = Path::new(&output_path).parent() {
+        fs::create_dir_all(parent).map_err(|e| CommandError::io("Failed to create directories", e))?;
+    }
+
+    let file = fs::File::create(&output_path)
+        .map_err(|e| CommandError::io("Failed to create ZIP file", e))?;
+
+    let writer = ZipWriter::new(file);
+    let id = format!("{}", std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos());
+
+    let mut map = state.0.lock().map_err(|e| CommandError::Io(e.to_string()))?;
+    map.insert(id.clone(), Mutex::new(writer));
+
+    Ok(id)
+}
+
+/// Add a file to an open ZIP archive.
+/// Data is base64-encoded (with optional data URL prefix).
+#[tauri::command]
+#[tracing::instrument(skip(state, base64_data), err)]
+fn zip_add_file(state: State<ZipState>, zip_id: String, entry_name: String, base64_data: String) -> Result<(), CommandError> {
+    // Strip data URL prefix if present
+    let raw_base64 = if let Some(comma_pos) = base64_data.find(',') {
+        &base64_data[comma_pos + 1..]
+    } else {
+        &base64_data
+    };
+
+    let decoded = STANDARD
+        .decode(raw_base64)
+        .map_err(|e| CommandError::Parse(format!("Failed to decode base64: {}", e)))?;
+
+    let map = state.0.lock().map_err(|e| CommandError::Io(e.to_string()))?;
+    let writer_mutex = map.get(&zip_id)
+        .ok_or_else(|| CommandError::NotFound(format!("ZIP archive not found: {}", zip_id)))?;
+
+    let mut writer = writer_mutex.lock().map_err(|e| CommandError::Io(e.to_string()))?;
+
+    let options = SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated);
+
+    writer.start_file(&entry_name, options)
+        .map_err(|e| CommandError::Io(format!("Failed to start ZIP entry: {}", e)))?;
+
+    writer.write_all(&decoded)
+        .map_err(|e| CommandError::io("Failed to write ZIP entry", e))?;
+
+    Ok(())
+}
+
+/// Add a file from disk to an open ZIP archive by its filesystem path.
+#[tauri::command]
+#[tracing::instrument(skip(state), err)]
+fn zip_add_path(state: State<ZipState>, zip_id: String, entry_name: String, file_path: String) -> Result<(), CommandError> {
+    let contents = fs::read(&file_path)
+        .map_err(|e| CommandError::io(&format!("Failed to read file {}", file_path), e))?;
+
+    let map = state.0.lock().map_err(|e| CommandError::Io(e.to_string()))?;
+    let writer_mutex = map.get(&zip_id)
+        .ok_or_else(|| CommandError::NotFound(format!("ZIP archive not found: {}", zip_id)))?;
+
+    let mut writer = writer_mutex.lock().map_err(|e| CommandError::Io(e.to_string()))?;
+
+    let options = SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated);
+
+    writer.start_file(&entry_name, options)
+        .map_err(|e| CommandError::Io(format!("Failed to start ZIP entry: {}", e)))?;
+
+    writer.write_all(&contents)
+        .map_err(|e| CommandError::io("Failed to write ZIP entry", e))?;
+
+    Ok(())
+}
+
+/// Get system information for the About dialog.
+/// Returns platform, OS version, arch, and app version.
+#[tauri::command]
+#[tracing::instrument(skip(window), err)]
+fn get_system_info(window: tauri::Window) -> Result<HashMap<String, String>, CommandError> {
+    let mut info = HashMap::new();
+
+    info.insert("platform".to_string(), std::env::consts::OS.to_string());
+    info.insert("arch".to_string(), std::env::consts::ARCH.to_string());
+
+    // App version from Cargo.toml (embedded by Tauri)
+    info.insert("appVersion".to_string(), env!("CARGO_PKG_VERSION").to_string());
+
+    // Get OS details
+    #[cfg(target_os = "linux")]
+    {
+        // Try to read /etc/os-release for distro info
+        if let Ok(content) = fs::read_to_string("/etc/os-release") {
+            for line in content.lines() {
+                if let Some(name) = line.strip_prefix("PRETTY_NAME=") {
+                    let name = name.trim_matches('"');
+                    info.insert("osVersion".to_string(), name.to_string());
+                    break;
+                }
+            }
+        }
+        // Kernel version
+        if let Ok(uname) = fs::read_to_string("/proc/version") {
+            if let Some(kernel) = uname.split_whitespace().nth(2) {
+                info.insert("kernelVersion".to_string(), kernel.to_string());
+            }
+        }
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        info.insert("osVersion".to_string(), "Windows".to_string());
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        info.insert("osVersion".to_string(), "macOS".to_string());
+    }
+
+    // Monitor info
+    if let Ok(Some(monitor)) = window.current_monitor() {
+        let size = monitor.size();
+        let scale = monitor.scale_factor();
+        info.insert("monitorWidth".to_string(), size.width.to_string());
+        info.insert("monitorHeight".to_string(), size.height.to_string());
+        info.insert("scaleFactor".to_string(), format!("{:.2}", scale));
+    }
+
+    // Config paths
+    if let Ok(config_dir) = get_user_config_dir() {
+        info.insert("configPath".to_string(), config_dir.to_string_lossy().to_string());
+    }
+
+    Ok(info)
+}
+
+/// Turn command-line arguments into absolute paths of files that exist.
+/// Skips argv[0] and flags, expands ~/ and resolves relative paths against `cwd`
+/// (a second instance may have been launched from a different directory).
+fn collect_file_args(args: &[String], cwd: &Path) -> Vec<String> {
+    let home_dir = dirs::home_dir()
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    args.iter()
+        .skip(1)
+        .filter(|arg| !arg.starts_with('-'))
+        .map(|arg| expand_home_prefix(arg, &home_dir).unwrap_or_else(|| arg.clone()))
+        .map(|arg| cwd.join(arg))
+        .filter(|path| path.is_file())
+        .map(|path| path.to_string_lossy().to_string())
+        .collect()
+}
+
+/// Documents (.ssce) passed on the command line at startup.
+/// Held until the frontend has its open-file listener ready (see frontend_ready).
+struct StartupFiles(Mutex<Vec<String>>);
+
+/// Startup status events (backend-ready or backend-error, library-ready)
+/// already emitted to the main window. Its frontend may not be listening yet
+/// when setup runs, so frontend_ready sends them again.
+struct StartupEvents(Mutex<Vec<(&'static str, serde_json::Value)>>);
+
+impl StartupEvents {
+    /// Emit `event` to the main window and keep it for frontend_ready
+    fn emit<S: Serialize>(&self, app_handle: &tauri::AppHandle, event: &'static str, payload: S) {
+        let payload = serde_json::to_value(payload).unwrap_or_default();
+        let _ = app_handle.emit_to(MAIN_WINDOW_LABEL, event, payload.clone());
+        if let Ok(mut events) = self.0.lock() {
+            events.push((event, payload));
+        }
+    }
+}
+
+/// Payload of the library-ready event, sent once startup indexing is done
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LibraryReady {
+    /// Whether the search index was rebuilt (for a changed tokenizer)
+    index_rebuilt: bool,
+}
+
+/// Check if a path looks like an SSCE document (by extension, case-insensitive)
+fn is_ssce_path(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .map(|e| e.eq_ignore_ascii_case("ssce"))
+        .unwrap_or(false)
+}
+
+/// Called by each window's frontend once its open-file listener is
+/// registered. The main window gets an open-file event for each .ssce
+/// document the app was launched with (e.g. double-clicking a file in the
+/// file manager), only the first time; a document window gets its document.
+/// The main window also gets a restore-session event (once per run) with
+/// the documents that were open when the app last closed, and the startup
+/// status events again (see StartupEvents).
+#[tauri::command]
+#[tracing::instrument(
+    skip(app_handle, window, state, windows, session, startup),
+    fields(window = %window.label()),
+    err
+)]
+fn frontend_ready(
+    app_handle: tauri::AppHandle,
+    window: tauri::WebviewWindow,
+    state: State<StartupFiles>,
+    windows: State<DocumentWindows>,
+    session: State<SessionPending>,
+    startup: State<StartupEvents>,
+) -> Result<(), CommandError> {
+    let files = if window.label() == MAIN_WINDOW_LABEL {
+        let events = startup.0.lock().map_err(|e| CommandError::Io(e.to_string()))?.clone();
+        for (event, payload) in events {
+            app_handle
+                .emit_to(window.label(), event, payload)
+                .map_err(|e| CommandError::Io(format!("Failed to emit {}: {}", event, e)))?;
+        }
+        if session.0.swap(false, Ordering::SeqCst) {
+            let paths = get_user_config_dir().map(|dir| read_session(&dir)).unwrap_or_default();
+            if !paths.is_empty() {
+                app_handle
+                    .emit_to(window.label(), "restore-session", paths)
+                    .map_err(|e| CommandError::Io(format!("Failed to emit restore-session: {}", e)))?;
+            }
+        }
+        std::mem::take(&mut *state.0.lock().map_err(|e| CommandError::Io(e.to_string()))?)
+    } else {
+        let map = windows.0.lock().map_err(|e| CommandError::Io(e.to_string()))?;
+        map.get(window.label()).cloned().into_iter().collect()
+    };
+
+    for file_path in files {
+        app_handle
+            .emit_to(window.label(), "open-file", file_path)
+            .map_err(|e| CommandError::Io(format!("Failed to emit open-file: {}", e)))?;
+    }
+
+    Ok(())
+}
+
+/// File in the user config directory listing the documents open at exit
+const SESSION_FILE_NAME: &str = "session.json";
+
+/// Documents open in the app, saved so the next launch can reopen them
+#[derive(Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct Session {
+    open_paths: Vec<String>,
+}
+
+/// Whether the main window still has to be sent restore-session this run
+struct SessionPending(AtomicBool);
+
+/// Saved session paths that still exist, in the order they were saved
+fn read_session(config_dir: &Path) -> Vec<String> {
+    fs::read_to_string(config_dir.join(SESSION_FILE_NAME))
+        .ok()
+        .and_then(|json| serde_json::from_str::<Session>(&json).ok())
+        .map(|session| session.open_paths)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|path| Path::new(path).is_file())
+        .collect()
+}
+
+fn write_session(config_dir: &Path, open_paths: Vec<String>) -> Result<(), CommandError> {
+    let json = serde_json::to_string_pretty(&Session { open_paths })?;
+    write_atomic(&config_dir.join(SESSION_FILE_NAME), |writer| writer.write_all(json.as_bytes()))
+}
+
+/// Remember which documents are open, so they can be reopened next launch.
+/// The main window calls this with its document whenever that changes;
+/// documents open in their own windows are added from DocumentWindows.
+#[tauri::command]
+#[tracing::instrument(skip(windows), err)]
+fn save_session(windows: State<DocumentWindows>, open_paths: Vec<String>) -> Result<(), CommandError> {
+    let mut paths = open_paths;
+    if let Ok(map) = windows.0.lock() {
+        for path in map.values() {
+            if !paths.contains(path) {
+                paths.push(path.clone());
+            }
+        }
+    }
+    write_session(&get_user_config_dir()?, paths)
+}
+
+/// Documents open when the app last closed, leaving out any that have
+/// since been deleted or moved
+#[tauri::command]
+#[tracing::instrument(err)]
+fn get_session() -> Result<Vec<String>, CommandError> {
+    Ok(read_session(&get_user_config_dir()?))
+}
+
+/// Return the first non-document file passed as a CLI argument, if any
+/// (e.g. an image to edit). Documents are delivered via frontend_ready instead.
+#[tauri::command]
+#[tracing::instrument]
+fn get_cli_file_arg() -> Option<String> {
+    let args: Vec<String> = std::env::args().collect();
+    let cwd = std::env::current_dir().unwrap_or_default();
+    collect_file_args(&args, &cwd)
+        .into_iter()
+        .find(|path| !is_ssce_path(path))
+}
+
+/// Clamp window size to 90% of current monitor dimensions.
+/// Called from JS after page load, when window-state plugin has already restored saved size.
+#[tauri::command]
+#[tracing::instrument(skip(window), err)]
+fn clamp_window_size(window: tauri::Window) -> Result<(), CommandError> {
+    if let Ok(Some(monitor)) = window.current_monitor() {
+        let screen = monitor.size();
+        let scale = monitor.scale_factor();
+        let max_w = (screen.width as f64 / scale * 0.9) as u32;
+        let max_h = (screen.height as f64 / scale * 0.9) as u32;
+
+        if let Ok(size) = window.outer_size() {
+            let cur_w = (size.width as f64 / scale) as u32;
+            let cur_h = (size.height as f64 / scale) as u32;
+            if cur_w > max_w || cur_h > max_h {
+                let new_w = cur_w.min(max_w);
+                let new_h = cur_h.min(max_h);
+                let _ = window.set_size(tauri::Size::Logical(tauri::LogicalSize::new(new_w as f64, new_h as f64)));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// An area of the desktop in physical pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ScreenRect {
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+}
+
+impl ScreenRect {
+    fn of_monitor(monitor: &tauri::Monitor) -> Self {
+        ScreenRect {
+            x: monitor.position().x,
+            y: monitor.position().y,
+            width: monitor.size().width,
+            height: monitor.size().height,
+        }
+    }
+
+    /// Width and height of the part of `self` inside `other`
+    fn overlap(&self, other: &ScreenRect) -> (i64, i64) {
+        let span = |start: i32, len: u32, other_start: i32, other_len: u32| {
+            let end = (start as i64 + len as i64).min(other_start as i64 + other_len as i64);
+            (end - (start as i64).max(other_start as i64)).max(0)
+        };
+        (
+            span(self.x, self.width, other.x, other.width),
+            span(self.y, self.height, other.y, other.height),
+        )
+    }
+}
+
+/// How much of a window (in each direction) must be on some monitor for it
+/// to count as reachable; a thinner sliver can't be grabbed with the mouse
+const MIN_VISIBLE_WINDOW_PIXELS: i64 = 50;
+
+/// Whether no monitor shows enough of `window` to reach it
+fn is_off_screen(window: &ScreenRect, monitors: &[ScreenRect]) -> bool {
+    let needed_w = MIN_VISIBLE_WINDOW_PIXELS.min(window.width as i64);
+    let needed_h = MIN_VISIBLE_WINDOW_PIXELS.min(window.height as i64);
+    !monitors.iter().any(|monitor| {
+        let (w, h) = window.overlap(monitor);
+        w >= needed_w && h >= needed_h && w > 0 && h > 0
+    })
+}
+
+/// Top-left position that centres a window of the given size on `monitor`.
+/// A window bigger than the monitor is aligned to its top-left corner, so
+/// the title bar stays reachable.
+fn centered_position(width: u32, height: u32, monitor: &ScreenRect) -> (i32, i32) {
+    let offset = |len: u32, available: u32| (available.saturating_sub(len) / 2) as i32;
+    (monitor.x + offset(width, monitor.width), monitor.y + offset(height, monitor.height))
+}
+
+/// Centre `window` on the primary monitor if it's off screen (say, restored
+/// onto a monitor that has since been unplugged), or always with `force`.
+/// Returns whether it was moved.
+fn move_onto_screen<R: tauri::Runtime>(window: &tauri::Window<R>, force: bool) -> tauri::Result<bool> {
+    let monitors = window.available_monitors()?;
+    let Some(target) = window.primary_monitor()?.or_else(|| monitors.first().cloned()) else {
+        // Nothing to measure against (e.g. no display yet)
+        return Ok(false);
+    };
+
+    let position = window.outer_position()?;
+    let size = window.outer_size()?;
+    let rect = ScreenRect {
+        x: position.x,
+        y: position.y,
+        width: size.width,
+        height: size.height,
+    };
+    let screens: Vec<ScreenRect> = monitors.iter().map(ScreenRect::of_monitor).collect();
+    if !force && !is_off_screen(&rect, &screens) {
+        return Ok(false);
+    }
+
+    let (x, y) = centered_position(size.width, size.height, &ScreenRect::of_monitor(&target));
+    window.set_position(tauri::PhysicalPosition::new(x, y))?;
+    tracing::info!(window = window.label(), x, y, "moved window onto screen");
+    Ok(true)
+}
+
+/// Centre the calling window on the primary monitor, for when it has ended
+/// up somewhere it can't be reached
+#[tauri::command]
+#[tracing::instrument(skip(window), err)]
+fn reset_window_position(window: tauri::Window) -> Result<(), CommandError> {
+    move_onto_screen(&window, true)
+        .map(|_| ())
+        .map_err(|e| CommandError::Io(format!("Failed to move window: {}", e)))
+}
+
+/// Finalize and close a ZIP archive.
+/// Must be called after all files are added to produce a valid ZIP.
+#[tauri::command]
+#[tracing::instrument(skip(state), err)]
+fn zip_finalize(state: State<ZipState>, zip_id: String) -> Result<(), CommandError> {
+    let mut map = state.0.lock().map_err(|e| CommandError::Io(e.to_string()))?;
+    let writer_mutex = map.remove(&zip_id)
+        .ok_or_else(|| CommandError::NotFound(format!("ZIP archive not found: {}", zip_id)))?;
+
+    let writer = writer_mutex.into_inner().map_err(|e| CommandError::Io(e.to_string()))?;
+
+    writer.finish().map_err(|e| CommandError::Io(format!("Failed to finalize ZIP: {}", e)))?;
+
+    Ok(())
+}
+
+/// Result of export_zip
+#[derive(Serialize, Debug)]
+struct ZipExport {
+    path: String,
+    /// Files in the archive, documents and assets
+    included: usize,
+    /// Documents (or assets) left out, and why
+    skipped: Vec<ImportSkip>,
+}
+
+/// Progress of export_zip, emitted as "export-zip-progress" after each file
+#[derive(Serialize, Clone, Debug)]
+struct ZipExportProgress {
+    done: usize,
+    total: usize,
+}
+
+/// Bundle documents into one ZIP archive, keeping their folder layout below
+/// the deepest folder they all share. Thumbnails are embedded in the
+/// documents; with `include_assets` snapshot images stored as separate
+/// files in the document's folder are added too. Missing documents are
+/// skipped and reported rather than failing the export. The archive is
+/// written atomically, on a blocking thread.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle, paths), fields(count = paths.len()), err)]
+async fn export_zip(
+    app_handle: tauri::AppHandle,
+    paths: Vec<String>,
+    out_path: String,
+    include_assets: Option<bool>,
+) -> Result<ZipExport, CommandError> {
+    ensure_write_allowed(&app_handle, Path::new(&out_path))?;
+    let app = app_handle.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        write_document_zip(&paths, Path::new(&out_path), include_assets.unwrap_or(false), |done, total| {
+            let _ = app.emit("export-zip-progress", ZipExportProgress { done, total });
+        })
+    })
+    .await
+    .map_err(|e| CommandError::Io(format!("ZIP export failed: {}", e)))?
+}
+
+/// The deepest folder containing every one of `paths`
+fn common_folder(paths: &[std::path::PathBuf]) -> std::path::PathBuf {
+    let mut folders = paths.iter().filter_map(|p| p.parent());
+    let Some(first) = folders.next() else {
+        return std::path::PathBuf::new();
+    };
+    let mut common: Vec<_> = first.components().collect();
+    for folder in folders {
+        let shared = common.iter().zip(folder.components()).take_while(|(a, b)| **a == *b).count();
+        common.truncate(shared);
+    }
+    common.iter().collect()
+}
+
+/// Whether a file's leading bytes are an image format we recognise
+fn is_image_file(path: &Path) -> bool {
+    image::ImageReader::open(path)
+        .and_then(|reader| reader.with_guessed_format())
+        .is_ok_and(|reader| reader.format().is_some())
+}
+
+/// Snapshot images of a document that are stored as files in its folder.
+/// References that are missing, outside the folder or not images come back
+/// as errors with the reason, so a crafted document can't pull other files
+/// into an archive.
+fn document_assets(path: &Path) -> Vec<Result<std::path::PathBuf, (std::path::PathBuf, &'static str)>> {
+    let Ok(text) = read_document_text(path) else {
+        return Vec::new();
+    };
+    let Ok(json) = serde_json::from_str::<serde_json::Value>(&text) else {
+        return Vec::new();
+    };
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+    json["snapshots"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|snapshot| snapshot["image"].as_str())
+        .filter(|source| !source.is_empty() && !source.starts_with("data:"))
+        .map(|source| match document_image_path(source, base_dir) {
+            Some(asset) if is_image_file(&asset) => Ok(asset),
+            Some(asset) => Err((asset, "Asset is not an image")),
+            None => {
+                let asset = snapshot_image_path(source, base_dir);
+                let reason =
+                    if asset.exists() { "Asset is outside the document folder" } else { "Asset does not exist" };
+                Err((asset, reason))
+            }
+        })
+        .collect()
+}
+
+fn write_document_zip<F>(
+    paths: &[String],
+    out_path: &Path,
+    include_assets: bool,
+    mut progress: F,
+) -> Result<ZipExport, CommandError>
+where
+    F: FnMut(usize, usize),
+{
+    let mut skipped = Vec::new();
+    let skip = |path: &Path, reason: &str| ImportSkip {
+        path: path.to_string_lossy().to_string(),
+        reason: reason.to_string(),
+    };
+
+    let mut files: Vec<std::path::PathBuf> = Vec::new();
+    let mut seen = std::collections::HashSet::new();
+    for path in paths.iter().map(Path::new) {
+        let resolved = match dunce::canonicalize(path) {
+            Ok(resolved) if resolved.is_file() => resolved,
+            Ok(_) => {
+                skipped.push(skip(path, "Not a file"));
+                continue;
+            }
+            Err(_) => {
+                skipped.push(skip(path, "File does not exist"));
+                continue;
+            }
+        };
+        if !seen.insert(resolved.clone()) {
+            continue;
+        }
+        let assets = if include_assets { document_assets(&resolved) } else { Vec::new() };
+        files.push(resolved);
+        for asset in assets {
+            match asset {
+                Ok(asset) => {
+                    if seen.insert(asset.clone()) {
+                        files.push(asset);
+                    }
+                }
+                Err((asset, reason)) => skipped.push(skip(&asset, reason)),
+            }
+        }
+    }
+
+    if files.is_empty() {
+        return Err(CommandError::Validation("None of the documents exist".to_string()));
+    }
+
+    let root = common_folder(&files);
+    let total = files.len();
+    write_atomic(out_path, |w| {
+        let mut zip = ZipWriter::new(w);
+        let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        for (index, file) in files.iter().enumerate() {
+            let relative = file.strip_prefix(&root).unwrap_or(file);
+            // Only the names count; a prefix or root is left when the files
+            // share no folder (e.g. on different drives)
+            let name = relative
+                .components()
+                .filter_map(|c| match c {
+                    std::path::Component::Normal(part) => Some(part.to_string_lossy()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join("/");
+            zip.start_file(name, options).map_err(std::io::Error::other)?;
+            std::io::copy(&mut fs::File::open(file)?, &mut zip)?;
+            progress(index + 1, total);
+        }
+        zip.finish().map_err(std::io::Error::other)?;
+        Ok(())
+    })?;
+
+    tracing::info!(included = total, skipped = skipped.len(), "zip export finished");
+    Ok(ZipExport {
+        path: out_path.to_string_lossy().to_string(),
+        included: total,
+        skipped,
+    })
+}
+
+// ============================================================================
+// Logging
+// ============================================================================
+//
+// Commands log through `tracing` to daily-rotated files in:
+//   Linux:   ~/.config/ssce-desktop/logs/
+//   Windows: %APPDATA%/ssce-desktop/logs/
+//
+// Every command is wrapped in a span (#[tracing::instrument]) carrying its
+// name and arguments, so any error it returns is logged with that context.
+// Large payloads (image data, file contents) are skipped.
+//
+// The level comes from `logLevel` in defaults.json ("error", "warn", "info",
+// "debug", "trace"). At "debug" or more verbose, every command call is logged,
+// not just failures.
+//
+// ============================================================================
+
+/// Number of daily log files to keep before the oldest is deleted
+const LOG_FILES_RETAINED: usize = 7;
+
+/// Keeps the background log writer alive for the lifetime of the app.
+/// Dropping the guard flushes any buffered log lines.
+struct LogGuard(#[allow(dead_code)] WorkerGuard);
+
+/// Directory where log files are written
+fn get_log_dir() -> Result<std::path::PathBuf, CommandError> {
+    Ok(get_user_config_dir()?.join("logs"))
+}
+
+/// Set up the rolling file logger at the given level.
+/// Returns None if the log directory can't be created; the app still runs,
+/// just without file logging.
+fn init_logging(level: &str) -> Option<WorkerGuard> {
+    let log_dir = get_log_dir().ok()?;
+    fs::create_dir_all(&log_dir).ok()?;
+
+    let appender = tracing_appender::rolling::Builder::new()
+        .rotation(Rotation::DAILY)
+        .filename_prefix("ssce-desktop")
+        .filename_suffix("log")
+        .max_log_files(LOG_FILES_RETAINED)
+        .build(&log_dir)
+        .ok()?;
+
+    let (writer, guard) = tracing_appender::non_blocking(appender);
+    let level: LevelFilter = level.parse().unwrap_or(LevelFilter::INFO);
+
+    // Log every command call at debug/trace, otherwise only events (errors etc.)
+    let span_events = if level >= LevelFilter::DEBUG {
+        FmtSpan::NEW
+    } else {
+        FmtSpan::NONE
+    };
+
+    tracing_subscriber::fmt()
+        .with_writer(writer)
+        .with_ansi(false)
+        .with_max_level(level)
+        .with_span_events(span_events)
+        .try_init()
+        .ok()?;
+
+    Some(guard)
+}
+
+/// Get the directory containing the log files (for an "Open logs" action)
+#[tauri::command]
+#[tracing::instrument(err)]
+fn get_log_path() -> Result<String, CommandError> {
+    get_log_dir().map(|p| p.to_string_lossy().to_string())
+}
+
+// ============================================================================
+// Document Windows
+// ============================================================================
+//
+// Besides the "main" window, documents can be opened in windows of their
+// own for side-by-side comparison. Each gets the label "doc-<hash of its
+// path>", so reopening a document reuses its window and the window-state
+// plugin restores its size and position per document.
+//
+// Closing the main window hides it to the tray; closing a document window
+// really closes it (after its state has been saved).
+//
+// ============================================================================
+
+/// Label of the main window (from tauri.conf.json)
+const MAIN_WINDOW_LABEL: &str = "main";
+
+/// Document path for each open document window, by label. The window asks
+/// for its document through frontend_ready once it has loaded.
+struct DocumentWindows(Mutex<HashMap<String, String>>);
+
+/// Stable window label for a document
+fn document_window_label(path: &str) -> String {
+    use sha2::{Digest, Sha256};
+
+    let digest = Sha256::digest(normalize_path(path).as_bytes());
+    let hex: String = digest.iter().take(8).map(|b| format!("{:02x}", b)).collect();
+    format!("doc-{}", hex)
+}
+
+/// Open a document in a window of its own, or bring its window forward if
+/// it's already open. Returns the window label.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle, windows), err)]
+async fn open_document_window(
+    app_handle: tauri::AppHandle,
+    windows: State<'_, DocumentWindows>,
+    path: String,
+) -> Result<String, CommandError> {
+    if !Path::new(&path).is_file() {
+        return Err(CommandError::NotFound(format!("File does not exist: {}", path)));
+    }
+
+    let label = document_window_label(&path);
+
+    if let Some(window) = app_handle.get_webview_window(&label) {
+        let _ = window.show();
+        let _ = window.unminimize();
+        let _ = window.set_focus();
+        return Ok(label);
+    }
+
+    windows.0.lock().map_err(|e| CommandError::Io(e.to_string()))?.insert(label.clone(), path.clone());
+
+    let filename = Path::new(&path)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let built = tauri::WebviewWindowBuilder::new(&app_handle, &label, tauri::WebviewUrl::App("index.html".into()))
+        .title(format!("{} - SSCE Desktop", filename))
+        .inner_size(1200.0, 800.0)
+        .min_inner_size(800.0, 600.0)
+        .build();
+
+    if let Err(e) = built {
+        if let Ok(mut map) = windows.0.lock() {
+            map.remove(&label);
+        }
+        return Err(CommandError::Io(format!("Failed to open window: {}", e)));
+    }
+
+    Ok(label)
+}
+
+/// Show every window (including ones hidden to the tray) and focus the
+/// main one
+fn show_all_windows(app: &tauri::AppHandle) {
+    for window in app.webview_windows().values() {
+        let _ = window.show();
+        let _ = window.unminimize();
+    }
+
+    if let Some(window) = app.get_webview_window(MAIN_WINDOW_LABEL) {
+        let _ = window.set_focus();
+    }
+}
+
+// ============================================================================
+// Global Hotkey
+// ============================================================================
+//
+// A system-wide shortcut that brings the app back from the tray.
+// Pressing it shows and focuses the main window, or hides it to the tray
+// if it's already focused. The binding comes from `globalHotkey` in
+// defaults.json and can be changed at runtime with set_global_hotkey.
+//
+// Bindings use the plugin's format, e.g. "Ctrl+Alt+S", "CmdOrCtrl+Shift+F12".
+//
+// ============================================================================
+
+/// Hotkey used when defaults.json doesn't set one.
+/// (Ctrl+Shift+S is taken by Save As inside the editor.)
+const DEFAULT_GLOBAL_HOTKEY: &str = "Ctrl+Alt+S";
+
+/// Currently registered global hotkey binding (None if registration failed)
+struct HotkeyState(Mutex<Option<String>>);
+
+/// Whether closing the main window hides it to the tray (true, the default)
+/// or quits the app. Read from `closeToTray` at startup; set_close_to_tray
+/// changes it at runtime.
+struct CloseToTray(AtomicBool);
+
+/// Choose whether the main window's close button hides to the tray or quits
+#[tauri::command]
+#[tracing::instrument(skip(state))]
+fn set_close_to_tray(state: State<CloseToTray>, enabled: bool) {
+    state.0.store(enabled, Ordering::SeqCst);
+}
+
+/// The system tray icon, if one could be created, and whether it's showing.
+/// Shown or hidden at startup from `showTrayIcon`; set_tray_visible changes
+/// it at runtime.
+struct SystemTray {
+    icon: Option<TrayIcon>,
+    status: Mutex<TrayStatus>,
+}
+
+/// Whether the app has a tray icon, for get_tray_status
+#[derive(Serialize, Debug, Clone, PartialEq)]
+struct TrayStatus {
+    /// False if the tray icon couldn't be created (e.g. no tray on a
+    /// minimal Linux desktop)
+    available: bool,
+    visible: bool,
+    /// Why the tray icon couldn't be created
+    error: Option<String>,
+}
+
+impl TrayStatus {
+    /// Status after trying to create the tray icon, which is shown if
+    /// `show` says so
+    fn after_build(built: Result<(), String>, show: bool) -> Self {
+        match built {
+            Ok(()) => TrayStatus {
+                available: true,
+                visible: show,
+                error: None,
+            },
+            Err(error) => TrayStatus {
+                available: false,
+                visible: false,
+                error: Some(error),
+            },
+        }
+    }
+
+    /// Whether closing the main window should hide it. Without a visible
+    /// tray icon there'd be no way back to a hidden window, so closing quits.
+    fn hides_on_close(&self, close_to_tray: bool) -> bool {
+        close_to_tray && self.available && self.visible
+    }
+}
+
+/// Whether closing the main window should hide it (see TrayStatus::hides_on_close)
+fn hides_to_tray(app: &tauri::AppHandle) -> bool {
+    let close_to_tray = app.state::<CloseToTray>().0.load(Ordering::SeqCst);
+    app.try_state::<SystemTray>()
+        .and_then(|tray| tray.status.lock().ok().map(|status| status.hides_on_close(close_to_tray)))
+        .unwrap_or(false)
+}
+
+/// Show or hide the system tray icon. While it's hidden, closing the main
+/// window quits instead of hiding to the tray. Fails if there is no tray icon.
+#[tauri::command]
+#[tracing::instrument(skip(state), err)]
+fn set_tray_visible(state: State<SystemTray>, visible: bool) -> Result<(), CommandError> {
+    let icon = state
+        .icon
+        .as_ref()
+        .ok_or_else(|| CommandError::NotFound("The system tray isn't available".to_string()))?;
+    icon.set_visible(visible)
+        .map_err(|e| CommandError::Io(format!("Failed to change tray icon: {}", e)))?;
+    state.status.lock().map_err(|e| CommandError::Io(e.to_string()))?.visible = visible;
+    Ok(())
+}
+
+/// Whether the system tray icon is showing
+#[tauri::command]
+#[tracing::instrument(skip(state))]
+fn is_tray_visible(state: State<SystemTray>) -> bool {
+    state.status.lock().is_ok_and(|status| status.visible)
+}
+
+/// Whether the tray icon exists and is showing, and if it couldn't be
+/// created, why
+#[tauri::command]
+#[tracing::instrument(skip(state), err)]
+fn get_tray_status(state: State<SystemTray>) -> Result<TrayStatus, CommandError> {
+    Ok(state.status.lock().map_err(|e| CommandError::Io(e.to_string()))?.clone())
+}
+
+/// Create the tray icon with its Show/Quit menu
+fn build_tray(app: &tauri::App) -> tauri::Result<TrayIcon> {
+    let show_item = MenuItem::with_id(app, "show", "Show SSCE", true, None::<&str>)?;
+    let quit_item = MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?;
+    let menu = Menu::with_items(app, &[&show_item, &quit_item])?;
+
+    // Load tray icon
+    let icon = Image::from_path("icons/tray-icon.png")
+        .or_else(|_| Image::from_bytes(include_bytes!("../icons/tray-icon.png")))?;
+
+    TrayIconBuilder::with_id(TRAY_ID)
+        .icon(icon)
+        .menu(&menu)
+        .tooltip("SSCE Desktop")
+        .on_menu_event(|app, event| match event.id.as_ref() {
+            "show" => show_all_windows(app),
+            "quit" => app.exit(0),
+            _ => {}
+        })
+        .on_tray_icon_event(|tray, event| {
+            if let TrayIconEvent::Click {
+                button: MouseButton::Left,
+                button_state: MouseButtonState::Up,
+                ..
+            } = event
+            {
+                show_all_windows(tray.app_handle());
+            }
+        })
+        .build(app)
+}
+
+/// Show and focus the main window, or hide it to the tray if it already has focus
+fn toggle_main_window(app: &tauri::AppHandle) {
+    if let Some(window) = app.get_webview_window(MAIN_WINDOW_LABEL) {
+        let visible = window.is_visible().unwrap_or(false);
+        let focused = window.is_focused().unwrap_or(false);
+
+        if visible && focused {
+            let _ = app.save_window_state(StateFlags::all());
+            let _ = window.hide();
+        } else {
+            let _ = window.show();
+            let _ = window.unminimize();
+            // A monitor may have been unplugged while it was hidden
+            let _ = move_onto_screen(&window.as_ref().window(), false);
+            let _ = window.set_focus();
+        }
+    }
+}
+
+/// Register `binding` as the global hotkey, replacing any previous one.
+/// Fails if the binding can't be parsed or another app already owns it.
+fn register_global_hotkey(app: &tauri::AppHandle, binding: &str) -> Result<(), String> {
+    let shortcuts = app.global_shortcut();
+
+    shortcuts
+        .unregister_all()
+        .map_err(|e| format!("Failed to unregister hotkey: {}", e))?;
+
+    shortcuts
+        .register(binding)
+        .map_err(|e| format!("Failed to register hotkey '{}': {}", binding, e))
+}
+
+/// Change the global show/hide hotkey at runtime.
+/// If the new binding can't be registered, the previous one is restored.
+#[tauri::command]
+#[tracing::instrument(skip(app_handle, state), err)]
+fn set_global_hotkey(app_handle: tauri::AppHandle, state: State<HotkeyState>, binding: String) -> Result<(), CommandError> {
+    let mut current = state.0.lock().map_err(|e| CommandError::Io(e.to_string()))?;
+
+    if let Err(e) = register_global_hotkey(&app_handle, &binding) {
+        // Put the old binding back so the user isn't left without a hotkey
+        if let Some(previous) = current.as_deref() {
+            let _ = register_global_hotkey(&app_handle, previous);
+        }
+        return Err(CommandError::Validation(e));
+    }
+
+    *current = Some(binding);
+    Ok(())
+}
+
+// ============================================================================
+// Shutdown
+// ============================================================================
+//
+// Every way out of the app - the tray's Quit, app.exit(), or SIGTERM/SIGINT
+// from the OS - ends in RunEvent::Exit, which runs shutdown() once before
+// the process exits.
+//
+// ============================================================================
+
+/// Id of the system tray icon, used to remove it on exit
+const TRAY_ID: &str = "main";
+
+/// Set once shutdown has run, so a second exit path doesn't repeat it
+static SHUT_DOWN: AtomicBool = AtomicBool::new(false);
+
+/// Checkpoint the database, release document locks and remove the tray
+/// icon (some Linux desktops leave a dead icon behind otherwise)
+fn shutdown(app: &tauri::AppHandle) {
+    if SHUT_DOWN.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    tracing::info!("Shutting down");
+
+    if let Some(checkpointer) = app.try_state::<WalCheckpointer>() {
+        checkpointer.stop();
+    }
+    if let Err(e) = app.state::<DbState>().with_conn(|conn| checkpoint_wal(conn)) {
+        tracing::warn!("{}", e);
+    }
+
+    let held = app
+        .state::<HeldLocks>()
+        .0
+        .lock()
+        .map(|mut held| std::mem::take(&mut *held))
+        .unwrap_or_default();
+    for path in held {
+        if let Err(e) = release_lock(&path, std::process::id()) {
+            tracing::warn!("{}", e);
+        }
+    }
+
+    let _ = app.remove_tray_by_id(TRAY_ID);
+}
+
+/// Turn SIGTERM, SIGINT and SIGHUP into a normal app exit so they get the
+/// same cleanup as Quit
+#[cfg(unix)]
+fn exit_on_terminate_signal(app: tauri::AppHandle) -> Result<(), String> {
+    use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+
+    let mut signals = signal_hook::iterator::Signals::new([SIGTERM, SIGINT, SIGHUP])
+        .map_err(|e| format!("Failed to install signal handler: {}", e))?;
+
+    std::thread::spawn(move || {
+        if let Some(signal) = signals.forever().next() {
+            tracing::info!(signal, "Received terminate signal");
+            app.exit(0);
+        }
+    });
+    Ok(())
+}
+
+// ============================================================================
+// Application Entry Point
+// ============================================================================
+//
+// main() sets up the Tauri application:
+// 1. Initializes the SQLite database
+// 2. Registers plugins (dialogs, clipboard, window state)
+// 3. Sets up the system tray with menu
+// 4. Registers all command handlers
+// 5. Starts the application event loop
+//
+// ============================================================================
+
+fn main() {
+    // Initialize the SQLite database for the file library (reported to the
+    // frontend from setup, once logging and the window exist)
+    let (db, db_status) = open_startup_database(&database_path());
+
+    // .ssce documents passed on the command line (file association / double-click)
+    let args: Vec<String> = std::env::args().collect();
+    let cwd = std::env::current_dir().unwrap_or_default();
+    let startup_files: Vec<String> = collect_file_args(&args, &cwd)
+        .into_iter()
+        .filter(|path| is_ssce_path(path))
+        .collect();
+
+    tauri::Builder::default()
+        // Single-instance must be registered first so a second launch exits
+        // before any other plugin (window state, tray) touches shared state.
+        // On Linux the lock is a D-Bus name owned by the running instance,
+        // released automatically when the app exits.
+        .plugin(tauri_plugin_single_instance::init(|app, args, cwd| {
+            // Second instance launched - show existing window
+            if let Some(window) = app.get_webview_window(MAIN_WINDOW_LABEL) {
+                let _ = window.show();
+                let _ = window.unminimize();
+                let _ = window.set_focus();
+            }
+
+            // Forward every file the second instance was launched with
+            for file_path in collect_file_args(&args, Path::new(&cwd)) {
+                let _ = app.emit_to(MAIN_WINDOW_LABEL, "open-file", file_path);
+            }
+        }))
+        // Make the database connection available to all commands via State<DbState>
+        .manage(DbState::new(db))
+        .manage(ConfigCache::default())
+        .manage(ThumbnailPool::default())
+        .manage(ZipState(Mutex::new(HashMap::new())))
+        .manage(RebuildCancel(AtomicBool::new(false)))
+        .manage(RebuildRunning(AtomicBool::new(false)))
+        .manage(ThumbnailRegenerationSlot(OperationSlot::new("Thumbnail regeneration")))
+        .manage(DiskUsageSlot(OperationSlot::new("A disk usage count")))
+        .manage(LibraryBackupSlot(OperationSlot::new("A library backup")))
+        .manage(SuggestionVocab(Mutex::new(None)))
+        .manage(HotkeyState(Mutex::new(None)))
+        .manage(StartupFiles(Mutex::new(startup_files)))
+        .manage(StartupEvents(Mutex::new(Vec::new())))
+        .manage(WriteGrants(Mutex::new(Vec::new())))
+        .manage(SessionPending(AtomicBool::new(true)))
+        .manage(DocumentWindows(Mutex::new(HashMap::new())))
+        .manage(DocumentWatchers(Mutex::new(HashMap::new())))
+        .manage(HeldLocks(Mutex::new(std::collections::HashSet::new())))
+        .plugin(tauri_plugin_shell::init())
+        .plugin(tauri_plugin_dialog::init())
+        .plugin(tauri_plugin_fs::init())
+        .plugin(tauri_plugin_clipboard_manager::init())
+        .plugin(tauri_plugin_notification::init())
+        .plugin(tauri_plugin_window_state::Builder::new().build())
+        .plugin(
+            tauri_plugin_global_shortcut::Builder::new()
+                .with_handler(|app, _shortcut, event| {
+                    // Only one shortcut is ever registered, so any press is the show/hide hotkey
+                    if event.state() == ShortcutState::Pressed {
+                        toggle_main_window(app);
+                    }
+                })
+                .build(),
+        )
+        .setup(|app| {
+            // Start file logging at the configured level
+            let defaults = load_defaults_typed(app.handle());
+            if let Some(guard) = init_logging(&defaults.log_level) {
+                app.manage(LogGuard(guard));
+            }
+            tracing::info!(version = env!("CARGO_PKG_VERSION"), "SSCE Desktop starting");
+
+            let startup = app.state::<StartupEvents>();
+            let db_ready = db_status.is_ok();
+            match db_status {
+                Ok(ready) => {
+                    tracing::info!(
+                        schema_version = ready.schema_version,
+                        migrated = ready.migrated,
+                        "Library database ready"
+                    );
+                    startup.emit(app.handle(), "backend-ready", ready);
+                }
+                Err(error) => {
+                    tracing::error!(
+                        path = %error.database_path,
+                        "Failed to open the library database: {}",
+                        error.message
+                    );
+                    startup.emit(app.handle(), "backend-error", error);
+                }
+            }
+
+            app.manage(ThumbnailCache::from_config(&defaults));
+            app.manage(start_index_worker(app.handle().clone()));
+
+            // Switch the search index to the configured tokenizer (a rebuild,
+            // so only when it has changed)
+            let tokenizer = FtsTokenizer::from_config(&defaults);
+            let index_rebuilt = match app
+                .state::<DbState>()
+                .with_conn(|conn| apply_fts_tokenizer(conn, &tokenizer).map_err(CommandError::from))
+            {
+                Ok(true) => {
+                    app.state::<DbState>().forget_schema();
+                    tracing::info!(tokenizer = %tokenizer.spec(), "Rebuilt search index for new tokenizer");
+                    true
+                }
+                Ok(false) => false,
+                Err(e) => {
+                    tracing::warn!("Failed to apply search tokenizer: {}", e);
+                    false
+                }
+            };
+            if db_ready {
+                startup.emit(app.handle(), "library-ready", LibraryReady { index_rebuilt });
+            }
+
+            #[cfg(unix)]
+            if let Err(e) = exit_on_terminate_signal(app.handle().clone()) {
+                tracing::warn!("{}", e);
+            }
+
+            // Hot-reload hand edits to defaults.json (non-fatal if watching fails)
+            if let Err(e) = watch_user_config(app.handle().clone()) {
+                tracing::warn!("{}", e);
+            }
+
+            app.manage(WalCheckpointer::start(
+                app.handle().clone(),
+                std::time::Duration::from_secs(defaults.library.checkpoint_interval_seconds),
+            ));
+            app.manage(CloseToTray(AtomicBool::new(defaults.close_to_tray)));
+
+            // Register the global show/hide hotkey (non-fatal if the binding is taken)
+            let hotkey = defaults.global_hotkey.clone();
+            match register_global_hotkey(app.handle(), &hotkey) {
+                Ok(()) => {
+                    if let Ok(mut current) = app.state::<HotkeyState>().0.lock() {
+                        *current = Some(hotkey);
+                    }
+                }
+                Err(e) => tracing::warn!("{}", e),
+            }
+
+            // Set window icon
+            if let Some(window) = app.get_webview_window(MAIN_WINDOW_LABEL) {
+                let window_icon = Image::from_bytes(include_bytes!("../icons/128x128.png"))
+                    .expect("Failed to load window icon");
+                let _ = window.set_icon(window_icon);
+
+                // The window-state plugin may have restored it onto a
+                // monitor that is no longer connected
+                if let Err(e) = move_onto_screen(&window.as_ref().window(), false) {
+                    tracing::warn!("Failed to check the window position: {}", e);
+                }
+            }
+
+            // Build the system tray. Some minimal Linux desktops have none;
+            // the app then runs without it and closing the window quits.
+            let tray = build_tray(app).and_then(|tray| {
+                if !defaults.show_tray_icon {
+                    tray.set_visible(false)?;
+                }
+                Ok(tray)
+            });
+            if let Err(e) = &tray {
+                tracing::warn!("System tray unavailable, close-to-tray disabled: {}", e);
+            }
+            let status = TrayStatus::after_build(
+                tray.as_ref().map(|_| ()).map_err(|e| e.to_string()),
+                defaults.show_tray_icon,
+            );
+            app.manage(SystemTray {
+                icon: tray.ok(),
+                status: Mutex::new(status),
+            });
+
+            Ok(())
+        })
+        .on_window_event(|window, event| match event {
+            tauri::WindowEvent::CloseRequested { api, .. } => {
+                // Save window state before hiding or closing
+                let _ = window.app_handle().save_window_state(StateFlags::all());
+
+                // The main window minimizes to tray (unless closeToTray is off
+                // or the tray icon is hidden, then closing it quits); document
+                // windows close
+                if window.label() == MAIN_WINDOW_LABEL {
+                    api.prevent_close();
+                    if hides_to_tray(window.app_handle()) {
+                        let _ = window.hide();
+                    } else {
+                        window.app_handle().exit(0);
+                    }
+                }
+            }
+            tauri::WindowEvent::Destroyed => {
+                if let Ok(mut map) = window.state::<DocumentWindows>().0.lock() {
+                    map.remove(window.label());
+                }
+            }
+            _ => {}
+        })
+        .invoke_handler(tauri::generate_handler![
+            browse_directory,
+            browse_directory_stream,
+            get_last_browse_state,
+            db_recent_folders,
+            db_clear_recent_folders,
+            load_image,
+            save_image,
+            save_clipboard_image,
+            load_ssce,
+            load_ssce_header,
+            save_ssce,
+            file_writable,
+            save_ssce_encrypted,
+            load_ssce_encrypted,
+            show_open_dialog,
+            show_save_dialog,
+            show_folder_dialog,
+            load_thumbnail,
+            load_thumbnails_batch,
+            generate_ssce_thumbnail,
+            regenerate_thumbnails,
+            cancel_regenerate_thumbnails,
+            clear_thumbnail_cache,
+            thumbnail_cache_stats,
+            save_text_file,
+            get_ssce_thumbnail,
+            get_ssce_version,
+            get_ssce_stats,
+            migrate_ssce,
+            get_ssce_thumbnail_cached,
+            get_ssce_metadata,
+            db_verify_snapshot_counts,
+            file_exists,
+            file_exists_timeout,
+            get_parent_directory,
+            file_fingerprint,
+            watch_document,
+            unwatch_document,
+            save_autosave,
+            delete_autosave,
             list_autosave_files,
+            compact_autosaves,
+            list_autosaves_for,
+            check_recovery,
             get_home_dir,
             get_downloads_dir,
             get_env_config,
             get_defaults_config,
+            get_active_config_source,
+            get_defaults_typed,
             save_defaults_config,
+            list_config_backups,
+            restore_config_backup,
+            validate_defaults_config,
+            get_setting,
+            set_setting,
             get_user_config_path,
             open_in_default_app,
+            open_and_register,
+            open_path_with,
+            open_config_dir,
+            get_config_dir_path,
             db_upsert_file,
             db_get_recent_files,
+            db_get_recently_modified,
             db_search_files,
+            db_search_suggestions,
+            db_recent_searches,
+            db_clear_search_history,
             db_remove_file,
             db_update_last_opened,
+            db_most_used_files,
+            db_update_metadata,
+            db_rename_keyword,
+            db_keyword_cooccurrence,
+            db_set_tag_color,
+            db_get_tag_colors,
+            db_create_collection,
+            db_add_to_collection,
+            db_remove_from_collection,
+            db_list_collections,
+            db_collection_files,
+            import_document,
+            import_folder,
+            now_iso,
+            format_timestamp,
+            library_disk_usage,
+            cancel_library_disk_usage,
+            backup_library,
+            cancel_backup_library,
+            resolve_autosave_dir,
+            decode_path,
+            find_orphaned_sidecars,
+            clean_orphaned_sidecars,
+            queue_index,
+            index_queue_status,
+            pause_library_watch,
+            resume_library_watch,
+            db_bulk_tag,
+            normalize_keywords,
             db_rebuild_from_library,
+            db_prune_missing,
+            cancel_rebuild,
+            db_find_duplicates,
+            db_library_size,
+            db_check_integrity,
+            db_rebuild_index,
+            db_reindex_fts,
+            db_optimize_fts,
+            db_vacuum,
+            force_checkpoint,
+            db_reconnect,
+            export_database,
+            import_database,
+            merge_database,
+            db_stats,
+            database_health,
+            export_library,
+            move_file,
+            move_files,
+            copy_file,
+            create_directory,
+            remove_directory,
+            acquire_file_lock,
+            release_file_lock,
+            trash_file,
+            trash_file_permanent,
             list_ssce_files,
             get_monthly_summary,
             save_exported_image,
+            export_html,
+            export_pdf,
+            print_document,
             zip_create,
             zip_add_file,
             zip_add_path,
             zip_finalize,
+            export_zip,
             clamp_window_size,
+            reset_window_position,
             get_cli_file_arg,
+            frontend_ready,
+            save_session,
+            get_session,
+            open_document_window,
             get_system_info,
+            get_log_path,
+            set_global_hotkey,
+            set_close_to_tray,
+            set_tray_visible,
+            is_tray_visible,
+            get_tray_status,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            if let tauri::RunEvent::Exit = event {
+                shutdown(app);
+            }
+        });
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// search_files_ranked with the default rank weights
+    fn search_files<Tz: chrono::TimeZone>(
+        conn: &Connection,
+        params: SearchParams,
+        tz: &Tz,
+    ) -> Result<Vec<LibraryFile>, CommandError> {
+        search_files_ranked(conn, params, &RankWeights::default(), fts_folds_accents(conn)?, tz)
+    }
+
+    #[test]
+    fn deep_merge_merges_nested_objects() {
+        let mut base = json!({
+            "tools": { "arrow": { "colour": "#FF0000", "lineWidth": 2 } },
+            "canvas": { "background": "#CCCCCC" }
+        });
+        let user = json!({ "tools": { "arrow": { "colour": "#00FF00" } } });
+
+        deep_merge(&mut base, user);
+
+        assert_eq!(base["tools"]["arrow"]["colour"], "#00FF00");
+        assert_eq!(base["tools"]["arrow"]["lineWidth"], 2);
+        assert_eq!(base["canvas"]["background"], "#CCCCCC");
+    }
+
+    #[test]
+    fn deep_merge_overrides_arrays_and_scalars() {
+        let mut base = json!({
+            "palette": { "colours": ["#FFFFFF", "#000000", "#FF0000"] },
+            "textLineHeight": 1.2
+        });
+        let user = json!({
+            "palette": { "colours": ["#123456"] },
+            "textLineHeight": 1.5,
+            "newKey": true
+        });
+
+        deep_merge(&mut base, user);
+
+        assert_eq!(base["palette"]["colours"], json!(["#123456"]));
+        assert_eq!(base["textLineHeight"], 1.5);
+        assert_eq!(base["newKey"], true);
+    }
+
+    #[test]
+    fn bundled_defaults_pass_validation() {
+        let config: serde_json::Value =
+            serde_json::from_str(include_str!("../../src/config/defaults.json")).unwrap();
+        assert!(validate_defaults(&config).is_empty());
+    }
+
+    #[test]
+    fn defaults_config_reports_its_source() {
+        let dir = test_dir("config-source");
+        let dev = dir.join("dev/defaults.json");
+        let bundled = dir.join("resources/config/defaults.json");
+        let system = dir.join("usr/lib/defaults.json");
+        let user = dir.join("user/defaults.json");
+        let candidates = vec![
+            (ConfigSource::Dev, dev.clone()),
+            (ConfigSource::Bundled, bundled.clone()),
+            (ConfigSource::LinuxSystem, system.clone()),
+        ];
+        let write = |path: &Path, json: &str| {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, json).unwrap();
+        };
+
+        assert!(matches!(load_defaults_config(&candidates, Some(&user)), Err(CommandError::NotFound(_))));
+
+        // Each bundled location, lowest priority first
+        let expected = [
+            (&system, ConfigSource::LinuxSystem),
+            (&bundled, ConfigSource::Bundled),
+            (&dev, ConfigSource::Dev),
+        ];
+        for (path, source) in expected {
+            write(path, r#"{"logLevel": "info", "paths": {"library": "~/lib"}}"#);
+            let loaded = load_defaults_config(&candidates, Some(&user)).unwrap();
+            assert_eq!(loaded.source, source);
+            assert_eq!(loaded.path, path.to_string_lossy());
+        }
+
+        // The user's file wins, merged over the bundled one
+        write(&user, r#"{"logLevel": "debug"}"#);
+        let loaded = load_defaults_config(&candidates, Some(&user)).unwrap();
+        assert_eq!(loaded.source, ConfigSource::User);
+        assert_eq!(loaded.path, user.to_string_lossy());
+        let config: serde_json::Value = serde_json::from_str(&loaded.config).unwrap();
+        assert_eq!(config["logLevel"], "debug");
+        assert!(!config["paths"]["library"].as_str().unwrap().starts_with('~'));
+
+        // Only a user file
+        let loaded = load_defaults_config(&[], Some(&user)).unwrap();
+        assert_eq!(loaded.source, ConfigSource::User);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn typed_defaults_fill_in_missing_keys() {
+        let bundled: serde_json::Value =
+            serde_json::from_str(include_str!("../../src/config/defaults.json")).unwrap();
+        let typed = typed_defaults(bundled).unwrap();
+        // Defaults::default() matches the bundled file
+        let expected = Defaults { extra: typed.extra.clone(), ..Defaults::default() };
+        assert_eq!(typed, expected);
+        assert!(typed.extra.contains_key("tools"));
+        assert!(!typed.extra.contains_key("paths"));
+        assert!(!typed.extra.contains_key("theme"));
+
+        let partial = typed_defaults(json!({
+            "autosave": { "enabled": false, "futureOption": 1 },
+            "closeToTray": false,
+            "futureFeature": { "enabled": true }
+        }))
+        .unwrap();
+        assert!(!partial.autosave.enabled);
+        assert_eq!(partial.autosave.inactivity_seconds, 30);
+        assert_eq!(partial.autosave.extra["futureOption"], 1);
+        assert!(!partial.close_to_tray);
+        assert!(partial.show_tray_icon);
+        assert_eq!(partial.global_hotkey, DEFAULT_GLOBAL_HOTKEY);
+        assert_eq!(partial.library.ignore_patterns, vec!["*.bak"]);
+        assert_eq!(partial.theme, "system");
+        assert_eq!(partial.extra["futureFeature"], json!({ "enabled": true }));
+
+        // Unknown keys survive a round trip
+        let round_trip = serde_json::to_value(&partial).unwrap();
+        assert_eq!(round_trip["futureFeature"]["enabled"], true);
+        assert_eq!(round_trip["autosave"]["futureOption"], 1);
+        assert_eq!(round_trip["closeToTray"], false);
+
+        assert!(typed_defaults(json!({ "recentFiles": { "maxCount": -1 } })).is_err());
+    }
+
+    #[test]
+    fn validate_defaults_reports_type_errors() {
+        let config = json!({
+            "paths": { "defaultImageLoad": 42 },
+            "autosave": { "inactivitySeconds": -5 }
+        });
+
+        let problems = validate_defaults(&config);
+        let keys: Vec<&str> = problems.iter().map(|p| p.key.as_str()).collect();
+
+        assert_eq!(keys, vec!["paths.defaultImageLoad", "autosave.inactivitySeconds"]);
+        assert!(problems.iter().all(|p| p.severity == ProblemSeverity::Error));
+    }
+
+    #[test]
+    fn validate_defaults_warns_on_unknown_top_level_keys() {
+        let config = json!({ "futureFeature": { "enabled": true } });
+
+        let problems = validate_defaults(&config);
+
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].key, "futureFeature");
+        assert_eq!(problems[0].severity, ProblemSeverity::Warning);
+    }
+
+    #[test]
+    fn expand_paths_walks_nested_values() {
+        let config = json!({
+            "paths": { "library": "~/Pictures/ssce-library" },
+            "autosave": { "tempDirectory": "$HOME/.ssce-temp" },
+            "extra": { "roots": ["${HOME}/docs", "/abs/path"] },
+            "user": { "initials": "NC" }
+        });
+
+        let expanded: serde_json::Value =
+            serde_json::from_str(&expand_paths_in_config(config).unwrap()).unwrap();
+        let home = dirs::home_dir().unwrap().to_string_lossy().to_string();
+
+        assert_eq!(expanded["paths"]["library"], format!("{}/Pictures/ssce-library", home));
+        assert_eq!(expanded["autosave"]["tempDirectory"], format!("{}/.ssce-temp", home));
+        assert_eq!(expanded["extra"]["roots"][0], format!("{}/docs", home));
+        assert_eq!(expanded["extra"]["roots"][1], "/abs/path");
+        assert_eq!(expanded["user"]["initials"], "NC");
+    }
+
+    #[test]
+    fn expand_home_prefix_only_expands_leading_reference() {
+        assert_eq!(expand_home_prefix("~/a", "/home/u"), Some("/home/u/a".to_string()));
+        assert_eq!(expand_home_prefix("$HOME", "/home/u"), Some("/home/u".to_string()));
+        assert_eq!(expand_home_prefix("a/~/b", "/home/u"), None);
+        assert_eq!(expand_home_prefix("$HOMEDIR/x", "/home/u"), None);
+        assert_eq!(expand_home_prefix("~user/x", "/home/u"), None);
+    }
+
+    #[test]
+    fn collect_file_args_resolves_existing_files() {
+        let dir = std::env::temp_dir().join(format!("ssce-test-args-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.ssce"), "{}").unwrap();
+        fs::write(dir.join("b.png"), "").unwrap();
+
+        let args: Vec<String> = ["ssce-desktop", "a.ssce", "--flag", "missing.ssce", "b.png"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let files = collect_file_args(&args, &dir);
+
+        assert_eq!(
+            files,
+            vec![
+                dir.join("a.ssce").to_string_lossy().to_string(),
+                dir.join("b.png").to_string_lossy().to_string(),
+            ]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn is_ssce_path_matches_extension_case_insensitively() {
+        assert!(is_ssce_path("/docs/report.ssce"));
+        assert!(is_ssce_path("/docs/REPORT.SSCE"));
+        assert!(!is_ssce_path("/docs/report.png"));
+        assert!(!is_ssce_path("/docs/ssce"));
+    }
+
+    /// In-memory database with the full schema, for DB tests
+    fn test_db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        init_schema(&conn).unwrap();
+        conn
+    }
+
+    #[test]
+    fn migrations_bring_new_database_to_latest_version() {
+        let conn = test_db();
+        let version: i64 = conn.query_row("PRAGMA user_version", [], |r| r.get(0)).unwrap();
+        assert_eq!(version, MIGRATIONS.len() as i64);
+
+        // Running again is a no-op
+        assert_eq!(run_migrations(&conn).unwrap(), 0);
+    }
+
+    #[test]
+    fn startup_reports_migrations_and_falls_back_when_the_database_cannot_open() {
+        let dir = test_dir("startup-status");
+        let db_path = dir.join("library.db");
+
+        let (_, status) = open_startup_database(&db_path);
+        let ready = status.unwrap();
+        assert_eq!(ready.schema_version, MIGRATIONS.len() as i64);
+        assert!(ready.migrated);
+
+        // Already up to date the second time
+        let (_, status) = open_startup_database(&db_path);
+        assert!(!status.unwrap().migrated);
+
+        // A directory in place of the database file: reported, not fatal
+        let (conn, status) = open_startup_database(&dir);
+        let error = status.unwrap_err();
+        assert_eq!(error.database_path, dir.display().to_string());
+        assert!(!error.message.is_empty());
+        let count: i64 = conn.query_row("SELECT COUNT(*) FROM files", [], |r| r.get(0)).unwrap();
+        assert_eq!(count, 0);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn content_hash_ignores_volatile_fields_and_key_order() {
+        let a = json!({
+            "version": "1.1",
+            "thumbnail": "data:image/png;base64,AAA",
+            "keywords": ["report", "2025"],
+            "frontMatter": { "title": "Report", "modified": "2025-01-01T10:00:00Z" },
+            "layers": [{ "type": "text", "data": { "x": 1, "y": 2 } }]
+        });
+        let b = json!({
+            "layers": [{ "data": { "y": 2, "x": 1 }, "type": "text" }],
+            "frontMatter": { "modified": "2025-06-01T09:30:00Z", "title": "Report" },
+            "keywords": ["copy", "report"],
+            "version": "1.1"
+        });
+        let c = json!({
+            "version": "1.1",
+            "frontMatter": { "title": "Different" },
+            "layers": []
+        });
+
+        assert_eq!(ssce_content_hash(&a), ssce_content_hash(&b));
+        assert_ne!(ssce_content_hash(&a), ssce_content_hash(&c));
+
+        // Canonical saves of differently ordered documents are identical,
+        // and the saved form hashes the same as the original
+        let first = canonicalize_ssce(r#"{"b": {"y": 1, "x": [2, {"q": 1, "p": 0}]}, "a": "text"}"#).unwrap();
+        let second = canonicalize_ssce(r#"{"a":"text","b":{"x":[2,{"p":0,"q":1}],"y":1}}"#).unwrap();
+        assert_eq!(first, second);
+        assert!(first.find("\"a\"").unwrap() < first.find("\"b\"").unwrap());
+        let reparsed: serde_json::Value = serde_json::from_str(&first).unwrap();
+        assert_eq!(
+            ssce_content_hash(&reparsed),
+            ssce_content_hash(&json!({"a": "text", "b": {"x": [2, {"p": 0, "q": 1}], "y": 1}}))
+        );
+        assert!(canonicalize_ssce("{not json").is_err());
+    }
+
+    #[test]
+    fn find_duplicates_groups_shared_hashes() {
+        let conn = test_db();
+        for (path, hash) in [("/a.ssce", "h1"), ("/b.ssce", "h1"), ("/c.ssce", "h2")] {
+            conn.execute(
+                "INSERT INTO files (path, filename, content_hash) VALUES (?1, ?1, ?2)",
+                params![path, hash],
+            )
+            .unwrap();
+        }
+
+        let groups = find_duplicates(&conn).unwrap();
+
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].content_hash, "h1");
+        assert_eq!(groups[0].paths, vec!["/a.ssce", "/b.ssce"]);
+    }
+
+    #[test]
+    fn library_size_sums_known_sizes() {
+        let conn = test_db();
+        for (path, size) in [("/a.ssce", Some(100)), ("/b.ssce", Some(250)), ("/c.ssce", None)] {
+            conn.execute(
+                "INSERT INTO files (path, filename, size) VALUES (?1, ?1, ?2)",
+                params![path, size],
+            )
+            .unwrap();
+        }
+
+        let total = library_size(&conn).unwrap();
+
+        assert_eq!(total.total_bytes, 350);
+        assert_eq!(total.file_count, 3);
+    }
+
+    fn insert_test_file(conn: &Connection, path: &str, title: &str) {
+        conn.execute(
+            "INSERT INTO files (path, filename, title, keywords, modified) VALUES (?1, ?1, ?2, ?2, '2025-01-01')",
+            params![path, title],
+        )
+        .unwrap();
+    }
+
+    fn search_params(query: &str) -> SearchParams {
+        SearchParams {
+            query: Some(query.to_string()),
+            from_date: None,
+            to_date: None,
+            limit: None,
+            offset: None,
+            accent_sensitive: None,
+            fuzzy: None,
+            path_prefix: None,
+            author: None,
+            date_field: SearchDateField::Modified,
+            fields: None,
+        }
+    }
+
+    #[test]
+    fn search_is_limited_to_a_folder() {
+        let conn = test_db();
+        for path in [
+            "/library/docs/menu.ssce",
+            "/library/docs/2024/menu.ssce",
+            "/library/docs-old/menu.ssce",
+            "/library/Docs/menu.ssce",
+            "/library/a_b/menu.ssce",
+            "/library/axb/menu.ssce",
+            "/library/100%/menu.ssce",
+            "/library/1000/menu.ssce",
+        ] {
+            insert_test_file(&conn, path, "Lunch menu");
+        }
+        conn.execute("UPDATE files SET modified = '2024-06-01T00:00:00.000Z' WHERE path LIKE '%/2024/%'", [])
+            .unwrap();
+
+        let found = |params: SearchParams| -> Vec<String> {
+            let mut paths: Vec<String> = search_files(&conn, params, &chrono::Utc)
+                .unwrap()
+                .into_iter()
+                .map(|f| f.path)
+                .collect();
+            paths.sort();
+            paths
+        };
+        let within = |folder: &str, query: &str| SearchParams {
+            path_prefix: Some(folder.to_string()),
+            ..search_params(query)
+        };
+
+        assert_eq!(
+            found(within("/library/docs", "menu")),
+            vec!["/library/docs/2024/menu.ssce", "/library/docs/menu.ssce"]
+        );
+        assert_eq!(found(within("/library/docs/", "")), found(within("/library/docs", "menu")));
+        // LIKE wildcards in the folder name are literal
+        assert_eq!(found(within("/library/a_b", "menu")), vec!["/library/a_b/menu.ssce"]);
+        assert_eq!(found(within("/library/100%", "menu")), vec!["/library/100%/menu.ssce"]);
+        // Composes with date filters
+        let dated = SearchParams {
+            from_date: Some("2024-01-01".to_string()),
+            to_date: Some("2024-12-31".to_string()),
+            ..within("/library/docs", "menu")
+        };
+        assert_eq!(found(dated), vec!["/library/docs/2024/menu.ssce"]);
+        // Fuzzy fallback stays in the folder too
+        let fuzzy = SearchParams { fuzzy: Some(true), ..within("/library/a_b", "lunhc") };
+        assert_eq!(found(fuzzy), vec!["/library/a_b/menu.ssce"]);
+
+        assert_eq!(found(search_params("menu")).len(), 8);
+    }
+
+    #[test]
+    fn fts_query_quotes_terms_and_drops_syntax() {
+        assert_eq!(build_fts_query("scr shot").as_deref(), Some("\"scr\"* \"shot\"*"));
+        assert_eq!(build_fts_query("a\"b").as_deref(), Some("\"a\"* \"b\"*"));
+        assert_eq!(build_fts_query("*"), None);
+        assert_eq!(build_fts_query("  "), None);
+    }
+
+    #[test]
+    fn search_can_be_limited_to_named_fields() {
+        let conn = test_db();
+        insert_test_file(&conn, "/title.ssce", "Quarterly report");
+        insert_test_file(&conn, "/summary.ssce", "Notes");
+        conn.execute("UPDATE files SET summary = 'quarterly figures' WHERE path = '/summary.ssce'", [])
+            .unwrap();
+
+        let found = |fields: Option<Vec<&str>>| -> Result<Vec<String>, CommandError> {
+            let params = SearchParams {
+                fields: fields.map(|f| f.into_iter().map(String::from).collect()),
+                ..search_params("quarter")
+            };
+            let mut paths: Vec<String> =
+                search_files(&conn, params, &chrono::Utc)?.into_iter().map(|f| f.path).collect();
+            paths.sort();
+            Ok(paths)
+        };
+
+        assert_eq!(found(None).unwrap(), vec!["/summary.ssce", "/title.ssce"]);
+        assert_eq!(found(Some(vec![])).unwrap(), vec!["/summary.ssce", "/title.ssce"]);
+        assert_eq!(found(Some(vec!["title"])).unwrap(), vec!["/title.ssce"]);
+        assert_eq!(found(Some(vec!["Summary", "content"])).unwrap(), vec!["/summary.ssce"]);
+        assert!(matches!(found(Some(vec!["path"])), Err(CommandError::Validation(_))));
+        assert_eq!(
+            restrict_fts_columns("\"a\"*".into(), &["title".into(), "summary".into()]).unwrap(),
+            "{title summary} : (\"a\"*)"
+        );
+    }
+
+    #[test]
+    fn search_without_terms_lists_the_newest_documents() {
+        let conn = test_db();
+        for (path, modified) in [
+            ("/old.ssce", "2024-01-10T00:00:00.000Z"),
+            ("/new.ssce", "2025-06-01T00:00:00.000Z"),
+            ("/mid.ssce", "2024-09-15T00:00:00.000Z"),
+        ] {
+            conn.execute(
+                "INSERT INTO files (path, filename, modified) VALUES (?1, ?1, ?2)",
+                params![path, modified],
+            )
+            .unwrap();
+        }
+        let found = |params: SearchParams| -> Vec<String> {
+            search_files(&conn, params, &chrono::Utc).unwrap().into_iter().map(|f| f.path).collect()
+        };
+        let newest_first = vec!["/new.ssce", "/mid.ssce", "/old.ssce"];
+
+        // An empty, blank or missing query is a listing, not an error
+        assert_eq!(found(search_params("")), newest_first);
+        assert_eq!(found(search_params("  ")), newest_first);
+        assert_eq!(found(SearchParams { query: None, ..search_params("") }), newest_first);
+
+        // Only dates
+        let in_2024 = SearchParams {
+            query: None,
+            from_date: Some("2024-01-01".to_string()),
+            to_date: Some("2024-12-31".to_string()),
+            ..search_params("")
+        };
+        assert_eq!(found(in_2024), vec!["/mid.ssce", "/old.ssce"]);
+        let since_march = SearchParams { from_date: Some("2024-03-01".to_string()), ..search_params("") };
+        assert_eq!(found(since_march), vec!["/new.ssce", "/mid.ssce"]);
+
+        // Only a limit (and paging through with an offset)
+        let first_two = SearchParams { query: None, limit: Some(2), ..search_params("") };
+        assert_eq!(found(first_two), vec!["/new.ssce", "/mid.ssce"]);
+        let second_page = SearchParams { limit: Some(2), offset: Some(2), ..search_params("") };
+        assert_eq!(found(second_page), vec!["/old.ssce"]);
+    }
+
+    #[test]
+    fn search_with_fts_syntax_characters_does_not_error() {
+        let conn = test_db();
+        insert_test_file(&conn, "/foo.ssce", "foo report");
+
+        for query in ["foo)", "a\"b", "*", "(", "\"", "NOT", "foo AND", "^foo", "foo:bar"] {
+            let result = search_files(&conn, search_params(query), &chrono::Utc);
+            assert!(result.is_ok(), "query {:?} failed: {:?}", query, result.err());
+        }
+
+        let found = search_files(&conn, search_params("foo)"), &chrono::Utc).unwrap();
+        assert_eq!(found.len(), 1);
+    }
+
+    #[test]
+    fn clamp_limit_handles_missing_negative_zero_and_oversized() {
+        assert_eq!(clamp_limit(None), DEFAULT_RESULT_LIMIT);
+        assert_eq!(clamp_limit(Some(-5)), 1);
+        assert_eq!(clamp_limit(Some(0)), 1);
+        assert_eq!(clamp_limit(Some(20)), 20);
+        assert_eq!(clamp_limit(Some(i32::MAX)), MAX_RESULT_LIMIT);
+        assert_eq!(clamp_offset(Some(-10)), 0);
+        assert_eq!(clamp_offset(None), 0);
+    }
+
+    #[test]
+    fn search_applies_clamped_limit_and_offset() {
+        let conn = test_db();
+        for i in 0..3 {
+            insert_test_file(&conn, &format!("/doc{}.ssce", i), "doc");
+        }
+
+        let with_limit = |limit, offset| {
+            let mut params = search_params("");
+            params.limit = Some(limit);
+            params.offset = Some(offset);
+            search_files(&conn, params, &chrono::Utc).unwrap().len()
+        };
+
+        assert_eq!(with_limit(-1, 0), 1);
+        assert_eq!(with_limit(0, 0), 1);
+        assert_eq!(with_limit(100_000, 0), 3);
+        assert_eq!(with_limit(10, 2), 1);
+        assert_eq!(with_limit(10, -4), 3);
+    }
+
+    #[test]
+    fn normalize_timestamp_converts_offsets_to_utc() {
+        assert_eq!(normalize_timestamp("2025-03-01T14:00:00.000Z"), "2025-03-01T14:00:00.000Z");
+        assert_eq!(normalize_timestamp("2025-03-01T14:00:00+02:00"), "2025-03-01T12:00:00.000Z");
+        assert_eq!(normalize_timestamp("not a date"), "not a date");
+    }
+
+    #[test]
+    fn format_timestamp_converts_to_the_requested_zone() {
+        let stored = "2025-07-01T14:05:00.000Z";
+        assert_eq!(format_timestamp_in(stored, "%Y-%m-%d %H:%M", Some("UTC")).unwrap(), "2025-07-01 14:05");
+        assert_eq!(format_timestamp_in(stored, "%H:%M %Z", Some("Europe/London")).unwrap(), "15:05 BST");
+        assert_eq!(format_timestamp_in(stored, "%d/%m/%Y", Some("Pacific/Auckland")).unwrap(), "02/07/2025");
+
+        assert!(matches!(format_timestamp_in("yesterday", "%Y", Some("UTC")), Err(CommandError::Validation(_))));
+        assert!(matches!(format_timestamp_in(stored, "%Y", Some("Mars/Olympus")), Err(CommandError::Validation(_))));
+        assert!(matches!(format_timestamp_in(stored, "%Q", Some("UTC")), Err(CommandError::Validation(_))));
+
+        let now = now_iso();
+        assert_eq!(normalize_timestamp(&now), now);
+    }
+
+    #[test]
+    fn parse_date_bound_handles_plain_dates_and_timezones() {
+        let utc = chrono::Utc;
+        assert_eq!(parse_date_bound("2025-03-01", false, &utc).unwrap(), "2025-03-01T00:00:00.000Z");
+        assert_eq!(parse_date_bound("2025-03-01", true, &utc).unwrap(), "2025-03-01T23:59:59.999Z");
+        assert_eq!(parse_date_bound("2025-03-01T10:30", true, &utc).unwrap(), "2025-03-01T10:30:00.000Z");
+
+        let plus_two = chrono::FixedOffset::east_opt(2 * 3600).unwrap();
+        assert_eq!(parse_date_bound("2025-03-01", false, &plus_two).unwrap(), "2025-02-28T22:00:00.000Z");
+
+        assert!(parse_date_bound("01/03/2025", false, &utc).is_err());
+        assert!(parse_date_bound("2025-02-30", false, &utc).is_err());
+    }
+
+    #[test]
+    fn search_date_range_is_inclusive_at_day_boundaries() {
+        let conn = test_db();
+        for (path, modified) in [
+            ("/before.ssce", "2025-02-28T23:59:59.999Z"),
+            ("/start.ssce", "2025-03-01T00:00:00.000Z"),
+            ("/afternoon.ssce", "2025-03-05T14:00:00.000Z"),
+            ("/end.ssce", "2025-03-05T23:59:59.999Z"),
+            ("/after.ssce", "2025-03-06T00:00:00.000Z"),
+        ] {
+            conn.execute(
+                "INSERT INTO files (path, filename, modified) VALUES (?1, ?1, ?2)",
+                params![path, modified],
+            )
+            .unwrap();
+        }
+
+        let mut params = search_params("");
+        params.from_date = Some("2025-03-01".to_string());
+        params.to_date = Some("2025-03-05".to_string());
+        let mut paths: Vec<String> = search_files(&conn, params, &chrono::Utc)
+            .unwrap()
+            .into_iter()
+            .map(|f| f.path)
+            .collect();
+        paths.sort();
+
+        assert_eq!(paths, vec!["/afternoon.ssce", "/end.ssce", "/start.ssce"]);
+
+        let mut bad = search_params("");
+        bad.to_date = Some("yesterday".to_string());
+        assert!(search_files(&conn, bad, &chrono::Utc).is_err());
+    }
+
+    #[test]
+    fn rebuild_index_repairs_desynced_fts() {
+        let conn = test_db();
+        assert_eq!(check_integrity(&conn).unwrap(), IntegrityReport::Ok);
+
+        // Insert a row behind the trigger's back so the index misses it
+        conn.execute("DROP TRIGGER files_ai", []).unwrap();
+        insert_test_file(&conn, "/hidden.ssce", "hidden");
+        assert!(search_files(&conn, search_params("hidden"), &chrono::Utc).unwrap().is_empty());
+        assert!(matches!(check_integrity(&conn).unwrap(), IntegrityReport::Problems(_)));
+
+        rebuild_fts_index(&conn).unwrap();
+
+        assert_eq!(check_integrity(&conn).unwrap(), IntegrityReport::Ok);
+        assert_eq!(search_files(&conn, search_params("hidden"), &chrono::Utc).unwrap().len(), 1);
+
+        // Triggers are back, so new rows are indexed again
+        insert_test_file(&conn, "/visible.ssce", "visible");
+        assert_eq!(search_files(&conn, search_params("visible"), &chrono::Utc).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn reindex_fts_repairs_index_in_place() {
+        let conn = test_db();
+        insert_test_file(&conn, "/kept.ssce", "kept");
+
+        // Empty the index without touching the files table
+        conn.execute("INSERT INTO files_fts(files_fts) VALUES('delete-all')", []).unwrap();
+        assert!(search_files(&conn, search_params("kept"), &chrono::Utc).unwrap().is_empty());
+        assert!(matches!(check_integrity(&conn).unwrap(), IntegrityReport::Problems(_)));
+
+        reindex_fts(&conn).unwrap();
+        optimize_fts(&conn).unwrap();
+
+        assert_eq!(check_integrity(&conn).unwrap(), IntegrityReport::Ok);
+        assert_eq!(search_files(&conn, search_params("kept"), &chrono::Utc).unwrap().len(), 1);
+
+        // The triggers were never dropped
+        insert_test_file(&conn, "/later.ssce", "later");
+        assert_eq!(search_files(&conn, search_params("later"), &chrono::Utc).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn stats_and_vacuum_report_library_health() {
+        let conn = test_db();
+        for i in 0..20 {
+            insert_test_file(&conn, &format!("/doc{}.ssce", i), &"padding ".repeat(200));
+        }
+        conn.execute("UPDATE files SET content_hash = 'h' WHERE id <= 5", []).unwrap();
+
+        let stats = database_stats(&conn).unwrap();
+        assert_eq!(stats.total_files, 20);
+        assert_eq!(stats.indexed_files, 5);
+        assert_eq!(stats.fts_row_count, 20);
+        assert!(stats.db_size_bytes > 0);
+
+        conn.execute("DELETE FROM files", []).unwrap();
+        assert!(vacuum_database(&conn).unwrap() >= 0);
+        assert!(database_stats(&conn).unwrap().db_size_bytes <= stats.db_size_bytes);
+
+        let tx = conn.unchecked_transaction().unwrap();
+        assert!(vacuum_database(&tx).is_err());
+    }
+
+    #[test]
+    fn csv_field_quotes_only_when_needed() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn export_library_writes_csv_and_json() {
+        let conn = test_db();
+        insert_test_file(&conn, "/a.ssce", "Report, \"final\"");
+        insert_test_file(&conn, "/b.ssce", "notes");
+        let dir = std::env::temp_dir().join(format!("ssce-export-test-{}", std::process::id()));
+
+        let csv_path = dir.join("library.csv");
+        assert_eq!(export_library_to(&conn, ExportFormat::Csv, &csv_path).unwrap(), 2);
+        let csv = fs::read_to_string(&csv_path).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], EXPORT_COLUMNS.join(","));
+        assert!(lines[1].contains("\"Report, \"\"final\"\"\""));
+        assert_eq!(lines.len(), 3);
+
+        let json_path = dir.join("library.json");
+        assert_eq!(export_library_to(&conn, ExportFormat::Json, &json_path).unwrap(), 2);
+        let parsed: serde_json::Value = serde_json::from_str(&fs::read_to_string(&json_path).unwrap()).unwrap();
+        assert_eq!(parsed[1]["path"], "/b.ssce");
+        assert_eq!(parsed[0]["snapshot_count"], 0);
+        assert_eq!(parsed[0]["last_opened"], serde_json::Value::Null);
+
+        assert!(!path_with_suffix(&json_path, ".tmp").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Empty temp directory unique to this test
+    fn test_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("ssce-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn rebuild_indexes_many_files_in_nested_folders() {
+        let dir = test_dir("rebuild");
+        for i in 0..500 {
+            let folder = dir.join(format!("folder{}", i % 7));
+            fs::create_dir_all(&folder).unwrap();
+            let doc = json!({
+                "version": "1.1",
+                "keywords": ["doc", i.to_string()],
+                "frontMatter": { "title": format!("Doc {}", i), "modified": "2025-01-01T00:00:00.000Z" },
+                "canvas": { "width": 10, "height": 10 },
+                "layers": [],
+                "snapshots": []
+            });
+            fs::write(folder.join(format!("doc{}.ssce", i)), doc.to_string()).unwrap();
+        }
+        fs::write(dir.join("notes.txt"), "not a document").unwrap();
+
+        let conn = test_db();
+        insert_test_file(&conn, "/gone/stale.ssce", "stale");
+
+        assert_eq!(rebuild_library(&conn, &dir, &AtomicBool::new(false), &RebuildOptions::default()).unwrap().indexed, 500);
+
+        let rows: i64 = conn.query_row("SELECT COUNT(*) FROM files", [], |r| r.get(0)).unwrap();
+        assert_eq!(rows, 500);
+        let opened: i64 = conn
+            .query_row("SELECT COUNT(last_opened) FROM files", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(opened, 0);
+        assert_eq!(search_files(&conn, search_params("doc"), &chrono::Utc).unwrap().len(), 50);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rebuild_stops_at_the_scan_depth_limit() {
+        let dir = test_dir("rebuild-depth");
+        fs::create_dir_all(dir.join("a/b/c")).unwrap();
+        for name in ["top.ssce", "a/one.ssce", "a/b/two.ssce", "a/b/c/three.ssce"] {
+            fs::write(dir.join(name), json!({ "version": "1.1" }).to_string()).unwrap();
+        }
+        let conn = test_db();
+        insert_test_file(&conn, "/gone/stale.ssce", "stale");
+        let rebuild = |max_scan_depth| {
+            let options = RebuildOptions { max_scan_depth, ..RebuildOptions::default() };
+            rebuild_library(&conn, &dir, &AtomicBool::new(false), &options).unwrap()
+        };
+
+        // Too deep: reported, and the index is left as it was
+        let result = rebuild(2);
+        assert_eq!(result.limit_exceeded, Some(ScanLimit::MaxScanDepth(2)));
+        assert_eq!(result.indexed, 0);
+        let rows: i64 = conn.query_row("SELECT COUNT(*) FROM files", [], |r| r.get(0)).unwrap();
+        assert_eq!(rows, 1);
+        assert_eq!(
+            serde_json::to_value(result.limit_exceeded).unwrap(),
+            json!({ "limit": "maxScanDepth", "value": 2 })
+        );
+
+        // Exactly deep enough works as before
+        let result = rebuild(3);
+        assert_eq!((result.indexed, result.limit_exceeded), (4, None));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rebuild_stops_at_the_scan_file_limit() {
+        let dir = test_dir("rebuild-files");
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        for name in ["a.ssce", "b.ssce", "sub/c.ssce"] {
+            fs::write(dir.join(name), json!({ "version": "1.1" }).to_string()).unwrap();
+        }
+        // Every file counts, not just documents
+        fs::write(dir.join("sub/notes.txt"), "not a document").unwrap();
+        let conn = test_db();
+        let rebuild = |max_scan_files| {
+            let options = RebuildOptions { max_scan_files, ..RebuildOptions::default() };
+            rebuild_library(&conn, &dir, &AtomicBool::new(false), &options).unwrap()
+        };
+
+        let result = rebuild(3);
+        assert_eq!(result.limit_exceeded, Some(ScanLimit::MaxScanFiles(3)));
+        assert_eq!(result.indexed, 0);
+        let rows: i64 = conn.query_row("SELECT COUNT(*) FROM files", [], |r| r.get(0)).unwrap();
+        assert_eq!(rows, 0);
+
+        let result = rebuild(4);
+        assert_eq!((result.indexed, result.limit_exceeded), (3, None));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rebuild_skips_and_reports_unparseable_files() {
+        let dir = test_dir("rebuild-bad");
+        for name in ["a.ssce", "b.ssce", "c.ssce"] {
+            fs::write(dir.join(name), json!({ "version": "1.1" }).to_string()).unwrap();
+        }
+        fs::write(dir.join("bad.ssce"), "{ not json").unwrap();
+
+        let conn = test_db();
+        let result = rebuild_library(&conn, &dir, &AtomicBool::new(false), &RebuildOptions::default()).unwrap();
+        assert_eq!((result.indexed, result.skipped), (3, 1));
+        assert_eq!(result.errors.len(), 1);
+        assert!(result.errors[0].path.ends_with("bad.ssce"));
+        assert!(!result.errors[0].message.is_empty());
+
+        let rows: i64 = conn.query_row("SELECT COUNT(*) FROM files", [], |r| r.get(0)).unwrap();
+        assert_eq!(rows, 3);
+
+        // Failures of the whole rebuild say what kind they are
+        let bad_pattern = RebuildOptions {
+            ignore_patterns: vec!["draft[".to_string()],
+            ..RebuildOptions::default()
+        };
+        let rebuild = |path: &Path, options| rebuild_library(&conn, path, &AtomicBool::new(false), options);
+        assert!(matches!(rebuild(&dir, &bad_pattern), Err(CommandError::Validation(_))));
+        assert!(matches!(rebuild(&dir.join("missing"), &RebuildOptions::default()), Err(CommandError::NotFound(_))));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn cancelled_rebuild_keeps_indexed_files_and_stale_entries() {
+        let dir = test_dir("rebuild-cancel");
+        fs::write(dir.join("doc.ssce"), json!({ "version": "1.1" }).to_string()).unwrap();
+
+        let conn = test_db();
+        insert_test_file(&conn, "/gone/stale.ssce", "stale");
+
+        let cancel = AtomicBool::new(true);
+        assert_eq!(rebuild_library(&conn, &dir, &cancel, &RebuildOptions::default()).unwrap().indexed, 0);
+
+        // Stale cleanup is skipped, and the DB is left usable
+        let rows: i64 = conn.query_row("SELECT COUNT(*) FROM files", [], |r| r.get(0)).unwrap();
+        assert_eq!(rows, 1);
+        assert!(conn.is_autocommit());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn browse_directory_recurses_with_depth_limit() {
+        let dir = test_dir("browse");
+        fs::create_dir_all(dir.join("a/b")).unwrap();
+        fs::write(dir.join("top.ssce"), "").unwrap();
+        fs::write(dir.join("a/mid.ssce"), "").unwrap();
+        fs::write(dir.join("a/skip.txt"), "").unwrap();
+        fs::write(dir.join("a/b/deep.ssce"), "").unwrap();
+        fs::write(dir.join(".hidden.ssce"), "").unwrap();
+        let root = dir.to_string_lossy().to_string();
+
+        let paths = |recursive, max_depth| -> Vec<String> {
+            list_directory(root.clone(), "ssce".into(), recursive, max_depth, None)
+                .unwrap()
+                .into_iter()
+                .map(|e| e.path)
+                .collect()
+        };
+
+        assert_eq!(paths(false, None), vec!["a", "top.ssce"]);
+        assert_eq!(paths(true, Some(1)), vec!["a", "a/b", "a/mid.ssce", "top.ssce"]);
+        assert_eq!(
+            paths(true, None),
+            vec!["a", "a/b", "a/b/deep.ssce", "a/mid.ssce", "top.ssce"]
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn browse_directory_survives_symlink_cycles() {
+        let dir = test_dir("browse-cycle");
+        fs::create_dir_all(dir.join("a")).unwrap();
+        std::os::unix::fs::symlink(&dir, dir.join("a/loop")).unwrap();
+
+        let entries = list_directory(dir.to_string_lossy().to_string(), "all".into(), true, None, None).unwrap();
+        let paths: Vec<String> = entries.into_iter().map(|e| e.path).collect();
+
+        assert_eq!(paths, vec!["a", "a/loop"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn browse_directory_sorts_by_size_with_directories_first() {
+        let dir = test_dir("browse-sort");
+        fs::create_dir_all(dir.join("zdir")).unwrap();
+        fs::write(dir.join("small.ssce"), "a").unwrap();
+        fs::write(dir.join("large.ssce"), "abcdef").unwrap();
+        fs::write(dir.join("medium.ssce"), "abc").unwrap();
+
+        let entries = list_directory(
+            dir.to_string_lossy().to_string(),
+            "all".into(),
+            false,
+            None,
+            Some(BrowseSort::Size),
+        )
+        .unwrap();
+        let names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+
+        assert_eq!(names, vec!["zdir", "large.ssce", "medium.ssce", "small.ssce"]);
+        assert!(entries.iter().all(|e| e.mtime > 0));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn last_browse_state_round_trips_and_falls_back_for_deleted_folders() {
+        let dir = test_dir("last-browse");
+        let conn = test_db();
+        let browsed = dir.join("shots");
+        fs::create_dir_all(&browsed).unwrap();
+        let browsed = browsed.to_string_lossy().to_string();
+
+        assert_eq!(
+            last_browse_state(&conn, Some("/fallback")).unwrap(),
+            LastBrowseState { directory: Some("/fallback".into()), filter: "all".into() }
+        );
+
+        record_browse_state(&conn, &browsed, "*.ssce,png").unwrap();
+        assert_eq!(
+            last_browse_state(&conn, Some("/fallback")).unwrap(),
+            LastBrowseState { directory: Some(normalize_path(&browsed)), filter: "*.ssce,png".into() }
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            last_browse_state(&conn, Some("/fallback")).unwrap(),
+            LastBrowseState { directory: Some("/fallback".into()), filter: "*.ssce,png".into() }
+        );
+        assert_eq!(last_browse_state(&conn, None).unwrap().directory, None);
+    }
+
+    #[test]
+    fn file_filter_accepts_extension_lists_and_globs() {
+        let list = FileFilter::parse("*.ssce, md,.TXT").unwrap();
+        assert!(list.matches("doc.ssce"));
+        assert!(list.matches("README.MD"));
+        assert!(list.matches("notes.txt"));
+        assert!(!list.matches("image.png"));
+
+        let glob = FileFilter::parse("prefix_*").unwrap();
+        assert!(glob.matches("prefix_one.ssce"));
+        assert!(glob.matches("PREFIX_two.png"));
+        assert!(!glob.matches("other_prefix_three.ssce"));
+
+        // Keywords keep their old meaning
+        assert!(FileFilter::parse("images").unwrap().matches("a.webp"));
+        assert!(FileFilter::parse("all").unwrap().matches("anything"));
+        assert!(FileFilter::parse("[").is_err());
+    }
+
+    #[test]
+    fn recently_modified_includes_files_never_opened() {
+        let conn = test_db();
+        for (path, modified, last_opened) in [
+            ("/old.ssce", Some("2025-01-01T00:00:00.000Z"), Some("2025-06-01T00:00:00.000Z")),
+            ("/new.ssce", Some("2025-05-01T00:00:00.000Z"), None),
+            ("/undated.ssce", None, None),
+        ] {
+            conn.execute(
+                "INSERT INTO files (path, filename, modified, last_opened) VALUES (?1, ?1, ?2, ?3)",
+                params![path, modified, last_opened],
+            )
+            .unwrap();
+        }
+
+        let paths: Vec<String> = recently_modified(&conn, 10).unwrap().into_iter().map(|f| f.path).collect();
+
+        assert_eq!(paths, vec!["/new.ssce", "/old.ssce"]);
+    }
+
+    #[test]
+    fn second_rebuild_is_turned_away_until_the_first_ends() {
+        let running = RebuildRunning(AtomicBool::new(false));
+        let first = running.start().unwrap();
+        assert!(matches!(running.start(), Err(CommandError::AlreadyRunning(_))));
+        drop(first);
+
+        // Released even when the rebuild fails
+        let failed: Result<(), CommandError> = (|| {
+            let _running = running.start()?;
+            Err(CommandError::Db("disk I/O error".into()))
+        })();
+        assert!(failed.is_err());
+        assert!(running.start().is_ok());
+    }
+
+    #[test]
+    fn operation_slots_cancel_independently() {
+        let thumbnails = OperationSlot::new("Thumbnail regeneration");
+        let other = OperationSlot::new("Other");
+        let running = thumbnails.start().unwrap();
+        let _other = other.start().unwrap();
+
+        // A second start is turned away and leaves the pending cancel alone
+        thumbnails.cancel();
+        assert!(matches!(thumbnails.start(), Err(CommandError::AlreadyRunning(_))));
+        assert!(thumbnails.cancel.load(Ordering::Relaxed));
+        assert!(!other.cancel.load(Ordering::Relaxed));
+
+        // The next run starts uncancelled
+        drop(running);
+        let _again = thumbnails.start().unwrap();
+        assert!(!thumbnails.cancel.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn external_opens_count_against_the_source_document() {
+        let conn = test_db();
+        insert_test_file(&conn, "/library/report.ssce", "Report");
+
+        assert!(register_external_open(&conn, "/library/report.ssce", "2025-02-01T00:00:00.000Z").unwrap());
+        assert!(register_external_open(&conn, "/library/report.ssce", "2025-02-02T00:00:00.000Z").unwrap());
+        // An export that isn't itself in the library records nothing
+        assert!(!register_external_open(&conn, "/tmp/report.html", "2025-02-03T00:00:00.000Z").unwrap());
+
+        let (open_count, last_opened): (i64, String) = conn
+            .query_row("SELECT open_count, last_opened FROM files WHERE path = '/library/report.ssce'", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!(open_count, 2);
+        assert_eq!(last_opened, "2025-02-02T00:00:00.000Z");
+    }
+
+    #[test]
+    fn open_count_rises_with_each_open() {
+        let conn = test_db();
+        for path in ["/a.ssce", "/b.ssce", "/never.ssce"] {
+            insert_test_file(&conn, path, "doc");
+        }
+
+        record_open(&conn, "/a.ssce", "2025-01-01T00:00:00.000Z").unwrap();
+        for day in 1..=3 {
+            record_open(&conn, "/b.ssce", &format!("2025-01-0{}T00:00:00.000Z", day)).unwrap();
+        }
+
+        let used: Vec<(String, i64, Option<String>)> = most_used_files(&conn, 10)
+            .unwrap()
+            .into_iter()
+            .map(|f| (f.path, f.open_count, f.last_opened))
+            .collect();
+        assert_eq!(
+            used,
+            vec![
+                ("/b.ssce".to_string(), 3, Some("2025-01-03T00:00:00.000Z".to_string())),
+                ("/a.ssce".to_string(), 1, Some("2025-01-01T00:00:00.000Z".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn index_content_collects_snapshot_and_text_layer_text() {
+        let doc = json!({
+            "layers": [
+                { "type": "text", "data": { "text": "Click the button" } },
+                { "type": "arrow", "data": { "text": "ignored" } }
+            ],
+            "snapshots": [
+                { "frontMatter": { "title": "Step one", "summary": "Open the café menu" } },
+                { "frontMatter": { "title": "Step two" } }
+            ]
+        });
+
+        assert_eq!(
+            extract_index_content(&doc, 1000),
+            "Step one\nOpen the café menu\nStep two\nClick the button"
+        );
+
+        // Truncation never splits a multi-byte character
+        let truncated = extract_index_content(&doc, 22);
+        assert_eq!(truncated, "Step one\nOpen the caf");
+    }
+
+    #[test]
+    fn rebuild_makes_snapshot_text_searchable() {
+        let dir = test_dir("rebuild-content");
+        let doc = json!({
+            "version": "1.1",
+            "frontMatter": { "title": "Plain" },
+            "snapshots": [{ "frontMatter": { "title": "Checkout", "summary": "payment gateway timeout" } }]
+        });
+        fs::write(dir.join("doc.ssce"), doc.to_string()).unwrap();
+
+        let conn = test_db();
+        rebuild_library(&conn, &dir, &AtomicBool::new(false), &RebuildOptions::default()).unwrap();
+
+        assert_eq!(search_files(&conn, search_params("gateway"), &chrono::Utc).unwrap().len(), 1);
+        assert_eq!(check_integrity(&conn).unwrap(), IntegrityReport::Ok);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rebuild_indexes_author_and_created() {
+        let dir = test_dir("rebuild-author");
+        let doc = json!({
+            "version": "1.1",
+            "frontMatter": { "title": "Audit", "author": " Ann O'Neil ", "created": "2024-03-01T10:00:00+01:00" }
+        });
+        fs::write(dir.join("audit.ssce"), doc.to_string()).unwrap();
+        fs::write(dir.join("plain.ssce"), json!({ "frontMatter": { "title": "Plain", "author": 7 } }).to_string())
+            .unwrap();
+
+        let conn = test_db();
+        rebuild_library(&conn, &dir, &AtomicBool::new(false), &RebuildOptions::default()).unwrap();
+
+        let mut files = search_files(&conn, search_params(""), &chrono::Utc).unwrap();
+        files.sort_by(|a, b| a.filename.cmp(&b.filename));
+        assert_eq!(files[0].author.as_deref(), Some("Ann O'Neil"));
+        assert_eq!(files[0].created.as_deref(), Some("2024-03-01T09:00:00.000Z"));
+        // Missing or non-text fields are stored as NULL
+        assert_eq!((files[1].author.as_deref(), files[1].created.as_deref()), (None, None));
+
+        // The author is searchable text
+        let hits = search_files(&conn, search_params("neil"), &chrono::Utc).unwrap();
+        assert_eq!(hits.iter().map(|f| f.filename.as_str()).collect::<Vec<_>>(), vec!["audit.ssce"]);
+        assert_eq!(check_integrity(&conn).unwrap(), IntegrityReport::Ok);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn search_filters_by_author_and_created_date() {
+        let conn = test_db();
+        for (path, author, created) in [
+            ("/library/a.ssce", Some("Ann Lee"), Some("2023-05-01T00:00:00.000Z")),
+            ("/library/b.ssce", Some("Bob_Smith"), Some("2024-05-01T00:00:00.000Z")),
+            ("/library/c.ssce", None, None),
+        ] {
+            insert_test_file(&conn, path, "Report");
+            conn.execute("UPDATE files SET author = ?2, created = ?3 WHERE path = ?1", params![path, author, created])
+                .unwrap();
+        }
+
+        let found = |params: SearchParams| -> Vec<String> {
+            let mut paths: Vec<String> =
+                search_files(&conn, params, &chrono::Utc).unwrap().into_iter().map(|f| f.path).collect();
+            paths.sort();
+            paths
+        };
+        let by = |author: &str, query: &str| SearchParams { author: Some(author.to_string()), ..search_params(query) };
+
+        // Part of the name, ignoring case, with or without search terms
+        assert_eq!(found(by("ann", "report")), vec!["/library/a.ssce"]);
+        assert_eq!(found(by("SMITH", "")), vec!["/library/b.ssce"]);
+        // LIKE wildcards are literal
+        assert_eq!(found(by("b_s", "")), vec!["/library/b.ssce"]);
+        assert!(found(by("%", "")).is_empty());
+        // A blank filter is ignored
+        assert_eq!(found(by("  ", "report")).len(), 3);
+        // The fuzzy fallback keeps the filter
+        assert_eq!(found(SearchParams { fuzzy: Some(true), ..by("lee", "reprot") }), vec!["/library/a.ssce"]);
+
+        // Dates apply to created when asked; rows without one are left out
+        let created_in_2024 = SearchParams {
+            from_date: Some("2024-01-01".to_string()),
+            to_date: Some("2024-12-31".to_string()),
+            date_field: SearchDateField::Created,
+            ..search_params("")
+        };
+        assert_eq!(found(created_in_2024), vec!["/library/b.ssce"]);
+        let modified_in_2024 = SearchParams {
+            from_date: Some("2024-01-01".to_string()),
+            to_date: Some("2024-12-31".to_string()),
+            ..search_params("")
+        };
+        assert!(found(modified_in_2024).is_empty());
+    }
+
+    #[test]
+    fn content_migration_reindexes_existing_rows() {
+        // A database from before migration 4, with a row already indexed
+        let conn = Connection::open_in_memory().unwrap();
+        create_base_schema(&conn).unwrap();
+        migrate_to(&conn, 3).unwrap();
+        conn.execute("INSERT INTO files (path, filename, title) VALUES ('/a.ssce', 'a.ssce', 'existing')", [])
+            .unwrap();
+
+        assert_eq!(run_migrations(&conn).unwrap(), MIGRATIONS.len() - 3);
+
+        assert_eq!(search_files(&conn, search_params("existing"), &chrono::Utc).unwrap().len(), 1);
+        assert_eq!(check_integrity(&conn).unwrap(), IntegrityReport::Ok);
+    }
+
+    #[test]
+    fn update_metadata_changes_only_given_fields() {
+        let conn = test_db();
+        conn.execute(
+            "INSERT INTO files (path, filename, title, summary, keywords, thumbnail, snapshot_count)
+             VALUES ('/a.ssce', 'a.ssce', 'Old title', 'kept summary', 'alpha', 'thumb', 3)",
+            [],
+        )
+        .unwrap();
+
+        update_metadata(&conn, "/a.ssce", Some("New title".into()), None, None).unwrap();
+
+        let (title, summary, keywords, thumbnail, snapshots): (String, String, String, String, i32) = conn
+            .query_row(
+                "SELECT title, summary, keywords, thumbnail, snapshot_count FROM files WHERE path = '/a.ssce'",
+                [],
+                |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?)),
+            )
+            .unwrap();
+        assert_eq!(
+            (title.as_str(), summary.as_str(), keywords.as_str(), thumbnail.as_str(), snapshots),
+            ("New title", "kept summary", "alpha", "thumb", 3)
+        );
+
+        // Index follows the new title and still finds the untouched fields
+        let search = |q: &str| search_files(&conn, search_params(q), &chrono::Utc).unwrap().len();
+        assert_eq!(search("new"), 1);
+        assert_eq!(search("old"), 0);
+        assert_eq!(search("alpha"), 1);
+        assert_eq!(search("kept"), 1);
+
+        assert!(update_metadata(&conn, "/missing.ssce", Some("x".into()), None, None).is_err());
+    }
+
+    #[test]
+    fn normalize_path_resolves_dots_and_separators() {
+        assert_eq!(normalize_path_with("/lib/./docs/../Doc.ssce", false, false), "/lib/Doc.ssce");
+        assert_eq!(normalize_path_with("/../a//b/", false, false), "/a/b");
+        assert_eq!(normalize_path_with("../a/./b", false, false), "../a/b");
+        assert_eq!(normalize_path_with("C:/Lib\\..\\Docs/Doc.ssce", false, true), "C:\\Docs\\Doc.ssce");
+        assert_eq!(normalize_path_with("C:\\..", false, true), "C:\\");
+        assert_eq!(normalize_path_with("\\\\server\\share\\a.ssce", false, true), "\\\\server\\share\\a.ssce");
+    }
+
+    #[test]
+    fn normalize_path_folds_case_when_case_insensitive() {
+        let a = normalize_path_with("/Lib/Doc.ssce", true, false);
+        let b = normalize_path_with("/lib/doc.ssce", true, false);
+        assert_eq!(a, b);
+
+        // Case is preserved where the filesystem is case-sensitive
+        assert_ne!(
+            normalize_path_with("/Lib/Doc.ssce", false, false),
+            normalize_path_with("/lib/doc.ssce", false, false)
+        );
+    }
+
+    #[test]
+    fn path_normalization_migration_merges_duplicates() {
+        let conn = test_db();
+        conn.execute_batch(
+            "INSERT INTO files (path, filename, last_opened) VALUES ('/library/./a.ssce', 'a.ssce', '2025-01-01');
+             INSERT INTO files (path, filename, last_opened) VALUES ('/library/x/../a.ssce', 'a.ssce', '2025-02-01');
+             INSERT INTO files (path, filename) VALUES ('/library/b.ssce', 'b.ssce');",
+        )
+        .unwrap();
+
+        normalize_stored_paths(&conn).unwrap();
+
+        let mut stmt = conn.prepare("SELECT path, last_opened FROM files ORDER BY path").unwrap();
+        let rows: Vec<(String, Option<String>)> = stmt
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                ("/library/a.ssce".to_string(), Some("2025-02-01".to_string())),
+                ("/library/b.ssce".to_string(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn soft_deleted_files_are_hidden_until_saved_again() {
+        let conn = test_db();
+        insert_test_file(&conn, "/library/a.ssce", "alpha");
+        conn.execute("UPDATE files SET last_opened = '2025-01-01'", []).unwrap();
+
+        soft_delete_file(&conn, "/library/a.ssce").unwrap();
+
+        assert!(search_files(&conn, search_params("alpha"), &chrono::Utc).unwrap().is_empty());
+        assert!(recently_modified(&conn, 10).unwrap().is_empty());
+        assert_eq!(library_size(&conn).unwrap().file_count, 0);
+
+        conn.execute(
+            "INSERT INTO files (path, filename) VALUES ('/library/a.ssce', 'a.ssce')
+             ON CONFLICT(path) DO UPDATE SET deleted_at = NULL",
+            [],
+        )
+        .unwrap();
+        assert_eq!(search_files(&conn, search_params("alpha"), &chrono::Utc).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn library_root_check_rejects_escapes() {
+        let dir = test_dir("within");
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(dir.join("lib/doc.ssce"), "").unwrap();
+        fs::write(dir.join("outside.ssce"), "").unwrap();
+        let root = dir.join("lib");
+
+        let root = [root];
+        assert!(is_within_root(&dir.join("lib/doc.ssce"), &root));
+        assert!(!is_within_root(&dir.join("outside.ssce"), &root));
+        assert!(!is_within_root(&dir.join("lib/../outside.ssce"), &root));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn writes_are_confined_to_allowed_roots() {
+        let dir = test_dir("roots");
+        fs::create_dir_all(dir.join("lib/sub")).unwrap();
+        fs::create_dir_all(dir.join("extra")).unwrap();
+        let roots = vec![dir.join("lib"), dir.join("extra")];
+
+        assert!(is_within_root(&dir.join("lib/sub/doc.ssce"), &roots));
+        // Files and folders that don't exist yet
+        assert!(is_within_root(&dir.join("lib/new/deeper/doc.ssce"), &roots));
+        assert!(is_within_root(&dir.join("extra/doc.png"), &roots));
+
+        assert!(!is_within_root(&dir.join("outside.ssce"), &roots));
+        assert!(!is_within_root(&dir.join("lib/../../etc/passwd"), &roots));
+        assert!(!is_within_root(&dir.join("lib/sub/../../outside.ssce"), &roots));
+        assert!(!is_within_root(Path::new("lib/doc.ssce"), &roots));
+        assert!(!is_within_root(Path::new("/etc/passwd"), &roots));
+        assert!(!is_within_root(&dir.join("lib/doc.ssce"), &[]));
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&dir, dir.join("lib/escape")).unwrap();
+            assert!(!is_within_root(&dir.join("lib/escape/outside.ssce"), &roots));
+        }
+
+        let err = check_within_roots(&dir.join("lib/../../etc"), &roots).unwrap_err();
+        assert!(matches!(err, CommandError::Permission(ref m) if m.contains("paths.extraRoots")), "{}", err);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn granted_folders_are_writable() {
+        let dir = test_dir("write-grants");
+        fs::create_dir_all(dir.join("usb/docs")).unwrap();
+        let grants = WriteGrants(Mutex::new(Vec::new()));
+        assert!(check_within_roots(&dir.join("usb/docs/report.ssce"), &grants.folders()).is_err());
+
+        grants.grant_folder_of(&dir.join("usb/docs/picked.ssce"));
+        grants.grant_folder_of(&dir.join("usb/docs/other.png"));
+        assert_eq!(grants.folders(), vec![dir.join("usb/docs")]);
+        assert!(check_within_roots(&dir.join("usb/docs/report.ssce"), &grants.folders()).is_ok());
+        assert!(check_within_roots(&dir.join("usb/report.ssce"), &grants.folders()).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn loading_outside_the_roots_grants_nothing() {
+        let dir = test_dir("load-no-grant");
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::create_dir_all(dir.join("private")).unwrap();
+        let doc = dir.join("private/doc.ssce");
+        let image = dir.join("private/shot.png");
+        fs::write(&doc, r#"{"version":"1.0","snapshots":[]}"#).unwrap();
+        write_test_png(&image, 2, 2);
+
+        let grants = WriteGrants(Mutex::new(Vec::new()));
+        let roots = vec![dir.join("lib")];
+        read_ssce(&doc.to_string_lossy(), &RetryPolicy::default()).unwrap();
+        checked_image_data_url(&image, &ImageLimits::default()).unwrap();
+
+        let mut writable = roots.clone();
+        writable.extend(grants.folders());
+        assert!(grants.folders().is_empty());
+        assert!(check_within_roots(&dir.join("private/doc.ssce"), &writable).is_err());
+        assert!(check_within_roots(&dir.join("private/new.png"), &writable).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn allowed_roots_come_from_config() {
+        let config = json!({
+            "paths": {
+                "library": "/data/library",
+                "defaultImageLoad": "",
+                "extraRoots": ["/mnt/shared", ""]
+            },
+            "autosave": { "tempDirectory": "/var/tmp/ssce" }
+        });
+
+        let roots = allowed_roots(&typed_defaults(config).unwrap());
+        assert!(roots.contains(&std::path::PathBuf::from("/data/library")));
+        assert!(roots.contains(&std::path::PathBuf::from("/mnt/shared")));
+        assert!(roots.contains(&std::path::PathBuf::from("/var/tmp/ssce")));
+        assert!(!roots.contains(&std::path::PathBuf::from("")));
+
+        // A config that fails validation falls back to the built-in paths,
+        // expanded so they are still usable roots
+        let fallback = defaults_or_builtin(typed_defaults(json!({ "paths": "not an object" })));
+        let home = dirs::home_dir().unwrap();
+        assert_eq!(std::path::PathBuf::from(&fallback.paths.library), home.join("Pictures/ssce-library"));
+        assert!(allowed_roots(&fallback).iter().all(|root| root.is_absolute()));
+        assert!(is_within_root(&home.join("Pictures/ssce-out/doc.ssce"), &allowed_roots(&fallback)));
+    }
+
+    #[test]
+    fn open_with_only_starts_listed_apps() {
+        let config = typed_defaults(json!({ "openWithApps": ["gimp", " /usr/bin/krita "] })).unwrap();
+        assert!(ensure_open_with_allowed(&config, "gimp").is_ok());
+        assert!(ensure_open_with_allowed(&config, "/usr/bin/krita").is_ok());
+        assert!(matches!(ensure_open_with_allowed(&config, "sh"), Err(CommandError::Permission(_))));
+        assert!(ensure_open_with_allowed(&Defaults::default(), "gimp").is_err());
+    }
+
+    #[test]
+    fn disk_space_check_rejects_oversized_writes() {
+        let dir = test_dir("disk-space");
+        // Parent folders that don't exist yet are fine
+        let target = dir.join("new/sub/doc.ssce");
+
+        assert!(ensure_disk_space(&target, 1024).is_ok());
+        let err = ensure_disk_space(&target, u64::MAX / 2).unwrap_err();
+        assert!(matches!(err, CommandError::Io(ref m) if m.starts_with("Not enough disk space")), "{}", err);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn copied_document_has_an_independent_library_row() {
+        let dir = test_dir("copy");
+        let src = dir.join("src.ssce");
+        let doc = json!({
+            "version": "1.1",
+            "keywords": ["original"],
+            "frontMatter": { "title": "Original", "summary": "Shared summary" }
+        });
+        fs::write(&src, doc.to_string()).unwrap();
+
+        let conn = test_db();
+        rebuild_library(&conn, &dir, &AtomicBool::new(false), &RebuildOptions::default()).unwrap();
+        let src_key = normalize_path(&src.to_string_lossy());
+        conn.execute("UPDATE files SET last_opened = '2025-01-01' WHERE path = ?1", params![src_key])
+            .unwrap();
+
+        let dest = dir.join("copies/dest.ssce");
+        copy_document(&conn, &src, &dest, false, &RebuildOptions::default()).unwrap();
+        let dest_key = normalize_path(&dest.to_string_lossy());
+
+        let row = |path: &str| -> (String, Option<String>) {
+            conn.query_row(
+                "SELECT title, last_opened FROM files WHERE path = ?1",
+                params![path],
+                |r| Ok((r.get(0)?, r.get(1)?)),
+            )
+            .unwrap()
+        };
+        assert_eq!(row(&dest_key), ("Original".to_string(), None));
+
+        update_metadata(&conn, &dest_key, Some("Edited copy".into()), None, None).unwrap();
+        assert_eq!(row(&src_key), ("Original".to_string(), Some("2025-01-01".to_string())));
+        assert_eq!(row(&dest_key).0, "Edited copy");
+
+        // Refuses to overwrite unless asked
+        assert!(copy_document(&conn, &src, &dest, false, &RebuildOptions::default()).is_err());
+        assert!(copy_document(&conn, &src, &dest, true, &RebuildOptions::default()).is_ok());
+        assert!(copy_document(&conn, &src, &src, true, &RebuildOptions::default()).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn remove_directory_rejects_non_empty_and_outside_root() {
+        let dir = test_dir("remove-dir");
+        let root = dir.join("lib");
+        make_directory(root.join("full/sub").to_string_lossy().to_string()).unwrap();
+        make_directory(root.join("empty").to_string_lossy().to_string()).unwrap();
+        fs::create_dir_all(dir.join("outside")).unwrap();
+
+        assert!(matches!(
+            make_directory(root.join("empty").to_string_lossy().to_string()),
+            Err(DirectoryError::AlreadyExists(_))
+        ));
+        assert!(matches!(
+            remove_directory_within(&root.join("full"), &root, false),
+            Err(DirectoryError::NotEmpty(_))
+        ));
+        assert!(matches!(
+            remove_directory_within(&dir.join("outside"), &root, true),
+            Err(DirectoryError::OutsideRoot(_))
+        ));
+        assert!(matches!(
+            remove_directory_within(&root.join("../outside"), &root, true),
+            Err(DirectoryError::OutsideRoot(_))
+        ));
+        assert!(matches!(
+            remove_directory_within(&root, &root, true),
+            Err(DirectoryError::OutsideRoot(_))
+        ));
+
+        remove_directory_within(&root.join("empty"), &root, false).unwrap();
+        remove_directory_within(&root.join("full"), &root, true).unwrap();
+        assert!(!root.join("empty").exists() && !root.join("full").exists());
+        assert!(dir.join("outside").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn file_lock_refuses_fresh_lock_and_reclaims_stale_one() {
+        let dir = test_dir("file-lock");
+        let doc = dir.join("doc.ssce");
+        let now = chrono::Utc::now();
+
+        acquire_lock(&doc, 100, now).unwrap();
+        assert_eq!(read_lock(&lock_path(&doc)).unwrap().pid, 100);
+
+        // Same process may re-acquire, another may not
+        acquire_lock(&doc, 100, now).unwrap();
+        let err = acquire_lock(&doc, 200, now + chrono::Duration::minutes(5)).unwrap_err();
+        assert!(matches!(err, CommandError::Permission(ref m) if m.contains("PID 100")), "{}", err);
+
+        // Releasing someone else's lock leaves it in place
+        release_lock(&doc, 200).unwrap();
+        assert!(lock_path(&doc).exists());
+
+        // Once stale, the lock can be taken over
+        let later = now + chrono::Duration::seconds(LOCK_STALE_AFTER_SECS + 1);
+        acquire_lock(&doc, 200, later).unwrap();
+        assert_eq!(read_lock(&lock_path(&doc)).unwrap().pid, 200);
+
+        release_lock(&doc, 200).unwrap();
+        assert!(!lock_path(&doc).exists());
+        release_lock(&doc, 200).unwrap();
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn encrypted_ssce_round_trips_and_rejects_wrong_passphrase() {
+        let dir = test_dir("encrypted-ssce");
+        let path = dir.join("secret.ssce");
+        let path_str = path.to_string_lossy().to_string();
+        let data = json!({ "version": "1.1", "thumbnail": "data:x", "snapshots": [{}] }).to_string();
+
+        write_ssce_encrypted(path_str.clone(), data.clone(), "correct horse".to_string(), &RetryPolicy::default())
+            .unwrap();
+
+        let raw = fs::read(&path).unwrap();
+        assert!(raw.starts_with(ENCRYPTED_SSCE_MAGIC));
+        let header = &raw[ENCRYPTED_SSCE_MAGIC.len()..][..KdfParams::ENCODED_LEN];
+        assert_eq!(KdfParams::from_bytes(header), ENCRYPTION_KDF);
+        assert!(!String::from_utf8_lossy(&raw).contains("thumbnail"));
+
+        assert_eq!(read_ssce_encrypted(&path_str, "correct horse").unwrap(), data);
+        let err = read_ssce_encrypted(&path_str, "wrong").unwrap_err();
+        assert!(matches!(err, CommandError::Validation(ref m) if m.contains("Wrong passphrase")), "{}", err);
+        assert!(read_ssce(&path_str, &RetryPolicy::default()).is_err());
+
+        // Encoded paths (from listings of non-UTF-8 names) reach the file too
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            let odd = dir.join(std::ffi::OsStr::from_bytes(b"s\xe9cret.ssce"));
+            let encoded = encode_os_path(odd.as_os_str()).unwrap();
+            write_ssce_encrypted(encoded.clone(), data.clone(), "staple".to_string(), &RetryPolicy::default())
+                .unwrap();
+            assert!(odd.exists());
+            assert_eq!(read_ssce_encrypted(&encoded, "staple").unwrap(), data);
+        }
+
+        let metadata = get_ssce_metadata(path_str.clone(), None).unwrap();
+        assert_eq!(metadata.thumbnail, None);
+        assert_eq!(metadata.snapshot_count, 0);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn encrypted_ssce_uses_the_kdf_parameters_in_its_header() {
+        let cheap = KdfParams { memory_kib: 64, iterations: 1, parallelism: 1 };
+        let encrypted = encrypt_ssce_with(b"{}", "pass", cheap).unwrap();
+        assert_eq!(decrypt_ssce(&encrypted, "pass").unwrap(), "{}");
+
+        // Files written before the parameters were recorded
+        let mut legacy = encrypt_ssce_with(b"{}", "pass", LEGACY_KDF).unwrap();
+        legacy.splice(..ENCRYPTED_SSCE_MAGIC.len() + KdfParams::ENCODED_LEN, ENCRYPTED_SSCE_MAGIC_V1.iter().copied());
+        assert_eq!(decrypt_ssce(&legacy, "pass").unwrap(), "{}");
+
+        // A header asking for absurd memory, passes or lanes is refused
+        // before deriving
+        let crafted_with = |params: KdfParams| {
+            let mut crafted = encrypted.clone();
+            crafted[ENCRYPTED_SSCE_MAGIC.len()..][..KdfParams::ENCODED_LEN].copy_from_slice(&params.to_bytes());
+            decrypt_ssce(&crafted, "pass")
+        };
+        let refused = |params: KdfParams, what: &str| {
+            assert!(
+                matches!(crafted_with(params), Err(CommandError::Validation(ref m)) if m.contains(what)),
+                "{:?}",
+                params
+            );
+        };
+        refused(KdfParams { memory_kib: u32::MAX, ..cheap }, "memory");
+        refused(KdfParams { memory_kib: MAX_KDF_MEMORY_KIB + 1, ..cheap }, "memory");
+        refused(KdfParams { iterations: u32::MAX, ..cheap }, "iterations");
+        refused(KdfParams { iterations: MAX_KDF_ITERATIONS + 1, ..cheap }, "iterations");
+        refused(KdfParams { parallelism: u32::MAX, ..cheap }, "lanes");
+        refused(KdfParams { parallelism: MAX_KDF_PARALLELISM + 1, ..cheap }, "lanes");
+    }
+
+    /// A small solid PNG to build thumbnails from
+    fn write_test_png(path: &Path, width: u32, height: u32) {
+        image::RgbaImage::from_pixel(width, height, image::Rgba([200, 40, 40, 255]))
+            .save(path)
+            .unwrap();
+    }
+
+    #[test]
+    fn thumbnail_cache_reuses_entries_until_source_changes() {
+        let dir = test_dir("thumbnail-cache");
+        let cache = ThumbnailCache::new(dir.join("cache"), DEFAULT_THUMBNAIL_CACHE_BYTES);
+        let source = dir.join("photo.png");
+        write_test_png(&source, 400, 200);
+
+        let key = ThumbnailCache::key(&source, 100).unwrap();
+        let png = cache.get_or_generate(&key, &source, || render_thumbnail(&source, 100, ThumbnailEncoding::PNG, &ImageLimits::default())).unwrap();
+        let thumb = image::load_from_memory(&png).unwrap();
+        assert_eq!((thumb.width(), thumb.height()), (100, 50));
+
+        // A hit doesn't call the generator
+        let cached = cache.get_or_generate(&key, &source, || panic!("should be cached")).unwrap();
+        assert_eq!(cached, png);
+        assert_eq!(cache.stats().entries, 1);
+
+        // Rewriting the source with a new mtime changes the key
+        let file = fs::File::options().write(true).open(&source).unwrap();
+        file.set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(60)).unwrap();
+        assert_ne!(ThumbnailCache::key(&source, 100).unwrap(), key);
+        assert_ne!(ThumbnailCache::key(&source, 50).unwrap(), key);
+
+        cache.clear().unwrap();
+        assert_eq!(cache.stats(), ThumbnailCacheStats { entries: 0, bytes: 0 });
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn thumbnails_are_encoded_in_the_requested_format() {
+        let dir = test_dir("thumbnail-formats");
+        let cache = ThumbnailCache::new(dir.join("cache"), DEFAULT_THUMBNAIL_CACHE_BYTES);
+        let source = dir.join("photo.png");
+        write_test_png(&source, 400, 200);
+
+        let cases = [
+            (ThumbnailFormat::Png, "data:image/png;base64,", image::ImageFormat::Png),
+            (ThumbnailFormat::Webp, "data:image/webp;base64,", image::ImageFormat::WebP),
+            (ThumbnailFormat::Jpeg, "data:image/jpeg;base64,", image::ImageFormat::Jpeg),
+            // Not built in: falls back to PNG
+            (ThumbnailFormat::Avif, "data:image/png;base64,", image::ImageFormat::Png),
+        ];
+        for (format, mime_prefix, expected) in cases {
+            let url = thumbnail_data_url(&cache, &source, 100, ThumbnailEncoding::new(format, None)).unwrap();
+            assert!(url.starts_with(mime_prefix), "{:?}: {}", format, &url[..30]);
+            let bytes = decode_data_url(&url).unwrap();
+            assert_eq!(image::guess_format(&bytes).unwrap(), expected);
+            let thumb = image::load_from_memory(&bytes).unwrap();
+            assert_eq!((thumb.width(), thumb.height()), (100, 50));
+        }
+
+        // Each format and JPEG quality is cached separately; AVIF shares PNG's entry
+        assert_eq!(cache.stats().entries, 3);
+        let key = |format, quality| ThumbnailCache::key_for(&source, 100, ThumbnailEncoding::new(format, quality)).unwrap();
+        assert_ne!(key(ThumbnailFormat::Jpeg, Some(50)), key(ThumbnailFormat::Jpeg, Some(90)));
+        assert_eq!(key(ThumbnailFormat::Webp, Some(50)), key(ThumbnailFormat::Webp, Some(90)));
+        assert_eq!(key(ThumbnailFormat::Avif, None), ThumbnailCache::key(&source, 100).unwrap());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn thumbnail_cache_evicts_least_recently_used() {
+        let dir = test_dir("thumbnail-evict");
+        let cache = ThumbnailCache::new(dir.join("cache"), 250);
+        let entry = |n: u8| move || Ok(vec![n; 100]);
+
+        cache.get_or_generate("a", Path::new("/docs/a.png"), entry(1)).unwrap();
+        cache.get_or_generate("b", Path::new("/docs/b.png"), entry(2)).unwrap();
+
+        // Make "a" the most recently used, so adding "c" evicts "b"
+        let old = std::time::SystemTime::now() - std::time::Duration::from_secs(60);
+        fs::File::options().write(true).open(cache.entry_path("b")).unwrap().set_modified(old).unwrap();
+        fs::File::options().write(true).open(cache.entry_path("a")).unwrap().set_modified(old).unwrap();
+        cache.read("a").unwrap();
+        cache.get_or_generate("c", Path::new("/docs/c.png"), entry(3)).unwrap();
+
+        assert!(cache.entry_path("a").exists());
+        assert!(!cache.entry_path("b").exists());
+        assert!(cache.entry_path("c").exists());
+        assert_eq!(cache.stats(), ThumbnailCacheStats { entries: 2, bytes: 200 });
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn thumbnail_cache_coalesces_concurrent_generation() {
+        let dir = test_dir("thumbnail-coalesce");
+        let cache = ThumbnailCache::new(dir.join("cache"), DEFAULT_THUMBNAIL_CACHE_BYTES);
+        let generated = std::sync::atomic::AtomicUsize::new(0);
+
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    cache
+                        .get_or_generate("same", Path::new("/docs/same.png"), || {
+                            generated.fetch_add(1, Ordering::SeqCst);
+                            std::thread::sleep(std::time::Duration::from_millis(50));
+                            Ok(vec![7; 10])
+                        })
+                        .unwrap()
+                });
+            }
+        });
+
+        assert_eq!(generated.load(Ordering::SeqCst), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn thumbnail_batch_reports_each_file_separately() {
+        let dir = test_dir("thumbnail-batch");
+        let cache = ThumbnailCache::new(dir.join("cache"), DEFAULT_THUMBNAIL_CACHE_BYTES);
+        let wide = dir.join("wide.png");
+        let tall = dir.join("tall.png");
+        write_test_png(&wide, 400, 200);
+        write_test_png(&tall, 100, 300);
+        let broken = dir.join("broken.png");
+        fs::write(&broken, b"not an image").unwrap();
+
+        let request = |path: &Path, max_dim| ThumbnailRequest {
+            path: path.to_string_lossy().to_string(),
+            max_dim,
+        };
+        let requests = vec![
+            request(&wide, None),
+            request(&tall, Some(60)),
+            request(&broken, None),
+            request(&dir.join("missing.png"), None),
+        ];
+
+        let pools = ThumbnailPool::default();
+        let results = load_thumbnails(&cache, &requests, 100, &pools.get(2).unwrap());
+        assert_eq!(results.len(), 4);
+
+        let size = |path: &Path| {
+            let url = results[&path.to_string_lossy().to_string()].thumbnail.clone().unwrap();
+            let thumb = image::load_from_memory(&decode_data_url(&url).unwrap()).unwrap();
+            (thumb.width(), thumb.height())
+        };
+        assert_eq!(size(&wide), (100, 50));
+        assert_eq!(size(&tall), (20, 60));
+        assert!(results[&broken.to_string_lossy().to_string()].error.is_some());
+        assert!(matches!(
+            results[&dir.join("missing.png").to_string_lossy().to_string()].error,
+            Some(CommandError::NotFound(_))
+        ));
+
+        // Repeats are served from the cache
+        assert_eq!(cache.stats().entries, 2);
+        let again = load_thumbnails(&cache, &requests[..2], 100, &pools.get(1).unwrap());
+        assert_eq!(again.len(), 2);
+        assert_eq!(cache.stats().entries, 2);
+
+        // The pool is kept until the concurrency changes
+        assert!(Arc::ptr_eq(&pools.get(1).unwrap(), &pools.get(1).unwrap()));
+        assert_eq!(pools.get(3).unwrap().current_num_threads(), 3);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ssce_thumbnail_is_derived_from_first_usable_snapshot() {
+        let dir = test_dir("derive-thumbnail");
+        let png_path = dir.join("snap.png");
+        write_test_png(&png_path, 600, 300);
+        let snapshot_url = format!("data:image/png;base64,{}", STANDARD.encode(fs::read(&png_path).unwrap()));
+
+        let doc = dir.join("old.ssce");
+        let doc_str = doc.to_string_lossy().to_string();
+        let ssce = json!({
+            "version": "1.0",
+            "snapshots": [{ "id": 1, "image": "not an image" }, { "id": 2, "image": snapshot_url }],
+        });
+        fs::write(&doc, ssce.to_string()).unwrap();
+
+        let derived = generate_ssce_thumbnail(doc_str.clone(), None).unwrap().unwrap();
+        let thumb = image::load_from_memory(&decode_data_url(&derived).unwrap()).unwrap();
+        assert_eq!((thumb.width(), thumb.height()), (150, 75));
+        assert_eq!(get_ssce_metadata(doc_str.clone(), Some(true)).unwrap().thumbnail, Some(derived.clone()));
+
+        // Only written into the file when asked
+        assert!(!fs::read_to_string(&doc).unwrap().contains("\"thumbnail\""));
+        generate_ssce_thumbnail(doc_str.clone(), Some(true)).unwrap();
+        let saved: serde_json::Value = serde_json::from_str(&fs::read_to_string(&doc).unwrap()).unwrap();
+        assert_eq!(saved["thumbnail"].as_str(), Some(derived.as_str()));
+
+        let empty = dir.join("empty.ssce");
+        fs::write(&empty, json!({ "version": "1.0", "snapshots": [] }).to_string()).unwrap();
+        assert_eq!(generate_ssce_thumbnail(empty.to_string_lossy().to_string(), Some(true)).unwrap(), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ssce_stats_count_text_and_flag_malformed_parts() {
+        let doc = json!({
+            "version": "1.1",
+            "frontMatter": { "title": "Release notes", "summary": "" },
+            "layers": [
+                { "type": "text", "data": { "text": "Click  here\nthen save" } },
+                { "type": "image", "data": {} },
+                { "type": "arrow", "data": {} }
+            ],
+            "snapshots": [{ "frontMatter": { "title": "Before", "summary": "héllo" } }, {}]
+        });
+        assert_eq!(
+            ssce_stats(&doc),
+            SsceStats { snapshot_count: 2, word_count: 8, image_count: 1, character_count: 45, partial: false }
+        );
+
+        let damaged = json!({
+            "frontMatter": { "title": 42 },
+            "layers": [{ "data": {} }, { "type": "image" }],
+            "snapshots": ["oops"]
+        });
+        assert_eq!(
+            ssce_stats(&damaged),
+            SsceStats { snapshot_count: 1, word_count: 0, image_count: 1, character_count: 0, partial: true }
+        );
+
+        let dir = test_dir("stats");
+        let truncated = dir.join("truncated.ssce");
+        fs::write(&truncated, r#"{"version":"1.1","layers":["#).unwrap();
+        let stats = get_ssce_stats(truncated.to_string_lossy().to_string()).unwrap();
+        assert!(stats.partial);
+        assert!(get_ssce_stats(dir.join("missing.ssce").to_string_lossy().to_string()).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn migrate_ssce_upgrades_old_documents_and_refuses_downgrades() {
+        let dir = test_dir("migrate");
+        let png_path = dir.join("snap.png");
+        write_test_png(&png_path, 300, 300);
+        let snapshot_url = format!("data:image/png;base64,{}", STANDARD.encode(fs::read(&png_path).unwrap()));
+
+        let doc = dir.join("old.ssce");
+        let original = json!({ "version": "1.0", "snapshots": [{ "id": 1, "image": snapshot_url }] }).to_string();
+        fs::write(&doc, &original).unwrap();
+        assert_eq!(get_ssce_version(doc.to_string_lossy().to_string()).unwrap().as_deref(), Some("1.0"));
+
+        let result = migrate_ssce_file(&doc, SSCE_FORMAT_VERSION, &RetryPolicy::default()).unwrap();
+        assert_eq!(result, SsceMigration { from: "1.0".to_string(), to: "1.1".to_string(), migrated: true });
+        let migrated: serde_json::Value = serde_json::from_str(&fs::read_to_string(&doc).unwrap()).unwrap();
+        assert_eq!(migrated["version"], "1.1");
+        assert!(migrated["thumbnail"].as_str().unwrap().starts_with("data:image/png"));
+        assert_eq!(migrated["keywords"], json!([]));
+        assert_eq!(fs::read_to_string(path_with_suffix(&doc, ".bak")).unwrap(), original);
+
+        // Already current: nothing to do
+        assert!(!migrate_ssce_file(&doc, "1.1", &RetryPolicy::default()).unwrap().migrated);
+
+        let err = migrate_ssce_file(&doc, "1.0", &RetryPolicy::default()).unwrap_err();
+        assert!(matches!(err, CommandError::UnsupportedVersion(ref m) if m.contains("downgrade")), "{}", err);
+
+        let newer = dir.join("newer.ssce");
+        fs::write(&newer, json!({ "schemaVersion": "2.0" }).to_string()).unwrap();
+        assert_eq!(get_ssce_version(newer.to_string_lossy().to_string()).unwrap().as_deref(), Some("2.0"));
+        let err = migrate_ssce_file(&newer, SSCE_FORMAT_VERSION, &RetryPolicy::default()).unwrap_err();
+        assert!(matches!(err, CommandError::UnsupportedVersion(_)), "{}", err);
+        assert!(!path_with_suffix(&newer, ".bak").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn search_history_dedupes_consecutive_queries_and_caps_rows() {
+        let conn = test_db();
+
+        record_search(&conn, "invoice", 3).unwrap();
+        record_search(&conn, "invoice", 5).unwrap();
+        record_search(&conn, "receipt", 1).unwrap();
+        record_search(&conn, "invoice", 4).unwrap();
+
+        let count: i64 = conn.query_row("SELECT COUNT(*) FROM search_history", [], |r| r.get(0)).unwrap();
+        assert_eq!(count, 3);
+
+        let recent = recent_searches(&conn, 10).unwrap();
+        let queries: Vec<_> = recent.iter().map(|e| (e.query.as_str(), e.result_count)).collect();
+        assert_eq!(queries, vec![("invoice", 4), ("receipt", 1)]);
+
+        for i in 0..MAX_SEARCH_HISTORY + 20 {
+            record_search(&conn, &format!("query {}", i), 0).unwrap();
+        }
+        let count: i64 = conn.query_row("SELECT COUNT(*) FROM search_history", [], |r| r.get(0)).unwrap();
+        assert_eq!(count, MAX_SEARCH_HISTORY);
+        assert_eq!(recent_searches(&conn, 1).unwrap()[0].query, format!("query {}", MAX_SEARCH_HISTORY + 19));
+    }
+
+    #[test]
+    fn document_watch_reports_external_edits_only() {
+        let dir = test_dir("document-watch");
+        let path = dir.join("doc.ssce");
+        fs::write(&path, "original").unwrap();
+        fs::write(dir.join("other.ssce"), "other").unwrap();
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let watch = DocumentWatch::start(&path, move |change| {
+            let _ = tx.send(change);
+        })
+        .unwrap();
+        let wait = std::time::Duration::from_secs(5);
+
+        // Another app edits the document
+        fs::write(&path, "edited elsewhere").unwrap();
+        let change = rx.recv_timeout(wait).unwrap();
+        assert_eq!(change, DocumentChange { path: path.to_string_lossy().to_string(), deleted: false });
+
+        // Our own save is recorded first, so it isn't reported
+        write_ssce(path.to_string_lossy().to_string(), "saved by us".to_string(), None, &RetryPolicy::default()).unwrap();
+        watch.record_write(&path);
+        // Neither is a change to another file in the folder
+        fs::write(dir.join("other.ssce"), "changed").unwrap();
+        assert!(rx.recv_timeout(DOCUMENT_WATCH_DEBOUNCE * 3).is_err());
+
+        fs::remove_file(&path).unwrap();
+        assert!(rx.recv_timeout(wait).unwrap().deleted);
+
+        drop(watch);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn file_fingerprint_changes_with_content() {
+        let dir = test_dir("fingerprint");
+        let path = dir.join("doc.ssce");
+        fs::write(&path, "hello").unwrap();
+
+        let first = fingerprint_file(&path, true).unwrap();
+        assert_eq!(first.size, 5);
+        assert_eq!(
+            first.sha256.as_deref(),
+            Some("2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824")
+        );
+        assert_eq!(fingerprint_file(&path, true).unwrap(), first);
+
+        // Same size and mtime, different content: only the hash notices
+        let mtime = fs::metadata(&path).unwrap().modified().unwrap();
+        fs::write(&path, "jello").unwrap();
+        fs::File::options().write(true).open(&path).unwrap().set_modified(mtime).unwrap();
+        let second = fingerprint_file(&path, true).unwrap();
+        assert_eq!((second.size, second.mtime), (first.size, first.mtime));
+        assert_ne!(second.sha256, first.sha256);
+
+        let cheap = fingerprint_file(&path, false).unwrap();
+        assert_eq!(cheap.sha256, None);
+
+        assert!(matches!(fingerprint_file(&dir.join("missing"), true), Err(CommandError::NotFound(_))));
+        assert!(matches!(fingerprint_file(&dir, true), Err(CommandError::Validation(_))));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn hash_file_streams_large_files() {
+        use sha2::{Digest, Sha256};
+
+        let dir = test_dir("hash-file");
+        let path = dir.join("large.bin");
+        // Several buffers' worth, not a multiple of the buffer size
+        let bytes: Vec<u8> = (0..HASH_BUFFER_SIZE * 5 + 123).map(|i| (i % 251) as u8).collect();
+        fs::write(&path, &bytes).unwrap();
+
+        assert_eq!(hash_file(&path).unwrap(), format!("{:x}", Sha256::digest(&bytes)));
+        assert_eq!(fingerprint_file(&path, true).unwrap().sha256, Some(hash_file(&path).unwrap()));
+
+        fs::write(&path, "").unwrap();
+        assert_eq!(
+            hash_file(&path).unwrap(),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert!(matches!(hash_file(&dir.join("missing")), Err(CommandError::NotFound(_))));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn parent_directory_is_canonical() {
+        let dir = test_dir("parent-dir");
+        fs::create_dir_all(dir.join("docs/sub")).unwrap();
+        fs::write(dir.join("docs/a.ssce"), "").unwrap();
+        let docs = dunce::canonicalize(dir.join("docs")).unwrap();
+
+        assert_eq!(parent_directory(&dir.join("docs/a.ssce")).unwrap(), docs);
+        assert_eq!(parent_directory(&dir.join("docs/sub/")).unwrap(), docs);
+        assert_eq!(parent_directory(&dir.join("docs/sub/../a.ssce")).unwrap(), docs);
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(dir.join("docs"), dir.join("link")).unwrap();
+            assert_eq!(parent_directory(&dir.join("link/a.ssce")).unwrap(), docs);
+        }
+
+        assert!(matches!(parent_directory(Path::new("/")), Err(CommandError::Validation(_))));
+        assert!(matches!(parent_directory(Path::new("a.ssce")), Err(CommandError::Validation(_))));
+        assert!(matches!(parent_directory(&dir.join("missing/a.ssce")), Err(CommandError::NotFound(_))));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn search_suggestions_correct_typos_from_library_words() {
+        let conn = test_db();
+        insert_test_file(&conn, "/library/q1-report.ssce", "Quarterly report");
+        insert_test_file(&conn, "/library/q2-report.ssce", "Sales report");
+        insert_test_file(&conn, "/library/repost.ssce", "Repost");
+        let vocab = SuggestionVocab(Mutex::new(None));
+
+        // "report" is in more documents than "repost", which is as close
+        assert_eq!(search_suggestions(&conn, &vocab, "reprot", 5).unwrap(), vec!["report", "repost"]);
+        assert_eq!(search_suggestions(&conn, &vocab, "Quartely reprot", 1).unwrap(), vec!["quarterly report"]);
+        // Nothing to correct, or nothing close enough
+        assert!(search_suggestions(&conn, &vocab, "sales report", 5).unwrap().is_empty());
+        assert!(search_suggestions(&conn, &vocab, "zzzzzz", 5).unwrap().is_empty());
+
+        // New documents are picked up without restarting
+        insert_test_file(&conn, "/library/invoice.ssce", "Invoice");
+        assert_eq!(search_suggestions(&conn, &vocab, "invocie", 5).unwrap(), vec!["invoice"]);
+        conn.execute("UPDATE files SET deleted_at = '2025-01-01' WHERE path = '/library/invoice.ssce'", [])
+            .unwrap();
+        assert!(search_suggestions(&conn, &vocab, "invocie", 5).unwrap().is_empty());
+    }
+
+    #[test]
+    fn fuzzy_search_finds_typos_when_nothing_matches() {
+        let conn = test_db();
+        insert_test_file(&conn, "/library/report.ssce", "Quarterly numbers");
+        insert_test_file(&conn, "/library/notes.ssce", "Meeting notes");
+        insert_test_file(&conn, "/library/holiday.ssce", "Beach photos");
+
+        let fuzzy = |query: &str| SearchParams { fuzzy: Some(true), ..search_params(query) };
+
+        // Off by default
+        assert!(search_files(&conn, search_params("reprot"), &chrono::Utc).unwrap().is_empty());
+
+        let hits = search_files(&conn, fuzzy("reprot"), &chrono::Utc).unwrap();
+        assert_eq!(hits.iter().map(|f| f.path.as_str()).collect::<Vec<_>>(), vec!["/library/report.ssce"]);
+        assert!(hits[0].fuzzy);
+
+        // Titles count too
+        let hits = search_files(&conn, fuzzy("meetnig"), &chrono::Utc).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].path, "/library/notes.ssce");
+
+        // Exact matches are returned as usual, not flagged
+        let hits = search_files(&conn, fuzzy("beach"), &chrono::Utc).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert!(!hits[0].fuzzy);
+
+        assert!(search_files(&conn, fuzzy("zzzzzz"), &chrono::Utc).unwrap().is_empty());
+    }
+
+    #[test]
+    fn keyword_cooccurrence_counts_pairs_per_document() {
+        let conn = test_db();
+        for (path, keywords) in [
+            ("/a.ssce", "tax receipts 2024"),
+            ("/b.ssce", "Receipts tax tax"),
+            ("/c.ssce", "tax 2024 receipts"),
+            ("/d.ssce", "holiday"),
+            ("/e.ssce", "tax receipts"),
+        ] {
+            insert_test_file(&conn, path, "doc");
+            conn.execute("UPDATE files SET keywords = ?1 WHERE path = ?2", params![keywords, path])
+                .unwrap();
+        }
+        // Trashed documents don't count
+        conn.execute("UPDATE files SET deleted_at = '2025-01-01' WHERE path = '/e.ssce'", []).unwrap();
+
+        let pair = |a: &str, b: &str, count| KeywordPair { a: a.to_string(), b: b.to_string(), count };
+        assert_eq!(
+            keyword_cooccurrence(&conn, 1, 50).unwrap(),
+            vec![pair("receipts", "tax", 3), pair("2024", "receipts", 2), pair("2024", "tax", 2)]
+        );
+        assert_eq!(keyword_cooccurrence(&conn, 3, 50).unwrap(), vec![pair("receipts", "tax", 3)]);
+
+        // Only the first two keywords of each document are paired
+        assert_eq!(
+            keyword_cooccurrence(&conn, 1, 2).unwrap(),
+            vec![pair("receipts", "tax", 2), pair("2024", "tax", 1)]
+        );
+    }
+
+    #[test]
+    fn rename_keyword_matches_whole_tokens_only() {
+        let conn = test_db();
+        insert_test_file(&conn, "/a.ssce", "a");
+        insert_test_file(&conn, "/b.ssce", "b");
+        insert_test_file(&conn, "/c.ssce", "c");
+        let set_keywords = |path: &str, keywords: &str| {
+            conn.execute("UPDATE files SET keywords = ?1 WHERE path = ?2", params![keywords, path])
+                .unwrap();
+        };
+        set_keywords("/a.ssce", "tax 2024 receipts");
+        set_keywords("/b.ssce", "taxonomy notes");
+        set_keywords("/c.ssce", "Tax refund");
+
+        let keywords = |path: &str| -> String {
+            conn.query_row("SELECT keywords FROM files WHERE path = ?1", params![path], |r| r.get(0))
+                .unwrap()
+        };
+
+        assert_eq!(rename_keyword(&conn, "tax", "refund").unwrap(), 2);
+        assert_eq!(keywords("/a.ssce"), "refund 2024 receipts");
+        assert_eq!(keywords("/b.ssce"), "taxonomy notes");
+        // Renaming onto an existing keyword doesn't duplicate it
+        assert_eq!(keywords("/c.ssce"), "refund");
+
+        // The FTS index follows through the update trigger
+        let hits = search_files(&conn, search_params("refund"), &chrono::Utc).unwrap();
+        assert_eq!(hits.len(), 2);
+        assert!(search_files(&conn, search_params("tax"), &chrono::Utc).unwrap().iter().all(|f| f.path == "/b.ssce"));
+
+        assert_eq!(rename_keyword(&conn, "receipts", "").unwrap(), 1);
+        assert_eq!(keywords("/a.ssce"), "refund 2024");
+
+        assert!(matches!(rename_keyword(&conn, "two words", "x"), Err(CommandError::Validation(_))));
+        assert!(matches!(rename_keyword(&conn, "  ", "x"), Err(CommandError::Validation(_))));
+        assert!(matches!(rename_keyword(&conn, "2024", "a b"), Err(CommandError::Validation(_))));
+    }
+
+    #[test]
+    fn rename_keyword_normalizes_like_the_indexer() {
+        let conn = test_db();
+        insert_test_file(&conn, "/a.ssce", "a");
+        insert_test_file(&conn, "/b.ssce", "b");
+        conn.execute("UPDATE files SET keywords = 'Ärger notes' WHERE path = '/a.ssce'", []).unwrap();
+        conn.execute("UPDATE files SET keywords = 'ärger' WHERE path = '/b.ssce'", []).unwrap();
+        let keywords = |path: &str| -> String {
+            conn.query_row("SELECT keywords FROM files WHERE path = ?1", params![path], |r| r.get(0))
+                .unwrap()
+        };
+
+        // Non-ASCII case is matched, and the new name is stored normalized
+        assert_eq!(rename_keyword(&conn, "ÄRGER", "  Streit ").unwrap(), 2);
+        assert_eq!(keywords("/a.ssce"), "streit notes");
+        assert_eq!(keywords("/b.ssce"), "streit");
+
+        // Renaming onto a keyword the document has, in another case, merges them
+        assert_eq!(rename_keyword(&conn, "notes", "STREIT").unwrap(), 1);
+        assert_eq!(keywords("/a.ssce"), "streit");
+    }
+
+    #[test]
+    fn collection_membership_round_trips() {
+        let conn = test_db();
+        insert_test_file(&conn, "/library/a.ssce", "a");
+        insert_test_file(&conn, "/library/b.ssce", "b");
+        insert_test_file(&conn, "/library/c.ssce", "c");
+        let paths = |list: &[&str]| list.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        let members = |name: &str| -> Vec<String> {
+            let mut files: Vec<String> = collection_files(&conn, name).unwrap().into_iter().map(|f| f.path).collect();
+            files.sort();
+            files
+        };
+
+        create_collection(&conn, " Invoices ").unwrap();
+        create_collection(&conn, "Drafts").unwrap();
+        assert!(matches!(create_collection(&conn, "Invoices"), Err(CommandError::Validation(_))));
+        assert!(matches!(create_collection(&conn, "  "), Err(CommandError::Validation(_))));
+
+        assert_eq!(add_to_collection(&conn, "Invoices", &paths(&["/library/a.ssce", "/library/b.ssce"])).unwrap(), 2);
+        // Already a member: not counted again
+        assert_eq!(add_to_collection(&conn, "Invoices", &paths(&["/library/b.ssce", "/library/c.ssce"])).unwrap(), 1);
+        assert_eq!(members("Invoices"), paths(&["/library/a.ssce", "/library/b.ssce", "/library/c.ssce"]));
+
+        // One unknown path and nothing is added
+        let err =
+            add_to_collection(&conn, "Drafts", &paths(&["/library/a.ssce", "/library/missing.ssce"])).unwrap_err();
+        assert!(matches!(err, CommandError::NotFound(_)), "{}", err);
+        assert!(members("Drafts").is_empty());
+        assert!(matches!(
+            add_to_collection(&conn, "Nope", &paths(&["/library/a.ssce"])),
+            Err(CommandError::NotFound(_))
+        ));
+
+        assert_eq!(
+            remove_from_collection(&conn, "Invoices", &paths(&["/library/a.ssce", "/library/missing.ssce"])).unwrap(),
+            1
+        );
+        assert_eq!(members("Invoices"), paths(&["/library/b.ssce", "/library/c.ssce"]));
+
+        // Trashing or deleting a document takes it out of every collection
+        add_to_collection(&conn, "Drafts", &paths(&["/library/b.ssce"])).unwrap();
+        soft_delete_file(&conn, "/library/b.ssce").unwrap();
+        conn.execute("DELETE FROM files WHERE path = '/library/c.ssce'", []).unwrap();
+        assert!(members("Invoices").is_empty());
+
+        let listed = list_collections(&conn).unwrap();
+        assert_eq!(
+            listed.iter().map(|c| (c.name.as_str(), c.file_count)).collect::<Vec<_>>(),
+            vec![("Drafts", 0), ("Invoices", 0)]
+        );
+
+        // A restored document doesn't rejoin by itself
+        conn.execute("UPDATE files SET deleted_at = NULL WHERE path = '/library/b.ssce'", []).unwrap();
+        assert!(members("Drafts").is_empty());
+    }
+
+    #[test]
+    fn tag_colors_are_validated_and_follow_renames() {
+        let conn = test_db();
+
+        set_tag_color(&conn, "Tax", Some("#f00")).unwrap();
+        set_tag_color(&conn, "notes", Some("#00aa33")).unwrap();
+        for bad in ["red", "#12345", "#GGGGGG", "00AA33", ""] {
+            let err = set_tag_color(&conn, "notes", Some(bad)).unwrap_err();
+            assert!(matches!(err, CommandError::Validation(_)), "{}: {}", bad, err);
+        }
+        assert!(set_tag_color(&conn, "two words", Some("#000")).is_err());
+
+        let colors = tag_colors(&conn).unwrap();
+        assert_eq!(colors.len(), 2);
+        assert_eq!(colors["tax"], "#FF0000");
+        assert_eq!(colors["notes"], "#00AA33");
+
+        rename_keyword(&conn, "TAX", "refund").unwrap();
+        let colors = tag_colors(&conn).unwrap();
+        assert_eq!(colors.get("refund").map(String::as_str), Some("#FF0000"));
+        assert!(!colors.contains_key("tax"));
+
+        // A keyword that already has a colour keeps it
+        rename_keyword(&conn, "refund", "notes").unwrap();
+        assert_eq!(tag_colors(&conn).unwrap(), HashMap::from([("notes".to_string(), "#00AA33".to_string())]));
+
+        // Removing a keyword removes its colour
+        rename_keyword(&conn, "notes", "").unwrap();
+        set_tag_color(&conn, "misc", Some("#abc")).unwrap();
+        set_tag_color(&conn, "misc", None).unwrap();
+        assert!(tag_colors(&conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn bulk_tag_merges_and_strips_keywords_per_path() {
+        let conn = test_db();
+        insert_test_file(&conn, "/a.ssce", "Draft");
+        insert_test_file(&conn, "/b.ssce", "report");
+
+        let paths = vec!["/a.ssce".to_string(), "/missing.ssce".to_string(), "/b.ssce".to_string()];
+        let results = bulk_tag(
+            &conn,
+            &paths,
+            &["Work".to_string(), "report 2025".to_string()],
+            &["draft".to_string()],
+        )
+        .unwrap();
+
+        let summary: Vec<_> = results
+            .iter()
+            .map(|r| (r.path.as_str(), r.keywords.as_deref(), r.error.is_some()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("/a.ssce", Some("work report 2025"), false),
+                ("/missing.ssce", None, true),
+                ("/b.ssce", Some("report work 2025"), false),
+            ]
+        );
+
+        let hits = search_files(&conn, search_params("work"), &chrono::Utc).unwrap();
+        assert_eq!(hits.len(), 2);
+
+        // Case and spacing don't create new entries
+        let results = bulk_tag(&conn, &paths[..1], &["  WORK   Ärger".to_string()], &["ÄRGER".to_string()]).unwrap();
+        assert_eq!(results[0].keywords.as_deref(), Some("work report 2025"));
+
+        // Trashed documents are left alone
+        conn.execute("UPDATE files SET deleted_at = '2025-01-01T00:00:00.000Z' WHERE path = '/b.ssce'", [])
+            .unwrap();
+        let results = bulk_tag(&conn, &paths[2..], &["extra".to_string()], &[]).unwrap();
+        assert!(results[0].error.is_some());
+
+        assert!(matches!(bulk_tag(&conn, &paths, &[" ".to_string()], &[]), Err(CommandError::Validation(_))));
+    }
+
+    #[test]
+    fn export_html_inlines_local_images_and_marks_missing_ones() {
+        let dir = test_dir("export-html");
+        write_test_png(&dir.join("shot.png"), 4, 4);
+        fs::write(dir.join("notes.txt"), "not an image").unwrap();
+        let outside = test_dir("export-html-outside");
+        let secret = outside.join("secret.png");
+        write_test_png(&secret, 4, 4);
+        let escape = format!("../{}/secret.png", outside.file_name().unwrap().to_string_lossy());
+
+        let doc = dir.join("doc.ssce");
+        let ssce = json!({
+            "version": "1.1",
+            "frontMatter": { "title": "Q3 <Report>" },
+            "snapshots": [
+                { "id": 1, "image": "data:image/png;base64,AAAA", "frontMatter": { "title": "Inline" } },
+                { "id": 2, "image": "shot.png", "frontMatter": { "summary": "From disk" } },
+                { "id": 3, "image": "gone.png" },
+                { "id": 4, "image": "notes.txt" },
+                { "id": 5, "image": secret.to_string_lossy() },
+                { "id": 6, "image": escape },
+            ],
+        });
+        fs::write(&doc, ssce.to_string()).unwrap();
+
+        let out = dir.join("out/doc.html");
+        let (ssce_path, out_path) = (doc.to_string_lossy().to_string(), out.to_string_lossy().to_string());
+        let result = write_html_export(ssce_path, out_path, &ImageLimits::default()).unwrap();
+        // Non-images and files outside the document's folder are never embedded
+        assert_eq!((result.images_inlined, result.images_missing), (1, 4));
+
+        let html = fs::read_to_string(&out).unwrap();
+        assert!(html.contains("<h1>Q3 &lt;Report&gt;</h1>"));
+        assert!(html.contains("data:image/png;base64,AAAA"));
+        assert_eq!(html.matches("data:image/png;base64,iVBOR").count(), 1);
+        assert!(html.contains("Image not found: gone.png"));
+        assert!(html.contains("Image not found: notes.txt"));
+        assert!(html.contains("<h3>Snapshot 2</h3>"));
+        assert!(!html.contains("src=\"shot.png\""));
+
+        fs::remove_dir_all(&dir).unwrap();
+        fs::remove_dir_all(&outside).unwrap();
+    }
+
+    #[test]
+    fn export_html_refuses_data_urls_that_could_inject_markup() {
+        let ssce = json!({
+            "snapshots": [
+                { "id": 1, "image": "data:x\" onerror=\"alert(1)" },
+                { "id": 2, "image": "data:image/png;base64,AAAA\" onerror=\"alert(1)" },
+                { "id": 3, "image": "data:image/svg+xml;utf8,<svg onload=alert(1)>" },
+                { "id": 4, "image": "data:image/png;base64,AAAA" },
+            ],
+        });
+
+        let (html, inlined, missing) =
+            render_ssce_html(&ssce, Path::new("/nonexistent"), "doc", &ImageLimits::default());
+        assert_eq!((inlined, missing), (0, 3));
+        assert!(!html.contains("onerror"));
+        assert!(!html.contains("onload"));
+        assert_eq!(html.matches("Image not found: invalid embedded image").count(), 3);
+        assert!(html.contains("<img src=\"data:image/png;base64,AAAA\""));
+
+        assert_eq!(embeddable_data_url("data:image/jpeg;base64,/9j/"), Some("data:image/jpeg;base64,/9j/"));
+        assert_eq!(embeddable_data_url("data:image/png;base64,!!!"), None);
+        assert_eq!(embeddable_data_url("data:text/html;base64,AAAA"), None);
+    }
+
+    #[test]
+    fn export_pdf_writes_one_page_per_snapshot() {
+        let dir = test_dir("export-pdf");
+        write_test_png(&dir.join("shot.png"), 40, 20);
+
+        let doc = dir.join("doc.ssce");
+        let ssce = json!({
+            "version": "1.1",
+            "frontMatter": { "title": "Quarterly" },
+            "snapshots": [
+                { "id": 1, "image": "shot.png", "frontMatter": { "title": "Caf\u{e9}", "summary": "First page" } },
+                { "id": 2, "image": "missing.png" },
+            ],
+        });
+        fs::write(&doc, ssce.to_string()).unwrap();
+
+        let out = dir.join("doc.pdf");
+        let mut seen = Vec::new();
+        let limits = ImageLimits::default();
+        let pages =
+            export_pdf_to(&doc, &out, PageSize::Letter, 10.0, &limits, |page, total| seen.push((page, total))).unwrap();
+        assert_eq!(pages, 2);
+        assert_eq!(seen, vec![(1, 2), (2, 2)]);
+
+        let pdf = fs::read(&out).unwrap();
+        assert!(pdf.len() > 100);
+        assert!(pdf.starts_with(b"%PDF-"));
+        assert!(String::from_utf8_lossy(&pdf).contains("/Count 2"));
+
+        assert!(export_pdf_to(&doc, &out, PageSize::A4, 400.0, &limits, |_, _| {}).is_err());
+
+        // The configured limits apply: an image over them isn't drawn
+        let tiny = ImageLimits { max_dimension: 10, ..ImageLimits::default() };
+        export_pdf_to(&doc, &out, PageSize::Letter, 10.0, &tiny, |_, _| {}).unwrap();
+        assert!(!String::from_utf8_lossy(&fs::read(&out).unwrap()).contains("/Im1"));
+
+        // A summary that fills the page leaves no room for the image, and
+        // no reference to an image that was never written
+        let crowded = PdfPage {
+            title: "Crowded".to_string(),
+            summary: "word ".repeat(3000),
+            image: Ok(image::DynamicImage::new_rgb8(4, 4)),
+        };
+        let pdf = render_pdf(&[crowded], PageSize::A4, 15.0, |_, _| {}).unwrap();
+        assert!(!String::from_utf8_lossy(&pdf).contains("/Im1"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn save_rgba_png_writes_decodable_png() {
+        let dir = test_dir("clipboard-png");
+        let path = dir.join("nested/paste.png");
+        let pixels: Vec<u8> = [10, 20, 30, 255].repeat(6);
+
+        save_rgba_png(&path, pixels.clone(), 3, 2).unwrap();
+        let saved = image::open(&path).unwrap().to_rgba8();
+        assert_eq!(saved.dimensions(), (3, 2));
+        assert_eq!(saved.into_raw(), pixels);
+
+        assert!(save_rgba_png(&path, vec![0; 5], 3, 2).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn settings_fall_back_to_config_then_default_and_are_type_checked() {
+        let conn = test_db();
+        let config = typed_defaults(json!({ "autosave": { "inactivitySeconds": 45 } })).unwrap();
+
+        assert_eq!(read_setting(&conn, &config, "autosaveIntervalSeconds").unwrap(), json!(45));
+        assert_eq!(read_setting(&conn, &Defaults::default(), "autosaveIntervalSeconds").unwrap(), json!(30));
+        assert_eq!(
+            read_setting(&conn, &config, "thumbnailMaxDim").unwrap(),
+            json!(DEFAULT_THUMBNAIL_MAX_DIM)
+        );
+
+        write_setting(&conn, "autosaveIntervalSeconds", &json!(90)).unwrap();
+        assert_eq!(read_setting(&conn, &config, "autosaveIntervalSeconds").unwrap(), json!(90));
+
+        assert!(write_setting(&conn, "autosaveIntervalSeconds", &json!("soon")).is_err());
+        assert!(write_setting(&conn, "autosaveIntervalSeconds", &json!(0)).is_err());
+        assert!(write_setting(&conn, "noSuchSetting", &json!(1)).is_err());
+        assert!(read_setting(&conn, &config, "noSuchSetting").is_err());
+
+        // Every built-in default satisfies its own type
+        for spec in SETTINGS {
+            let default: serde_json::Value = serde_json::from_str(spec.default).unwrap();
+            assert!(spec.kind.check(&default).0, "{}", spec.key);
+        }
+    }
+
+    #[test]
+    fn recent_folders_count_visits_and_skip_missing_folders() {
+        let conn = test_db();
+        let dir = test_dir("recent-folders");
+        fs::create_dir_all(dir.join("a")).unwrap();
+        fs::create_dir_all(dir.join("b")).unwrap();
+        let a = dir.join("a").to_string_lossy().to_string();
+        let b = dir.join("b").to_string_lossy().to_string();
+
+        record_folder_visit(&conn, &a).unwrap();
+        record_folder_visit(&conn, &b).unwrap();
+        record_folder_visit(&conn, &a).unwrap();
+        record_folder_visit(&conn, "/no/such/folder").unwrap();
+
+        let folders = recent_folders(&conn, 10).unwrap();
+        let summary: Vec<_> = folders.iter().map(|f| (f.path.clone(), f.visit_count)).collect();
+        assert_eq!(summary.len(), 2);
+        assert!(summary.contains(&(normalize_path(&a), 2)));
+        assert!(summary.contains(&(normalize_path(&b), 1)));
+        assert_eq!(recent_folders(&conn, 1).unwrap().len(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Statements currently prepared on the connection. Plain `prepare`
+    /// finalizes its statement when dropped; cached ones stay alive.
+    fn live_statements(conn: &Connection) -> usize {
+        let mut count = 0;
+        // SAFETY: only walks the connection's statement list, which isn't
+        // modified while we hold the only reference to the connection
+        unsafe {
+            let db = conn.handle();
+            let mut stmt = rusqlite::ffi::sqlite3_next_stmt(db, std::ptr::null_mut());
+            while !stmt.is_null() {
+                count += 1;
+                stmt = rusqlite::ffi::sqlite3_next_stmt(db, stmt);
+            }
+        }
+        count
+    }
+
+    #[test]
+    fn repeated_queries_reuse_cached_statements() {
+        let conn = test_db();
+        conn.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
+        insert_test_file(&conn, "/a.ssce", "alpha");
+        let dated = || {
+            let mut params = search_params("alpha");
+            params.from_date = Some("2024-01-01".to_string());
+            params
+        };
+
+        // First calls prepare (and keep) their statements; FTS5 also keeps
+        // a few internal statements of its own after the first MATCH
+        let before = live_statements(&conn);
+        recent_files(&conn, 10).unwrap();
+        search_files(&conn, search_params("alpha"), &chrono::Utc).unwrap();
+        search_files(&conn, dated(), &chrono::Utc).unwrap();
+        let warm = live_statements(&conn);
+        assert!(warm > before);
+
+        // Repeats reuse them rather than preparing new ones
+        for _ in 0..100 {
+            recent_files(&conn, 10).unwrap();
+            search_files(&conn, search_params("alpha"), &chrono::Utc).unwrap();
+            search_files(&conn, dated(), &chrono::Utc).unwrap();
+        }
+        assert_eq!(live_statements(&conn), warm);
+    }
+
+    #[test]
+    fn stream_directory_sends_folders_first_in_batches() {
+        let dir = test_dir("browse-stream");
+        fs::create_dir_all(dir.join("zeta")).unwrap();
+        fs::create_dir_all(dir.join("Alpha")).unwrap();
+        for name in ["e.ssce", "b.ssce", "d.txt", "a.ssce", "c.ssce", ".hidden.ssce"] {
+            fs::write(dir.join(name), name).unwrap();
+        }
+
+        let mut batches: Vec<Vec<(String, bool)>> = Vec::new();
+        let total = stream_directory(&dir, &FileFilter::parse("ssce").unwrap(), 3, |entries| {
+            batches.push(entries.into_iter().map(|e| (e.name, e.is_dir)).collect());
+        })
+        .unwrap();
+
+        let names: Vec<Vec<&str>> = batches
+            .iter()
+            .map(|batch| batch.iter().map(|(name, _)| name.as_str()).collect())
+            .collect();
+        assert_eq!(
+            names,
+            vec![vec!["Alpha", "zeta", "a.ssce"], vec!["b.ssce", "c.ssce", "e.ssce"]]
+        );
+        assert_eq!(batches[0].iter().filter(|(_, is_dir)| *is_dir).count(), 2);
+        assert_eq!(total, 6);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rebuild_skips_ignored_files_and_folders() {
+        let dir = test_dir("rebuild-ignore");
+        let doc = json!({ "version": "1.1" }).to_string();
+        fs::create_dir_all(dir.join("backups/2024")).unwrap();
+        fs::create_dir_all(dir.join("work")).unwrap();
+        fs::write(dir.join("keep.ssce"), &doc).unwrap();
+        fs::write(dir.join("work/keep.ssce"), &doc).unwrap();
+        fs::write(dir.join("work/draft.ssce"), &doc).unwrap();
+        fs::write(dir.join("backups/old.ssce"), &doc).unwrap();
+        fs::write(dir.join("backups/2024/older.ssce"), &doc).unwrap();
+        fs::write(dir.join(IGNORE_FILE_NAME), "# old copies\nbackups/\n").unwrap();
+
+        let conn = test_db();
+        // Indexed before the ignore file existed
+        insert_test_file(&conn, &dir.join("backups/old.ssce").to_string_lossy(), "old");
+
+        let options = RebuildOptions {
+            ignore_patterns: vec!["draft.ssce".to_string()],
+            ..RebuildOptions::default()
+        };
+        assert_eq!(rebuild_library(&conn, &dir, &AtomicBool::new(false), &options).unwrap().indexed, 2);
+
+        let mut stmt = conn.prepare("SELECT filename FROM files ORDER BY path").unwrap();
+        let indexed: Vec<String> = stmt
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(indexed, vec!["keep.ssce", "keep.ssce"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rebuild_indexes_configured_extensions() {
+        use std::io::Write;
+
+        let dir = test_dir("rebuild-extensions");
+        let doc = |title: &str| json!({ "version": "1.1", "frontMatter": { "title": title } }).to_string();
+        fs::write(dir.join("plain.ssce"), doc("Plain")).unwrap();
+        fs::write(dir.join("SHOUTY.SSCE"), doc("Shouty")).unwrap();
+        fs::write(dir.join("notes.txt"), "not a document").unwrap();
+        let mut gz = flate2::write::GzEncoder::new(
+            fs::File::create(dir.join("packed.ssce.gz")).unwrap(),
+            flate2::Compression::default(),
+        );
+        gz.write_all(doc("Packed").as_bytes()).unwrap();
+        gz.finish().unwrap();
+
+        let config = typed_defaults(json!({ "indexedExtensions": ["ssce", ".SSCE.GZ"] })).unwrap();
+        let options = RebuildOptions::from_config(&config);
+        assert_eq!(options.extensions, vec!["ssce", "ssce.gz"]);
+        assert_eq!(RebuildOptions::from_config(&Defaults::default()).extensions, vec!["ssce"]);
+
+        let conn = test_db();
+        assert_eq!(rebuild_library(&conn, &dir, &AtomicBool::new(false), &options).unwrap().indexed, 3);
+
+        let mut stmt = conn.prepare("SELECT title FROM files ORDER BY title").unwrap();
+        let titles: Vec<String> = stmt
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(titles, vec!["Packed", "Plain", "Shouty"]);
+
+        let autosaves = list_autosave_entries(&dir.to_string_lossy(), &options.extensions, 0).unwrap();
+        assert_eq!(autosaves.len(), 3);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn compressed_ssce_round_trips() {
+        let dir = test_dir("ssce-gzip");
+        let path = dir.join("big.ssce");
+        let data = serde_json::to_string_pretty(&json!({
+            "version": "1.1",
+            "thumbnail": "data:image/png;base64,AAAA",
+            "frontMatter": { "title": "Compressed" },
+            "snapshots": [{ "id": 1 }, { "id": 2 }],
+            "layers": []
+        }))
+        .unwrap();
+
+        write_ssce(path.to_string_lossy().to_string(), data.clone(), Some(true), &RetryPolicy::default()).unwrap();
+        assert!(is_gzipped(&path));
+        assert!(fs::metadata(&path).unwrap().len() < data.len() as u64);
+
+        assert_eq!(read_ssce(&path.to_string_lossy(), &RetryPolicy::default()).unwrap(), data);
+        let metadata = get_ssce_metadata(path.to_string_lossy().to_string(), Some(true)).unwrap();
+        assert_eq!(metadata.thumbnail.as_deref(), Some("data:image/png;base64,AAAA"));
+        assert_eq!(metadata.snapshot_count, 2);
+
+        // A truncated stream gives a clear error rather than garbage
+        let raw = fs::read(&path).unwrap();
+        fs::write(&path, &raw[..raw.len() / 2]).unwrap();
+        let err = read_ssce(&path.to_string_lossy(), &RetryPolicy::default()).unwrap_err();
+        assert!(matches!(err, CommandError::Parse(ref m) if m.contains("could not be decompressed")), "{}", err);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rebuild_handles_symlinked_folders() {
+        use std::os::unix::fs::symlink;
+
+        let dir = test_dir("rebuild-symlinks");
+        let outside = test_dir("rebuild-symlinks-outside");
+        let doc = json!({ "version": "1.1" }).to_string();
+        fs::create_dir_all(dir.join("a/b")).unwrap();
+        fs::write(dir.join("a/b/doc.ssce"), &doc).unwrap();
+        fs::write(outside.join("elsewhere.ssce"), &doc).unwrap();
+        // A cycle back to the root, a second route to a/b, and a way out
+        symlink(&dir, dir.join("a/b/loop")).unwrap();
+        symlink(dir.join("a/b"), dir.join("shortcut")).unwrap();
+        symlink(&outside, dir.join("outside")).unwrap();
+
+        let conn = test_db();
+        let count = |options: &RebuildOptions| rebuild_library(&conn, &dir, &AtomicBool::new(false), options).unwrap().indexed;
+
+        // Links aren't followed by default
+        assert_eq!(count(&RebuildOptions::default()), 1);
+
+        let follow = RebuildOptions {
+            follow_symlinks: true,
+            ..RebuildOptions::default()
+        };
+        assert_eq!(count(&follow), 2);
+
+        let mut stmt = conn.prepare("SELECT path FROM files ORDER BY path").unwrap();
+        let paths: Vec<String> = stmt
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let canonical = |p: std::path::PathBuf| normalize_path(&dunce::canonicalize(p).unwrap().to_string_lossy());
+        let mut expected = vec![canonical(dir.join("a/b/doc.ssce")), canonical(outside.join("elsewhere.ssce"))];
+        expected.sort();
+        assert_eq!(paths, expected);
+
+        // Commands resolve a path given through a link, and find the same row
+        let linked = dir.join("shortcut/doc.ssce").to_string_lossy().to_string();
+        assert_eq!(normalize_path(&resolve_path(&linked)), canonical(dir.join("a/b/doc.ssce")));
+        record_open(&conn, &resolve_path(&linked), "2025-01-01T00:00:00.000Z").unwrap();
+        let opens: i64 = conn.query_row("SELECT SUM(open_count) FROM files", [], |r| r.get(0)).unwrap();
+        assert_eq!(opens, 1);
+        // A file not created yet resolves through its folder
+        assert_eq!(
+            normalize_path(&resolve_path(&dir.join("shortcut/new.ssce").to_string_lossy())),
+            canonical(dir.join("a/b")) + "/new.ssce"
+        );
+        // normalize_path itself (and so the path migration) never looks at the disk
+        let lexical = normalize_path_with(&linked, cfg!(any(windows, target_os = "macos")), cfg!(windows));
+        assert_eq!(normalize_path(&linked), lexical);
+        assert_ne!(normalize_path(&linked), canonical(dir.join("a/b/doc.ssce")));
+
+        fs::remove_dir_all(&dir).unwrap();
+        fs::remove_dir_all(&outside).unwrap();
+    }
+
+    #[test]
+    fn regenerate_thumbnails_counts_each_outcome() {
+        let dir = test_dir("regenerate-thumbnails");
+        let png_path = dir.join("snap.png");
+        write_test_png(&png_path, 200, 100);
+        let snapshot_url = format!("data:image/png;base64,{}", STANDARD.encode(fs::read(&png_path).unwrap()));
+
+        let with_snapshot = json!({ "version": "1.1", "snapshots": [{ "id": 1, "image": snapshot_url }] });
+        fs::write(dir.join("needs.ssce"), with_snapshot.to_string()).unwrap();
+        let mut has_one = with_snapshot.clone();
+        has_one["thumbnail"] = json!("data:image/png;base64,AAAA");
+        fs::write(dir.join("has.ssce"), has_one.to_string()).unwrap();
+        fs::write(dir.join("broken.ssce"), "{ not json").unwrap();
+
+        let mut updates = Vec::new();
+        let result = regenerate_library_thumbnails(&dir, &RebuildOptions::default(), &AtomicBool::new(false), |done, total| {
+            updates.push((done, total))
+        })
+        .unwrap();
+
+        assert_eq!(
+            result,
+            ThumbnailRegeneration {
+                generated: 1,
+                skipped: 1,
+                failed: 1,
+                cancelled: false
+            }
+        );
+        assert_eq!(updates, vec![(1, 3), (2, 3), (3, 3)]);
+        let saved: serde_json::Value = serde_json::from_str(&fs::read_to_string(dir.join("needs.ssce")).unwrap()).unwrap();
+        assert!(saved["thumbnail"].is_string());
+
+        let cancelled = regenerate_library_thumbnails(&dir, &RebuildOptions::default(), &AtomicBool::new(true), |_, _| {}).unwrap();
+        assert!(cancelled.cancelled);
+        assert_eq!(cancelled.generated + cancelled.skipped + cancelled.failed, 0);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn search_ignores_accents_and_normalization_form() {
+        let conn = test_db();
+        // Stored decomposed ("e" + combining acute), as macOS filenames are
+        insert_test_file(&conn, "/library/menu.ssce", "Cafe\u{301} menu");
+        insert_test_file(&conn, "/library/plain.ssce", "Cafe prices");
+        normalize_stored_text(&conn).unwrap();
+
+        let title: String = conn
+            .query_row("SELECT title FROM files WHERE path = '/library/menu.ssce'", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(title, "Caf\u{e9} menu");
+
+        let found = |query: &str, accent_sensitive: bool| -> Vec<String> {
+            let params = SearchParams {
+                accent_sensitive: Some(accent_sensitive),
+                ..search_params(query)
+            };
+            let mut paths: Vec<String> = search_files(&conn, params, &chrono::Utc)
+                .unwrap()
+                .into_iter()
+                .map(|f| f.path)
+                .collect();
+            paths.sort();
+            paths
+        };
+        let both = vec!["/library/menu.ssce".to_string(), "/library/plain.ssce".to_string()];
+
+        // Precomposed, decomposed and plain queries all find both
+        assert_eq!(found("caf\u{e9}", false), both);
+        assert_eq!(found("cafe\u{301}", false), both);
+        assert_eq!(found("CAFE", false), both);
+
+        // Exact mode keeps the accents, in either form
+        assert_eq!(found("caf\u{e9}", true), vec!["/library/menu.ssce"]);
+        assert_eq!(found("cafe\u{301} menu", true), vec!["/library/menu.ssce"]);
+        assert_eq!(found("cafe", true), vec!["/library/plain.ssce"]);
+    }
+
+    #[test]
+    fn db_state_reopens_after_poisoning_or_a_bad_handle() {
+        let state = DbState::new(test_db());
+        insert_test_file(&state.0.lock().unwrap(), "/a.ssce", "a");
+        let reopen = || Ok(test_db());
+        let count = |conn: &mut Connection| {
+            conn.query_row("SELECT COUNT(*) FROM files", [], |r| r.get::<_, i64>(0))
+                .map_err(|e| e.to_string())
+        };
+
+        // A panic while holding the lock poisons it
+        let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _conn = state.0.lock().unwrap();
+            panic!("boom");
+        }));
+        assert!(state.0.is_poisoned());
+
+        // The fresh (empty) connection is used and the lock is usable again
+        assert_eq!(state.with_conn_reopening(reopen, false, count).unwrap(), 0);
+        assert!(!state.0.is_poisoned());
+
+        // A read hitting a corruption error is retried once on a reopened connection
+        let mut attempts = 0;
+        let result = state.with_conn_reopening(reopen, true, |conn| {
+            attempts += 1;
+            if attempts == 1 {
+                return Err("database disk image is malformed".to_string());
+            }
+            count(conn)
+        });
+        assert_eq!(result.unwrap(), 0);
+        assert_eq!(attempts, 2);
+
+        // A write is never run twice: the handle is reopened for the next
+        // call, and the error returned
+        insert_test_file(&state.0.lock().unwrap(), "/b.ssce", "b");
+        let mut attempts = 0;
+        let result: Result<(), String> = state.with_conn_reopening(reopen, false, |_| {
+            attempts += 1;
+            Err("disk I/O error".to_string())
+        });
+        assert_eq!(result.unwrap_err(), "disk I/O error");
+        assert_eq!(attempts, 1);
+        assert_eq!(state.with_conn_reopening(reopen, false, count).unwrap(), 0);
+
+        // Ordinary errors aren't retried
+        let mut attempts = 0;
+        let result: Result<(), String> = state.with_conn_reopening(reopen, true, |_| {
+            attempts += 1;
+            Err("no such column: nope".to_string())
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn search_settings_are_cached_until_they_change() {
+        let state = DbState::new(test_db());
+        let folds = || state.with_conn(|conn| state.folds_accents(conn).map_err(|e| e.to_string())).unwrap();
+        assert!(folds());
+
+        // A tokenizer that keeps accents isn't seen until the schema is forgotten
+        let strict = FtsTokenizer { remove_diacritics: 0, ..FtsTokenizer::default() };
+        apply_fts_tokenizer(&state.0.lock().unwrap(), &strict).unwrap();
+        assert!(folds());
+        state.forget_schema();
+        assert!(!folds());
+
+        let cache = ConfigCache::default();
+        let mut loads = 0;
+        let mut load = || {
+            cache.get_or_load(|| {
+                loads += 1;
+                Defaults::default()
+            })
+        };
+        load();
+        load();
+        cache.clear();
+        load();
+        assert_eq!(loads, 2);
+    }
+
+    #[test]
+    fn database_export_and_import_round_trip() {
+        let dir = test_dir("database-transfer");
+        let source = open_database(&dir.join("source.db")).unwrap();
+        insert_test_file(&source, "/library/moved.ssce", "moved");
+        backup_database(&source, &dir.join("export.db")).unwrap();
+
+        let live_path = dir.join("live.db");
+        let mut live = open_database(&live_path).unwrap();
+        insert_test_file(&live, "/library/old.ssce", "old");
+
+        import_database_file(&mut live, &dir.join("export.db"), &live_path).unwrap();
+
+        // The live handle now reads the imported library, and so does a new one
+        for conn in [&live, &open_database(&live_path).unwrap()] {
+            let paths: Vec<String> = conn
+                .prepare("SELECT path FROM files")
+                .unwrap()
+                .query_map([], |row| row.get(0))
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap();
+            assert_eq!(paths, vec!["/library/moved.ssce"]);
+            assert_eq!(search_files(conn, search_params("moved"), &chrono::Utc).unwrap().len(), 1);
+        }
+
+        // Newer schemas and non-databases are refused without touching the live file
+        source
+            .pragma_update(None, "user_version", MIGRATIONS.len() as i64 + 1)
+            .unwrap();
+        backup_database(&source, &dir.join("newer.db")).unwrap();
+        let err = import_database_file(&mut live, &dir.join("newer.db"), &live_path).unwrap_err();
+        assert!(matches!(err, CommandError::UnsupportedVersion(_)), "{:?}", err);
+        assert!(err.message().contains("newer version"), "{}", err);
+
+        fs::write(dir.join("junk.db"), "not a database at all, just some text").unwrap();
+        assert!(matches!(
+            import_database_file(&mut live, &dir.join("junk.db"), &live_path),
+            Err(CommandError::Validation(_))
+        ));
+        assert!(matches!(
+            import_database_file(&mut live, &dir.join("missing.db"), &live_path),
+            Err(CommandError::NotFound(_))
+        ));
+
+        let count: i64 = live.query_row("SELECT COUNT(*) FROM files", [], |r| r.get(0)).unwrap();
+        assert_eq!(count, 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn document_window_labels_are_stable_per_document() {
+        let label = document_window_label("/library/a.ssce");
+        assert_eq!(label, document_window_label("/library/./a.ssce"));
+        assert_ne!(label, document_window_label("/library/b.ssce"));
+        assert_eq!(label.len(), "doc-".len() + 16);
+        // Window labels may only use alphanumerics and - / : _
+        assert!(label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'));
+    }
+
+    #[test]
+    fn default_printer_is_read_from_lpstat_output() {
+        assert_eq!(
+            parse_default_printer("system default destination: Office_Laser\n"),
+            Some("Office_Laser".to_string())
+        );
+        assert_eq!(parse_default_printer("no system default destination\n"), None);
+        assert_eq!(parse_default_printer(""), None);
+    }
+
+    #[test]
+    fn save_ssce_refuses_read_only_files_before_writing() {
+        let dir = test_dir("read-only");
+        let doc = dir.join("doc.ssce");
+        fs::write(&doc, "{}").unwrap();
+        assert!(file_writable(doc.to_string_lossy().to_string()));
+        assert!(file_writable(dir.join("new.ssce").to_string_lossy().to_string()));
+
+        let mut perms = fs::metadata(&doc).unwrap().permissions();
+        perms.set_readonly(true);
+        fs::set_permissions(&doc, perms.clone()).unwrap();
+
+        assert!(!file_writable(doc.to_string_lossy().to_string()));
+        let result = write_ssce(doc.to_string_lossy().to_string(), "{\"changed\":true}".to_string(), None, &RetryPolicy::default());
+        assert!(matches!(result, Err(SaveError::ReadOnly(_))));
+        assert_eq!(fs::read_to_string(&doc).unwrap(), "{}");
+
+        #[allow(clippy::permissions_set_readonly_false)]
+        perms.set_readonly(false);
+        fs::set_permissions(&doc, perms).unwrap();
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn save_ssce_keeps_the_existing_file_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = test_dir("keep-mode");
+        let doc = dir.join("doc.ssce");
+        fs::write(&doc, "{}").unwrap();
+        fs::set_permissions(&doc, fs::Permissions::from_mode(0o640)).unwrap();
+
+        write_ssce(doc.to_string_lossy().to_string(), "{\"saved\":true}".to_string(), None, &RetryPolicy::default()).unwrap();
+
+        assert_eq!(fs::read_to_string(&doc).unwrap(), "{\"saved\":true}");
+        assert_eq!(fs::metadata(&doc).unwrap().permissions().mode() & 0o777, 0o640);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn config_changes_are_recognised_by_file_name() {
+        use notify::event::{AccessKind, CreateKind, ModifyKind};
+
+        let config = std::path::PathBuf::from("/home/u/.config/ssce-desktop/defaults.json");
+        let other = std::path::PathBuf::from("/home/u/.config/ssce-desktop/library.db");
+
+        let modified = notify::Event::new(notify::EventKind::Modify(ModifyKind::Any)).add_path(config.clone());
+        let created = notify::Event::new(notify::EventKind::Create(CreateKind::File)).add_path(config.clone());
+        let read = notify::Event::new(notify::EventKind::Access(AccessKind::Any)).add_path(config);
+        let unrelated = notify::Event::new(notify::EventKind::Modify(ModifyKind::Any)).add_path(other);
+
+        assert!(is_config_change(&modified));
+        assert!(is_config_change(&created));
+        assert!(!is_config_change(&read));
+        assert!(!is_config_change(&unrelated));
+    }
+
+    #[test]
+    fn config_backups_rotate_and_restore() {
+        let dir = test_dir("config-backups");
+        let start = chrono::Utc::now();
+
+        // Nothing to back up before the first save
+        backup_config(&dir, 2, start).unwrap();
+        assert!(list_config_backups_in(&dir).is_empty());
+
+        for (i, version) in ["one", "two", "three"].iter().enumerate() {
+            fs::write(dir.join("defaults.json"), format!("{{\"v\":\"{}\"}}", version)).unwrap();
+            backup_config(&dir, 2, start + chrono::Duration::seconds(i as i64)).unwrap();
+        }
+
+        let backups = list_config_backups_in(&dir);
+        assert_eq!(backups.len(), 2);
+        assert_eq!(read_config_backup(&dir, &backups[0].name).unwrap(), "{\"v\":\"three\"}");
+        assert_eq!(read_config_backup(&dir, &backups[1].name).unwrap(), "{\"v\":\"two\"}");
+        assert!(read_config_backup(&dir, "../defaults.json").is_err());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn library_backups_only_copy_changed_files_and_rotate() {
+        let dir = test_dir("library-backup");
+        let library = dir.join("library");
+        let dest = dir.join("backups");
+        fs::create_dir_all(library.join("work")).unwrap();
+        fs::write(library.join("a.ssce"), "one").unwrap();
+        fs::write(library.join("work/b.ssce"), "two").unwrap();
+        let options = RebuildOptions::default();
+        let job = LibraryBackupJob { library_path: &library, dest_dir: &dest, keep: 2, options: &options };
+        let start = chrono::Utc::now();
+        let run = |seconds: i64| {
+            job.run(start + chrono::Duration::seconds(seconds), &AtomicBool::new(false), |_, _| {})
+        };
+
+        let first = run(0).unwrap();
+        assert_eq!((first.copied, first.skipped, first.bytes), (2, 0, 6));
+        assert_eq!(fs::read_to_string(Path::new(&first.path).join("work/b.ssce")).unwrap(), "two");
+
+        // Nothing changed: everything comes from the previous backup
+        let second = run(1).unwrap();
+        assert_eq!((second.copied, second.skipped, second.bytes), (0, 2, 0));
+        assert_eq!(fs::read_to_string(Path::new(&second.path).join("a.ssce")).unwrap(), "one");
+
+        fs::write(library.join("a.ssce"), "changed").unwrap();
+        let third = run(2).unwrap();
+        assert_eq!((third.copied, third.skipped, third.bytes), (1, 1, 7));
+        assert_eq!(fs::read_to_string(Path::new(&third.path).join("a.ssce")).unwrap(), "changed");
+
+        // Only the newest two are kept
+        let kept = list_library_backups(&dest);
+        assert_eq!(kept, vec![std::path::PathBuf::from(&third.path), std::path::PathBuf::from(&second.path)]);
+
+        // A cancelled run leaves nothing behind
+        let cancelled = job.run(start + chrono::Duration::seconds(3), &AtomicBool::new(true), |_, _| {}).unwrap();
+        assert!(cancelled.cancelled);
+        assert_eq!(fs::read_dir(&dest).unwrap().count(), 2);
+
+        // So does one cancelled after it started copying
+        fs::write(library.join("a.ssce"), "changed again").unwrap();
+        let cancel = AtomicBool::new(false);
+        let stopped = job
+            .run(start + chrono::Duration::seconds(4), &cancel, |_, _| cancel.store(true, Ordering::Relaxed))
+            .unwrap();
+        assert!(stopped.cancelled);
+        assert_eq!(list_library_backups(&dest).len(), 2);
+        assert_eq!(fs::read_dir(&dest).unwrap().count(), 2);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn merge_database_keeps_the_newest_copy_of_each_path() {
+        let dir = test_dir("merge-db");
+        let conn = test_db();
+        let other_path = dir.join("other.db");
+        let other = open_database(&other_path).unwrap();
+        let insert = |conn: &Connection, path: &str, title: &str, modified: &str| {
+            conn.execute(
+                "INSERT INTO files (path, filename, title, modified) VALUES (?1, ?1, ?2, ?3)",
+                params![path, title, modified],
+            )
+            .unwrap();
+        };
+        insert(&conn, "/library/a.ssce", "Old draft", "2025-01-01T00:00:00.000Z");
+        insert(&conn, "/library/b.ssce", "Local edit", "2025-03-01T00:00:00.000Z");
+        insert(&other, "/library/a.ssce", "Revised plan", "2025-02-01T00:00:00.000Z");
+        insert(&other, "/library/b.ssce", "Stale copy", "2025-01-01T00:00:00.000Z");
+        insert(&other, "/library/c.ssce", "Laptop only", "2025-01-01T00:00:00.000Z");
+        drop(other);
+
+        let summary = merge_database_file(&conn, &other_path).unwrap();
+        assert_eq!(summary, MergeSummary { added: 1, updated: 1, skipped: 1 });
+
+        let title = |path: &str| -> String {
+            conn.query_row("SELECT title FROM files WHERE path = ?1", params![path], |row| row.get(0)).unwrap()
+        };
+        assert_eq!(title("/library/a.ssce"), "Revised plan");
+        assert_eq!(title("/library/b.ssce"), "Local edit");
+        assert_eq!(title("/library/c.ssce"), "Laptop only");
+
+        // The search index follows the merged rows
+        let found = |query: &str| search_files(&conn, search_params(query), &chrono::Utc).unwrap().len();
+        assert_eq!(found("revised"), 1);
+        assert_eq!(found("draft"), 0);
+        assert_eq!(found("laptop"), 1);
+
+        // Merging again changes nothing
+        let again = merge_database_file(&conn, &other_path).unwrap();
+        assert_eq!(again, MergeSummary { added: 0, updated: 0, skipped: 3 });
+
+        // Databases from another schema version are refused
+        let old_path = dir.join("old.db");
+        let old = Connection::open(&old_path).unwrap();
+        create_base_schema(&old).unwrap();
+        drop(old);
+        let err = merge_database_file(&conn, &old_path).unwrap_err();
+        assert!(matches!(err, CommandError::UnsupportedVersion(_)), "{}", err);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn checkpoint_wal_empties_the_log() {
+        let dir = test_dir("checkpoint");
+        let db_path = dir.join("library.db");
+        let conn = open_database(&db_path).unwrap();
+        conn.execute(
+            "INSERT INTO files (path, filename) VALUES ('/library/a.ssce', 'a.ssce')",
+            [],
+        )
+        .unwrap();
+
+        let wal = path_with_suffix(&db_path, "-wal");
+        assert!(fs::metadata(&wal).unwrap().len() > 0);
+
+        checkpoint_wal(&conn).unwrap();
+        assert_eq!(fs::metadata(&wal).unwrap().len(), 0);
+
+        // Everything written so far is in the main file
+        drop(conn);
+        let reopened = Connection::open(&db_path).unwrap();
+        let count: i64 = reopened.query_row("SELECT COUNT(*) FROM files", [], |row| row.get(0)).unwrap();
+        assert_eq!(count, 1);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn passive_checkpoint_lets_the_wal_shrink() {
+        let dir = test_dir("passive-checkpoint");
+        let db_path = dir.join("library.db");
+        let conn = open_database(&db_path).unwrap();
+        let wal = path_with_suffix(&db_path, "-wal");
+
+        // Grow the WAL well past its size limit
+        let padding = "x".repeat(64 * 1024);
+        let tx = conn.unchecked_transaction().unwrap();
+        for i in 0..128 {
+            conn.execute(
+                "INSERT INTO files (path, filename, summary) VALUES (?1, ?1, ?2)",
+                params![format!("/library/{}.ssce", i), padding],
+            )
+            .unwrap();
+        }
+        tx.commit().unwrap();
+        let grown = fs::metadata(&wal).unwrap().len();
+        assert!(grown > WAL_SIZE_LIMIT as u64);
+
+        let checkpoint = checkpoint_wal_passive(&conn).unwrap();
+        assert!(!checkpoint.busy);
+        assert!(checkpoint.log_frames > 0);
+        assert_eq!(checkpoint.checkpointed_frames, checkpoint.log_frames);
+
+        // The next write starts the log over and cuts the file back
+        conn.execute("UPDATE files SET title = 'a' WHERE id = 1", []).unwrap();
+        assert!(fs::metadata(&wal).unwrap().len() <= WAL_SIZE_LIMIT as u64);
+
+        drop(conn);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn ssce_header_skips_snapshots_in_large_files() {
+        let dir = test_dir("ssce-header");
+        let image = format!("data:image/png;base64,{}", "A".repeat(2 * HEADER_STREAM_MIN_BYTES as usize));
+        let doc = json!({
+            "version": "1.1",
+            "thumbnail": "data:image/png;base64,THUMB",
+            "frontMatter": { "title": "Big one" },
+            "layers": [{ "type": "image", "data": { "src": image } }],
+            "snapshots": [{ "id": 1, "image": image }],
+        })
+        .to_string();
+
+        let plain = dir.join("plain.ssce");
+        fs::write(&plain, &doc).unwrap();
+        let compressed = dir.join("compressed.ssce");
+        fs::write(&compressed, gzip(doc.as_bytes()).unwrap()).unwrap();
+        let small = dir.join("small.ssce");
+        fs::write(&small, r#"{"version":"1.1","frontMatter":{"title":"Small"},"snapshots":[]}"#).unwrap();
+
+        for path in [&plain, &compressed] {
+            let header = read_ssce_header(path).unwrap();
+            assert_eq!(header.version.as_deref(), Some("1.1"));
+            assert_eq!(header.front_matter.unwrap()["title"], "Big one");
+            assert_eq!(header.thumbnail.as_deref(), Some("data:image/png;base64,THUMB"));
+        }
+
+        let header = read_ssce_header(&small).unwrap();
+        assert_eq!(header.front_matter.unwrap()["title"], "Small");
+        assert_eq!(header.thumbnail, None);
+
+        // In the app's own field order, reading stops before the snapshots -
+        // even a truncated snapshot array is never reached
+        let ordered = dir.join("ordered.ssce");
+        let truncated = format!(
+            r#"{{"version":"1.1","thumbnail":"T","frontMatter":{{"title":"Ordered"}},"snapshots":[{{"image":"{}"#,
+            "A".repeat(2 * HEADER_STREAM_MIN_BYTES as usize)
+        );
+        fs::write(&ordered, truncated).unwrap();
+        let header = read_ssce_header(&ordered).unwrap();
+        assert_eq!(header.front_matter.unwrap()["title"], "Ordered");
+        assert_eq!(header.thumbnail.as_deref(), Some("T"));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn slow_existence_checks_count_as_unknown() {
+        let slow = |answer: bool| {
+            move || {
+                std::thread::sleep(std::time::Duration::from_millis(500));
+                answer
+            }
+        };
+        let short = std::time::Duration::from_millis(20);
+        assert_eq!(check_with_timeout(slow(true), short), None);
+        assert_eq!(check_with_timeout(|| true, std::time::Duration::from_secs(5)), Some(true));
+        assert_eq!(path_exists_within(Path::new("/definitely/not/here"), VOLUME_CHECK_TIMEOUT), Some(false));
+
+        let conn = test_db();
+        insert_test_file(&conn, "/library/slow.ssce", "slow");
+        insert_test_file(&conn, "/library/gone.ssce", "gone");
+
+        // A stub that hangs on one path: only the answered one is pruned
+        let pruned = prune_missing_with(&conn, None, |path| {
+            if path.ends_with("slow.ssce") {
+                check_with_timeout(slow(false), short)
+            } else {
+                Some(false)
+            }
+        })
+        .unwrap();
+        assert_eq!(pruned, 1);
+
+        let remaining: Vec<String> = conn
+            .prepare("SELECT title FROM files")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(remaining, vec!["slow".to_string()]);
+    }
+
+    #[test]
+    fn prune_missing_skips_unmounted_volumes() {
+        let dir = test_dir("prune-missing");
+        let kept = dir.join("kept.ssce");
+        fs::write(&kept, "{}").unwrap();
+
+        let conn = test_db();
+        insert_test_file(&conn, &kept.to_string_lossy(), "kept");
+        insert_test_file(&conn, &dir.join("deleted.ssce").to_string_lossy(), "deleted");
+        insert_test_file(&conn, "/Volumes/NotMounted/away.ssce", "away");
+
+        assert_eq!(prune_missing(&conn, None, VOLUME_CHECK_TIMEOUT).unwrap(), 1);
+
+        let remaining: Vec<String> = conn
+            .prepare("SELECT title FROM files ORDER BY title")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(remaining, ["away", "kept"]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn volume_root_finds_the_mount_folder() {
+        assert_eq!(volume_root(Path::new("/media/ann/USB/docs/a.ssce")), Path::new("/media/ann/USB"));
+        assert_eq!(volume_root(Path::new("/run/media/ann/USB/a.ssce")), Path::new("/run/media/ann/USB"));
+        assert_eq!(volume_root(Path::new("/mnt/share/a.ssce")), Path::new("/mnt/share"));
+        assert_eq!(volume_root(Path::new("/Volumes/Backup/a.ssce")), Path::new("/Volumes/Backup"));
+        assert_eq!(volume_root(Path::new("/home/ann/a.ssce")), Path::new("/"));
+    }
+
+    #[test]
+    fn recursive_autosave_listing_skips_hidden_folders() {
+        let dir = test_dir("autosave-recursive");
+        let project = dir.join("project-a");
+        let hidden = dir.join(".trash");
+        fs::create_dir_all(&project).unwrap();
+        fs::create_dir_all(&hidden).unwrap();
+
+        fs::write(dir.join("top.ssce"), "{}").unwrap();
+        fs::write(project.join("nested.ssce"), "{}").unwrap();
+        fs::write(project.join("notes.txt"), "").unwrap();
+        fs::write(hidden.join("old.ssce"), "{}").unwrap();
+        let newer = fs::File::options().write(true).open(project.join("nested.ssce")).unwrap();
+        newer.set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(60)).unwrap();
+
+        let extensions = default_indexed_extensions();
+        let flat = list_autosave_entries(&dir.to_string_lossy(), &extensions, 0).unwrap();
+        assert_eq!(flat.iter().map(|e| e.name.as_str()).collect::<Vec<_>>(), ["top.ssce"]);
+
+        let all = list_autosave_entries(&dir.to_string_lossy(), &extensions, MAX_AUTOSAVE_DEPTH).unwrap();
+        assert_eq!(all.iter().map(|e| e.name.as_str()).collect::<Vec<_>>(), ["nested.ssce", "top.ssce"]);
+        assert_eq!(Path::new(&all[0].path), project.join("nested.ssce"));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn snapshot_count_drift_is_reported_and_fixed() {
+        let dir = test_dir("snapshot-drift");
+        let conn = test_db();
+        let doc = dir.join("doc.ssce");
+        fs::write(&doc, json!({ "snapshots": [{ "id": 1 }, { "id": 2 }] }).to_string()).unwrap();
+        rebuild_library(&conn, &dir, &AtomicBool::new(false), &RebuildOptions::default()).unwrap();
+        let path = normalize_path(&doc.to_string_lossy());
+        let library = dir.to_string_lossy().to_string();
+
+        // Edited elsewhere: a third snapshot the index doesn't know about
+        fs::write(&doc, json!({ "snapshots": [{ "id": 1 }, { "id": 2 }, { "id": 3 }] }).to_string()).unwrap();
+        // Rows for missing documents aren't reported
+        insert_test_file(&conn, &format!("{}/gone.ssce", library), "Gone");
+
+        let mut calls = Vec::new();
+        let report = verify_snapshot_counts(&conn, &library, false, |done, total| calls.push((done, total))).unwrap();
+        assert_eq!(report.checked, 1);
+        assert_eq!(report.mismatches, vec![SnapshotCountMismatch { path: path.clone(), stored: 2, actual: 3 }]);
+        assert!(!report.fixed);
+        assert_eq!(calls, vec![(2, 2)]);
+        let stored = |conn: &Connection| -> i64 {
+            conn.query_row("SELECT snapshot_count FROM files WHERE path = ?1", params![path], |row| row.get(0))
+                .unwrap()
+        };
+        assert_eq!(stored(&conn), 2);
+
+        let report = verify_snapshot_counts(&conn, &library, true, |_, _| {}).unwrap();
+        assert!(report.fixed);
+        assert_eq!(stored(&conn), 3);
+        assert!(verify_snapshot_counts(&conn, &library, true, |_, _| {}).unwrap().mismatches.is_empty());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn ssce_metadata_leaves_out_the_thumbnail_unless_asked() {
+        let dir = test_dir("metadata-thumbnail");
+        let doc = dir.join("doc.ssce");
+        let ssce = json!({
+            "version": "1.1",
+            "thumbnail": "data:image/png;base64,AAAA",
+            "snapshots": [{ "id": 1 }, { "id": 2 }, { "id": 3 }],
+        });
+        fs::write(&doc, ssce.to_string()).unwrap();
+        let path = doc.to_string_lossy().to_string();
+
+        let lean = get_ssce_metadata(path.clone(), None).unwrap();
+        assert_eq!(lean.snapshot_count, 3);
+        assert_eq!(lean.thumbnail, None);
+
+        let full = get_ssce_metadata(path, Some(true)).unwrap();
+        assert_eq!(full.snapshot_count, 3);
+        assert_eq!(full.thumbnail.as_deref(), Some("data:image/png;base64,AAAA"));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn session_round_trip_drops_missing_documents() {
+        let dir = test_dir("session");
+        let kept = dir.join("kept.ssce");
+        fs::write(&kept, "{}").unwrap();
+        let gone = dir.join("gone.ssce");
+
+        assert!(read_session(&dir).is_empty());
+
+        write_session(
+            &dir,
+            vec![kept.to_string_lossy().to_string(), gone.to_string_lossy().to_string()],
+        )
+        .unwrap();
+        assert_eq!(read_session(&dir), [kept.to_string_lossy().to_string()]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn keyword_lists_are_lowercased_and_deduplicated() {
+        assert_eq!(normalize_keyword_list("Tax tax TAX"), "tax");
+        assert_eq!(normalize_keyword_list("  invoice\t 2024\n\nInvoice  receipt "), "invoice 2024 receipt");
+        assert_eq!(normalize_keyword_list("   "), "");
+
+        let conn = test_db();
+        insert_test_file(&conn, "/a.ssce", "A");
+        update_metadata(&conn, "/a.ssce", None, None, Some("Tax  tax Receipt TAX".into())).unwrap();
+        let keywords: String = conn
+            .query_row("SELECT keywords FROM files WHERE path = '/a.ssce'", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(keywords, "tax receipt");
+        assert_eq!(search_files(&conn, search_params("receipt"), &chrono::Utc).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn save_image_checks_bytes_and_writes_atomically() {
+        let dir = test_dir("save_image");
+        let mut png = Vec::new();
+        image::DynamicImage::new_rgb8(3, 2)
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        let encoded = STANDARD.encode(&png);
+
+        let save = |path: &Path, data: String| {
+            write_image(path.to_string_lossy().to_string(), data, &RetryPolicy::default(), &ImageLimits::default())
+        };
+
+        let path = dir.join("out/shot.png");
+        let saved = save(&path, format!("data:image/png;base64,{}", encoded)).unwrap();
+        assert_eq!(saved, SavedImage { format: "png".into(), width: 3, height: 2 });
+        assert_eq!(fs::read(&path).unwrap(), png);
+        assert!(!path_with_suffix(&path, ".tmp").exists());
+
+        // Declared JPEG holding PNG bytes
+        let err = save(&path, format!("data:image/jpeg;base64,{}", encoded)).unwrap_err();
+        assert!(matches!(err, CommandError::Validation(ref m) if m.contains("mismatch")), "{}", err);
+
+        // Valid base64 that isn't an image leaves the existing file alone
+        let other = dir.join("other.png");
+        assert!(save(&other, STANDARD.encode(b"not an image")).is_err());
+        assert!(!other.exists());
+        assert_eq!(fs::read(&path).unwrap(), png);
+
+        assert_eq!(data_url_mime("data:image/webp;base64,AAAA"), Some("image/webp"));
+        assert_eq!(data_url_mime("AAAA"), None);
+    }
+
+    #[test]
+    fn oversized_images_are_refused_before_decoding() {
+        let dir = test_dir("image-limits");
+        let big = dir.join("big.png");
+        write_test_png(&big, 300, 200);
+        let small = dir.join("small.png");
+        write_test_png(&small, 100, 100);
+
+        let limits = ImageLimits::from_config(&typed_defaults(json!({ "images": { "maxDimension": 256, "maxPixels": 50000 } })).unwrap());
+        assert_eq!(limits.max_bytes, DEFAULT_MAX_IMAGE_BYTES);
+        fn too_large<T>(result: Result<T, CommandError>) -> bool {
+            matches!(result, Err(CommandError::TooLarge(_)))
+        }
+
+        // Longer than 256 pixels on one side
+        assert!(too_large(check_image_file(&big, &limits)));
+        assert!(too_large(decode_image_file(&big, &limits)));
+        assert!(too_large(checked_image_data_url(&big, &limits)));
+        assert!(check_image_file(&small, &limits).is_ok());
+        // 100x100 is within both limits, 250x250 is over the pixel count only
+        assert!(limits.check_dimensions(100, 100).is_ok());
+        assert!(too_large(limits.check_dimensions(250, 250)));
+
+        let bytes = fs::read(&big).unwrap();
+        assert!(too_large(decode_image_bytes(&bytes, &limits)));
+        let out = dir.join("saved.png");
+        let data = format!("data:image/png;base64,{}", STANDARD.encode(&bytes));
+        assert!(too_large(write_image(out.to_string_lossy().to_string(), data, &RetryPolicy::default(), &limits)));
+        assert!(!out.exists());
+
+        // File size is checked first
+        let tiny = ImageLimits { max_bytes: 10, ..ImageLimits::default() };
+        assert!(too_large(check_image_file(&small, &tiny)));
+
+        // Thumbnails report the limit rather than a rendering error
+        let cache = ThumbnailCache { image_limits: limits, ..ThumbnailCache::new(dir.join("cache"), 1 << 20) };
+        assert!(too_large(thumbnail_data_url(&cache, &big, 64, ThumbnailEncoding::PNG)));
+        assert!(thumbnail_data_url(&cache, &small, 64, ThumbnailEncoding::PNG).is_ok());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn command_errors_carry_their_kind() {
+        let dir = test_dir("command_errors");
+        let missing = dir.join("missing.ssce").to_string_lossy().to_string();
+        assert!(matches!(read_ssce(&missing, &RetryPolicy::default()), Err(CommandError::NotFound(_))));
+
+        let broken = dir.join("broken.ssce");
+        fs::write(&broken, "{ not json").unwrap();
+        assert!(matches!(
+            get_ssce_metadata(broken.to_string_lossy().to_string(), None),
+            Err(CommandError::Parse(_))
+        ));
+
+        let conn = test_db();
+        assert!(matches!(update_metadata(&conn, "/nowhere.ssce", None, None, None), Err(CommandError::NotFound(_))));
+        assert!(matches!(
+            write_setting(&conn, "autosaveIntervalSeconds", &json!("soon")),
+            Err(CommandError::Validation(_))
+        ));
+
+        let denied = std::io::Error::new(std::io::ErrorKind::PermissionDenied, "denied");
+        assert!(matches!(CommandError::io("Failed to read file", denied), CommandError::Permission(_)));
+
+        // The frontend sees { kind, message } with the message unchanged
+        let err = CommandError::NotFound(format!("File does not exist: {}", missing));
+        assert_eq!(
+            serde_json::to_value(&err).unwrap(),
+            json!({ "kind": "NotFound", "message": format!("File does not exist: {}", missing) })
+        );
+        assert_eq!(err.to_string(), format!("File does not exist: {}", missing));
+    }
+
+    #[test]
+    fn index_queue_batches_upserts_and_removals() {
+        let dir = test_dir("index-queue");
+        let doc = dir.join("doc.ssce");
+        fs::write(&doc, json!({ "version": "1.1", "frontMatter": { "title": "Queued" } }).to_string()).unwrap();
+        let doc = doc.to_string_lossy().to_string();
+        let gone = dir.join("gone.ssce").to_string_lossy().to_string();
+
+        let conn = test_db();
+        insert_test_file(&conn, &gone, "stale");
+
+        let (queue, receiver) = IndexQueue::new();
+        for path in [&doc, &gone, &doc] {
+            queue.push(path.clone()).unwrap();
+        }
+        assert_eq!(queue.status().pending, 3);
+
+        // The repeated path is written once
+        let (paths, count) = next_index_batch(&receiver).unwrap();
+        assert_eq!((paths.len(), count), (2, 3));
+
+        let jobs: Vec<IndexJob> = paths.iter().map(|p| index_job(p, &RebuildOptions::default()).unwrap()).collect();
+        assert_eq!(apply_index_jobs(&conn, &jobs).unwrap(), 2);
+
+        assert_eq!(search_files(&conn, search_params("queued"), &chrono::Utc).unwrap().len(), 1);
+        assert!(search_files(&conn, search_params("stale"), &chrono::Utc).unwrap().is_empty());
+
+        // No worker left to drain the queue
+        drop(receiver);
+        assert!(matches!(queue.push(doc), Err(CommandError::Validation(_))));
+        assert_eq!(queue.status().pending, 3);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn only_sidecars_of_missing_documents_are_orphaned() {
+        let dir = test_dir("orphaned-sidecars");
+        let library = dir.join("library");
+        fs::create_dir_all(library.join("sub")).unwrap();
+        let cache = ThumbnailCache::new(dir.join("cache"), DEFAULT_THUMBNAIL_CACHE_BYTES);
+
+        let live = library.join("live.ssce");
+        fs::write(&live, "{}").unwrap();
+        fs::write(path_with_suffix(&live, ".bak"), "{}").unwrap();
+        let orphan_bak = path_with_suffix(&library.join("sub").join("gone.ssce"), ".bak");
+        fs::write(&orphan_bak, "{}").unwrap();
+
+        let picture = dir.join("picture.png");
+        write_test_png(&picture, 20, 20);
+        let gone_picture = dir.join("gone.png");
+        write_test_png(&gone_picture, 20, 20);
+        for source in [&picture, &gone_picture] {
+            let key = ThumbnailCache::key(source, 50).unwrap();
+            cache.get_or_generate(&key, source, || render_thumbnail(source, 50, ThumbnailEncoding::PNG, &ImageLimits::default())).unwrap();
+        }
+        fs::remove_file(&gone_picture).unwrap();
+        // An entry with no recorded source can't be traced, so it's kept
+        write_atomic(&cache.entry_path("untraced"), |w| w.write_all(b"png")).unwrap();
+
+        let mut calls = Vec::new();
+        let orphans = orphaned_sidecars(&library, &cache, |done, total| calls.push((done, total))).unwrap();
+        assert_eq!(calls, vec![(5, 5)]);
+        let found: Vec<(&str, &str)> = orphans.iter().map(|o| (o.kind, o.base.as_str())).collect();
+        let gone_doc = library.join("sub").join("gone.ssce").to_string_lossy().to_string();
+        assert_eq!(
+            found,
+            vec![("backup", gone_doc.as_str()), ("thumbnail", gone_picture.to_string_lossy().as_ref())]
+        );
+
+        // A live document's backup is never removed, even if asked
+        let mut paths: Vec<String> = orphans.iter().map(|o| o.path.clone()).collect();
+        paths.push(path_with_suffix(&live, ".bak").to_string_lossy().to_string());
+        assert_eq!(remove_orphaned_sidecars(&paths, &cache, std::slice::from_ref(&dir)).unwrap(), 2);
+
+        assert!(!orphan_bak.exists());
+        assert!(path_with_suffix(&live, ".bak").exists());
+        assert_eq!(cache.stats().entries, 2);
+        assert!(orphaned_sidecars(&library, &cache, |_, _| {}).unwrap().is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn title_matches_outrank_keyword_matches() {
+        let conn = test_db();
+        // The keyword-only match is newer, so date order alone would put it first
+        conn.execute(
+            "INSERT INTO files (path, filename, title, keywords, modified)
+             VALUES ('/title.ssce', 'a.ssce', 'Invoice layout', 'misc', '2025-01-01T00:00:00Z'),
+                    ('/keyword.ssce', 'b.ssce', 'Other', 'invoice', '2025-06-01T00:00:00Z')",
+            [],
+        )
+        .unwrap();
+
+        let paths = |weights: &RankWeights| -> Vec<String> {
+            search_files_ranked(&conn, search_params("invoice"), weights, true, &chrono::Utc)
+                .unwrap()
+                .into_iter()
+                .map(|f| f.path)
+                .collect()
+        };
+        assert_eq!(paths(&RankWeights::default()), vec!["/title.ssce", "/keyword.ssce"]);
+
+        let config = typed_defaults(json!({ "search": { "rankWeights": { "title": 0.1, "keywords": 10 } } })).unwrap();
+        let keywords_first = RankWeights::from_config(&config);
+        assert_eq!(keywords_first.filename, 2.0);
+        assert_eq!(paths(&keywords_first), vec!["/keyword.ssce", "/title.ssce"]);
+
+        let negative = typed_defaults(json!({ "search": { "rankWeights": { "title": -1 } } })).unwrap();
+        assert_eq!(RankWeights::from_config(&negative), RankWeights::default());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_names_round_trip_through_listings_and_the_library() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = test_dir("non-utf8-names");
+        let name = std::ffi::OsStr::from_bytes(b"caf\xe9.ssce");
+        let path = dir.join(name);
+        fs::write(&path, json!({ "version": "1.1", "frontMatter": { "title": "Latin-1 name" } }).to_string()).unwrap();
+        fs::write(dir.join("plain.ssce"), "{}").unwrap();
+
+        let entries = list_directory(dir.to_string_lossy().to_string(), "ssce".into(), false, None, None).unwrap();
+        let odd = entries.iter().find(|e| e.name == "caf\u{fffd}.ssce").unwrap();
+        let encoded = odd.encoded_path.clone().unwrap();
+        assert!(entries.iter().find(|e| e.name == "plain.ssce").unwrap().encoded_path.is_none());
+
+        // The lossy name doesn't reach the file, the encoded form does
+        assert!(read_ssce(&dir.join(&odd.name).to_string_lossy(), &RetryPolicy::default()).is_err());
+        assert!(read_ssce(&encoded, &RetryPolicy::default()).unwrap().contains("Latin-1 name"));
+        assert_eq!(fs_path(&encoded).unwrap(), path);
+        assert!(decode_path(encoded.clone()).unwrap().exists);
+        assert!(matches!(decode_path("raw:***".into()), Err(CommandError::Parse(_))));
+
+        let conn = test_db();
+        rebuild_library(&conn, &dir, &AtomicBool::new(false), &RebuildOptions::default()).unwrap();
+        let found = search_files(&conn, search_params("latin"), &chrono::Utc).unwrap();
+        assert_eq!(found[0].encoded_path.as_deref(), Some(encoded.as_str()));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn autosave_dir_falls_back_when_unwritable() {
+        let dir = test_dir("autosave-dir");
+        let fallback = dir.join("fallback");
+
+        let configured = dir.join("autosaves");
+        let chosen = resolve_autosave_directory(Some(configured.clone()), &fallback).unwrap();
+        assert_eq!((chosen.path, chosen.fallback), (configured.to_string_lossy().to_string(), false));
+        assert!(configured.is_dir());
+
+        // A file where the folder should be can't be used
+        let blocked = dir.join("blocked");
+        fs::write(&blocked, "").unwrap();
+        let chosen = resolve_autosave_directory(Some(blocked.join("autosaves")), &fallback).unwrap();
+        assert!(chosen.fallback);
+        assert_eq!(chosen.path, fallback.to_string_lossy());
+        assert!(chosen.reason.unwrap().contains("cannot create"));
+
+        let home = dirs::home_dir().unwrap();
+        assert_eq!(
+            configured_autosave_dir(&typed_defaults(json!({ "autosave": { "tempDirectory": "~/saves" } })).unwrap()),
+            Some(home.join("saves"))
+        );
+        assert_eq!(configured_autosave_dir(&Defaults::default()), Some(home.join(".ssce-temp")));
+        // save_autosave's directory argument: relative folders are taken from home
+        assert_eq!(home_relative_dir(".ssce-temp"), Some(home.join(".ssce-temp")));
+        assert_eq!(home_relative_dir(&dir.to_string_lossy()), Some(dir.clone()));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn disk_usage_groups_files_by_kind() {
+        let dir = test_dir("disk-usage");
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("a.ssce"), vec![b'x'; 300]).unwrap();
+        fs::write(dir.join("sub").join("b.ssce"), vec![b'x'; 100]).unwrap();
+        fs::write(dir.join("a.ssce.bak"), vec![b'x'; 250]).unwrap();
+        fs::write(dir.join("photo.PNG"), vec![b'x'; 40]).unwrap();
+        fs::write(dir.join("notes.txt"), vec![b'x'; 10]).unwrap();
+
+        let mut calls = Vec::new();
+        let usage = disk_usage(&dir, &RebuildOptions::default(), 2, &AtomicBool::new(false), |done, total| {
+            calls.push((done, total))
+        })
+        .unwrap();
+
+        assert_eq!(usage.documents, UsageTotals { files: 2, bytes: 400 });
+        assert_eq!(usage.images, UsageTotals { files: 1, bytes: 40 });
+        assert_eq!(usage.backups, UsageTotals { files: 1, bytes: 250 });
+        assert_eq!(usage.other, UsageTotals { files: 1, bytes: 10 });
+        assert_eq!(usage.total_bytes, 700);
+        assert_eq!(calls, vec![(5, 5)]);
+
+        let largest: Vec<u64> = usage.largest.iter().map(|f| f.size).collect();
+        assert_eq!(largest, vec![300, 250]);
+        assert!(!usage.cancelled);
+
+        let cancelled = disk_usage(&dir, &RebuildOptions::default(), 2, &AtomicBool::new(true), |_, _| {}).unwrap();
+        assert!(cancelled.cancelled);
+        assert_eq!(cancelled.total_bytes, 0);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn export_zip_keeps_folders_and_skips_missing_documents() {
+        let dir = test_dir("export-zip");
+        let docs = dir.join("docs");
+        fs::create_dir_all(docs.join("2024")).unwrap();
+        write_test_png(&dir.join("outside.png"), 4, 4);
+        let with_asset = json!({
+            "version": "1.1",
+            "snapshots": [
+                { "id": 1, "image": "shot.png" },
+                { "id": 2, "image": "gone.png" },
+                { "id": 3, "image": "notes.txt" },
+                { "id": 4, "image": "../outside.png" },
+                { "id": 5, "image": dir.join("outside.png").to_string_lossy() },
+            ]
+        });
+        fs::write(docs.join("a.ssce"), with_asset.to_string()).unwrap();
+        fs::write(docs.join("2024/b.ssce"), r#"{"version":"1.1"}"#).unwrap();
+        fs::write(docs.join("notes.txt"), "not an image").unwrap();
+        write_test_png(&docs.join("shot.png"), 4, 4);
+
+        let paths: Vec<String> = ["a.ssce", "2024/b.ssce", "missing.ssce", "a.ssce"]
+            .iter()
+            .map(|name| docs.join(name).to_string_lossy().to_string())
+            .collect();
+        let out = dir.join("out/bundle.zip");
+        let mut updates = Vec::new();
+        let export = write_document_zip(&paths, &out, true, |done, total| updates.push((done, total))).unwrap();
+
+        assert_eq!(export.included, 3);
+        assert_eq!(updates.last(), Some(&(3, 3)));
+        let mut reasons: Vec<_> = export.skipped.iter().map(|s| s.reason.as_str()).collect();
+        reasons.sort();
+        assert_eq!(
+            reasons,
+            vec![
+                "Asset does not exist",
+                "Asset is not an image",
+                "Asset is outside the document folder",
+                "Asset is outside the document folder",
+                "File does not exist"
+            ]
+        );
+
+        let mut archive = zip::ZipArchive::new(fs::File::open(&out).unwrap()).unwrap();
+        let mut names: Vec<String> = archive.file_names().map(String::from).collect();
+        names.sort();
+        assert_eq!(names, vec!["2024/b.ssce", "a.ssce", "shot.png"]);
+        let mut extracted = String::new();
+        std::io::Read::read_to_string(&mut archive.by_name("2024/b.ssce").unwrap(), &mut extracted).unwrap();
+        assert_eq!(extracted, r#"{"version":"1.1"}"#);
+        assert!(!path_with_suffix(&out, ".tmp").exists());
+
+        // Without assets only the documents go in
+        let export = write_document_zip(&paths[..2], &out, false, |_, _| {}).unwrap();
+        assert_eq!((export.included, export.skipped.len()), (2, 0));
+
+        let nothing = write_document_zip(&paths[2..3], &out, false, |_, _| {});
+        assert!(matches!(nothing, Err(CommandError::Validation(_))));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn import_folder_copies_indexes_and_skips_duplicates() {
+        let dir = test_dir("import");
+        let outside = dir.join("outside");
+        let library = dir.join("library");
+        fs::create_dir_all(outside.join("nested")).unwrap();
+        fs::create_dir_all(&library).unwrap();
+
+        let png_path = dir.join("snap.png");
+        write_test_png(&png_path, 200, 100);
+        let snapshot_url = format!("data:image/png;base64,{}", STANDARD.encode(fs::read(&png_path).unwrap()));
+        let doc = json!({
+            "version": "1.1",
+            "frontMatter": { "title": "Imported" },
+            "snapshots": [{ "id": 1, "image": snapshot_url }]
+        });
+        fs::write(outside.join("nested/first.ssce"), doc.to_string()).unwrap();
+        // Same content under another name
+        fs::write(outside.join("again.ssce"), doc.to_string()).unwrap();
+        fs::write(outside.join("broken.ssce"), "{ not json").unwrap();
+
+        let state = DbState::new(test_db());
+        let mut updates = Vec::new();
+        let result = import_documents(&state, &outside, &library, false, &RebuildOptions::default(), |done, total| {
+            updates.push((done, total))
+        })
+        .unwrap();
+
+        assert_eq!(result.imported.len(), 1);
+        assert_eq!(result.duplicates.len(), 1);
+        assert_eq!(result.failed.len(), 1);
+        assert_eq!(updates, vec![(1, 3), (2, 3), (3, 3)]);
+
+        let imported = &result.imported[0];
+        assert_eq!(imported.title.as_deref(), Some("Imported"));
+        assert!(imported.thumbnail.is_some());
+        assert_eq!(result.duplicates[0].reason, imported.path);
+        let copied = if imported.path.ends_with("first.ssce") {
+            library.join("nested/first.ssce")
+        } else {
+            library.join("again.ssce")
+        };
+        let saved: serde_json::Value = serde_json::from_str(&fs::read_to_string(copied).unwrap()).unwrap();
+        assert!(saved["thumbnail"].is_string());
+
+        // Importing again finds the library copy
+        let again =
+            import_one(&state, &outside.join("again.ssce"), &library, true, &RebuildOptions::default()).unwrap();
+        assert!(matches!(again, ImportResult::Duplicate(_)));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn paused_index_queue_holds_paths_until_last_resume() {
+        let (queue, receiver) = IndexQueue::new();
+        queue.pause().unwrap();
+        {
+            let _bulk = queue.pause_while().unwrap();
+            for path in ["/library/a.ssce", "/library/b.ssce", "/library/a.ssce"] {
+                queue.push(path.to_string()).unwrap();
+            }
+        }
+
+        // Still paused by the outer pause; nothing reached the worker
+        let status = queue.status();
+        assert!(status.paused);
+        assert_eq!((status.pending, status.held), (0, 2));
+        assert!(receiver.try_recv().is_err());
+
+        // Coalesced into one batch on resume
+        assert_eq!(queue.resume().unwrap(), 2);
+        let (paths, count) = next_index_batch(&receiver).unwrap();
+        assert_eq!(paths, vec!["/library/a.ssce".to_string(), "/library/b.ssce".to_string()]);
+        assert_eq!(count, 2);
+
+        // Resuming an unpaused queue is harmless, and pushes go straight through
+        assert_eq!(queue.resume().unwrap(), 0);
+        queue.push("/library/c.ssce".into()).unwrap();
+        assert_eq!(queue.status().pending, 3);
+        assert!(!queue.status().paused);
+    }
+
+    #[test]
+    fn image_mime_comes_from_content_not_extension() {
+        let dir = test_dir("mislabeled");
+        let png = dir.join("real.png");
+        write_test_png(&png, 4, 4);
+        let jpeg_bytes = {
+            let mut out = std::io::Cursor::new(Vec::new());
+            image::DynamicImage::new_rgb8(4, 4).write_to(&mut out, image::ImageFormat::Jpeg).unwrap();
+            out.into_inner()
+        };
+        let misnamed = dir.join("photo.png");
+        fs::write(&misnamed, &jpeg_bytes).unwrap();
+        let unknown = dir.join("notes.gif");
+        fs::write(&unknown, b"not an image").unwrap();
+
+        assert!(image_data_url(&png).unwrap().starts_with("data:image/png;base64,"));
+        assert!(image_data_url(&misnamed).unwrap().starts_with("data:image/jpeg;base64,"));
+        // Unrecognised content falls back to the extension
+        assert!(image_data_url(&unknown).unwrap().starts_with("data:image/gif;base64,"));
+
+        assert!(!is_mislabeled(&png, image_mime(&fs::read(&png).unwrap(), &png)));
+        assert!(is_mislabeled(&misnamed, image_mime(&jpeg_bytes, &misnamed)));
+        assert!(!is_mislabeled(&dir.join("photo.jpeg"), image_mime(&jpeg_bytes, &misnamed)));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unreadable_images_get_a_placeholder_with_the_error() {
+        let dir = test_dir("placeholder");
+        let good = dir.join("good.png");
+        write_test_png(&good, 20, 10);
+        let corrupt = dir.join("corrupt.png");
+        fs::write(&corrupt, b"not a png").unwrap();
+        let cache = ThumbnailCache::new(dir.join("cache"), DEFAULT_THUMBNAIL_CACHE_BYTES);
+
+        let loaded = or_placeholder(checked_image_data_url(&good, &ImageLimits::default()), &good, PLACEHOLDER_IMAGE_SIZE);
+        assert!(!loaded.placeholder && loaded.error.is_none());
+        assert!(loaded.data_url.starts_with("data:image/png;base64,"));
+
+        for (path, result) in [
+            (&corrupt, checked_image_data_url(&corrupt, &ImageLimits::default())),
+            (&corrupt, thumbnail_data_url(&cache, &corrupt, 64, ThumbnailEncoding::PNG)),
+            (&dir.join("missing.png"), checked_image_data_url(&dir.join("missing.png"), &ImageLimits::default())),
+        ] {
+            let loaded = or_placeholder(result, path, 64);
+            assert!(loaded.placeholder);
+            assert!(loaded.error.is_some());
+            let svg = STANDARD.decode(loaded.data_url.strip_prefix("data:image/svg+xml;base64,").unwrap()).unwrap();
+            assert!(String::from_utf8(svg).unwrap().contains(">PNG</text>"));
+        }
+
+        // Without the opt-in the caller still gets a plain string
+        let plain = serde_json::to_value(LoadedImage::DataUrl("data:x".into())).unwrap();
+        assert_eq!(plain, json!("data:x"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn compacting_autosaves_drops_versions_identical_to_the_next() {
+        let dir = test_dir("compact-autosaves");
+        let base = std::time::SystemTime::now() - std::time::Duration::from_secs(3600);
+        let save = |name: &str, text: &str, step: u64, modified: &str| {
+            let path = dir.join(name);
+            let doc = json!({ "version": "1.1", "frontMatter": { "modified": modified }, "layers": [{ "text": text }] });
+            fs::write(&path, doc.to_string()).unwrap();
+            fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(base + std::time::Duration::from_secs(step * 60))
+                .unwrap();
+        };
+
+        // Oldest to newest: A A B B A, where only the timestamp differs
+        // within each run
+        save("autosave_s1_report.ssce", "A", 1, "2025-01-01");
+        save("autosave_s2_report.ssce", "A", 2, "2025-01-02");
+        save("autosave_s3_report.ssce", "B", 3, "2025-01-03");
+        save("autosave_s4_report.ssce", "B", 4, "2025-01-04");
+        save("autosave_s5_report.ssce", "A", 5, "2025-01-05");
+        // All different
+        save("autosave_s1_notes.ssce", "x", 1, "2025-01-01");
+        save("autosave_s2_notes.ssce", "y", 2, "2025-01-01");
+        fs::write(dir.join("autosave_s3_notes.ssce"), "{ not json").unwrap();
+
+        let results = compact_autosave_dir(&dir.to_string_lossy(), &RebuildOptions::default().extensions).unwrap();
+        assert_eq!(
+            results,
+            vec![
+                AutosaveCompaction { document: "notes".into(), kept: 3, removed: 0 },
+                AutosaveCompaction { document: "report".into(), kept: 3, removed: 2 },
+            ]
+        );
+
+        let mut left: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .filter(|name| name.ends_with("_report.ssce"))
+            .collect();
+        left.sort();
+        assert_eq!(left, vec!["autosave_s2_report.ssce", "autosave_s4_report.ssce", "autosave_s5_report.ssce"]);
+
+        // Nothing left to remove the second time
+        let again = compact_autosave_dir(&dir.to_string_lossy(), &RebuildOptions::default().extensions).unwrap();
+        assert!(again.iter().all(|r| r.removed == 0));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn autosaves_are_listed_for_the_document_they_came_from() {
+        let dir = test_dir("autosaves-for");
+        let autosaves = dir.join("autosave");
+        fs::create_dir_all(&autosaves).unwrap();
+        let doc = |title: &str, layers: usize| {
+            json!({
+                "version": "1.1",
+                "frontMatter": { "title": title },
+                "canvas": { "width": 100, "height": 100 },
+                "layers": vec![json!({ "type": "text" }); layers]
+            })
+            .to_string()
+        };
+        // Two documents with the same name in different folders
+        fs::create_dir_all(dir.join("a")).unwrap();
+        fs::create_dir_all(dir.join("b")).unwrap();
+        let first = dir.join("a/report.ssce");
+        let second = dir.join("b/report.ssce");
+        fs::write(&first, doc("First", 1)).unwrap();
+        fs::write(&second, doc("Second", 1)).unwrap();
+
+        let save = |name: &str, data: String, original: &Path, age: u64| {
+            let path = autosaves.join(name);
+            fs::write(&path, data).unwrap();
+            record_autosave_origin(&path, Some(&original.to_string_lossy())).unwrap();
+            fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(std::time::SystemTime::now() - std::time::Duration::from_secs(age))
+                .unwrap();
+        };
+        save("autosave_s1_report.ssce", doc("First", 1), &first, 300);
+        save("autosave_s2_report.ssce", doc("First draft", 3), &first, 100);
+        save("autosave_s3_report.ssce", doc("Second", 0), &second, 200);
+
+        let extensions = RebuildOptions::default().extensions;
+        let listed = |original: &Path| -> Vec<(String, String)> {
+            autosaves_for(&autosaves.to_string_lossy(), original, &extensions)
+                .unwrap()
+                .into_iter()
+                .map(|v| (v.name, v.changes))
+                .collect()
+        };
+        assert_eq!(
+            listed(&first),
+            vec![
+                ("autosave_s2_report.ssce".to_string(), "2 more layers, title changed".to_string()),
+                ("autosave_s1_report.ssce".to_string(), "no changes".to_string()),
+            ]
+        );
+        assert_eq!(
+            listed(&second),
+            vec![("autosave_s3_report.ssce".to_string(), "1 fewer layer".to_string())]
+        );
+
+        // Without a recorded origin, the filename decides
+        remove_autosave(&autosaves.join("autosave_s3_report.ssce")).unwrap();
+        fs::write(autosaves.join("autosave_s4_report.ssce"), doc("Second", 1)).unwrap();
+        assert_eq!(listed(&second), vec![("autosave_s4_report.ssce".to_string(), "no changes".to_string())]);
+        // ...which can't tell two documents of the same name apart
+        assert_eq!(listed(&first).len(), 3);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn move_files_moves_all_or_none() {
+        let dir = test_dir("move-files");
+        let mut conn = test_db();
+        let paths: Vec<std::path::PathBuf> = ["a.ssce", "b.ssce", "c.ssce"].iter().map(|n| dir.join(n)).collect();
+        for path in &paths {
+            fs::write(path, "{}").unwrap();
+            insert_test_file(&conn, &normalize_path(&path.to_string_lossy()), "doc");
+        }
+        fs::write(path_with_suffix(&paths[0], ".bak"), "{}").unwrap();
+        let to = |name: &str| dir.join(name).to_string_lossy().to_string();
+        let batch = |names: &[(usize, &str)]| -> Vec<FileMove> {
+            names
+                .iter()
+                .map(|&(i, dest)| FileMove { from: paths[i].to_string_lossy().to_string(), to: to(dest) })
+                .collect()
+        };
+        let indexed = |conn: &Connection| -> Vec<String> {
+            let mut stmt = conn.prepare("SELECT path FROM files ORDER BY path").unwrap();
+            stmt.query_map([], |r| r.get::<_, String>(0))
+                .unwrap()
+                .map(|p| Path::new(&p.unwrap()).file_name().unwrap().to_string_lossy().to_string())
+                .collect()
+        };
+
+        // One destination is taken, so nothing moves
+        fs::write(dir.join("taken.ssce"), "{}").unwrap();
+        let results = move_documents(&mut conn, &batch(&[(0, "new/a.ssce"), (1, "taken.ssce")])).unwrap();
+        assert!(results.iter().all(|r| !r.moved));
+        assert!(results[1].error.as_deref().unwrap().contains("already exists"));
+        assert!(results[0].error.as_deref().unwrap().contains("another move"));
+        assert!(paths[0].exists() && !dir.join("new").exists());
+
+        // The second rename fails (its folder would be inside a file), so the
+        // first is undone and the library is unchanged
+        let results = move_documents(&mut conn, &batch(&[(0, "new/a.ssce"), (1, "taken.ssce/b.ssce")])).unwrap();
+        assert!(results.iter().all(|r| !r.moved));
+        assert!(results[1].error.as_deref().unwrap().contains("Failed to move file"));
+        assert!(paths[0].exists() && paths[1].exists() && !dir.join("new/a.ssce").exists());
+        assert_eq!(indexed(&conn), vec!["a.ssce", "b.ssce", "c.ssce"]);
+
+        let results = move_documents(&mut conn, &batch(&[(0, "new/a.ssce"), (1, "new/b2.ssce"), (2, "new/c.ssce")])).unwrap();
+        assert!(results.iter().all(|r| r.moved && r.error.is_none()));
+        assert!(paths.iter().all(|p| !p.exists()));
+        assert!(dir.join("new/b2.ssce").exists());
+        assert!(path_with_suffix(&dir.join("new/a.ssce"), ".bak").exists());
+        assert_eq!(indexed(&conn), vec!["a.ssce", "b2.ssce", "c.ssce"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn fts_tokenizer_folds_accents_and_splits_filenames() {
+        let conn = test_db();
+        assert_eq!(current_fts_tokenizer(&conn).unwrap(), Some(FtsTokenizer::default().spec()));
+
+        insert_test_file(&conn, "/library/cafe_menu.ssce", "Crème brûlée");
+        let matches = |query: &str| -> i64 {
+            conn.query_row("SELECT COUNT(*) FROM files_fts WHERE files_fts MATCH ?1", params![query], |r| r.get(0))
+                .unwrap()
+        };
+        // Accents are folded on both sides of the match, by the tokenizer itself
+        assert_eq!(matches("creme"), 1);
+        assert_eq!(matches("brulée"), 1);
+        // Underscores separate words
+        assert_eq!(matches("menu"), 1);
+
+        // A tokenizer that keeps accents and underscores rebuilds the index once
+        let strict = FtsTokenizer::from_config(
+            &typed_defaults(json!({
+                "search": { "tokenizer": { "removeDiacritics": 0, "separators": "", "tokenChars": "_\"" } }
+            }))
+            .unwrap(),
+        );
+        assert_eq!(strict.spec(), "unicode61 remove_diacritics 0 tokenchars '_'");
+        assert!(apply_fts_tokenizer(&conn, &strict).unwrap());
+        assert!(!apply_fts_tokenizer(&conn, &strict).unwrap());
+        assert_eq!(matches("creme"), 0);
+        assert_eq!(matches("crème"), 1);
+        assert_eq!(matches("menu"), 0);
+
+        // Kept when the index is rebuilt
+        rebuild_fts_index(&conn).unwrap();
+        assert_eq!(current_fts_tokenizer(&conn).unwrap(), Some(strict.spec()));
+
+        assert_eq!(camel_case_words("QuarterlyReport2024.ssce").as_deref(), Some("Quarterly Report 2024"));
+        assert_eq!(camel_case_words("notes.ssce"), None);
+    }
+
+    #[test]
+    fn database_health_reports_journal_mode() {
+        let dir = test_dir("db-health");
+        let conn = open_database(&dir.join("library.db")).unwrap();
+
+        let health = check_database_health(&conn).unwrap();
+        assert!(health.path.ends_with("library.db"));
+        let network = health.filesystem.as_deref().is_some_and(is_network_filesystem);
+        assert_eq!(health.journal_mode, if network { "delete" } else { "wal" });
+        assert_eq!(health.warnings.is_empty(), !network);
+
+        assert!(is_network_filesystem("nfs"));
+        assert!(is_network_filesystem("cifs"));
+        assert!(is_network_filesystem("fuse.sshfs"));
+        assert!(is_network_filesystem("fuse"));
+        assert!(!is_network_filesystem("fuseblk"));
+        assert!(!is_network_filesystem("ext4"));
+        assert_eq!(database_filesystem(&test_db()), None);
+
+        drop(conn);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn fuse_mounts_are_named_by_their_driver() {
+        let mountinfo = "\
+22 1 259:2 / / rw,relatime shared:1 - ext4 /dev/nvme0n1p2 rw
+48 22 0:45 / /home/me/remote rw,nosuid,nodev shared:30 - fuse.sshfs me@server:/srv rw,user_id=1000
+49 22 0:46 / /media/my\\040drive rw,nosuid,nodev shared:31 - fuseblk /dev/sdb1 rw,user_id=0
+";
+        let fs_type = |path: &str| mount_filesystem_type(mountinfo, Path::new(path));
+
+        assert_eq!(fs_type("/home/me/remote/library").as_deref(), Some("fuse.sshfs"));
+        assert_eq!(fs_type("/media/my drive/library").as_deref(), Some("fuseblk"));
+        // A path that only shares a prefix with a mount point isn't on it
+        assert_eq!(fs_type("/home/me/remote-copy").as_deref(), Some("ext4"));
+        assert_eq!(mount_filesystem_type("", Path::new("/home")), None);
+        assert!(is_network_filesystem(&fs_type("/home/me/remote").unwrap()));
+    }
+
+    #[test]
+    fn retry_policy_retries_only_transient_errors() {
+        use std::io::{Error, ErrorKind};
+
+        let policy = RetryPolicy {
+            attempts: 3,
+            initial_delay: std::time::Duration::from_millis(1),
+            max_delay: std::time::Duration::from_millis(2),
+        };
+
+        // Fails twice with a timeout, then succeeds
+        let mut calls = 0;
+        let result = policy.run(|| {
+            calls += 1;
+            if calls <= 2 {
+                Err(Error::from(ErrorKind::TimedOut))
+            } else {
+                Ok(calls)
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+
+        // Never recovers: gives up after the configured retries
+        let mut calls = 0;
+        let result: std::io::Result<()> = policy.run(|| {
+            calls += 1;
+            Err(Error::from(ErrorKind::WouldBlock))
+        });
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::WouldBlock);
+        assert_eq!(calls, 4);
+
+        // Not retryable: fails on the first attempt
+        for kind in [ErrorKind::NotFound, ErrorKind::PermissionDenied] {
+            let mut calls = 0;
+            let result: std::io::Result<()> = policy.run(|| {
+                calls += 1;
+                Err(Error::from(kind))
+            });
+            assert_eq!(result.unwrap_err().kind(), kind);
+            assert_eq!(calls, 1);
+        }
+
+        // Off by default
+        let mut calls = 0;
+        let _ = RetryPolicy::default().run(|| -> std::io::Result<()> {
+            calls += 1;
+            Err(Error::from(ErrorKind::TimedOut))
+        });
+        assert_eq!(calls, 1);
+
+        let configured =
+            RetryPolicy::from_config(&typed_defaults(json!({"fileRetry": {"attempts": 2, "initialDelayMs": 50}})).unwrap());
+        assert_eq!(configured.attempts, 2);
+        assert_eq!(configured.initial_delay, std::time::Duration::from_millis(50));
+        assert_eq!(configured.max_delay, RetryPolicy::default().max_delay);
+    }
+
+    #[test]
+    fn close_quits_without_a_visible_tray() {
+        let built = TrayStatus::after_build(Ok(()), true);
+        assert_eq!(built, TrayStatus { available: true, visible: true, error: None });
+        assert!(built.hides_on_close(true));
+        assert!(!built.hides_on_close(false));
+
+        let hidden = TrayStatus::after_build(Ok(()), false);
+        assert!(!hidden.hides_on_close(true));
+
+        // A tray that couldn't be built is never shown, whatever the setting
+        let failed = TrayStatus::after_build(Err("no StatusNotifier host".to_string()), true);
+        assert!(!failed.available);
+        assert!(!failed.visible);
+        assert_eq!(failed.error.as_deref(), Some("no StatusNotifier host"));
+        assert!(!failed.hides_on_close(true));
+    }
+
+    #[test]
+    fn off_screen_windows_are_detected() {
+        let rect = |x, y, width, height| ScreenRect { x, y, width, height };
+        let primary = rect(0, 0, 1920, 1080);
+        // A second monitor to the left of the primary one
+        let left = rect(-2560, 0, 2560, 1440);
+        let monitors = [primary, left];
+
+        assert!(!is_off_screen(&rect(100, 100, 800, 600), &monitors));
+        assert!(!is_off_screen(&rect(-1000, 200, 800, 600), &monitors));
+        // Straddling both monitors
+        assert!(!is_off_screen(&rect(-400, 100, 800, 600), &monitors));
+
+        // Restored onto the left monitor after it was unplugged
+        assert!(is_off_screen(&rect(-1000, 200, 800, 600), &[primary]));
+        // Beyond every edge, or with only a sliver showing
+        assert!(is_off_screen(&rect(1920, 100, 800, 600), &monitors));
+        assert!(is_off_screen(&rect(100, 1070, 800, 600), &monitors));
+        assert!(is_off_screen(&rect(1900, 100, 800, 600), &monitors));
+        // In the gap below the shorter monitor
+        assert!(is_off_screen(&rect(200, 1200, 800, 200), &monitors));
+        assert!(is_off_screen(&rect(0, 0, 800, 600), &[]));
+    }
+
+    #[test]
+    fn centered_position_keeps_big_windows_reachable() {
+        let monitor = ScreenRect { x: -1920, y: 100, width: 1920, height: 1080 };
+        assert_eq!(centered_position(800, 600, &monitor), (-1920 + 560, 100 + 240));
+        assert_eq!(centered_position(2500, 600, &monitor), (-1920, 100 + 240));
+        assert_eq!(centered_position(1920, 2000, &monitor), (-1920, 100));
+    }
 }
@@ -309,27 +309,20 @@ async function init() {
   // Clamp window size to 90% of monitor (window-state plugin may have restored an oversized window)
   await tauriBridge.clampWindowSize();
 
-  // Check if app was launched with a file argument (e.g., ssce-desktop /path/to/file.png)
   if (window.__TAURI__?.core) {
-    try {
-      const filePath = await window.__TAURI__.core.invoke("get_cli_file_arg");
-      if (filePath) {
-        console.log("SSCE: CLI file argument:", filePath);
-        const { loadFileFromPath } = await import("./file-operations.js");
-        await loadFileFromPath(filePath, updateStatusBar);
-      }
-    } catch (err) {
-      console.error("SSCE: Failed to check CLI file arg:", err);
-    }
-
-    // Listen for file open events from single-instance plugin
-    // (when a second instance is launched with a file, it passes the path to the running instance)
+    // Listen for file open events. These come from:
+    // - .ssce documents the app was launched with (sent once frontend_ready is called)
+    // - a second instance launched with files (single-instance plugin forwards them)
+    // - the document a document window was opened for
+    // Events are sent to a specific window, so listen on this one only.
     if (window.__TAURI__?.event) {
-      window.__TAURI__.event.listen("open-file", async (event) => {
+      const currentWindow = window.__TAURI__.webviewWindow?.getCurrentWebviewWindow();
+      const listen = currentWindow ? currentWindow.listen.bind(currentWindow) : window.__TAURI__.event.listen;
+      await listen("open-file", async (event) => {
         const filePath = event.payload;
         if (!filePath) return;
 
-        console.log("SSCE: Open file event from second instance:", filePath);
+        console.log("SSCE: Open file event:", filePath);
 
         if (state.hasUnsavedChanges) {
           const { showConfirmModal } = await import("./ui/dialogs/alert-confirm.js");
@@ -345,6 +338,51 @@ async function init() {
           showToast(`Failed to open file: ${err.message || err}`, "error");
         }
       });
+
+      // Documents that were open when the app last closed (main window only, once per run).
+      // The first reopens here, the rest in windows of their own.
+      await listen("restore-session", async (event) => {
+        const [first, ...others] = event.payload || [];
+        if (!first || state.currentFilePath) return;
+
+        try {
+          const { loadFileFromPath } = await import("./file-operations.js");
+          await loadFileFromPath(first, updateStatusBar);
+          for (const path of others) {
+            await tauriBridge.openDocumentWindow(path);
+          }
+        } catch (err) {
+          console.error("SSCE: Failed to restore session:", err);
+        }
+      });
+
+      // The library database couldn't be opened; the backend is running on an empty in-memory one
+      await listen("backend-error", async (event) => {
+        const { databasePath, message } = event.payload || {};
+        console.error("SSCE: Library database unavailable:", databasePath, message);
+        const { showAlertModal } = await import("./ui/dialogs/alert-confirm.js");
+        showAlertModal("Library Unavailable", `The library database at ${databasePath} could not be opened:\n\n${message}\n\nSearch and recent files won't be saved this session.`);
+      });
+
+      // Listener is registered - ask the backend for any documents passed at launch
+      // (and the startup status events, in case they were sent before now)
+      try {
+        await window.__TAURI__.core.invoke("frontend_ready");
+      } catch (err) {
+        console.error("SSCE: Failed to signal frontend ready:", err);
+      }
+    }
+
+    // Check if app was launched with an image argument (e.g., ssce-desktop /path/to/file.png)
+    try {
+      const filePath = await window.__TAURI__.core.invoke("get_cli_file_arg");
+      if (filePath) {
+        console.log("SSCE: CLI file argument:", filePath);
+        const { loadFileFromPath } = await import("./file-operations.js");
+        await loadFileFromPath(filePath, updateStatusBar);
+      }
+    } catch (err) {
+      console.error("SSCE: Failed to check CLI file arg:", err);
     }
   }
 
@@ -34,7 +34,9 @@ export async function newCanvas(updateStatusBar) {
   // Clear filename state
   state.filename = null;
   state.currentFilePath = null;
+  state.fileFingerprint = null;
   state.hasUnsavedChanges = false;
+  rememberSession();
 
   // Update status bar
   if (updateStatusBar) {
@@ -42,6 +44,55 @@ export async function newCanvas(updateStatusBar) {
   }
 }
 
+/**
+ * Record the main window's document as the session to reopen on next launch
+ * (documents in their own windows are added by the backend)
+ */
+function rememberSession() {
+  const label = window.__TAURI__?.webviewWindow?.getCurrentWebviewWindow()?.label;
+  if (label !== "main") return;
+
+  const paths = state.currentFilePath && state.sourceFormat === "ssce" ? [state.currentFilePath] : [];
+  bridge.saveSession(paths).catch((err) => console.warn("Failed to save session:", err));
+}
+
+/**
+ * Remember the current .ssce file's fingerprint, so a later save can tell
+ * whether another app changed it in the meantime
+ */
+async function captureFingerprint() {
+  state.fileFingerprint = null;
+  if (!state.currentFilePath || !bridge.isTauri()) return;
+
+  try {
+    state.fileFingerprint = await bridge.fileFingerprint(state.currentFilePath);
+  } catch (err) {
+    console.warn("Failed to fingerprint file:", err);
+  }
+}
+
+/**
+ * Before overwriting the current file, check it hasn't changed on disk since
+ * it was loaded or last saved. Asks the user if it has.
+ * @returns {Promise<boolean>} true if it's fine to overwrite
+ */
+async function confirmOverwriteIfChanged() {
+  const before = state.fileFingerprint;
+  if (!before) return true;
+
+  let now;
+  try {
+    now = await bridge.fileFingerprint(state.currentFilePath);
+  } catch (err) {
+    // Deleted or unreadable - saving recreates it
+    return true;
+  }
+
+  if (now.size === before.size && now.sha256 === before.sha256) return true;
+
+  return await showConfirmModal("File Changed on Disk", `${state.filename} was changed by another program since you opened it.\n\nOverwrite those changes?`, { confirmText: "Overwrite", cancelText: "Cancel", type: "warning" });
+}
+
 /**
  * Open file dialog - uses native Tauri dialog or falls back to system file input
  * @param {Function} updateStatusBar - Callback to update status bar
@@ -121,6 +172,8 @@ export async function loadFileFromPath(filePath, updateStatusBar) {
       state.currentFilePath = filePath;
       state.hasUnsavedChanges = false;
       state.sourceFormat = "ssce";
+      rememberSession();
+      await captureFingerprint();
 
       // Render
       modules.canvasManager.render();
@@ -147,6 +200,8 @@ export async function loadFileFromPath(filePath, updateStatusBar) {
         summary: sessionData.frontMatter?.summary,
         keywords: sessionData.keywords?.join(" "),
         modified: sessionData.frontMatter?.modified,
+        author: sessionData.frontMatter?.author,
+        created: sessionData.frontMatter?.created,
         snapshotCount,
       });
 
@@ -173,6 +228,7 @@ export async function loadFileFromPath(filePath, updateStatusBar) {
 
       state.filename = bridge.getFilename(filePath);
       state.currentFilePath = filePath;
+      state.fileFingerprint = null;
       state.hasUnsavedChanges = false;
       state.sourceFormat = "image";
 
@@ -229,6 +285,7 @@ export function loadImageFile(file, updateStatusBar) {
       modules.layerManager.addImageLayer(img);
       state.filename = file.name;
       state.currentFilePath = null; // No path for File objects
+      state.fileFingerprint = null;
       state.hasUnsavedChanges = false;
       state.sourceFormat = "image";
 
@@ -293,6 +350,7 @@ async function loadSsceFromFile(file, updateStatusBar) {
       // Update file state
       state.filename = file.name;
       state.currentFilePath = null;
+      state.fileFingerprint = null;
       state.hasUnsavedChanges = false;
       state.sourceFormat = "ssce";
 
@@ -342,6 +400,8 @@ export async function handleSave(handleSaveAsCallback, updateStatusBar) {
 
   // If we have a current .ssce file path, save directly to it
   if (state.currentFilePath && bridge.isTauri() && bridge.isSsceFile(state.currentFilePath)) {
+    if (!(await confirmOverwriteIfChanged())) return;
+
     showSpinner();
     try {
       const { serialize } = await import("./utils/ssce-format.js");
@@ -368,6 +428,8 @@ export async function handleSave(handleSaveAsCallback, updateStatusBar) {
           summary: parsed.frontMatter?.summary,
           keywords: parsed.keywords?.join(" "),
           modified: parsed.frontMatter?.modified,
+          author: parsed.frontMatter?.author,
+          created: parsed.frontMatter?.created,
           snapshotCount: parsed.snapshots?.length || 0,
         });
       } catch (e) {
@@ -376,6 +438,7 @@ export async function handleSave(handleSaveAsCallback, updateStatusBar) {
 
       state.hasUnsavedChanges = false;
       state.frontMatter = frontMatter;
+      await captureFingerprint();
       showToast(`Saved: ${state.filename}`, "success");
       if (updateStatusBar) updateStatusBar();
       return;
@@ -597,6 +660,8 @@ export async function saveAsSsce(options = {}, updateStatusBar) {
         summary: parsed.frontMatter?.summary,
         keywords: parsed.keywords?.join(" "),
         modified: parsed.frontMatter?.modified,
+        author: parsed.frontMatter?.author,
+        created: parsed.frontMatter?.created,
         snapshotCount: parsed.snapshots?.length || 0,
       });
     } catch (e) {
@@ -609,6 +674,8 @@ export async function saveAsSsce(options = {}, updateStatusBar) {
     state.hasUnsavedChanges = false;
     state.frontMatter = frontMatter;
     state.sourceFormat = "ssce";
+    rememberSession();
+    await captureFingerprint();
 
     showToast(`Saved: ${state.filename}`, "success");
     if (updateStatusBar) updateStatusBar();
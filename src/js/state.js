@@ -36,6 +36,7 @@ export const state = {
   sourceFormat: "image", // Source format: "image" (PNG/JPG) or "ssce"
   currentSnapshotIndex: -1, // Index of currently restored snapshot (-1 = not at a snapshot)
   savedLoadedState: null, // Saved state of canvas when first undoing to snapshots (for redo back)
+  fileFingerprint: null, // {size, mtime, sha256} of currentFilePath when loaded/saved, to spot external edits
 };
 
 // ============================================================================
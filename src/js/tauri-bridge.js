@@ -31,14 +31,6 @@ function getInvoke() {
   return window.__TAURI__?.core?.invoke || null;
 }
 
-/**
- * Get the Tauri dialog API from global API
- * @returns {Object|null}
- */
-function getDialog() {
-  return window.__TAURI__?.dialog || null;
-}
-
 /**
  * Check if running in Tauri environment
  * @returns {boolean}
@@ -161,23 +153,27 @@ export async function showOpenDialog(options = {}) {
   }
 
   try {
-    const dialog = getDialog();
-    if (!dialog || !dialog.open) throw new Error("Tauri dialog API not available");
+    const invoke = getInvoke();
+    if (!invoke) throw new Error("Tauri invoke not available");
 
     const defaultPath = options.defaultPath || (await getOpenDirectory());
 
-    const result = await dialog.open({
-      title: options.title || "Open File",
-      defaultPath,
-      multiple: options.multiple || false,
-      directory: false,
-      filters: options.filters || [
-        { name: "All Supported", extensions: ["png", "jpg", "jpeg", "gif", "webp", "bmp", "ssce"] },
-        { name: "Images", extensions: ["png", "jpg", "jpeg", "gif", "webp", "bmp"] },
-        { name: "SSCE Files", extensions: ["ssce"] },
-        { name: "All Files", extensions: ["*"] },
-      ],
+    // The backend shows the dialog so it can allow saving next to the chosen files
+    const multiple = options.multiple || false;
+    const paths = await invoke("show_open_dialog", {
+      options: {
+        title: options.title || "Open File",
+        defaultPath,
+        multiple,
+        filters: options.filters || [
+          { name: "All Supported", extensions: ["png", "jpg", "jpeg", "gif", "webp", "bmp", "ssce"] },
+          { name: "Images", extensions: ["png", "jpg", "jpeg", "gif", "webp", "bmp"] },
+          { name: "SSCE Files", extensions: ["ssce"] },
+          { name: "All Files", extensions: ["*"] },
+        ],
+      },
     });
+    const result = paths.length === 0 ? null : multiple ? paths : paths[0];
 
     // Update last used directory if a file was selected
     if (result) {
@@ -208,8 +204,8 @@ export async function showSaveDialog(options = {}) {
   }
 
   try {
-    const dialog = getDialog();
-    if (!dialog || !dialog.save) throw new Error("Tauri dialog API not available");
+    const invoke = getInvoke();
+    if (!invoke) throw new Error("Tauri invoke not available");
 
     // Build default path from directory + filename
     let defaultPath = options.defaultPath;
@@ -221,15 +217,18 @@ export async function showSaveDialog(options = {}) {
       defaultPath = options.defaultName ? `${dir}/${options.defaultName}` : dir;
     }
 
-    const result = await dialog.save({
-      title: options.title || "Save File",
-      defaultPath,
-      filters: options.filters || [
-        { name: "PNG Image", extensions: ["png"] },
-        { name: "JPEG Image", extensions: ["jpg", "jpeg"] },
-        { name: "SSCE File", extensions: ["ssce"] },
-        { name: "All Files", extensions: ["*"] },
-      ],
+    // The backend shows the dialog so it can allow writing to the chosen folder
+    const result = await invoke("show_save_dialog", {
+      options: {
+        title: options.title || "Save File",
+        defaultPath,
+        filters: options.filters || [
+          { name: "PNG Image", extensions: ["png"] },
+          { name: "JPEG Image", extensions: ["jpg", "jpeg"] },
+          { name: "SSCE File", extensions: ["ssce"] },
+          { name: "All Files", extensions: ["*"] },
+        ],
+      },
     });
 
     // Update last used directory if a path was selected
@@ -248,10 +247,14 @@ export async function showSaveDialog(options = {}) {
 /**
  * Browse a directory and return file listing
  * @param {string} dir - Directory path
- * @param {string} filter - Filter: "all", "ssce", "images"
- * @returns {Promise<Array<{name: string, is_dir: boolean, size: number}>>}
+ * @param {string} filter - Filter: "all", "ssce", "images", or a comma-separated list of extensions/globs (e.g. "*.ssce,md")
+ * @param {boolean} [recursive=false] - Also list subdirectories
+ * @param {number|null} [maxDepth=null] - Levels below dir to list when recursive (null = unlimited)
+ * @param {string|null} [sort=null] - "Name", "Size" or "Modified" (null = Name)
+ * Entries whose name isn't valid UTF-8 carry an `encoded_path`; pass that (not the name) to commands that open the file.
+ * @returns {Promise<Array<{name: string, path: string, is_dir: boolean, size: number, mtime: number, encoded_path: string|null}>>}
  */
-export async function browseDirectory(dir, filter = "all") {
+export async function browseDirectory(dir, filter = "all", recursive = false, maxDepth = null, sort = null) {
   if (!isTauri()) {
     console.warn("browseDirectory: Not in Tauri environment");
     return [];
@@ -260,19 +263,77 @@ export async function browseDirectory(dir, filter = "all") {
   try {
     const invoke = getInvoke();
     if (!invoke) throw new Error("Tauri invoke not available");
-    return await invoke("browse_directory", { dir, filter });
+    return await invoke("browse_directory", { dir, filter, recursive, maxDepth, sort });
   } catch (error) {
     console.error("browseDirectory failed:", error);
     throw error;
   }
 }
 
+/**
+ * Browse a large directory progressively (non-recursive). Folders arrive
+ * first, then files by name, in batches.
+ * @param {string} dir - Directory path
+ * @param {string} filter - Same filters as browseDirectory
+ * @param {function(Array<Object>): void} onBatch - Called with each batch of entries
+ * @param {number|null} [batchSize=null] - Entries per batch (null = backend default)
+ * @returns {Promise<number>} Total number of entries listed
+ */
+export async function browseDirectoryStream(dir, filter, onBatch, batchSize = null) {
+  if (!isTauri()) {
+    console.warn("browseDirectoryStream: Not in Tauri environment");
+    return 0;
+  }
+
+  const listen = window.__TAURI__?.event?.listen;
+  const unlisten = listen ? await listen("directory-batch", (event) => {
+    if (event.payload.dir === dir) onBatch(event.payload.entries);
+  }) : null;
+
+  try {
+    const invoke = getInvoke();
+    if (!invoke) throw new Error("Tauri invoke not available");
+    return await invoke("browse_directory_stream", { dir, filter, batchSize });
+  } catch (error) {
+    console.error("browseDirectoryStream failed:", error);
+    throw error;
+  } finally {
+    if (unlisten) unlisten();
+  }
+}
+
+/**
+ * Folder and filter of the last browseDirectory call, for restoring the
+ * browser on startup. A folder that no longer exists is replaced by
+ * paths.defaultImageLoad.
+ * @returns {Promise<{directory: string|null, filter: string}>}
+ */
+export async function getLastBrowseState() {
+  if (!isTauri()) {
+    return { directory: null, filter: "all" };
+  }
+
+  try {
+    const invoke = getInvoke();
+    if (!invoke) throw new Error("Tauri invoke not available");
+    return await invoke("get_last_browse_state");
+  } catch (error) {
+    console.error("getLastBrowseState failed:", error);
+    return { directory: null, filter: "all" };
+  }
+}
+
 /**
  * Load an image file and return as base64 data URL
  * @param {string} path - File path
- * @returns {Promise<string>} Base64 data URL
+ * @param {boolean} [placeholder=false] - On a missing or unreadable image, resolve with a
+ *   placeholder tile instead of throwing
+ * @returns {Promise<string|{data_url: string, placeholder: boolean, error: string|null, mislabeled: boolean}>}
+ *   Base64 data URL, or with placeholder the data URL, whether it is a placeholder, and why. The data URL's
+ *   type comes from the file's content; `mislabeled` is true when that contradicts the extension.
+ * @throws {{kind: "TooLarge", message: string}} if the image is over the `images` limits in defaults.json
  */
-export async function loadImage(path) {
+export async function loadImage(path, placeholder = false) {
   if (!isTauri()) {
     throw new Error("loadImage: Not in Tauri environment");
   }
@@ -280,7 +341,7 @@ export async function loadImage(path) {
   try {
     const invoke = getInvoke();
     if (!invoke) throw new Error("Tauri invoke not available");
-    return await invoke("load_image", { path });
+    return await invoke("load_image", { path, placeholder });
   } catch (error) {
     console.error("loadImage failed:", error);
     throw error;
@@ -291,7 +352,8 @@ export async function loadImage(path) {
  * Save an image to file
  * @param {string} path - File path
  * @param {string} data - Base64 data (with or without data URL prefix)
- * @returns {Promise<void>}
+ * @returns {Promise<{format: string, width: number, height: number}>} Detected format and size
+ * @throws {{kind: "TooLarge", message: string}} if the image is over the `images` limits in defaults.json
  */
 export async function saveImage(path, data) {
   if (!isTauri()) {
@@ -301,7 +363,7 @@ export async function saveImage(path, data) {
   try {
     const invoke = getInvoke();
     if (!invoke) throw new Error("Tauri invoke not available");
-    await invoke("save_image", { path, data });
+    return await invoke("save_image", { path, data });
   } catch (error) {
     console.error("saveImage failed:", error);
     throw error;
@@ -332,9 +394,14 @@ export async function loadSsce(path) {
  * Save a .ssce file
  * @param {string} path - File path
  * @param {string} data - JSON string
+ * @param {boolean} [compress=false] - Write the file gzip-compressed (loading detects this automatically)
+ * @param {boolean} [canonical=false] - Rewrite the JSON with sorted keys and standard formatting first
  * @returns {Promise<void>}
+ * @throws {{kind: "ReadOnly"|"OutsideRoot"|"Io"|"Invalid", message: string}} ReadOnly if the file or its folder can't be written,
+ *   OutsideRoot if it isn't inside the library, default, autosave, Downloads or paths.extraRoots folders,
+ *   Invalid if canonical is set and data isn't valid JSON
  */
-export async function saveSsce(path, data) {
+export async function saveSsce(path, data, compress = false, canonical = false) {
   if (!isTauri()) {
     throw new Error("saveSsce: Not in Tauri environment");
   }
@@ -342,13 +409,28 @@ export async function saveSsce(path, data) {
   try {
     const invoke = getInvoke();
     if (!invoke) throw new Error("Tauri invoke not available");
-    await invoke("save_ssce", { path, data });
+    await invoke("save_ssce", { path, data, compress, canonical });
   } catch (error) {
     console.error("saveSsce failed:", error);
     throw error;
   }
 }
 
+/**
+ * Check whether a document can be saved back to its location
+ * @param {string} path - File path
+ * @returns {Promise<boolean>}
+ */
+export async function fileWritable(path) {
+  if (!isTauri()) {
+    return false;
+  }
+
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("file_writable", { path });
+}
+
 /**
  * Check if a file exists
  * @param {string} path - File path
@@ -435,10 +517,11 @@ export function isSsceFile(path) {
  * Save autosave data to a temp file
  * @param {string} data - JSON string data to save
  * @param {string} filename - Filename for the autosave file
- * @param {string} directory - Directory to save the file in
+ * @param {string|null} [directory=null] - Directory to save the file in (null = resolveAutosaveDir's choice)
+ * @param {string|null} [originalPath=null] - Saved document being edited, recorded for listAutosavesFor
  * @returns {Promise<string>} Full path of saved file
  */
-export async function saveAutosave(data, filename, directory) {
+export async function saveAutosave(data, filename, directory = null, originalPath = null) {
   if (!isTauri()) {
     throw new Error("saveAutosave: Not in Tauri environment");
   }
@@ -446,7 +529,7 @@ export async function saveAutosave(data, filename, directory) {
   try {
     const invoke = getInvoke();
     if (!invoke) throw new Error("Tauri invoke not available");
-    return await invoke("save_autosave", { data, filename, directory });
+    return await invoke("save_autosave", { data, filename, directory, originalPath });
   } catch (error) {
     console.error("saveAutosave failed:", error);
     throw error;
@@ -476,9 +559,10 @@ export async function deleteAutosave(path) {
 /**
  * List autosave files in a directory
  * @param {string} directory - Directory to list files from
+ * @param {boolean} [recursive=false] - Also search (non-hidden) subfolders
  * @returns {Promise<Array<{name: string, path: string, mtime: number}>>}
  */
-export async function listAutosaveFiles(directory) {
+export async function listAutosaveFiles(directory, recursive = false) {
   if (!isTauri()) {
     return [];
   }
@@ -486,13 +570,87 @@ export async function listAutosaveFiles(directory) {
   try {
     const invoke = getInvoke();
     if (!invoke) throw new Error("Tauri invoke not available");
-    return await invoke("list_autosave_files", { directory });
+    return await invoke("list_autosave_files", { directory, recursive });
   } catch (error) {
     console.error("listAutosaveFiles failed:", error);
     return [];
   }
 }
 
+/**
+ * Read an editor setting stored by the backend (e.g. "autosaveIntervalSeconds")
+ * @param {string} key - Setting name
+ * @returns {Promise<*>} The setting value, or null if unavailable
+ */
+export async function getSetting(key) {
+  if (!isTauri()) {
+    return null;
+  }
+
+  try {
+    const invoke = getInvoke();
+    if (!invoke) throw new Error("Tauri invoke not available");
+    return await invoke("get_setting", { key });
+  } catch (error) {
+    console.error("getSetting failed:", error);
+    return null;
+  }
+}
+
+/**
+ * Store an editor setting (the backend checks the value's type)
+ * @param {string} key - Setting name
+ * @param {*} value - New value
+ */
+export async function setSetting(key, value) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  await invoke("set_setting", { key, value });
+}
+
+/**
+ * Open a document in a window of its own (or focus its window if open)
+ * @param {string} path - .ssce file path
+ * @returns {Promise<string>} Window label
+ */
+export async function openDocumentWindow(path) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("open_document_window", { path });
+}
+
+/**
+ * Print a document on the default printer
+ * @param {string} sscePath - .ssce file path
+ * @returns {Promise<void>}
+ */
+export async function printDocument(sscePath) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  await invoke("print_document", { sscePath });
+}
+
+/**
+ * Startup recovery check - lists autosave files and shows a system
+ * notification if there is anything to recover
+ * @param {string} directory - Autosave directory
+ * @returns {Promise<Array<{name: string, path: string, mtime: number}>>}
+ */
+export async function checkRecovery(directory) {
+  if (!isTauri()) {
+    return [];
+  }
+
+  try {
+    const invoke = getInvoke();
+    if (!invoke) throw new Error("Tauri invoke not available");
+    return await invoke("check_recovery", { directory });
+  } catch (error) {
+    console.error("checkRecovery failed:", error);
+    return [];
+  }
+}
+
 // ============================================================================
 // Window Management
 
@@ -511,6 +669,18 @@ export async function clampWindowSize() {
   }
 }
 
+/**
+ * Centre this window on the primary monitor, for recovering a window that
+ * ended up off screen
+ * @returns {Promise<void>}
+ */
+export async function resetWindowPosition() {
+  if (!isTauri()) return;
+  const invoke = getInvoke();
+  if (!invoke) return;
+  await invoke("reset_window_position");
+}
+
 // Bulk Export Functions
 // ============================================================================
 
@@ -613,6 +783,21 @@ export async function zipFinalize(zipId) {
   await invoke("zip_finalize", { zipId });
 }
 
+/**
+ * Bundle documents into one ZIP archive, keeping their folder layout below
+ * the folder they share. Emits "export-zip-progress" ({done, total}).
+ * @param {string[]} paths - Document paths
+ * @param {string} outPath - Archive to write
+ * @param {boolean} [includeAssets=false] - Also add snapshot images stored as separate files
+ * @returns {Promise<{path: string, included: number, skipped: Array<{path: string, reason: string}>}>}
+ * @throws {{kind: "Validation", message: string}} if none of the documents exist
+ */
+export async function exportZip(paths, outPath, includeAssets = false) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("export_zip", { paths, outPath, includeAssets });
+}
+
 /**
  * Show native folder picker dialog
  * @param {Object} options
@@ -623,14 +808,11 @@ export async function zipFinalize(zipId) {
 export async function showFolderDialog(options = {}) {
   if (!isTauri()) return null;
   try {
-    const dialog = getDialog();
-    if (!dialog || !dialog.open) throw new Error("Tauri dialog API not available");
+    const invoke = getInvoke();
+    if (!invoke) throw new Error("Tauri invoke not available");
     const defaultPath = options.defaultPath || (await getHomeDir());
-    return await dialog.open({
-      title: options.title || "Select Folder",
-      defaultPath,
-      directory: true,
-      multiple: false,
+    return await invoke("show_folder_dialog", {
+      options: { title: options.title || "Select Folder", defaultPath },
     });
   } catch (error) {
     console.error("showFolderDialog failed:", error);
@@ -746,3 +928,708 @@ export async function hasClipboardImage() {
     return false;
   }
 }
+
+/**
+ * List saved copies of defaults.json, newest first
+ * @returns {Promise<Array<{name: string, size: number, mtime: number}>>}
+ */
+export async function listConfigBackups() {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("list_config_backups");
+}
+
+/**
+ * Restore defaults.json from a backup (the current config is backed up first)
+ * @param {string} name - Backup name from listConfigBackups
+ * @returns {Promise<string>} Path of the restored defaults.json
+ */
+export async function restoreConfigBackup(name) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("restore_config_backup", { name });
+}
+
+/**
+ * Open a file with a named application, or the default browser
+ * @param {string} path - File to open
+ * @param {string|null} [app=null] - Executable name or path (macOS: application name);
+ *   must be listed in openWithApps in defaults.json
+ * @returns {Promise<void>}
+ */
+export async function openPathWith(path, app = null) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  await invoke("open_path_with", { path, app });
+}
+
+/**
+ * Open a file (e.g. exported HTML) in the default browser and count it as an
+ * open of the document it came from, so it shows in recent and most-used lists
+ * @param {string} path - File to open
+ * @param {string|null} [sourcePath=null] - The .ssce it was exported from (null = path itself)
+ * @returns {Promise<{launch: {program: string, pid: number}, tracked: boolean}>}
+ *   What was started, and whether a library document recorded the open
+ */
+export async function openAndRegister(path, sourcePath = null) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("open_and_register", { path, sourcePath });
+}
+
+/**
+ * Choose whether closing the main window hides it to the tray or quits
+ * @param {boolean} enabled - true to hide to the tray
+ * @returns {Promise<void>}
+ */
+export async function setCloseToTray(enabled) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  await invoke("set_close_to_tray", { enabled });
+}
+
+/**
+ * Show or hide the system tray icon. While it's hidden, closing the main
+ * window quits instead of hiding to the tray.
+ * @param {boolean} visible
+ * @returns {Promise<void>}
+ */
+export async function setTrayVisible(visible) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  await invoke("set_tray_visible", { visible });
+}
+
+/**
+ * Whether the system tray icon is showing
+ * @returns {Promise<boolean>}
+ */
+export async function isTrayVisible() {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("is_tray_visible");
+}
+
+/**
+ * Whether the app has a tray icon. On desktops without a system tray the app
+ * runs without one, and closing the main window quits.
+ * @returns {Promise<{available: boolean, visible: boolean, error: string|null}>}
+ */
+export async function getTrayStatus() {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("get_tray_status");
+}
+
+/**
+ * Load only the version, front matter and thumbnail of a .ssce file
+ * (much cheaper than loadSsce for documents with many snapshots)
+ * @param {string} path - File path
+ * @returns {Promise<{version: string|null, frontMatter: Object|null, thumbnail: string|null}>}
+ */
+export async function loadSsceHeader(path) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("load_ssce_header", { path });
+}
+
+/**
+ * Remember the documents currently open, for reopening on the next launch
+ * @param {string[]} openPaths - Paths of the open .ssce documents
+ * @returns {Promise<void>}
+ */
+export async function saveSession(openPaths) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  await invoke("save_session", { openPaths });
+}
+
+/**
+ * Documents open when the app last closed (missing files are left out)
+ * @returns {Promise<string[]>}
+ */
+export async function getSession() {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("get_session");
+}
+
+/**
+ * Preview how a keyword string will be stored in the library
+ * (lowercased, whitespace collapsed, duplicates removed)
+ * @param {string} input - Space-separated keywords
+ * @returns {Promise<string>}
+ */
+export async function normalizeKeywords(input) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("normalize_keywords", { input });
+}
+
+/**
+ * Read a .ssce document's format version without loading its snapshots
+ * @param {string} path - Path to the .ssce file
+ * @returns {Promise<string|null>} e.g. "1.1", or null if the file has no version
+ */
+export async function getSsceVersion(path) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("get_ssce_version", { path });
+}
+
+/**
+ * Upgrade an older .ssce document in place (the original is kept as .bak)
+ * @param {string} path - Path to the .ssce file
+ * @param {string} [toVersion] - Target version (defaults to the current format)
+ * @returns {Promise<{from: string, to: string, migrated: boolean}>}
+ * @throws {{kind: "UnsupportedVersion", message: string}} if the version is unknown, newer, or a downgrade
+ */
+export async function migrateSsce(path, toVersion = null) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("migrate_ssce", { path, toVersion });
+}
+
+/**
+ * Load thumbnails for many files at once (rendered in parallel, cached on disk)
+ * @param {Array<{path: string, max_dim?: number}>} requests - Files and optional longest edge
+ * @returns {Promise<Object<string, {thumbnail: string|null, error: {kind: string, message: string}|null}>>} Results keyed by path
+ */
+export async function loadThumbnailsBatch(requests) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("load_thumbnails_batch", { requests });
+}
+
+/**
+ * Word, character, snapshot and image counts for a .ssce document
+ * @param {string} path - Path to the .ssce file
+ * @returns {Promise<{snapshot_count: number, word_count: number, image_count: number, character_count: number, partial: boolean}>}
+ *   partial is true if part of the document couldn't be read
+ */
+export async function getSsceStats(path) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("get_ssce_stats", { path });
+}
+
+/**
+ * Check if a file exists without hanging on an unreachable network mount
+ * @param {string} path - File path to check
+ * @param {number} [ms=2000] - How long to wait for an answer
+ * @returns {Promise<boolean|null>} null if the check didn't answer in time
+ */
+export async function fileExistsTimeout(path, ms = 2000) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("file_exists_timeout", { path, ms });
+}
+
+/**
+ * Set the chip colour for a keyword
+ * @param {string} keyword - A single keyword
+ * @param {string|null} color - "#RGB" or "#RRGGBB", or null to remove the colour
+ * @returns {Promise<void>}
+ * @throws {{kind: "Validation", message: string}} if the colour isn't a hex colour
+ */
+export async function setTagColor(keyword, color) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  await invoke("db_set_tag_color", { keyword, color });
+}
+
+/**
+ * Chip colours for every keyword that has one
+ * @returns {Promise<Object<string, string>>} keyword -> "#RRGGBB"
+ */
+export async function getTagColors() {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("db_get_tag_colors");
+}
+
+/**
+ * Pairs of keywords used together on the same documents, most frequent first
+ * @param {number} [minCount=2] - Fewest documents a pair must share
+ * @param {number} [maxKeywords=50] - Keywords per document that are paired (at most 200)
+ * @returns {Promise<Array<{a: string, b: string, count: number}>>}
+ */
+export async function getKeywordCooccurrence(minCount = 2, maxKeywords = 50) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("db_keyword_cooccurrence", { minCount, maxKeywords });
+}
+
+/**
+ * The folder containing a file, resolved canonically, for the file browser.
+ * Also emits a "navigate-to-folder" event with it to this window.
+ * @param {string} path - File or folder path
+ * @returns {Promise<string>}
+ * @throws {{kind: "Validation", message: string}} if the path has no parent (a root)
+ */
+export async function getParentDirectory(path) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("get_parent_directory", { path });
+}
+
+/**
+ * Size, modification time and (optionally) content hash of a file
+ * @param {string} path - File path
+ * @param {boolean} [hash=true] - Hash the content (skip for very large files to compare size + mtime only)
+ * @returns {Promise<{size: number, mtime: number, sha256: string|null}>}
+ * @throws {{kind: "NotFound", message: string}} if the file doesn't exist
+ */
+export async function fileFingerprint(path, hash = true) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("file_fingerprint", { path, hash });
+}
+
+/**
+ * Watch an open document for changes made by other apps. When its content
+ * changes on disk, "document-externally-modified" is emitted with
+ * {path, deleted}. The app's own saves through saveSsce aren't reported.
+ * @param {string} path - Document path
+ * @returns {Promise<void>}
+ * @throws {{kind: "NotFound", message: string}} if the file doesn't exist
+ */
+export async function watchDocument(path) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("watch_document", { path });
+}
+
+/**
+ * Stop watching a document (see watchDocument)
+ * @param {string} path - Document path
+ * @returns {Promise<boolean>} false if it wasn't being watched
+ */
+export async function unwatchDocument(path) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("unwatch_document", { path });
+}
+
+/**
+ * Create an empty named collection of documents
+ * @param {string} name - Collection name (must be unique)
+ * @returns {Promise<void>}
+ */
+export async function createCollection(name) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  await invoke("db_create_collection", { name });
+}
+
+/**
+ * Add library documents to a collection (all or nothing)
+ * @param {string} name - Collection name
+ * @param {string[]} paths - Document paths
+ * @returns {Promise<number>} How many weren't already members
+ */
+export async function addToCollection(name, paths) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("db_add_to_collection", { name, paths });
+}
+
+/**
+ * Remove documents from a collection
+ * @param {string} name - Collection name
+ * @param {string[]} paths - Document paths
+ * @returns {Promise<number>} How many were removed
+ */
+export async function removeFromCollection(name, paths) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("db_remove_from_collection", { name, paths });
+}
+
+/**
+ * All collections with their document counts
+ * @returns {Promise<Array<{name: string, created: string, file_count: number}>>}
+ */
+export async function listCollections() {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("db_list_collections");
+}
+
+/**
+ * The library entries in a collection, most recently modified first
+ * @param {string} name - Collection name
+ * @returns {Promise<Array<Object>>} LibraryFile rows
+ */
+export async function getCollectionFiles(name) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("db_collection_files", { name });
+}
+
+/**
+ * Queue a document to be re-indexed in the background (or removed from the
+ * library if it no longer exists). Returns immediately.
+ * @param {string} path - Document path
+ * @returns {Promise<void>}
+ */
+export async function queueIndex(path) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("queue_index", { path });
+}
+
+/**
+ * Progress of the background index queue
+ * @returns {Promise<{pending: number, last_error: string|null}>}
+ */
+export async function getIndexQueueStatus() {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("index_queue_status");
+}
+
+/**
+ * Find `.bak` files and thumbnail cache entries whose document no longer
+ * exists. Emits "orphan-scan-progress" ({done, total}) while it runs.
+ * @param {string} libraryPath - Library folder to scan for backups
+ * @returns {Promise<Array<{path: string, kind: "backup"|"thumbnail", base: string}>>}
+ */
+export async function findOrphanedSidecars(libraryPath) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("find_orphaned_sidecars", { libraryPath });
+}
+
+/**
+ * Delete orphaned sidecars. Any whose document has reappeared are kept.
+ * @param {string[]} paths - Paths from findOrphanedSidecars
+ * @returns {Promise<number>} How many were removed
+ */
+export async function cleanOrphanedSidecars(paths) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("clean_orphaned_sidecars", { paths });
+}
+
+/**
+ * Check the library's stored snapshot counts against the documents on disk.
+ * Emits "verify-snapshot-counts-progress" ({done, total}) while it runs.
+ * @param {string} libraryPath - Library folder whose documents to check
+ * @param {boolean} [autoFix=false] - Correct the rows that have drifted
+ * @returns {Promise<{checked: number, mismatches: Array<{path: string, stored: number, actual: number}>,
+ *   unreadable: number, fixed: boolean}>}
+ */
+export async function verifySnapshotCounts(libraryPath, autoFix = false) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("db_verify_snapshot_counts", { libraryPath, autoFix });
+}
+
+/**
+ * Readable form of an encoded path (the `encoded_path` of a listing entry or
+ * library file). Commands that open files accept the encoded form directly.
+ * @param {string} encoded - Encoded path ("raw:...")
+ * @returns {Promise<{display: string, exists: boolean}>}
+ */
+export async function decodePath(encoded) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("decode_path", { encoded });
+}
+
+/**
+ * The folder autosaves should go to: autosave.tempDirectory when writable,
+ * otherwise a fallback under the config directory
+ * @returns {Promise<{path: string, fallback: boolean, reason: string|null}>}
+ */
+export async function resolveAutosaveDir() {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("resolve_autosave_dir");
+}
+
+/**
+ * Repopulate the search index from the library table in place (no disk scan)
+ * @returns {Promise<void>}
+ */
+export async function reindexFts() {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("db_reindex_fts");
+}
+
+/**
+ * Merge the search index's segments for faster queries
+ * @returns {Promise<void>}
+ */
+export async function optimizeFts() {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("db_optimize_fts");
+}
+
+/**
+ * Combine another library database (e.g. from a second machine) into this one.
+ * For a document both have, the copy modified most recently wins.
+ * @param {string} otherDbPath - The other library.db; must be the same schema version
+ * @returns {Promise<{added: number, updated: number, skipped: number}>}
+ */
+export async function mergeDatabase(otherDbPath) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("merge_database", { otherDbPath });
+}
+
+/**
+ * Disk space used under the library folder, by kind of file, with the largest
+ * files. Emits "disk-usage-progress" ({done, total}); cancelLibraryDiskUsage stops it.
+ * @param {string} libraryPath - Library folder
+ * @param {number|null} [largest=null] - How many of the biggest files to list (null = 20)
+ * @returns {Promise<{documents: {files: number, bytes: number}, images: {files: number, bytes: number}, backups: {files: number, bytes: number}, other: {files: number, bytes: number}, total_bytes: number, largest: Array<{path: string, size: number}>, cancelled: boolean}>}
+ */
+export async function getLibraryDiskUsage(libraryPath, largest = null) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("library_disk_usage", { libraryPath, largest });
+}
+
+/**
+ * Stop an in-progress getLibraryDiskUsage (it resolves with cancelled: true)
+ * @returns {Promise<void>}
+ */
+export async function cancelLibraryDiskUsage() {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("cancel_library_disk_usage");
+}
+
+/**
+ * Copy the library folder into a new timestamped folder under destDir. Files
+ * unchanged since the previous backup are linked rather than copied, and the
+ * newest libraryBackups.maxCount backups are kept. Emits
+ * "backup-library-progress" ({done, total}); cancelBackupLibrary stops it.
+ * @param {string} destDir - Folder holding the backups
+ * @returns {Promise<{path: string, copied: number, skipped: number, bytes: number, cancelled: boolean}>}
+ */
+export async function backupLibrary(destDir) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("backup_library", { destDir });
+}
+
+/**
+ * Stop an in-progress backupLibrary and discard the partial backup (it
+ * resolves with cancelled: true)
+ * @returns {Promise<void>}
+ */
+export async function cancelBackupLibrary() {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("cancel_backup_library");
+}
+
+/**
+ * The current time in the format stored for modified/last_opened
+ * (e.g. "2025-03-01T14:00:00.000Z")
+ * @returns {Promise<string>}
+ */
+export async function nowIso() {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("now_iso");
+}
+
+/**
+ * Format a stored timestamp for display
+ * @param {string} iso - Stored ISO-8601 timestamp
+ * @param {string|null} [format=null] - strftime pattern (null = "%Y-%m-%d %H:%M")
+ * @param {string|null} [timezone=null] - IANA zone such as "Europe/London", or null for the system zone
+ * @returns {Promise<string>}
+ */
+export async function formatTimestamp(iso, format = null, timezone = null) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("format_timestamp", { iso, format, timezone });
+}
+
+/**
+ * Copy a document from outside the library into a library folder and index it.
+ * Fails if the library already holds the same content.
+ * @param {string} srcPath - Document to import
+ * @param {string} destDir - Library folder to copy it into
+ * @param {boolean} [force=false] - Replace a file of the same name in destDir
+ * @returns {Promise<Object>} The new library entry
+ */
+export async function importDocument(srcPath, destDir, force = false) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("import_document", { srcPath, destDir, force });
+}
+
+/**
+ * Import every document under a folder, keeping its subfolder layout.
+ * Emits "import-progress" events ({done, total}).
+ * @param {string} srcDir - Folder to import from
+ * @param {string} destDir - Library folder to copy into
+ * @param {boolean} [force=false] - Replace files of the same name
+ * @returns {Promise<{imported: Object[], duplicates: {path: string, reason: string}[], failed: {path: string, reason: string}[]}>}
+ */
+export async function importFolder(srcDir, destDir, force = false) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("import_folder", { srcDir, destDir, force });
+}
+
+/**
+ * Hold back background indexing (queueIndex) during a bulk change to the library.
+ * Paths queued meanwhile are indexed together on resume. Pauses nest.
+ * @returns {Promise<void>}
+ */
+export async function pauseLibraryWatch() {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("pause_library_watch");
+}
+
+/**
+ * Undo pauseLibraryWatch
+ * @returns {Promise<number>} Held paths sent for indexing (0 while still paused elsewhere)
+ */
+export async function resumeLibraryWatch() {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("resume_library_watch");
+}
+
+/**
+ * Thumbnail of an image or .ssce file as a data URL (cached on disk)
+ * @param {string} path - File path
+ * @param {number|null} [maxDim=null] - Longest side in pixels (null = thumbnailMaxDim setting)
+ * @param {boolean} [placeholder=false] - On a file that can't be rendered, resolve with a
+ *   placeholder tile instead of throwing
+ * @param {"Png"|"Webp"|"Jpeg"|"Avif"} [format="Png"] - Image format; one that isn't available
+ *   (Avif) comes back as PNG, so check the data URL's MIME type
+ * @param {number|null} [quality=null] - JPEG quality 1-100 (null = 80)
+ * @returns {Promise<string|{data_url: string, placeholder: boolean, error: string|null}>}
+ */
+export async function loadThumbnail(path, maxDim = null, placeholder = false, format = "Png", quality = null) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("load_thumbnail", { path, maxDim, placeholder, format, quality });
+}
+
+/**
+ * Delete autosaves identical (ignoring timestamps) to the next newer version of the
+ * same document, keeping distinct checkpoints and the newest
+ * @param {string} directory - Autosave folder
+ * @returns {Promise<Array<{document: string, kept: number, removed: number}>>}
+ */
+export async function compactAutosaves(directory) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("compact_autosaves", { directory });
+}
+
+/**
+ * Autosaves made from one document, newest first, each with a short summary of
+ * how it differs from the saved document
+ * @param {string} originalPath - The saved document
+ * @param {string|null} [directory=null] - Autosave folder (null = resolveAutosaveDir's choice)
+ * @returns {Promise<Array<{name: string, path: string, mtime: number, changes: string}>>}
+ */
+export async function listAutosavesFor(originalPath, directory = null) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("list_autosaves_for", { originalPath, directory });
+}
+
+/**
+ * "Did you mean" alternatives for a search that found nothing, built from the words in
+ * library filenames, titles and keywords
+ * @param {string} query - The search text
+ * @param {number|null} [limit=null] - Most suggestions to return (null = 5)
+ * @returns {Promise<string[]>} Corrected queries, best first (empty if nothing to correct)
+ */
+export async function getSearchSuggestions(query, limit = null) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("db_search_suggestions", { query, limit });
+}
+
+/**
+ * Move several documents at once, all or none (e.g. a multi-select drag into a folder).
+ * Library entries follow the files; if any move fails, the others are put back.
+ * @param {Array<{from: string, to: string}>} moves - Source and destination paths
+ * @returns {Promise<Array<{from: string, to: string, moved: boolean, error: string|null}>>}
+ */
+export async function moveFiles(moves) {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("move_files", { moves });
+}
+
+/**
+ * Open the app's config folder (defaults.json, library database, logs) in the file manager
+ * @returns {Promise<void>}
+ */
+export async function openConfigDir() {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("open_config_dir");
+}
+
+/**
+ * Path of the app's config folder, for display
+ * @returns {Promise<string>}
+ */
+export async function getConfigDirPath() {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("get_config_dir_path");
+}
+
+/**
+ * Where the library database lives and how SQLite is using it. On network
+ * file systems the database uses the rollback journal instead of WAL, and
+ * `warnings` explains why.
+ * @returns {Promise<{path: string, journal_mode: string, filesystem: string|null, warnings: string[]}>}
+ */
+export async function getDatabaseHealth() {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("database_health");
+}
+
+/**
+ * The active config with every app-level setting present: missing keys get
+ * their bundled default. Editor settings and unknown keys are passed through
+ * unchanged.
+ * @returns {Promise<Object>} Same shape as defaults.json
+ */
+export async function getDefaultsTyped() {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("get_defaults_typed");
+}
+
+/**
+ * Copy the library database's write-ahead log into the main file now,
+ * instead of waiting for the periodic checkpoint
+ * @returns {Promise<{busy: boolean, log_frames: number, checkpointed_frames: number}>}
+ */
+export async function forceCheckpoint() {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("force_checkpoint");
+}
+
+/**
+ * Which defaults.json is in effect, for diagnosing why an edit isn't applied
+ * @returns {Promise<{source: "User"|"Dev"|"Bundled"|"LinuxSystem", path: string}>}
+ */
+export async function getActiveConfigSource() {
+  const invoke = getInvoke();
+  if (!invoke) throw new Error("Tauri invoke not available");
+  return await invoke("get_active_config_source");
+}
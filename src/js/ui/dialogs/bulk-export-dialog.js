@@ -210,7 +210,7 @@ async function scanSourceFolder(directory) {
     // Apply current filter
     handleDateFilterChange();
   } catch (error) {
-    document.getElementById("bulk-export-file-count").textContent = `Error: ${error}`;
+    document.getElementById("bulk-export-file-count").textContent = `Error: ${error.message || error}`;
     allFiles = [];
     filteredFiles = [];
   }
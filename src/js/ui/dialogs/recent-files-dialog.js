@@ -3,7 +3,7 @@
  * Shows a grid of recently opened/saved .ssce files with thumbnails
  */
 
-import { getRecentFiles, removeRecentFile, rebuildFromLibrary } from "../../utils/recent-files.js";
+import { getRecentFiles, getRecentlyModified, removeRecentFile, rebuildFromLibrary } from "../../utils/recent-files.js";
 import { getLibraryPath } from "../../utils/config.js";
 import * as bridge from "../../tauri-bridge.js";
 
@@ -43,14 +43,20 @@ export function initRecentFilesDialog(fileSelectHandler) {
     rebuildBtn.textContent = "Rebuilding...";
 
     try {
-      const count = await rebuildFromLibrary(libraryPath);
+      const { indexed, skipped, errors } = await rebuildFromLibrary(libraryPath);
       await renderGrid();
       const { showAlertModal } = await import("./index.js");
-      await showAlertModal("Library Rebuilt", `Indexed ${count} file${count !== 1 ? "s" : ""} from the library.`);
+      let message = `Indexed ${indexed} file${indexed !== 1 ? "s" : ""} from the library.`;
+      if (skipped > 0) {
+        const listed = errors.map((e) => `${e.path}: ${e.message}`).join("\n");
+        const more = skipped > errors.length ? `\n...and ${skipped - errors.length} more` : "";
+        message += `\n\nSkipped ${skipped} unreadable file${skipped !== 1 ? "s" : ""}:\n${listed}${more}`;
+      }
+      await showAlertModal("Library Rebuilt", message);
     } catch (err) {
       console.error("Failed to rebuild library:", err);
       const { showAlertModal } = await import("./index.js");
-      await showAlertModal("Rebuild Failed", `Failed to rebuild library: ${err}`);
+      await showAlertModal("Rebuild Failed", `Failed to rebuild library: ${err.message || err}`);
     } finally {
       rebuildBtn.disabled = false;
       rebuildBtn.textContent = "Rebuild from Library";
@@ -97,7 +103,11 @@ async function renderGrid() {
   grid.classList.remove("hidden");
   emptyState.classList.add("hidden");
 
-  const files = await getRecentFiles();
+  // Nothing opened yet (e.g. a freshly rebuilt library) - show newest files instead
+  let files = await getRecentFiles();
+  if (files.length === 0) {
+    files = await getRecentlyModified();
+  }
 
   if (files.length === 0) {
     grid.classList.add("hidden");
@@ -208,13 +218,14 @@ function createThumbnailItem(file) {
 async function loadThumbnailFromFile(path, item) {
   try {
     const invoke = window.__TAURI__.core.invoke;
-    const metadata = await invoke("get_ssce_metadata", { path });
+    // Thumbnail comes from the backend's thumbnail cache, so the metadata call stays small
+    const [metadata, thumbnail] = await Promise.all([invoke("get_ssce_metadata", { path }), invoke("get_ssce_thumbnail_cached", { path })]);
 
-    if (metadata.thumbnail) {
+    if (thumbnail) {
       // Update the thumbnail display
       const placeholder = item.querySelector(".thumbnail-placeholder");
       if (placeholder) {
-        placeholder.parentElement.innerHTML = `<img src="${metadata.thumbnail}" alt="" class="w-full h-full object-contain" />`;
+        placeholder.parentElement.innerHTML = `<img src="${thumbnail}" alt="" class="w-full h-full object-contain" />`;
       }
     } else {
       // No thumbnail in file - show generic icon
@@ -264,6 +264,7 @@ async function performSearch() {
       fromDate: fromDate,
       toDate: toDate,
       limit: 100,
+      fuzzy: true,
     });
 
     if (results.length === 0) {
@@ -273,7 +274,8 @@ async function performSearch() {
       return;
     }
 
-    status.textContent = `Found ${results.length} file${results.length !== 1 ? "s" : ""}`;
+    const noun = `file${results.length !== 1 ? "s" : ""}`;
+    status.textContent = results[0].fuzzy ? `No exact matches - ${results.length} similar ${noun}` : `Found ${results.length} ${noun}`;
     grid.innerHTML = "";
     grid.classList.remove("hidden");
     emptyState.classList.add("hidden");
@@ -284,7 +286,7 @@ async function performSearch() {
     }
   } catch (err) {
     console.error("Search failed:", err);
-    grid.innerHTML = `<div class="col-span-full text-center text-red-400 py-8">Search failed: ${err}</div>`;
+    grid.innerHTML = `<div class="col-span-full text-center text-red-400 py-8">Search failed: ${err.message || err}</div>`;
     status.textContent = "";
   }
 }
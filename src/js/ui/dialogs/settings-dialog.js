@@ -125,7 +125,7 @@ export async function showSettingsDialog() {
     pathDisplay.textContent = `Save location: ${configPath}`;
 
     // Load current config
-    const configJson = await invoke("get_defaults_config");
+    const { config: configJson } = await invoke("get_defaults_config");
     originalConfig = configJson;
 
     // Pretty print the JSON
@@ -226,7 +226,7 @@ async function handleSave() {
     }
   } catch (err) {
     console.error("Failed to save settings:", err);
-    showError(`Failed to save: ${err.message}`);
+    showError(`Failed to save: ${err.message ?? err}`);
   }
 }
 
@@ -71,8 +71,18 @@ export async function initAutoSave(options = {}) {
 
   // Determine the full path to the temp directory
   if (tauriBridge.isTauri()) {
-    const homeDir = await tauriBridge.getHomeDir();
-    tempDirectoryPath = `${homeDir}/${config.tempDirectory}`;
+    // The backend checks the configured folder is writable and falls back if not
+    const autosaveDir = await tauriBridge.resolveAutosaveDir();
+    tempDirectoryPath = autosaveDir.path;
+    if (autosaveDir.fallback) {
+      console.warn(`SSCE AutoSave: Using ${autosaveDir.path} (${autosaveDir.reason})`);
+    }
+
+    // The backend setting is the source of truth for the interval
+    const interval = await tauriBridge.getSetting("autosaveIntervalSeconds");
+    if (interval) {
+      config.inactivitySeconds = interval;
+    }
   }
 
   // Start the auto-save check timer
@@ -160,7 +170,12 @@ async function performAutoSave() {
 
     if (tauriBridge.isTauri()) {
       // Tauri: Use native file system
-      const savedPath = await tauriBridge.saveAutosave(ssceData, tempFilename, tempDirectoryPath);
+      const savedPath = await tauriBridge.saveAutosave(
+        ssceData,
+        tempFilename,
+        tempDirectoryPath,
+        state.currentFilePath || null,
+      );
       currentTempFile = savedPath;
       console.log(`SSCE AutoSave: Saved to ${savedPath}`);
     } else {
@@ -229,8 +244,7 @@ export async function cleanupTempFile() {
 export async function getTempDirectory() {
   if (tauriBridge.isTauri()) {
     if (!tempDirectoryPath) {
-      const homeDir = await tauriBridge.getHomeDir();
-      tempDirectoryPath = `${homeDir}/${config.tempDirectory}`;
+      tempDirectoryPath = (await tauriBridge.resolveAutosaveDir()).path;
     }
     return tempDirectoryPath;
   }
@@ -246,7 +260,7 @@ export async function checkForRecovery() {
     if (tauriBridge.isTauri()) {
       // Tauri: Use native file system
       const directory = await getTempDirectory();
-      const files = await tauriBridge.listAutosaveFiles(directory);
+      const files = await tauriBridge.checkRecovery(directory);
       return files;
     } else {
       // HTTP: Use Bun server API
@@ -37,14 +37,22 @@ export async function loadConfig() {
       // Load via Tauri command using global API (requires withGlobalTauri: true)
       const invoke = window.__TAURI__.core.invoke;
       console.log("SSCE Config: Loading defaults via Tauri...");
-      const jsonStr = await invoke("get_defaults_config");
+      const { config: jsonStr, source, path } = await invoke("get_defaults_config");
       defaults = JSON.parse(jsonStr);
+      console.log(`SSCE Config: using ${source} config from ${path}`);
       console.log("SSCE Config: defaults loaded:", Object.keys(defaults));
 
       // Also load environment config
       console.log("SSCE Config: Loading env config...");
       envConfig = await invoke("get_env_config");
       console.log("SSCE Config: envConfig:", envConfig);
+
+      // Pick up hand edits to defaults.json without a restart.
+      // Getters read `defaults` on each call, so new values apply from the next use.
+      await window.__TAURI__.event?.listen("defaults-changed", (event) => {
+        defaults = event.payload;
+        console.log("SSCE Config: defaults reloaded");
+      });
     } else {
       // Fallback for browser development (fetch from API if available)
       const response = await fetch("/api/defaults");
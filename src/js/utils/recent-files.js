@@ -33,6 +33,9 @@ const invoke = window.__TAURI__?.core?.invoke;
  * @property {string} [modified] - ISO timestamp of last modification
  * @property {string} [lastOpened] - ISO timestamp of last open
  * @property {number} snapshotCount - Number of snapshots in the file
+ * @property {number} openCount - Times the file has been opened
+ * @property {string} [author] - Author from frontMatter
+ * @property {string} [created] - ISO timestamp from frontMatter.created
  */
 
 /**
@@ -60,6 +63,9 @@ export async function getRecentFiles(limit = DEFAULT_MAX_COUNT) {
       modified: f.modified,
       lastOpened: f.last_opened,
       snapshotCount: f.snapshot_count || 0,
+      openCount: f.open_count || 0,
+      author: f.author,
+      created: f.created,
     }));
   } catch (err) {
     console.error("Failed to load recent files from database:", err);
@@ -67,6 +73,77 @@ export async function getRecentFiles(limit = DEFAULT_MAX_COUNT) {
   }
 }
 
+/**
+ * Get files ordered by modified date, including ones never opened.
+ * Used to show content in Recent Files straight after a library rebuild.
+ * @param {number} [limit] - Maximum number of files to return
+ * @returns {Promise<RecentFile[]>}
+ */
+export async function getRecentlyModified(limit = DEFAULT_MAX_COUNT) {
+  if (!invoke) {
+    console.warn("Tauri invoke not available, returning empty recently modified files");
+    return [];
+  }
+
+  try {
+    const files = await invoke("db_get_recently_modified", { limit });
+    // Convert snake_case to camelCase for JS consumption
+    return files.map((f) => ({
+      id: f.id,
+      path: f.path,
+      filename: f.filename,
+      thumbnail: f.thumbnail,
+      title: f.title,
+      summary: f.summary,
+      keywords: f.keywords,
+      modified: f.modified,
+      lastOpened: f.last_opened,
+      snapshotCount: f.snapshot_count || 0,
+      openCount: f.open_count || 0,
+      author: f.author,
+      created: f.created,
+    }));
+  } catch (err) {
+    console.error("Failed to load recently modified files from database:", err);
+    return [];
+  }
+}
+
+/**
+ * Get the most opened files, for the "Most Used" view
+ * @param {number} [limit] - Maximum number of files to return
+ * @returns {Promise<RecentFile[]>}
+ */
+export async function getMostUsedFiles(limit = DEFAULT_MAX_COUNT) {
+  if (!invoke) {
+    console.warn("Tauri invoke not available, returning empty most used files");
+    return [];
+  }
+
+  try {
+    const files = await invoke("db_most_used_files", { limit });
+    // Convert snake_case to camelCase for JS consumption
+    return files.map((f) => ({
+      id: f.id,
+      path: f.path,
+      filename: f.filename,
+      thumbnail: f.thumbnail,
+      title: f.title,
+      summary: f.summary,
+      keywords: f.keywords,
+      modified: f.modified,
+      lastOpened: f.last_opened,
+      snapshotCount: f.snapshot_count || 0,
+      openCount: f.open_count || 0,
+      author: f.author,
+      created: f.created,
+    }));
+  } catch (err) {
+    console.error("Failed to load most used files from database:", err);
+    return [];
+  }
+}
+
 /**
  * Add or update a file in the library database
  * @param {string} path - Full file path
@@ -76,6 +153,8 @@ export async function getRecentFiles(limit = DEFAULT_MAX_COUNT) {
  * @param {string} [metadata.summary] - File summary
  * @param {string} [metadata.keywords] - Space-separated keywords
  * @param {string} [metadata.modified] - ISO timestamp
+ * @param {string} [metadata.author] - Author (ignored unless a string)
+ * @param {string} [metadata.created] - ISO timestamp the document was created
  * @param {number} [metadata.snapshotCount] - Number of snapshots
  * @returns {Promise<number>} - Database ID
  */
@@ -104,6 +183,8 @@ export async function addRecentFile(path, metadata = {}) {
         modified: metadata.modified || now,
         last_opened: now,
         snapshot_count: metadata.snapshotCount || 0,
+        author: typeof metadata.author === "string" ? metadata.author : null,
+        created: typeof metadata.created === "string" ? metadata.created : null,
       },
     });
     return id;
@@ -113,7 +194,7 @@ export async function addRecentFile(path, metadata = {}) {
 }
 
 /**
- * Update just the last_opened timestamp for a file
+ * Record that a file was opened (sets last_opened and counts the open)
  * @param {string} path - Full file path
  * @returns {Promise<void>}
  */
@@ -147,9 +228,15 @@ export async function removeRecentFile(path) {
  * Search files in the library using FTS5
  * @param {Object} params - Search parameters
  * @param {string} [params.query] - Search text (matches filename, title, summary, keywords)
- * @param {string} [params.fromDate] - Filter by modified date (ISO string)
- * @param {string} [params.toDate] - Filter by modified date (ISO string)
+ * @param {string} [params.fromDate] - Filter by date (ISO string), modified unless dateField says otherwise
+ * @param {string} [params.toDate] - Filter by date (ISO string), modified unless dateField says otherwise
+ * @param {"modified"|"created"} [params.dateField] - Which date fromDate/toDate apply to
  * @param {number} [params.limit] - Maximum results (default 50)
+ * @param {boolean} [params.fuzzy] - If nothing matches, return similarly spelled filenames/titles (flagged fuzzy)
+ * @param {string} [params.pathPrefix] - Only return documents inside this folder (including subfolders)
+ * @param {string} [params.author] - Only return documents whose author contains this (case-insensitive)
+ * @param {string[]} [params.fields] - Only match the query in these fields ("title", "filename", "summary",
+ *   "keywords", "content", "author"); all of them by default
  * @returns {Promise<RecentFile[]>}
  */
 export async function searchFiles(params = {}) {
@@ -165,6 +252,11 @@ export async function searchFiles(params = {}) {
         from_date: params.fromDate || null,
         to_date: params.toDate || null,
         limit: params.limit || 50,
+        fuzzy: params.fuzzy || false,
+        path_prefix: params.pathPrefix || null,
+        author: params.author || null,
+        date_field: params.dateField || "modified",
+        fields: params.fields || null,
       },
     });
 
@@ -179,6 +271,10 @@ export async function searchFiles(params = {}) {
       modified: f.modified,
       lastOpened: f.last_opened,
       snapshotCount: f.snapshot_count || 0,
+      openCount: f.open_count || 0,
+      author: f.author,
+      created: f.created,
+      fuzzy: f.fuzzy || false,
     }));
   } catch (err) {
     console.error("Failed to search files:", err);
@@ -190,20 +286,43 @@ export async function searchFiles(params = {}) {
  * Rebuild the library database from the library folder
  * Scans all .ssce files and updates/adds them to the database
  * Also removes stale entries for files that no longer exist
+ * Files that can't be read or parsed are skipped and reported
  * @param {string} libraryPath - Path to the library folder
- * @returns {Promise<number>} - Number of files indexed
+ * @returns {Promise<{indexed: number, skipped: number, errors: Array<{path: string, message: string}>,
+ *   limit_exceeded: {limit: "maxScanDepth"|"maxScanFiles", value: number}|null}>}
+ *   limit_exceeded is set (and nothing indexed) when the folder is deeper or bigger than the
+ *   library.maxScanDepth / maxScanFiles limits in defaults.json
+ * @throws {{kind: "AlreadyRunning", message: string}} if a rebuild is already in progress
  */
 export async function rebuildFromLibrary(libraryPath) {
   if (!invoke) {
     console.warn("Tauri invoke not available");
-    return 0;
+    return { indexed: 0, skipped: 0, errors: [], limit_exceeded: null };
   }
 
   try {
-    const count = await invoke("db_rebuild_from_library", { libraryPath });
-    return count;
+    return await invoke("db_rebuild_from_library", { libraryPath });
   } catch (err) {
     console.error("Failed to rebuild library:", err);
     throw err;
   }
 }
+
+/**
+ * Remove library entries for files deleted or moved outside the app
+ * (entries on unmounted or unresponsive drives are kept)
+ * @returns {Promise<number>} Number of entries removed
+ */
+export async function pruneMissingFiles() {
+  if (!invoke) {
+    console.warn("Tauri invoke not available");
+    return 0;
+  }
+
+  try {
+    return await invoke("db_prune_missing");
+  } catch (err) {
+    console.error("Failed to prune missing files:", err);
+    throw err;
+  }
+}